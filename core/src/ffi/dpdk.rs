@@ -522,6 +522,15 @@ pub(crate) fn eth_sym_rss_enable(port_id: PortId, num_queues: usize) -> Result<(
     Ok(())
 }
 
+/// Sets the MTU of a device.
+pub(crate) fn eth_dev_set_mtu(port_id: PortId, mtu: u16) -> Result<()> {
+    unsafe {
+        cffi::rte_eth_dev_set_mtu(port_id.0, mtu)
+            .into_result(DpdkError::from_errno)
+            .map(|_| ())
+    }
+}
+
 /// Configures a device.
 pub(crate) fn eth_dev_configure(
     port_id: PortId,
@@ -764,6 +773,20 @@ pub(crate) fn pktmbuf_alloc(mp: &mut MempoolPtr) -> Result<MbufPtr> {
     Ok(EasyPtr(ptr))
 }
 
+/// Registers the dynamic mbuf field and flag for the RX timestamp, and
+/// returns the offset of the field and the flag.
+pub(crate) fn mbuf_dyn_rx_timestamp_register() -> Result<(usize, u64)> {
+    let mut offset: raw::c_int = 0;
+    let mut flag: u64 = 0;
+
+    unsafe {
+        cffi::_rte_mbuf_dyn_rx_timestamp_register(&mut offset, &mut flag)
+            .into_result(DpdkError::from_errno)?;
+    }
+
+    Ok((offset as usize, flag))
+}
+
 /// Allocates a bulk of mbufs.
 pub(crate) fn pktmbuf_alloc_bulk(mp: &mut MempoolPtr, mbufs: &mut Vec<MbufPtr>) -> Result<()> {
     let len = mbufs.capacity();
@@ -789,40 +812,17 @@ pub(crate) fn pktmbuf_free(mut m: MbufPtr) {
     }
 }
 
-/// Frees a bulk of packet mbufs back into their original mempools.
+/// Frees a bulk of packet mbufs, and the segments chained to them, back
+/// into their original mempools.
 pub(crate) fn pktmbuf_free_bulk(mbufs: &mut Vec<MbufPtr>) {
     assert!(!mbufs.is_empty());
 
-    let mut to_free = Vec::with_capacity(mbufs.len());
-    let mut pool = mbufs[0].pool;
-
-    for mbuf in mbufs.drain(..) {
-        if pool == mbuf.pool {
-            to_free.push(mbuf);
-        } else {
-            unsafe {
-                let len = to_free.len();
-                cffi::_rte_mempool_put_bulk(
-                    pool,
-                    to_free.as_ptr() as *const *mut raw::c_void,
-                    len as u32,
-                );
-                to_free.set_len(0);
-            }
-
-            pool = mbuf.pool;
-            to_free.push(mbuf);
-        }
-    }
-
     unsafe {
-        let len = to_free.len();
-        cffi::_rte_mempool_put_bulk(
-            pool,
-            to_free.as_ptr() as *const *mut raw::c_void,
-            len as u32,
+        cffi::rte_pktmbuf_free_bulk(
+            mbufs.as_mut_ptr() as *mut *mut cffi::rte_mbuf,
+            mbufs.len() as raw::c_uint,
         );
-        to_free.set_len(0);
+        mbufs.set_len(0);
     }
 }
 
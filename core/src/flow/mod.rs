@@ -0,0 +1,570 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Per-lcore flow tracking.
+//!
+//! A [`FlowTable`] maps the flows of a connection, in both directions, to
+//! the application's state for the connection. Entries expire when the
+//! connection is idle for longer than the timeout of its protocol, or of
+//! its TCP state for TCP connections. Expired entries are returned by
+//! [`FlowTable::evict_expired`], so the application can release the state
+//! they hold. Until then, they count against the capacity of the table.
+//!
+//! The table is not synchronized. Each lcore should own its table, and
+//! rely on receive side scaling, ideally symmetric, to steer both
+//! directions of a connection to the same lcore.
+//!
+//! [`FlowTable`]: crate::flow::FlowTable
+
+mod tcp;
+
+pub use self::tcp::TcpState;
+
+use self::tcp::TcpFlags;
+use crate::ensure;
+use crate::packets::ip::{Flow, IpPacket, ProtocolNumbers};
+use crate::packets::tcp::Tcp;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// The direction of a packet relative to the first packet of its
+/// connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Same direction as the packet that created the entry.
+    Original,
+    /// Opposite direction.
+    Reply,
+}
+
+/// Configuration of a flow table.
+#[derive(Clone, Copy, Debug)]
+pub struct FlowTableConfig {
+    /// Maximum number of entries.
+    pub capacity: usize,
+    /// Idle timeout of a TCP connection during the handshake.
+    pub tcp_opening: Duration,
+    /// Idle timeout of an established TCP connection.
+    pub tcp_established: Duration,
+    /// Idle timeout of a TCP connection during the teardown.
+    pub tcp_closing: Duration,
+    /// Idle timeout of a closed or reset TCP connection.
+    pub tcp_closed: Duration,
+    /// Idle timeout of a UDP flow.
+    pub udp: Duration,
+    /// Idle timeout of flows of other protocols.
+    pub other: Duration,
+}
+
+impl Default for FlowTableConfig {
+    fn default() -> Self {
+        FlowTableConfig {
+            capacity: 65536,
+            tcp_opening: Duration::from_secs(60),
+            // RFC 5382 recommends at least 2 hours and 4 minutes.
+            tcp_established: Duration::from_secs(7440),
+            tcp_closing: Duration::from_secs(120),
+            tcp_closed: Duration::from_secs(10),
+            // RFC 4787 recommends at least 2 minutes.
+            udp: Duration::from_secs(120),
+            other: Duration::from_secs(30),
+        }
+    }
+}
+
+impl FlowTableConfig {
+    fn timeout(&self, flow: &Flow, tcp_state: Option<TcpState>) -> Duration {
+        match tcp_state {
+            Some(TcpState::Established) => self.tcp_established,
+            Some(state) if state.is_opening() => self.tcp_opening,
+            Some(state) if state.is_closing() => self.tcp_closing,
+            Some(_) => self.tcp_closed,
+            None if flow.protocol() == ProtocolNumbers::Tcp => self.tcp_opening,
+            None if flow.protocol() == ProtocolNumbers::Udp => self.udp,
+            None => self.other,
+        }
+    }
+}
+
+/// An entry in the flow table.
+#[derive(Debug)]
+pub struct FlowEntry<V> {
+    flow: Flow,
+    reply: Flow,
+    value: V,
+    tcp_state: Option<TcpState>,
+    last_seen: Instant,
+    deadline: Deadline,
+}
+
+/// When an entry expires, made unique by the order it was scheduled in.
+type Deadline = (Instant, u64);
+
+impl<V> FlowEntry<V> {
+    /// Returns the flow in the original direction.
+    #[inline]
+    pub fn flow(&self) -> Flow {
+        self.flow
+    }
+
+    /// Returns the flow in the reply direction.
+    #[inline]
+    pub fn reply_flow(&self) -> Flow {
+        self.reply
+    }
+
+    /// Returns the application's state for the connection.
+    #[inline]
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// Returns the application's state for the connection mutably.
+    #[inline]
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+
+    /// Consumes the entry and returns the application's state.
+    #[inline]
+    pub fn into_value(self) -> V {
+        self.value
+    }
+
+    /// Returns the TCP state of the connection, if tracked.
+    #[inline]
+    pub fn tcp_state(&self) -> Option<TcpState> {
+        self.tcp_state
+    }
+
+    /// Returns when the last packet of the connection was seen.
+    #[inline]
+    pub fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+}
+
+/// A flow table with bidirectional lookup and idle timeouts.
+///
+/// # Example
+///
+/// ```
+/// let mut table = FlowTable::new(FlowTableConfig::default());
+///
+/// let tcp = ipv4.parse::<Tcp4>()?;
+/// if table.get(&tcp.flow()).is_none() {
+///     table.insert(tcp.flow(), Session::new())?;
+/// }
+/// let (direction, state) = table.track_tcp(&tcp).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FlowTable<V> {
+    config: FlowTableConfig,
+    entries: HashMap<Flow, FlowEntry<V>>,
+    replies: HashMap<Flow, Flow>,
+    deadlines: BTreeMap<Deadline, Flow>,
+    next_seq: u64,
+    expired: Vec<FlowEntry<V>>,
+}
+
+impl<V> FlowTable<V> {
+    /// Creates a new flow table.
+    pub fn new(config: FlowTableConfig) -> Self {
+        FlowTable {
+            config,
+            entries: HashMap::new(),
+            replies: HashMap::new(),
+            deadlines: BTreeMap::new(),
+            next_seq: 0,
+            expired: vec![],
+        }
+    }
+
+    /// Returns the number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Inserts an entry for the flow. The reply direction is the reverse
+    /// of the flow.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either direction already has an entry, or if
+    /// the table is full. The expired entries not yet returned by
+    /// `evict_expired` count against the capacity.
+    pub fn insert(&mut self, flow: Flow, value: V) -> Result<&mut FlowEntry<V>> {
+        self.insert_with_reply(flow, flow.reverse(), value)
+    }
+
+    /// Inserts an entry for the flow with a reply direction that is not
+    /// the reverse of the flow, for example when the packets are
+    /// translated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either direction already has an entry, or if
+    /// the table is full. The expired entries not yet returned by
+    /// `evict_expired` count against the capacity.
+    pub fn insert_with_reply(
+        &mut self,
+        flow: Flow,
+        reply: Flow,
+        value: V,
+    ) -> Result<&mut FlowEntry<V>> {
+        let now = Instant::now();
+        self.remove_if_expired(&flow, now);
+        self.remove_if_expired(&reply, now);
+        ensure!(
+            !self.contains(&flow) && !self.contains(&reply),
+            anyhow!("flow already has an entry.")
+        );
+
+        ensure!(
+            self.entries.len() + self.expired.len() < self.config.capacity,
+            anyhow!("flow table is full.")
+        );
+
+        if reply != flow {
+            self.replies.insert(reply, flow);
+        }
+        let timeout = self.config.timeout(&flow, None);
+        let deadline = self.schedule(flow, now + timeout);
+        let entry = FlowEntry {
+            flow,
+            reply,
+            value,
+            tcp_state: None,
+            last_seen: now,
+            deadline,
+        };
+        Ok(self.entries.entry(flow).or_insert(entry))
+    }
+
+    /// Returns whether either direction of a connection matches the flow.
+    #[inline]
+    pub fn contains(&self, flow: &Flow) -> bool {
+        self.entries.contains_key(flow) || self.replies.contains_key(flow)
+    }
+
+    /// Returns the key of the entry matching the flow in either direction.
+    #[inline]
+    fn find(&self, flow: &Flow) -> Option<(Flow, Direction)> {
+        if self.entries.contains_key(flow) {
+            Some((*flow, Direction::Original))
+        } else {
+            self.replies
+                .get(flow)
+                .map(|original| (*original, Direction::Reply))
+        }
+    }
+
+    /// Adds the flow to the expiry order, and returns its deadline.
+    #[inline]
+    fn schedule(&mut self, flow: Flow, at: Instant) -> Deadline {
+        let deadline = (at, self.next_seq);
+        self.next_seq += 1;
+        self.deadlines.insert(deadline, flow);
+        deadline
+    }
+
+    /// Moves the entry of the key in the expiry order, based on when its
+    /// last packet was seen and the timeout of its current state.
+    fn reschedule(&mut self, key: &Flow) {
+        if let Some(entry) = self.entries.get(key) {
+            let at = entry.last_seen + self.config.timeout(&entry.flow, entry.tcp_state);
+            let old = entry.deadline;
+            self.deadlines.remove(&old);
+            let deadline = self.schedule(*key, at);
+            if let Some(entry) = self.entries.get_mut(key) {
+                entry.deadline = deadline;
+            }
+        }
+    }
+
+    /// Removes the entry matching the flow if it has expired, and holds it
+    /// until the next `evict_expired`.
+    fn remove_if_expired(&mut self, flow: &Flow, now: Instant) {
+        if let Some((key, _)) = self.find(flow) {
+            if self.entries[&key].deadline.0 <= now {
+                if let Some(entry) = self.remove(&key) {
+                    self.expired.push(entry);
+                }
+            }
+        }
+    }
+
+    /// Removes the expired entries, in the order they expired, and holds
+    /// them until the next `evict_expired`.
+    fn collect_expired(&mut self, now: Instant) {
+        while let Some((&deadline, &flow)) = self.deadlines.iter().next() {
+            if deadline.0 > now {
+                break;
+            }
+            self.deadlines.remove(&deadline);
+            if let Some(entry) = self.remove(&flow) {
+                self.expired.push(entry);
+            }
+        }
+    }
+
+    /// Looks up the entry matching the flow in either direction, and
+    /// returns it with the direction of the flow.
+    ///
+    /// Refreshes the idle timeout of the entry. An expired entry is
+    /// removed instead.
+    pub fn get(&mut self, flow: &Flow) -> Option<(Direction, &mut FlowEntry<V>)> {
+        let now = Instant::now();
+        self.remove_if_expired(flow, now);
+
+        let (key, direction) = self.find(flow)?;
+        self.entries.get_mut(&key)?.last_seen = now;
+        self.reschedule(&key);
+        let entry = self.entries.get_mut(&key)?;
+        Some((direction, entry))
+    }
+
+    /// Removes the entry matching the flow in either direction.
+    pub fn remove(&mut self, flow: &Flow) -> Option<FlowEntry<V>> {
+        let (key, _) = self.find(flow)?;
+        let entry = self.entries.remove(&key)?;
+        self.replies.remove(&entry.reply);
+        self.deadlines.remove(&entry.deadline);
+        Some(entry)
+    }
+
+    /// Updates the TCP state of the entry matching the segment's flow in
+    /// either direction, and returns the direction of the segment with the
+    /// new state.
+    ///
+    /// Returns `None` if there's no entry for the segment's flow.
+    pub fn track_tcp<E: IpPacket>(&mut self, tcp: &Tcp<E>) -> Option<(Direction, TcpState)> {
+        let flags = TcpFlags {
+            syn: tcp.syn(),
+            ack: tcp.ack(),
+            fin: tcp.fin(),
+            rst: tcp.rst(),
+        };
+
+        let (direction, entry) = self.get(&tcp.flow())?;
+        let state = match entry.tcp_state {
+            Some(state) => state.next(direction, flags),
+            None => TcpState::new(flags),
+        };
+        entry.tcp_state = Some(state);
+
+        // the timeout depends on the state.
+        let key = entry.flow;
+        self.reschedule(&key);
+        Some((direction, state))
+    }
+
+    /// Removes the expired entries and returns them, including the ones
+    /// already removed by lookups and inserts since the last call.
+    pub fn evict_expired(&mut self) -> Vec<FlowEntry<V>> {
+        self.collect_expired(Instant::now());
+        mem::take(&mut self.expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::tcp::Tcp4;
+    use crate::packets::{Mbuf, Packet};
+    use crate::testils::byte_arrays::IPV4_TCP_PACKET;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn udp_flow(src_port: u16) -> Flow {
+        Flow::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            src_port,
+            53,
+            ProtocolNumbers::Udp,
+        )
+    }
+
+    #[test]
+    fn insert_and_lookup_both_directions() {
+        let mut table = FlowTable::new(FlowTableConfig::default());
+        let flow = udp_flow(1234);
+        table.insert(flow, 1).unwrap();
+        assert_eq!(1, table.len());
+
+        let (direction, entry) = table.get(&flow).unwrap();
+        assert_eq!(Direction::Original, direction);
+        assert_eq!(1, *entry.value());
+
+        let (direction, entry) = table.get(&flow.reverse()).unwrap();
+        assert_eq!(Direction::Reply, direction);
+        *entry.value_mut() = 2;
+
+        assert_eq!(2, *table.get(&flow).unwrap().1.value());
+        assert!(table.get(&udp_flow(4321)).is_none());
+
+        // either direction is a duplicate
+        assert!(table.insert(flow.reverse(), 3).is_err());
+    }
+
+    #[test]
+    fn insert_with_translated_reply() {
+        let mut table = FlowTable::new(FlowTableConfig::default());
+        let flow = udp_flow(1234);
+        let mut reply = flow.reverse();
+        reply.set_dst_port(60000);
+        table.insert_with_reply(flow, reply, ()).unwrap();
+
+        assert_eq!(Direction::Reply, table.get(&reply).unwrap().0);
+        assert!(table.get(&flow.reverse()).is_none());
+
+        let entry = table.remove(&reply).unwrap();
+        assert_eq!(flow, entry.flow());
+        assert!(table.is_empty());
+        assert!(!table.contains(&reply));
+    }
+
+    #[test]
+    fn expire_idle_entries() {
+        let config = FlowTableConfig {
+            udp: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let mut table = FlowTable::new(config);
+        table.insert(udp_flow(1), ()).unwrap();
+        table.insert(udp_flow(2), ()).unwrap();
+
+        assert!(table.get(&udp_flow(1)).is_none());
+        assert_eq!(1, table.len());
+
+        // includes the entry removed by the lookup
+        assert_eq!(2, table.evict_expired().len());
+        assert!(table.is_empty());
+        assert!(table.evict_expired().is_empty());
+    }
+
+    #[test]
+    fn table_full() {
+        let config = FlowTableConfig {
+            capacity: 1,
+            ..Default::default()
+        };
+        let mut table = FlowTable::new(config);
+        table.insert(udp_flow(1), ()).unwrap();
+        assert!(table.insert(udp_flow(2), ()).is_err());
+
+        table.remove(&udp_flow(1));
+        assert!(table.insert(udp_flow(2), ()).is_ok());
+    }
+
+    #[test]
+    fn expired_entries_count_against_capacity() {
+        let config = FlowTableConfig {
+            capacity: 2,
+            udp: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let mut table = FlowTable::new(config);
+        table.insert(udp_flow(1), ()).unwrap();
+        table.insert(udp_flow(2), ()).unwrap();
+
+        // the expired entries are held until evicted
+        assert!(table.get(&udp_flow(1)).is_none());
+        assert!(table.insert(udp_flow(3), ()).is_err());
+
+        assert_eq!(2, table.evict_expired().len());
+        assert!(table.insert(udp_flow(3), ()).is_ok());
+    }
+
+    #[test]
+    fn evict_in_expiry_order() {
+        let config = FlowTableConfig {
+            udp: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let mut table = FlowTable::new(config);
+        table.insert(udp_flow(1), ()).unwrap();
+        let mut icmp = udp_flow(0);
+        icmp.set_protocol(ProtocolNumbers::Icmpv4);
+        table.insert(icmp, ()).unwrap();
+        table.insert(udp_flow(2), ()).unwrap();
+
+        let expired = table.evict_expired();
+        assert_eq!(2, expired.len());
+        assert_eq!(udp_flow(1), expired[0].flow());
+        assert_eq!(udp_flow(2), expired[1].flow());
+        assert_eq!(1, table.len());
+    }
+
+    #[capsule::test]
+    fn track_tcp_state() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        let mut table = FlowTable::new(FlowTableConfig::default());
+        assert!(table.track_tcp(&tcp).is_none());
+
+        tcp.set_syn();
+        tcp.unset_ack();
+        tcp.unset_fin();
+        tcp.unset_rst();
+        table.insert(tcp.flow(), ()).unwrap();
+        assert_eq!(
+            Some((Direction::Original, TcpState::SynSent)),
+            table.track_tcp(&tcp)
+        );
+
+        // swaps the direction for the SYN-ACK
+        let src = tcp.envelope().src();
+        let dst = tcp.envelope().dst();
+        tcp.envelope_mut().set_src(dst);
+        tcp.envelope_mut().set_dst(src);
+        let src_port = tcp.src_port();
+        let dst_port = tcp.dst_port();
+        tcp.set_src_port(dst_port);
+        tcp.set_dst_port(src_port);
+        tcp.set_ack();
+        assert_eq!(
+            Some((Direction::Reply, TcpState::SynReceived)),
+            table.track_tcp(&tcp)
+        );
+
+        tcp.set_rst();
+        assert_eq!(
+            Some((Direction::Reply, TcpState::Closed)),
+            table.track_tcp(&tcp)
+        );
+        assert_eq!(
+            Some(TcpState::Closed),
+            table.get(&tcp.flow()).unwrap().1.tcp_state()
+        );
+    }
+}
@@ -0,0 +1,210 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::Direction;
+use std::fmt;
+
+/// The state of a tracked TCP connection.
+///
+/// The state is inferred from the flags of the segments seen in both
+/// directions. It does not validate sequence numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    /// The originator sent a SYN.
+    SynSent,
+    /// The responder replied with a SYN.
+    SynReceived,
+    /// The three-way handshake completed.
+    Established,
+    /// The originator sent a FIN first.
+    FinWait,
+    /// The responder sent a FIN first.
+    CloseWait,
+    /// Both sides sent a FIN.
+    LastAck,
+    /// The last FIN is acknowledged.
+    TimeWait,
+    /// The connection is reset.
+    Closed,
+}
+
+/// The TCP flags that drive the state transitions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct TcpFlags {
+    pub(crate) syn: bool,
+    pub(crate) ack: bool,
+    pub(crate) fin: bool,
+    pub(crate) rst: bool,
+}
+
+impl TcpState {
+    /// Returns the initial state of a connection from its first segment.
+    ///
+    /// A connection picked up mid-stream is assumed to be established.
+    pub(crate) fn new(flags: TcpFlags) -> Self {
+        if flags.rst {
+            TcpState::Closed
+        } else if flags.syn && !flags.ack {
+            TcpState::SynSent
+        } else if flags.fin {
+            TcpState::FinWait
+        } else {
+            TcpState::Established
+        }
+    }
+
+    /// Returns the next state after a segment is seen in the direction.
+    pub(crate) fn next(self, direction: Direction, flags: TcpFlags) -> Self {
+        if flags.rst {
+            return TcpState::Closed;
+        }
+
+        match (self, direction) {
+            (TcpState::SynSent, Direction::Reply) if flags.syn => TcpState::SynReceived,
+            (TcpState::SynReceived, Direction::Original) if flags.ack && !flags.syn => {
+                TcpState::Established
+            }
+            (TcpState::Established, Direction::Original) if flags.fin => TcpState::FinWait,
+            (TcpState::Established, Direction::Reply) if flags.fin => TcpState::CloseWait,
+            (TcpState::FinWait, Direction::Reply) if flags.fin => TcpState::LastAck,
+            (TcpState::CloseWait, Direction::Original) if flags.fin => TcpState::LastAck,
+            (TcpState::LastAck, _) if flags.ack && !flags.fin => TcpState::TimeWait,
+            (TcpState::TimeWait, Direction::Original) | (TcpState::Closed, Direction::Original)
+                if flags.syn && !flags.ack =>
+            {
+                // the 5-tuple is reused for a new connection.
+                TcpState::SynSent
+            }
+            (state, _) => state,
+        }
+    }
+
+    /// Returns whether the connection is in the process of being set up.
+    pub fn is_opening(self) -> bool {
+        matches!(self, TcpState::SynSent | TcpState::SynReceived)
+    }
+
+    /// Returns whether the connection is in the process of being torn down.
+    pub fn is_closing(self) -> bool {
+        matches!(
+            self,
+            TcpState::FinWait | TcpState::CloseWait | TcpState::LastAck
+        )
+    }
+}
+
+impl fmt::Display for TcpState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            TcpState::SynSent => "SYN_SENT",
+            TcpState::SynReceived => "SYN_RECEIVED",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::FinWait => "FIN_WAIT",
+            TcpState::CloseWait => "CLOSE_WAIT",
+            TcpState::LastAck => "LAST_ACK",
+            TcpState::TimeWait => "TIME_WAIT",
+            TcpState::Closed => "CLOSED",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYN: TcpFlags = TcpFlags {
+        syn: true,
+        ack: false,
+        fin: false,
+        rst: false,
+    };
+    const SYN_ACK: TcpFlags = TcpFlags {
+        syn: true,
+        ack: true,
+        fin: false,
+        rst: false,
+    };
+    const ACK: TcpFlags = TcpFlags {
+        syn: false,
+        ack: true,
+        fin: false,
+        rst: false,
+    };
+    const FIN_ACK: TcpFlags = TcpFlags {
+        syn: false,
+        ack: true,
+        fin: true,
+        rst: false,
+    };
+    const RST: TcpFlags = TcpFlags {
+        syn: false,
+        ack: false,
+        fin: false,
+        rst: true,
+    };
+
+    #[test]
+    fn open_and_close_connection() {
+        let state = TcpState::new(SYN);
+        assert_eq!(TcpState::SynSent, state);
+        let state = state.next(Direction::Reply, SYN_ACK);
+        assert_eq!(TcpState::SynReceived, state);
+        let state = state.next(Direction::Original, ACK);
+        assert_eq!(TcpState::Established, state);
+        let state = state.next(Direction::Reply, ACK);
+        assert_eq!(TcpState::Established, state);
+        let state = state.next(Direction::Original, FIN_ACK);
+        assert_eq!(TcpState::FinWait, state);
+        let state = state.next(Direction::Reply, FIN_ACK);
+        assert_eq!(TcpState::LastAck, state);
+        let state = state.next(Direction::Original, ACK);
+        assert_eq!(TcpState::TimeWait, state);
+    }
+
+    #[test]
+    fn responder_closes_first() {
+        let state = TcpState::Established.next(Direction::Reply, FIN_ACK);
+        assert_eq!(TcpState::CloseWait, state);
+        let state = state.next(Direction::Original, FIN_ACK);
+        assert_eq!(TcpState::LastAck, state);
+    }
+
+    #[test]
+    fn reset_connection() {
+        assert_eq!(TcpState::Closed, TcpState::new(RST));
+        assert_eq!(
+            TcpState::Closed,
+            TcpState::Established.next(Direction::Reply, RST)
+        );
+        assert_eq!(
+            TcpState::SynSent,
+            TcpState::Closed.next(Direction::Original, SYN)
+        );
+    }
+
+    #[test]
+    fn pick_up_mid_stream() {
+        assert_eq!(TcpState::Established, TcpState::new(ACK));
+        // a SYN from the wrong direction doesn't advance the handshake.
+        assert_eq!(
+            TcpState::SynSent,
+            TcpState::SynSent.next(Direction::Original, SYN)
+        );
+    }
+}
@@ -118,7 +118,10 @@
 extern crate self as capsule;
 
 pub(crate) mod ffi;
+pub mod flow;
 mod macros;
+pub mod nat;
+pub mod neighbor;
 pub mod net;
 pub mod packets;
 pub mod runtime;
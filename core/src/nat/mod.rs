@@ -0,0 +1,387 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Network address and port translation, within and across address
+//! families.
+//!
+//! [`Nat44`] translates the IPv4 packets of an inside network to the
+//! addresses of a pool, as defined in [IETF RFC 3022]. TCP and UDP ports,
+//! and ICMP echo identifiers, are translated so many inside hosts can share
+//! an external address. The datagrams embedded in ICMP errors are
+//! rewritten to match the translated connection.
+//!
+//! The mappings are endpoint-independent, as required by [IETF RFC 4787].
+//! The same inside address and port is translated to the same external
+//! address and port regardless of the destination. Inbound packets are
+//! only accepted from the destinations the inside host sent packets to.
+//!
+//! The translator keeps its sessions in a [`FlowTable`], so like the flow
+//! table, it should be owned by a single lcore. Fragmented packets must be
+//! reassembled before they are translated.
+//!
+//! [`Siit`] translates between IPv6 and IPv4 packets statelessly, as
+//! defined in [IETF RFC 7915], with the addresses mapped by a
+//! [`Nat64Prefix`]. [`Nat64`] uses the same translation to let IPv6 hosts
+//! share the addresses of a pool, as defined in [IETF RFC 6146].
+//!
+//! [IETF RFC 3022]: https://tools.ietf.org/html/rfc3022
+//! [IETF RFC 4787]: https://tools.ietf.org/html/rfc4787
+//! [IETF RFC 6146]: https://tools.ietf.org/html/rfc6146
+//! [IETF RFC 7915]: https://tools.ietf.org/html/rfc7915
+//! [`Nat44`]: crate::nat::Nat44
+//! [`Nat64`]: crate::nat::Nat64
+//! [`Nat64Prefix`]: crate::nat::Nat64Prefix
+//! [`Siit`]: crate::nat::Siit
+//! [`FlowTable`]: crate::flow::FlowTable
+
+mod nat64;
+mod pool;
+mod prefix;
+mod rewrite;
+mod session;
+mod siit;
+mod translate;
+
+pub use self::nat64::*;
+pub use self::prefix::*;
+pub use self::siit::*;
+
+use self::rewrite::{
+    datagram_flow, icmp_error_offset, rewrite_datagram, rewrite_icmp_error, Endpoint,
+};
+use self::session::{Session, Sessions};
+use crate::ensure;
+use crate::flow::{Direction, FlowTableConfig};
+use crate::net::Ipv4Cidr;
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::ProtocolNumbers;
+use crate::packets::tcp::Tcp4;
+use crate::packets::Packet;
+use anyhow::{anyhow, Result};
+use std::cmp;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
+
+/// The maximum length of the headers rewritten, which covers an IPv4
+/// header with options, an ICMP header and an embedded IPv4 header with
+/// options and the start of its transport header.
+const MAX_HEADERS_LEN: usize = 60 + 8 + 60 + 20;
+
+/// Configuration of a NAT.
+#[derive(Clone, Debug)]
+pub struct NatConfig {
+    /// The external addresses.
+    pub pool: Vec<Ipv4Cidr>,
+    /// The range of external ports, and ICMP echo identifiers, allocated
+    /// on each external address.
+    pub ports: RangeInclusive<u16>,
+    /// The capacity and timeouts of the sessions.
+    pub sessions: FlowTableConfig,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        NatConfig {
+            pool: vec![],
+            ports: 1024..=65535,
+            sessions: FlowTableConfig::default(),
+        }
+    }
+}
+
+/// A stateful NAT44 translator.
+///
+/// # Example
+///
+/// ```
+/// let mut nat = Nat44::new(NatConfig {
+///     pool: vec!["203.0.113.0/30".parse()?],
+///     ..Default::default()
+/// })?;
+///
+/// // from the inside network
+/// let ipv4 = nat.outbound(ipv4)?;
+///
+/// // from the outside network
+/// let ipv4 = nat.inbound(ipv4)?;
+///
+/// // periodically
+/// nat.evict_expired();
+/// ```
+#[derive(Debug)]
+pub struct Nat44 {
+    sessions: Sessions,
+}
+
+impl Nat44 {
+    /// Creates a new translator.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool or the port range is empty.
+    pub fn new(config: NatConfig) -> Result<Self> {
+        Ok(Nat44 {
+            sessions: Sessions::new(&config.pool, config.ports, config.sessions)?,
+        })
+    }
+
+    /// Returns the number of sessions.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns whether there are no sessions.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Translates a packet from the inside network to the outside, and
+    /// creates a session for a new connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is fragmented or not translatable,
+    /// if an ICMP error doesn't match a session, or if the external ports
+    /// are exhausted.
+    pub fn outbound(&mut self, mut ipv4: Ipv4) -> Result<Ipv4> {
+        ensure_not_fragmented(&ipv4)?;
+        let bytes = headers_mut(&mut ipv4)?;
+
+        if let Some(inner) = icmp_error_offset(bytes)? {
+            // the embedded datagram was received from the outside.
+            let flow = datagram_flow(&bytes[inner..])?.reverse();
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Original, session)) => session.outside,
+                _ => return Err(anyhow!("ICMP error doesn't match a session.")),
+            };
+            rewrite_icmp_error(bytes, inner, Endpoint::Src, addr, port)?;
+        } else {
+            let flow = datagram_flow(bytes)?;
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Original, session)) => session.outside,
+                Some((Direction::Reply, _)) => {
+                    return Err(anyhow!("packet is already translated."))
+                }
+                None => self.sessions.create(flow, flow.dst_ip(), flow.protocol())?,
+            };
+            self.track_tcp(&ipv4);
+
+            let bytes = headers_mut(&mut ipv4)?;
+            rewrite_datagram(bytes, Endpoint::Src, addr, port)?;
+        }
+
+        Ok(ipv4)
+    }
+
+    /// Translates a packet from the outside network to the inside.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is fragmented or not translatable,
+    /// or if it doesn't match a session.
+    pub fn inbound(&mut self, mut ipv4: Ipv4) -> Result<Ipv4> {
+        ensure_not_fragmented(&ipv4)?;
+        ensure!(
+            self.sessions.owns(ipv4.dst()),
+            anyhow!("destination {} is not in the NAT pool.", ipv4.dst())
+        );
+        let bytes = headers_mut(&mut ipv4)?;
+
+        if let Some(inner) = icmp_error_offset(bytes)? {
+            // the embedded datagram was sent to the outside.
+            let flow = datagram_flow(&bytes[inner..])?.reverse();
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Reply, session)) => inside_v4(session)?,
+                _ => return Err(anyhow!("ICMP error doesn't match a session.")),
+            };
+            rewrite_icmp_error(bytes, inner, Endpoint::Dst, addr, port)?;
+        } else {
+            let flow = datagram_flow(bytes)?;
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Reply, session)) => inside_v4(session)?,
+                _ => return Err(anyhow!("packet doesn't match a session.")),
+            };
+            self.track_tcp(&ipv4);
+
+            let bytes = headers_mut(&mut ipv4)?;
+            rewrite_datagram(bytes, Endpoint::Dst, addr, port)?;
+        }
+
+        Ok(ipv4)
+    }
+
+    /// Removes the expired sessions and releases their external ports.
+    ///
+    /// Returns the number of sessions removed.
+    pub fn evict_expired(&mut self) -> usize {
+        self.sessions.evict_expired()
+    }
+
+    /// Updates the TCP state of the session, before the packet is rewritten.
+    fn track_tcp(&mut self, ipv4: &Ipv4) {
+        if ipv4.protocol() == ProtocolNumbers::Tcp {
+            if let Ok(tcp) = ipv4.peek::<Tcp4>() {
+                self.sessions.track_tcp(&tcp);
+            }
+        }
+    }
+}
+
+/// Returns the inside address and port of a NAT44 session.
+#[inline]
+fn inside_v4(session: &Session) -> Result<(Ipv4Addr, u16)> {
+    match session.inside {
+        (IpAddr::V4(addr), port, _) => Ok((addr, port)),
+        (IpAddr::V6(addr), _, _) => Err(anyhow!("inside address {} is not IPv4.", addr)),
+    }
+}
+
+/// Returns an error if the packet is a fragment.
+#[inline]
+fn ensure_not_fragmented(ipv4: &Ipv4) -> Result<()> {
+    ensure!(
+        ipv4.fragment_offset() == 0 && !ipv4.more_fragments(),
+        anyhow!("fragmented packets must be reassembled before translation.")
+    );
+    Ok(())
+}
+
+/// Returns the headers of the packet as a mutable byte slice.
+#[inline]
+fn headers_mut(ipv4: &mut Ipv4) -> Result<&mut [u8]> {
+    let offset = ipv4.offset();
+    let len = cmp::min(ipv4.len(), MAX_HEADERS_LEN);
+    let mut data = ipv4.mbuf_mut().read_data_slice::<u8>(offset, len)?;
+    Ok(unsafe { data.as_mut() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::Cidr;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::udp::Udp4;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::IPV4_UDP_PACKET;
+    use std::time::Duration;
+
+    const POOL: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+    fn nat(config: NatConfig) -> Nat44 {
+        Nat44::new(NatConfig {
+            pool: vec![Ipv4Cidr::new(POOL, 32).unwrap()],
+            ..config
+        })
+        .unwrap()
+    }
+
+    fn udp_packet() -> Ipv4 {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        ethernet.parse::<Ipv4>().unwrap()
+    }
+
+    /// Asserts the checksums are valid by comparing them to full recomputes.
+    fn assert_checksums(ipv4: Ipv4) -> Ipv4 {
+        let mut udp = ipv4.parse::<Udp4>().unwrap();
+        let ip_checksum = udp.envelope().checksum();
+        let udp_checksum = udp.checksum();
+        udp.reconcile_all();
+        assert_eq!(ip_checksum, udp.envelope().checksum());
+        assert_eq!(udp_checksum, udp.checksum());
+        udp.deparse()
+    }
+
+    #[capsule::test]
+    fn translate_udp_both_directions() {
+        let mut nat = nat(NatConfig::default());
+
+        let ipv4 = nat.outbound(udp_packet()).unwrap();
+        assert_eq!(POOL, ipv4.src());
+        assert_eq!(1, nat.len());
+        let ipv4 = assert_checksums(ipv4);
+
+        let mut udp = ipv4.parse::<Udp4>().unwrap();
+        let port = udp.src_port();
+        assert!(port >= 1024);
+
+        // the same inside endpoint keeps its mapping
+        let ipv4 = nat.outbound(udp_packet()).unwrap();
+        assert_eq!(port, ipv4.peek::<Udp4>().unwrap().src_port());
+        assert_eq!(1, nat.len());
+
+        // the reply is translated back
+        udp.swap_addresses_and_ports().unwrap();
+        udp.reconcile_all();
+        let ipv4 = nat.inbound(udp.deparse()).unwrap();
+        assert_eq!(Ipv4Addr::new(139, 133, 217, 110), ipv4.dst());
+        let ipv4 = assert_checksums(ipv4);
+        assert_eq!(39376, ipv4.peek::<Udp4>().unwrap().dst_port());
+    }
+
+    #[capsule::test]
+    fn drop_unsolicited_inbound() {
+        let mut nat = nat(NatConfig::default());
+
+        let mut ipv4 = udp_packet();
+        ipv4.set_dst(POOL);
+        assert!(nat.inbound(ipv4).is_err());
+
+        // not addressed to the pool
+        assert!(nat.inbound(udp_packet()).is_err());
+    }
+
+    #[capsule::test]
+    fn release_expired_sessions() {
+        let mut nat = nat(NatConfig {
+            ports: 2000..=2000,
+            sessions: FlowTableConfig {
+                udp: Duration::from_secs(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        assert!(nat.outbound(udp_packet()).is_ok());
+        assert_eq!(1, nat.evict_expired());
+        assert!(nat.is_empty());
+
+        // the only port is available again
+        let ipv4 = nat.outbound(udp_packet()).unwrap();
+        assert_eq!(2000, ipv4.peek::<Udp4>().unwrap().src_port());
+    }
+    #[test]
+    fn ipv6_inside_endpoint_is_an_error() {
+        let session = Session {
+            inside: ("2001:db8::1".parse().unwrap(), 1000, ProtocolNumbers::Udp),
+            outside: (POOL, 2000),
+        };
+        assert!(inside_v4(&session).is_err());
+
+        let session = Session {
+            inside: ("10.0.0.1".parse().unwrap(), 1000, ProtocolNumbers::Udp),
+            outside: (POOL, 2000),
+        };
+        assert_eq!(
+            (Ipv4Addr::new(10, 0, 0, 1), 1000),
+            inside_v4(&session).unwrap()
+        );
+    }
+}
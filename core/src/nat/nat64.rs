@@ -0,0 +1,366 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::rewrite::{
+    datagram_flow, icmp_error_offset, rewrite_datagram, rewrite_icmp_error, Endpoint,
+};
+use super::session::{Session, Sessions};
+use super::translate::{
+    icmpv6_error_offset, ipv6_datagram_flow, is_ipv6_fragment, translate_4to6, translate_6to4,
+};
+use super::{ensure_not_fragmented, headers_mut, SiitConfig};
+use crate::ensure;
+use crate::flow::{Direction, FlowTableConfig};
+use crate::net::Ipv4Cidr;
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::{Ipv6, Ipv6Packet};
+use crate::packets::ip::ProtocolNumbers;
+use crate::packets::tcp::{Tcp4, Tcp6};
+use crate::packets::Packet;
+use anyhow::{anyhow, Result};
+use std::cmp;
+use std::net::{IpAddr, Ipv6Addr};
+use std::ops::RangeInclusive;
+
+/// The maximum length of the IPv6 headers inspected, which covers an
+/// ICMPv6 error embedding a datagram with extension headers.
+const MAX_IPV6_HEADERS_LEN: usize = 256;
+
+/// Configuration of a NAT64.
+#[derive(Clone, Debug)]
+pub struct Nat64Config {
+    /// The IP/ICMP translation, including the prefix the IPv6 hosts use
+    /// to reach IPv4 hosts.
+    pub translation: SiitConfig,
+    /// The IPv4 addresses the IPv6 hosts are translated to.
+    pub pool: Vec<Ipv4Cidr>,
+    /// The range of IPv4 ports, and ICMP echo identifiers, allocated on
+    /// each address.
+    pub ports: RangeInclusive<u16>,
+    /// The capacity and timeouts of the sessions.
+    pub sessions: FlowTableConfig,
+}
+
+impl Default for Nat64Config {
+    fn default() -> Self {
+        Nat64Config {
+            translation: SiitConfig::default(),
+            pool: vec![],
+            ports: 1024..=65535,
+            sessions: FlowTableConfig::default(),
+        }
+    }
+}
+
+/// A stateful NAT64 translator, as defined in [IETF RFC 6146].
+///
+/// IPv6 hosts reach IPv4 hosts at their IPv4-embedded addresses, and are
+/// translated to the addresses and ports of a pool, like with [`Nat44`].
+/// The packets are translated with the same algorithm as [`Siit`].
+///
+/// Fragmented packets must be reassembled before they are translated.
+///
+/// # Example
+///
+/// ```
+/// let mut nat = Nat64::new(Nat64Config {
+///     pool: vec!["203.0.113.0/30".parse()?],
+///     ..Default::default()
+/// })?;
+///
+/// // from the IPv6 network
+/// let ipv4 = nat.outbound(ipv6)?;
+///
+/// // from the IPv4 network
+/// let ipv6 = nat.inbound(ipv4)?;
+///
+/// // periodically
+/// nat.evict_expired();
+/// ```
+///
+/// [IETF RFC 6146]: https://tools.ietf.org/html/rfc6146
+/// [`Nat44`]: crate::nat::Nat44
+/// [`Siit`]: crate::nat::Siit
+#[derive(Debug)]
+pub struct Nat64 {
+    config: SiitConfig,
+    sessions: Sessions,
+}
+
+impl Nat64 {
+    /// Creates a new translator.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool or the port range is empty.
+    pub fn new(config: Nat64Config) -> Result<Self> {
+        Ok(Nat64 {
+            config: config.translation,
+            sessions: Sessions::new(&config.pool, config.ports, config.sessions)?,
+        })
+    }
+
+    /// Returns the number of sessions.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns whether there are no sessions.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Translates a packet from the IPv6 network to IPv4, and creates a
+    /// session for a new connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is fragmented or not translatable,
+    /// if the destination is not IPv4-embedded, if an ICMPv6 error doesn't
+    /// match a session, or if the IPv4 ports are exhausted.
+    pub fn outbound(&mut self, ipv6: Ipv6) -> Result<Ipv4> {
+        let prefix = self.config.prefix;
+        let (flow, inner) = {
+            let len = cmp::min(ipv6.len(), MAX_IPV6_HEADERS_LEN);
+            let bytes = ipv6.mbuf().data_slice(ipv6.offset(), len)?;
+            ensure!(
+                !is_ipv6_fragment(&bytes)?,
+                anyhow!("fragmented packets must be reassembled before translation.")
+            );
+
+            match icmpv6_error_offset(&bytes)? {
+                // the embedded datagram was received from the IPv4 network.
+                Some(inner) => (ipv6_datagram_flow(&bytes[inner..])?.reverse(), true),
+                None => (ipv6_datagram_flow(&bytes)?, false),
+            }
+        };
+
+        if inner {
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Original, session)) => session.outside,
+                _ => return Err(anyhow!("ICMPv6 error doesn't match a session.")),
+            };
+
+            // the error may come from a router without an IPv4-embedded
+            // address, which is translated to the session's address.
+            let mut ipv4 = translate_6to4(ipv6, &self.config, |ip| {
+                Ok(prefix.extract(ip).unwrap_or(addr))
+            })?;
+
+            let bytes = headers_mut(&mut ipv4)?;
+            let inner = icmp_error_offset(bytes)?
+                .ok_or_else(|| anyhow!("ICMPv6 error is not translated to an ICMP error."))?;
+            rewrite_icmp_error(bytes, inner, Endpoint::Src, addr, port)?;
+            Ok(ipv4)
+        } else {
+            let ((addr, port), created) = match self.sessions.get(&flow) {
+                Some((_, session)) => (session.outside, false),
+                None => {
+                    let remote = extract(prefix.extract(ipv6.dst()), ipv6.dst())?;
+                    let protocol = if flow.protocol() == ProtocolNumbers::Icmpv6 {
+                        ProtocolNumbers::Icmpv4
+                    } else {
+                        flow.protocol()
+                    };
+                    let outside = self.sessions.create(flow, IpAddr::V4(remote), protocol)?;
+                    (outside, true)
+                }
+            };
+            if ipv6.next_header() == ProtocolNumbers::Tcp {
+                if let Ok(tcp) = ipv6.peek::<Tcp6>() {
+                    self.sessions.track_tcp(&tcp);
+                }
+            }
+
+            let src = flow.src_ip();
+            let translated = translate_6to4(ipv6, &self.config, |ip| {
+                if IpAddr::V6(ip) == src {
+                    Ok(addr)
+                } else {
+                    extract(prefix.extract(ip), ip)
+                }
+            })
+            .and_then(|mut ipv4| {
+                let bytes = headers_mut(&mut ipv4)?;
+                rewrite_datagram(bytes, Endpoint::Src, addr, port)?;
+                Ok(ipv4)
+            });
+
+            // doesn't hold on to an external port for a packet that is
+            // never sent.
+            if translated.is_err() && created {
+                self.sessions.remove(&flow);
+            }
+            translated
+        }
+    }
+
+    /// Translates a packet from the IPv4 network to IPv6.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is fragmented or not translatable,
+    /// or if it doesn't match a session.
+    pub fn inbound(&mut self, mut ipv4: Ipv4) -> Result<Ipv6> {
+        ensure_not_fragmented(&ipv4)?;
+        let pool = ipv4.dst();
+        ensure!(
+            self.sessions.owns(pool),
+            anyhow!("destination {} is not in the NAT pool.", pool)
+        );
+        let bytes = headers_mut(&mut ipv4)?;
+
+        let inside = if let Some(inner) = icmp_error_offset(bytes)? {
+            // the embedded datagram was sent to the IPv4 network.
+            let flow = datagram_flow(&bytes[inner..])?.reverse();
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Reply, session)) => inside_v6(session)?,
+                _ => return Err(anyhow!("ICMP error doesn't match a session.")),
+            };
+            rewrite_icmp_error(bytes, inner, Endpoint::Dst, pool, port)?;
+            addr
+        } else {
+            let flow = datagram_flow(bytes)?;
+            let (addr, port) = match self.sessions.get(&flow) {
+                Some((Direction::Reply, session)) => inside_v6(session)?,
+                _ => return Err(anyhow!("packet doesn't match a session.")),
+            };
+            if ipv4.protocol() == ProtocolNumbers::Tcp {
+                if let Ok(tcp) = ipv4.peek::<Tcp4>() {
+                    self.sessions.track_tcp(&tcp);
+                }
+            }
+
+            let bytes = headers_mut(&mut ipv4)?;
+            rewrite_datagram(bytes, Endpoint::Dst, pool, port)?;
+            addr
+        };
+
+        let prefix = self.config.prefix;
+        translate_4to6(ipv4, &self.config, |ip| {
+            if ip == pool {
+                Ok(inside)
+            } else {
+                Ok(prefix.embed(ip))
+            }
+        })
+    }
+
+    /// Removes the expired sessions and releases their IPv4 ports.
+    ///
+    /// Returns the number of sessions removed.
+    pub fn evict_expired(&mut self) -> usize {
+        self.sessions.evict_expired()
+    }
+}
+
+/// Returns the IPv4 address embedded in `ip`, or an error if there's none.
+#[inline]
+fn extract<T>(embedded: Option<T>, ip: Ipv6Addr) -> Result<T> {
+    embedded.ok_or_else(|| anyhow!("{} is not an IPv4-embedded address.", ip))
+}
+
+/// Returns the inside address and port of a NAT64 session.
+#[inline]
+fn inside_v6(session: &Session) -> Result<(Ipv6Addr, u16)> {
+    match session.inside {
+        (IpAddr::V6(addr), port, _) => Ok((addr, port)),
+        (IpAddr::V4(addr), _, _) => Err(anyhow!("inside address {} is not IPv6.", addr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nat::{Nat64Prefix, Siit};
+    use crate::net::Cidr;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::udp::{Udp4, Udp6};
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::IPV4_UDP_PACKET;
+    use std::net::Ipv4Addr;
+
+    const POOL: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+    /// Returns an IPv6 UDP packet from `2001:db8::1` to the IPv4-embedded
+    /// address of `139.133.233.2`.
+    fn udp_packet() -> Ipv6 {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut ipv6 = Siit::new(SiitConfig::default())
+            .translate_4to6(ipv4)
+            .unwrap();
+        ipv6.set_src("2001:db8::1".parse().unwrap());
+        let mut udp = ipv6.parse::<Udp6>().unwrap();
+        udp.reconcile_all();
+        udp.deparse()
+    }
+
+    #[capsule::test]
+    fn translate_udp_both_directions() {
+        let mut nat = Nat64::new(Nat64Config {
+            pool: vec![Ipv4Cidr::new(POOL, 32).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let ipv4 = nat.outbound(udp_packet()).unwrap();
+        assert_eq!(POOL, ipv4.src());
+        assert_eq!(Ipv4Addr::new(139, 133, 233, 2), ipv4.dst());
+        assert_eq!(1, nat.len());
+
+        let mut udp = ipv4.parse::<Udp4>().unwrap();
+        let checksum = udp.checksum();
+        udp.reconcile_all();
+        assert_eq!(checksum, udp.checksum());
+        assert!(udp.src_port() >= 1024);
+
+        // the reply is translated back
+        udp.swap_addresses_and_ports().unwrap();
+        udp.reconcile_all();
+        let ipv6 = nat.inbound(udp.deparse()).unwrap();
+        assert_eq!("2001:db8::1".parse::<Ipv6Addr>().unwrap(), ipv6.dst());
+        assert_eq!(
+            Nat64Prefix::default().embed(Ipv4Addr::new(139, 133, 233, 2)),
+            ipv6.src()
+        );
+
+        let mut udp = ipv6.parse::<Udp6>().unwrap();
+        assert_eq!(39376, udp.dst_port());
+        let checksum = udp.checksum();
+        udp.reconcile_all();
+        assert_eq!(checksum, udp.checksum());
+    }
+
+    #[capsule::test]
+    fn release_session_when_translation_fails() {
+        let mut nat = Nat64::new(Nat64Config {
+            pool: vec![Ipv4Cidr::new(POOL, 32).unwrap()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut ipv6 = udp_packet();
+        ipv6.set_hop_limit(1);
+        assert!(nat.outbound(ipv6).is_err());
+        assert!(nat.is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::net::Ipv4Cidr;
+use crate::packets::ip::ProtocolNumber;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
+
+/// Allocates ports, or ICMP echo identifiers, of a single external address
+/// and protocol.
+///
+/// Ports are handed out round-robin so a released port is not reused
+/// until the rest of the range is.
+#[derive(Debug)]
+pub(crate) struct PortAllocator {
+    start: u16,
+    used: Vec<u64>,
+    size: usize,
+    available: usize,
+    next: usize,
+}
+
+impl PortAllocator {
+    /// Creates a new allocator for the range of ports.
+    pub(crate) fn new(range: RangeInclusive<u16>) -> Self {
+        let start = *range.start();
+        let size = range.count();
+        PortAllocator {
+            start,
+            used: vec![0; size / 64 + 1],
+            size,
+            available: size,
+            next: 0,
+        }
+    }
+
+    #[inline]
+    fn is_used(&self, index: usize) -> bool {
+        self.used[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Allocates a port. Returns `None` if the range is exhausted.
+    pub(crate) fn allocate(&mut self) -> Option<u16> {
+        if self.available == 0 {
+            return None;
+        }
+
+        let mut index = self.next;
+        while self.is_used(index) {
+            index = (index + 1) % self.size;
+        }
+
+        self.used[index / 64] |= 1 << (index % 64);
+        self.available -= 1;
+        self.next = (index + 1) % self.size;
+        Some(self.start + index as u16)
+    }
+
+    /// Releases a port.
+    pub(crate) fn release(&mut self, port: u16) {
+        let index = port.wrapping_sub(self.start) as usize;
+        if index < self.size && self.is_used(index) {
+            self.used[index / 64] &= !(1 << (index % 64));
+            self.available += 1;
+        }
+    }
+}
+
+/// The external addresses of a NAT and their port allocators.
+#[derive(Debug)]
+pub(crate) struct AddressPool {
+    addresses: Vec<Ipv4Addr>,
+    ports: RangeInclusive<u16>,
+    allocators: HashMap<(Ipv4Addr, ProtocolNumber), PortAllocator>,
+}
+
+impl AddressPool {
+    /// Creates a new pool from the CIDR ranges.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool or the port range is empty.
+    pub(crate) fn new(pool: &[Ipv4Cidr], ports: RangeInclusive<u16>) -> Result<Self> {
+        let addresses = pool.iter().flat_map(|cidr| cidr.iter()).collect::<Vec<_>>();
+        ensure!(!addresses.is_empty(), anyhow!("NAT pool is empty."));
+        ensure!(!ports.is_empty(), anyhow!("NAT port range is empty."));
+
+        Ok(AddressPool {
+            addresses,
+            ports,
+            allocators: HashMap::new(),
+        })
+    }
+
+    /// Returns whether the address is in the pool.
+    #[inline]
+    pub(crate) fn contains(&self, addr: Ipv4Addr) -> bool {
+        self.addresses.contains(&addr)
+    }
+
+    /// Allocates an external address and port for an internal address.
+    ///
+    /// An internal address is always paired with the same external address,
+    /// as recommended by RFC 4787. The internal address is an IPv6 address
+    /// when the NAT translates between address families.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ports of the external address are exhausted.
+    pub(crate) fn allocate(
+        &mut self,
+        internal: IpAddr,
+        protocol: ProtocolNumber,
+    ) -> Result<(Ipv4Addr, u16)> {
+        let key = match internal {
+            IpAddr::V4(addr) => u32::from(addr),
+            IpAddr::V6(addr) => {
+                let bits = u128::from(addr);
+                (bits >> 96) as u32 ^ (bits >> 64) as u32 ^ (bits >> 32) as u32 ^ bits as u32
+            }
+        };
+        let addr = self.addresses[key as usize % self.addresses.len()];
+        let ports = &self.ports;
+        let port = self
+            .allocators
+            .entry((addr, protocol))
+            .or_insert_with(|| PortAllocator::new(ports.clone()))
+            .allocate()
+            .ok_or_else(|| anyhow!("NAT ports of {} are exhausted.", addr))?;
+        Ok((addr, port))
+    }
+
+    /// Releases an external port.
+    pub(crate) fn release(&mut self, addr: Ipv4Addr, protocol: ProtocolNumber, port: u16) {
+        if let Some(allocator) = self.allocators.get_mut(&(addr, protocol)) {
+            allocator.release(port);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::Cidr;
+    use crate::packets::ip::ProtocolNumbers;
+
+    #[test]
+    fn allocate_and_release_ports() {
+        let mut allocator = PortAllocator::new(1000..=1002);
+        assert_eq!(Some(1000), allocator.allocate());
+        assert_eq!(Some(1001), allocator.allocate());
+        assert_eq!(Some(1002), allocator.allocate());
+        assert_eq!(None, allocator.allocate());
+
+        allocator.release(1001);
+        allocator.release(1001);
+        allocator.release(5000);
+        assert_eq!(Some(1001), allocator.allocate());
+        assert_eq!(None, allocator.allocate());
+    }
+
+    #[test]
+    fn allocate_round_robin() {
+        let mut allocator = PortAllocator::new(1000..=1002);
+        assert_eq!(Some(1000), allocator.allocate());
+        allocator.release(1000);
+        assert_eq!(Some(1001), allocator.allocate());
+        assert_eq!(Some(1002), allocator.allocate());
+        assert_eq!(Some(1000), allocator.allocate());
+    }
+
+    #[test]
+    fn pair_internal_address() {
+        let cidr = Ipv4Cidr::new(Ipv4Addr::new(203, 0, 113, 0), 30).unwrap();
+        let mut pool = AddressPool::new(&[cidr], 1024..=65535).unwrap();
+        let internal = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        let (addr1, port1) = pool.allocate(internal, ProtocolNumbers::Tcp).unwrap();
+        let (addr2, port2) = pool.allocate(internal, ProtocolNumbers::Tcp).unwrap();
+        assert_eq!(addr1, addr2);
+        assert!(pool.contains(addr1));
+        assert_ne!(port1, port2);
+
+        // protocols have their own ports
+        let (_, port3) = pool.allocate(internal, ProtocolNumbers::Udp).unwrap();
+        assert_eq!(port1, port3);
+
+        // IPv6 internal addresses are paired as well
+        let internal = IpAddr::V6("2001:db8::1".parse().unwrap());
+        let (addr3, _) = pool.allocate(internal, ProtocolNumbers::Tcp).unwrap();
+        let (addr4, _) = pool.allocate(internal, ProtocolNumbers::Tcp).unwrap();
+        assert_eq!(addr3, addr4);
+
+        assert!(AddressPool::new(&[], 1024..=65535).is_err());
+    }
+}
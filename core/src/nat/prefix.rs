@@ -0,0 +1,152 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::net::{Cidr, Ipv6Cidr};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The index of the reserved octet, bits 64 to 71, of an IPv4-embedded
+/// IPv6 address.
+const U_OCTET: usize = 8;
+
+/// An IPv6 prefix used to embed IPv4 addresses in IPv6 addresses, as
+/// defined in [IETF RFC 6052].
+///
+/// The prefix length is one of 32, 40, 48, 56, 64 or 96. The default is
+/// the well-known prefix `64:ff9b::/96`.
+///
+/// [IETF RFC 6052]: https://tools.ietf.org/html/rfc6052#section-2.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nat64Prefix {
+    cidr: Ipv6Cidr,
+}
+
+impl Nat64Prefix {
+    /// Creates a new prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prefix length is not one of the lengths
+    /// defined by RFC 6052.
+    pub fn new(cidr: Ipv6Cidr) -> Result<Self> {
+        ensure!(
+            matches!(cidr.length(), 32 | 40 | 48 | 56 | 64 | 96),
+            anyhow!("{} is not a valid IPv4-embedded IPv6 prefix.", cidr)
+        );
+        Ok(Nat64Prefix { cidr })
+    }
+
+    /// Returns the prefix as a CIDR range.
+    #[inline]
+    pub fn cidr(&self) -> Ipv6Cidr {
+        self.cidr
+    }
+
+    /// Returns the IPv4-embedded IPv6 address of an IPv4 address.
+    pub fn embed(&self, addr: Ipv4Addr) -> Ipv6Addr {
+        let mut octets = self.cidr.network().octets();
+        let mut index = self.cidr.length() / 8;
+        for &octet in addr.octets().iter() {
+            if index == U_OCTET {
+                index += 1;
+            }
+            octets[index] = octet;
+            index += 1;
+        }
+        Ipv6Addr::from(octets)
+    }
+
+    /// Returns the IPv4 address embedded in an IPv6 address, or `None` if
+    /// the address doesn't have the prefix.
+    pub fn extract(&self, addr: Ipv6Addr) -> Option<Ipv4Addr> {
+        if !self.cidr.contains(addr) {
+            return None;
+        }
+
+        let octets = addr.octets();
+        let mut embedded = [0; 4];
+        let mut index = self.cidr.length() / 8;
+        for octet in embedded.iter_mut() {
+            if index == U_OCTET {
+                index += 1;
+            }
+            *octet = octets[index];
+            index += 1;
+        }
+        Some(Ipv4Addr::from(embedded))
+    }
+}
+
+impl Default for Nat64Prefix {
+    fn default() -> Self {
+        let addr = Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0);
+        Nat64Prefix {
+            cidr: Ipv6Cidr::new(addr, 96).unwrap(),
+        }
+    }
+}
+
+impl fmt::Display for Nat64Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.cidr, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embed_and_extract_ipv4_addresses() {
+        // examples from RFC 6052, section 2.4.
+        let addr = Ipv4Addr::new(192, 0, 2, 33);
+        let cases = [
+            ("2001:db8::/32", "2001:db8:c000:221::"),
+            ("2001:db8:100::/40", "2001:db8:1c0:2:21::"),
+            ("2001:db8:122::/48", "2001:db8:122:c000:2:2100::"),
+            ("2001:db8:122:300::/56", "2001:db8:122:3c0:0:221::"),
+            ("2001:db8:122:344::/64", "2001:db8:122:344:c0:2:2100:0"),
+            ("2001:db8:122:344::/96", "2001:db8:122:344::c000:221"),
+            ("64:ff9b::/96", "64:ff9b::c000:221"),
+        ];
+
+        for &(prefix, expected) in cases.iter() {
+            let prefix = Nat64Prefix::new(prefix.parse().unwrap()).unwrap();
+            let expected: Ipv6Addr = expected.parse().unwrap();
+            assert_eq!(expected, prefix.embed(addr));
+            assert_eq!(Some(addr), prefix.extract(expected));
+        }
+
+        assert_eq!(
+            "64:ff9b::c000:221".parse::<Ipv6Addr>().unwrap(),
+            Nat64Prefix::default().embed(addr)
+        );
+        assert_eq!(
+            None,
+            Nat64Prefix::default().extract("2001:db8::1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn invalid_prefix_length() {
+        assert!(Nat64Prefix::new("2001:db8::/36".parse().unwrap()).is_err());
+        assert!(Nat64Prefix::new("2001:db8::/128".parse().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::checksum;
+use crate::packets::ip::{Flow, ProtocolNumber, ProtocolNumbers};
+use anyhow::{anyhow, Result};
+use std::net::{IpAddr, Ipv4Addr};
+
+// offsets of the fields within the IPv4 header.
+pub(super) const IPV4_MIN_HEADER_LEN: usize = 20;
+pub(super) const IPV4_FRAGMENT: usize = 6;
+pub(super) const IPV4_PROTOCOL: usize = 9;
+pub(super) const IPV4_CHECKSUM: usize = 10;
+pub(super) const IPV4_SRC: usize = 12;
+pub(super) const IPV4_DST: usize = 16;
+
+// offsets of the fields within the transport header.
+pub(super) const SRC_PORT: usize = 0;
+pub(super) const DST_PORT: usize = 2;
+pub(super) const TCP_CHECKSUM: usize = 16;
+pub(super) const UDP_CHECKSUM: usize = 6;
+pub(super) const ICMP_TYPE: usize = 0;
+pub(super) const ICMP_CHECKSUM: usize = 2;
+pub(super) const ICMP_IDENTIFIER: usize = 4;
+pub(super) const ICMP_HEADER_LEN: usize = 8;
+
+pub(super) const ICMP_ECHO_REPLY: u8 = 0;
+pub(super) const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub(super) const ICMP_SOURCE_QUENCH: u8 = 4;
+pub(super) const ICMP_ECHO_REQUEST: u8 = 8;
+pub(super) const ICMP_TIME_EXCEEDED: u8 = 11;
+pub(super) const ICMP_PARAMETER_PROBLEM: u8 = 12;
+
+/// The endpoint of a packet to rewrite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Endpoint {
+    Src,
+    Dst,
+}
+
+impl Endpoint {
+    fn opposite(self) -> Self {
+        match self {
+            Endpoint::Src => Endpoint::Dst,
+            Endpoint::Dst => Endpoint::Src,
+        }
+    }
+}
+
+/// The 16-bit words changed by a rewrite, for the incremental update of
+/// the checksums covering them.
+#[derive(Debug, Default)]
+pub(crate) struct Changes {
+    old: Vec<u16>,
+    new: Vec<u16>,
+}
+
+impl Changes {
+    fn push(&mut self, old: u16, new: u16) {
+        self.old.push(old);
+        self.new.push(new);
+    }
+
+    fn append(&mut self, other: &Changes) {
+        self.old.extend_from_slice(&other.old);
+        self.new.extend_from_slice(&other.new);
+    }
+
+    fn apply(&self, checksum: u16) -> u16 {
+        checksum::compute_inc(checksum, &self.old, &self.new)
+    }
+}
+
+#[inline]
+pub(super) fn get_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Writes the word and records the change.
+#[inline]
+fn set_u16(bytes: &mut [u8], offset: usize, value: u16, changes: &mut Changes) {
+    changes.push(get_u16(bytes, offset), value);
+    bytes[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Replaces the checksum at offset with its incremental update, and
+/// records the change.
+#[inline]
+fn update_checksum(bytes: &mut [u8], offset: usize, delta: &Changes, changes: &mut Changes) {
+    let checksum = delta.apply(get_u16(bytes, offset));
+    set_u16(bytes, offset, checksum, changes);
+}
+
+#[inline]
+pub(super) fn header_len(bytes: &[u8]) -> Result<usize> {
+    ensure!(
+        bytes.len() >= IPV4_MIN_HEADER_LEN,
+        anyhow!("IPv4 header is truncated.")
+    );
+    let len = ((bytes[0] & 0x0f) as usize) * 4;
+    ensure!(
+        len >= IPV4_MIN_HEADER_LEN && bytes.len() >= len,
+        anyhow!("IPv4 header is truncated.")
+    );
+    Ok(len)
+}
+
+/// Returns whether the datagram is the first, or only, fragment.
+#[inline]
+fn is_first_fragment(bytes: &[u8]) -> bool {
+    get_u16(bytes, IPV4_FRAGMENT) & 0x1fff == 0
+}
+
+/// Returns the offset of the datagram embedded in the ICMP error, if the
+/// datagram in `bytes` is an ICMP error.
+pub(crate) fn icmp_error_offset(bytes: &[u8]) -> Result<Option<usize>> {
+    let ihl = header_len(bytes)?;
+    if bytes[IPV4_PROTOCOL] != ProtocolNumbers::Icmpv4.0 || bytes.len() < ihl + ICMP_HEADER_LEN {
+        return Ok(None);
+    }
+
+    match bytes[ihl + ICMP_TYPE] {
+        ICMP_DEST_UNREACHABLE
+        | ICMP_SOURCE_QUENCH
+        | ICMP_TIME_EXCEEDED
+        | ICMP_PARAMETER_PROBLEM => Ok(Some(ihl + ICMP_HEADER_LEN)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the flow of the datagram in `bytes`.
+///
+/// For ICMP echo messages, the identifier is used as the source port of
+/// a request and as the destination port of a reply, so both directions
+/// are the reverse of each other.
+///
+/// # Errors
+///
+/// Returns an error if the protocol or ICMP message is not translatable,
+/// or if the transport header is truncated.
+pub(crate) fn datagram_flow(bytes: &[u8]) -> Result<Flow> {
+    let ihl = header_len(bytes)?;
+    let src = IpAddr::V4(Ipv4Addr::from(get_u32(bytes, IPV4_SRC)));
+    let dst = IpAddr::V4(Ipv4Addr::from(get_u32(bytes, IPV4_DST)));
+    let l4 = &bytes[ihl..];
+
+    match ProtocolNumber::new(bytes[IPV4_PROTOCOL]) {
+        protocol if protocol == ProtocolNumbers::Tcp || protocol == ProtocolNumbers::Udp => {
+            ensure!(l4.len() >= 4, anyhow!("transport header is truncated."));
+            Ok(Flow::new(
+                src,
+                dst,
+                get_u16(l4, SRC_PORT),
+                get_u16(l4, DST_PORT),
+                protocol,
+            ))
+        }
+        ProtocolNumbers::Icmpv4 => {
+            ensure!(
+                l4.len() >= ICMP_IDENTIFIER + 2,
+                anyhow!("ICMP header is truncated.")
+            );
+            let id = get_u16(l4, ICMP_IDENTIFIER);
+            match l4[ICMP_TYPE] {
+                ICMP_ECHO_REQUEST => Ok(Flow::new(src, dst, id, 0, ProtocolNumbers::Icmpv4)),
+                ICMP_ECHO_REPLY => Ok(Flow::new(src, dst, 0, id, ProtocolNumbers::Icmpv4)),
+                t => Err(anyhow!("ICMP type {} is not translatable.", t)),
+            }
+        }
+        protocol => Err(anyhow!("protocol {} is not translatable.", protocol)),
+    }
+}
+
+#[inline]
+pub(super) fn get_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Rewrites the address and port of an endpoint of the IPv4 datagram in
+/// `bytes`, and updates the IP and transport checksums incrementally. For
+/// ICMP echo messages, the identifier is rewritten instead of the port.
+///
+/// `bytes` may be truncated, like the datagram embedded in an ICMP error,
+/// in which case the fields that are not present are left alone.
+///
+/// Returns all the words changed.
+pub(crate) fn rewrite_datagram(
+    bytes: &mut [u8],
+    endpoint: Endpoint,
+    addr: Ipv4Addr,
+    port: u16,
+) -> Result<Changes> {
+    let ihl = header_len(bytes)?;
+    let mut changes = Changes::default();
+
+    // the pseudo-header covers the address.
+    let mut pseudo = Changes::default();
+    let offset = match endpoint {
+        Endpoint::Src => IPV4_SRC,
+        Endpoint::Dst => IPV4_DST,
+    };
+    let addr = u32::from(addr);
+    set_u16(bytes, offset, (addr >> 16) as u16, &mut pseudo);
+    set_u16(bytes, offset + 2, addr as u16, &mut pseudo);
+    update_checksum(bytes, IPV4_CHECKSUM, &pseudo, &mut changes);
+    changes.append(&pseudo);
+
+    if !is_first_fragment(bytes) {
+        return Ok(changes);
+    }
+
+    let protocol = ProtocolNumber::new(bytes[IPV4_PROTOCOL]);
+    let l4 = &mut bytes[ihl..];
+    let port_offset = match endpoint {
+        Endpoint::Src => SRC_PORT,
+        Endpoint::Dst => DST_PORT,
+    };
+
+    match protocol {
+        ProtocolNumbers::Tcp | ProtocolNumbers::Udp => {
+            if l4.len() < 4 {
+                return Ok(changes);
+            }
+
+            let mut delta = Changes::default();
+            set_u16(l4, port_offset, port, &mut delta);
+            changes.append(&delta);
+            delta.append(&pseudo);
+
+            let checksum_offset = if protocol == ProtocolNumbers::Tcp {
+                TCP_CHECKSUM
+            } else {
+                UDP_CHECKSUM
+            };
+            if l4.len() >= checksum_offset + 2 {
+                let checksum = get_u16(l4, checksum_offset);
+                // a zero UDP checksum is not used.
+                if protocol == ProtocolNumbers::Tcp || checksum != 0 {
+                    let mut checksum = delta.apply(checksum);
+                    if protocol == ProtocolNumbers::Udp && checksum == 0 {
+                        checksum = 0xffff;
+                    }
+                    set_u16(l4, checksum_offset, checksum, &mut changes);
+                }
+            }
+        }
+        ProtocolNumbers::Icmpv4 => {
+            if l4.len() < ICMP_IDENTIFIER + 2
+                || !matches!(l4[ICMP_TYPE], ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY)
+            {
+                return Ok(changes);
+            }
+
+            let mut delta = Changes::default();
+            set_u16(l4, ICMP_IDENTIFIER, port, &mut delta);
+            update_checksum(l4, ICMP_CHECKSUM, &delta, &mut changes);
+            changes.append(&delta);
+        }
+        _ => (),
+    }
+
+    Ok(changes)
+}
+
+/// Rewrites an endpoint of an ICMP error and the opposite endpoint of the
+/// datagram embedded in it, so the embedded datagram matches the
+/// translated connection. The ICMP checksum is updated incrementally.
+pub(crate) fn rewrite_icmp_error(
+    bytes: &mut [u8],
+    inner: usize,
+    endpoint: Endpoint,
+    addr: Ipv4Addr,
+    port: u16,
+) -> Result<()> {
+    let ihl = header_len(bytes)?;
+
+    // ICMP checksum doesn't cover the outer IP header.
+    rewrite_datagram(bytes, endpoint, addr, port)?;
+
+    let (header, data) = bytes.split_at_mut(inner);
+    let changes = rewrite_datagram(data, endpoint.opposite(), addr, port)?;
+    let mut unused = Changes::default();
+    update_checksum(&mut header[ihl..], ICMP_CHECKSUM, &changes, &mut unused);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Computes the checksum of `bytes` from scratch.
+    fn full(bytes: &[u8]) -> u16 {
+        checksum::compute(0, bytes)
+    }
+
+    #[test]
+    fn rewrite_udp_datagram() {
+        #[rustfmt::skip]
+        let mut bytes = [
+            // IPv4 header
+            0x45, 0x00, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x00,
+            0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+            0x0a, 0x00, 0x00, 0x02,
+            // UDP header
+            0x04, 0xd2, 0x00, 0x35, 0x00, 0x0a, 0x00, 0x00,
+            // data
+            0x01, 0x02,
+        ];
+        let ip_checksum = full(&bytes[..20]);
+        bytes[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+
+        let pseudo = checksum::PseudoHeader::V4 {
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            packet_len: 10,
+            protocol: ProtocolNumbers::Udp,
+        };
+        let udp_checksum = checksum::compute(pseudo.sum(), &bytes[20..]);
+        bytes[26..28].copy_from_slice(&udp_checksum.to_be_bytes());
+
+        let addr = Ipv4Addr::new(203, 0, 113, 1);
+        rewrite_datagram(&mut bytes, Endpoint::Src, addr, 40000).unwrap();
+
+        let flow = datagram_flow(&bytes).unwrap();
+        assert_eq!(IpAddr::V4(addr), flow.src_ip());
+        assert_eq!(40000, flow.src_port());
+
+        // incremental checksums match full recomputes
+        let expected = bytes[10..12].to_vec();
+        bytes[10..12].copy_from_slice(&[0, 0]);
+        assert_eq!(expected, full(&bytes[..20]).to_be_bytes());
+
+        let expected = bytes[26..28].to_vec();
+        bytes[26..28].copy_from_slice(&[0, 0]);
+        let pseudo = checksum::PseudoHeader::V4 {
+            src: addr,
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            packet_len: 10,
+            protocol: ProtocolNumbers::Udp,
+        };
+        assert_eq!(
+            expected,
+            checksum::compute(pseudo.sum(), &bytes[20..]).to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn rewrite_icmp_error_inner_datagram() {
+        #[rustfmt::skip]
+        let mut bytes = [
+            // IPv4 header
+            0x45, 0x00, 0x00, 0x38, 0x00, 0x00, 0x00, 0x00,
+            0x40, 0x01, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x02,
+            0xcb, 0x00, 0x71, 0x01,
+            // ICMP destination unreachable
+            0x03, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // embedded IPv4 header
+            0x45, 0x00, 0x00, 0x1e, 0x00, 0x00, 0x00, 0x00,
+            0x40, 0x11, 0x00, 0x00, 0xcb, 0x00, 0x71, 0x01,
+            0x0a, 0x00, 0x00, 0x02,
+            // embedded UDP header
+            0x9c, 0x40, 0x00, 0x35, 0x00, 0x0a, 0x00, 0x00,
+        ];
+        let checksum = full(&bytes[28..48]);
+        bytes[38..40].copy_from_slice(&checksum.to_be_bytes());
+        let checksum = full(&bytes[20..]);
+        bytes[22..24].copy_from_slice(&checksum.to_be_bytes());
+
+        let inner = icmp_error_offset(&bytes).unwrap().unwrap();
+        assert_eq!(28, inner);
+
+        let flow = datagram_flow(&bytes[inner..]).unwrap();
+        assert_eq!(40000, flow.src_port());
+
+        let addr = Ipv4Addr::new(10, 0, 0, 1);
+        rewrite_icmp_error(&mut bytes, inner, Endpoint::Dst, addr, 1234).unwrap();
+
+        let flow = datagram_flow(&bytes).unwrap_err();
+        assert!(flow.to_string().contains("ICMP type 3"));
+        let flow = datagram_flow(&bytes[inner..]).unwrap();
+        assert_eq!(IpAddr::V4(addr), flow.src_ip());
+        assert_eq!(1234, flow.src_port());
+        assert_eq!(&bytes[16..20], &[10, 0, 0, 1]);
+
+        // the incremental ICMP checksum matches a full recompute
+        let expected = bytes[22..24].to_vec();
+        bytes[22..24].copy_from_slice(&[0, 0]);
+        assert_eq!(expected, full(&bytes[20..]).to_be_bytes());
+    }
+}
@@ -0,0 +1,182 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::pool::AddressPool;
+use crate::flow::{Direction, FlowTable, FlowTableConfig};
+use crate::net::Ipv4Cidr;
+use crate::packets::ip::{Flow, IpPacket, ProtocolNumber};
+use crate::packets::tcp::Tcp;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
+
+/// An inside address and port, or ICMP echo identifier.
+pub(crate) type InsideEndpoint = (IpAddr, u16, ProtocolNumber);
+
+/// The external address and port assigned to an inside endpoint.
+#[derive(Debug)]
+struct Mapping {
+    addr: Ipv4Addr,
+    port: u16,
+    protocol: ProtocolNumber,
+    sessions: usize,
+}
+
+/// A translated connection.
+#[derive(Debug)]
+pub(crate) struct Session {
+    pub(crate) inside: InsideEndpoint,
+    pub(crate) outside: (Ipv4Addr, u16),
+}
+
+/// The sessions of a stateful NAT, and the endpoint-independent mappings
+/// they share.
+#[derive(Debug)]
+pub(crate) struct Sessions {
+    pool: AddressPool,
+    mappings: HashMap<InsideEndpoint, Mapping>,
+    table: FlowTable<Session>,
+}
+
+impl Sessions {
+    /// Creates an empty session table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool or the port range is empty.
+    pub(crate) fn new(
+        pool: &[Ipv4Cidr],
+        ports: RangeInclusive<u16>,
+        config: FlowTableConfig,
+    ) -> Result<Self> {
+        Ok(Sessions {
+            pool: AddressPool::new(pool, ports)?,
+            mappings: HashMap::new(),
+            table: FlowTable::new(config),
+        })
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Returns whether the address is in the NAT pool.
+    #[inline]
+    pub(crate) fn owns(&self, addr: Ipv4Addr) -> bool {
+        self.pool.contains(addr)
+    }
+
+    /// Returns the direction of the flow and its session.
+    #[inline]
+    pub(crate) fn get(&mut self, flow: &Flow) -> Option<(Direction, &Session)> {
+        self.table
+            .get(flow)
+            .map(|(direction, entry)| (direction, entry.value()))
+    }
+
+    /// Creates a session for a flow from the inside network, reusing the
+    /// mapping of the inside endpoint if one exists.
+    ///
+    /// `remote` is the address the outside endpoint replies from, and
+    /// `protocol` the protocol of the replies. They differ from the flow's
+    /// when the NAT translates between address families.
+    pub(crate) fn create(
+        &mut self,
+        flow: Flow,
+        remote: IpAddr,
+        protocol: ProtocolNumber,
+    ) -> Result<(Ipv4Addr, u16)> {
+        let inside = (flow.src_ip(), flow.src_port(), flow.protocol());
+
+        let (addr, port) = match self.mappings.get_mut(&inside) {
+            Some(mapping) => {
+                mapping.sessions += 1;
+                (mapping.addr, mapping.port)
+            }
+            None => {
+                let (addr, port) = self.pool.allocate(flow.src_ip(), protocol)?;
+                self.mappings.insert(
+                    inside,
+                    Mapping {
+                        addr,
+                        port,
+                        protocol,
+                        sessions: 1,
+                    },
+                );
+                (addr, port)
+            }
+        };
+
+        let reply = Flow::new(remote, IpAddr::V4(addr), flow.dst_port(), port, protocol);
+        let session = Session {
+            inside,
+            outside: (addr, port),
+        };
+        if let Err(e) = self.table.insert_with_reply(flow, reply, session) {
+            self.release(inside);
+            return Err(e);
+        }
+
+        Ok((addr, port))
+    }
+
+    /// Removes the session of a flow and releases its external port.
+    pub(crate) fn remove(&mut self, flow: &Flow) {
+        if let Some(entry) = self.table.remove(flow) {
+            self.release(entry.value().inside);
+        }
+    }
+
+    /// Updates the TCP state of the session, before the packet is rewritten.
+    #[inline]
+    pub(crate) fn track_tcp<E: IpPacket>(&mut self, tcp: &Tcp<E>) {
+        self.table.track_tcp(tcp);
+    }
+
+    /// Removes the expired sessions and releases their external ports.
+    ///
+    /// Returns the number of sessions removed.
+    pub(crate) fn evict_expired(&mut self) -> usize {
+        let expired = self.table.evict_expired();
+        for entry in expired.iter() {
+            self.release(entry.value().inside);
+        }
+        expired.len()
+    }
+
+    /// Releases a session's reference to the mapping of the inside endpoint,
+    /// and the external port when there are no more sessions.
+    fn release(&mut self, inside: InsideEndpoint) {
+        if let Some(mapping) = self.mappings.get_mut(&inside) {
+            mapping.sessions -= 1;
+            if mapping.sessions == 0 {
+                self.pool
+                    .release(mapping.addr, mapping.protocol, mapping.port);
+                self.mappings.remove(&inside);
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::translate::{translate_4to6, translate_6to4};
+use super::Nat64Prefix;
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use anyhow::{anyhow, Result};
+
+/// Configuration of the IP/ICMP translation.
+#[derive(Clone, Copy, Debug)]
+pub struct SiitConfig {
+    /// The prefix of the IPv4-embedded IPv6 addresses.
+    pub prefix: Nat64Prefix,
+    /// Whether the IPv4 type of service and the IPv6 traffic class are
+    /// copied to each other. When `false`, they are set to zero.
+    pub traffic_class: bool,
+    /// Whether the IPv6 packets translated from IPv4 are given a flow
+    /// label hashed from the addresses, protocol and ports. When `false`,
+    /// the flow label is set to zero.
+    pub flow_label: bool,
+}
+
+impl Default for SiitConfig {
+    fn default() -> Self {
+        SiitConfig {
+            prefix: Nat64Prefix::default(),
+            traffic_class: true,
+            flow_label: false,
+        }
+    }
+}
+
+/// A stateless IP/ICMP translator, as defined in [IETF RFC 7915].
+///
+/// The addresses of both the IPv4 and the IPv6 hosts are mapped
+/// algorithmically with the [`Nat64Prefix`], so IPv6 hosts must use
+/// IPv4-embedded addresses.
+///
+/// TCP and UDP checksums are updated for the new pseudo-header, and a UDP
+/// checksum is computed when an IPv4 packet has none. ICMP echo messages
+/// and errors are translated to their counterparts, including the datagram
+/// embedded in an error. Messages without a counterpart are not
+/// translatable.
+///
+/// Fragments are translated individually. The IPv6 fragment header is
+/// mapped to the IPv4 identification and fragment fields, and back. The
+/// TTL and hop limit are decremented, as the translator is a router.
+///
+/// # Example
+///
+/// ```
+/// let siit = Siit::new(SiitConfig {
+///     prefix: Nat64Prefix::new("2001:db8:64::/96".parse()?)?,
+///     ..Default::default()
+/// });
+///
+/// let ipv4 = siit.translate_6to4(ipv6)?;
+/// let ipv6 = siit.translate_4to6(ipv4)?;
+/// ```
+///
+/// [IETF RFC 7915]: https://tools.ietf.org/html/rfc7915
+/// [`Nat64Prefix`]: crate::nat::Nat64Prefix
+#[derive(Debug)]
+pub struct Siit {
+    config: SiitConfig,
+}
+
+impl Siit {
+    /// Creates a new translator.
+    pub fn new(config: SiitConfig) -> Self {
+        Siit { config }
+    }
+
+    /// Translates an IPv6 packet to IPv4.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an address is not IPv4-embedded, or if the
+    /// packet is not translatable.
+    pub fn translate_6to4(&self, ipv6: Ipv6) -> Result<Ipv4> {
+        let prefix = self.config.prefix;
+        translate_6to4(ipv6, &self.config, |addr| {
+            prefix
+                .extract(addr)
+                .ok_or_else(|| anyhow!("{} is not an IPv4-embedded address.", addr))
+        })
+    }
+
+    /// Translates an IPv4 packet to IPv6.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is not translatable.
+    pub fn translate_4to6(&self, ipv4: Ipv4) -> Result<Ipv6> {
+        let prefix = self.config.prefix;
+        translate_4to6(ipv4, &self.config, |addr| Ok(prefix.embed(addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v6::Ipv6Packet;
+    use crate::packets::ip::ProtocolNumbers;
+    use crate::packets::udp::{Udp4, Udp6};
+    use crate::packets::{Mbuf, Packet};
+    use crate::testils::byte_arrays::IPV4_UDP_PACKET;
+
+    fn udp_packet() -> Ipv4 {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        ethernet.parse::<Ipv4>().unwrap()
+    }
+
+    #[capsule::test]
+    fn translate_udp_round_trip() {
+        let siit = Siit::new(SiitConfig::default());
+
+        let ipv4 = udp_packet();
+        let (src, dst, ttl, tos) = (ipv4.src(), ipv4.dst(), ipv4.ttl(), ipv4.dscp());
+        let udp = ipv4.peek::<Udp4>().unwrap();
+        let (src_port, dst_port, data) = (udp.src_port(), udp.dst_port(), udp.data_to_vec());
+
+        let ipv6 = siit.translate_4to6(udp_packet()).unwrap();
+        assert_eq!(ProtocolNumbers::Udp, ipv6.next_header());
+        assert_eq!(Nat64Prefix::default().embed(src), ipv6.src());
+        assert_eq!(Nat64Prefix::default().embed(dst), ipv6.dst());
+        assert_eq!(ttl - 1, ipv6.hop_limit());
+        assert_eq!(tos, ipv6.dscp());
+        assert_eq!(0, ipv6.flow_label());
+
+        // the incremental checksum matches a full recompute
+        let mut udp = ipv6.parse::<Udp6>().unwrap();
+        assert_eq!(src_port, udp.src_port());
+        assert_eq!(dst_port, udp.dst_port());
+        let checksum = udp.checksum();
+        udp.reconcile_all();
+        assert_eq!(checksum, udp.checksum());
+
+        let ipv4 = siit.translate_6to4(udp.deparse()).unwrap();
+        assert_eq!(src, ipv4.src());
+        assert_eq!(dst, ipv4.dst());
+        assert_eq!(ttl - 2, ipv4.ttl());
+        assert!(!ipv4.dont_fragment());
+
+        let mut udp = ipv4.parse::<Udp4>().unwrap();
+        assert_eq!(data, udp.data().to_vec());
+        let ip_checksum = udp.envelope().checksum();
+        let checksum = udp.checksum();
+        udp.reconcile_all();
+        assert_eq!(ip_checksum, udp.envelope().checksum());
+        assert_eq!(checksum, udp.checksum());
+    }
+
+    #[capsule::test]
+    fn translate_fragment() {
+        let siit = Siit::new(SiitConfig::default());
+
+        let mut ipv4 = udp_packet();
+        ipv4.set_identification(0x1234);
+        ipv4.set_more_fragments();
+        ipv4.reconcile();
+
+        let ipv6 = siit.translate_4to6(ipv4).unwrap();
+        assert_eq!(ProtocolNumbers::Ipv6Frag, ipv6.next_header());
+
+        let ipv4 = siit.translate_6to4(ipv6).unwrap();
+        assert_eq!(ProtocolNumbers::Udp, ipv4.protocol());
+        assert_eq!(0x1234, ipv4.identification());
+        assert!(ipv4.more_fragments());
+        assert_eq!(0, ipv4.fragment_offset());
+    }
+
+    #[capsule::test]
+    fn drop_non_embedded_address() {
+        let siit = Siit::new(SiitConfig::default());
+
+        let mut ipv6 = siit.translate_4to6(udp_packet()).unwrap();
+        ipv6.set_src("2001:db8::1".parse().unwrap());
+        assert!(siit.translate_6to4(ipv6).is_err());
+    }
+}
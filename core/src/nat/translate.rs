@@ -0,0 +1,1132 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::rewrite::{
+    get_u16, get_u32, header_len, DST_PORT, ICMP_CHECKSUM, ICMP_DEST_UNREACHABLE, ICMP_ECHO_REPLY,
+    ICMP_ECHO_REQUEST, ICMP_HEADER_LEN, ICMP_IDENTIFIER, ICMP_PARAMETER_PROBLEM,
+    ICMP_TIME_EXCEEDED, ICMP_TYPE, IPV4_CHECKSUM, IPV4_DST, IPV4_FRAGMENT, IPV4_MIN_HEADER_LEN,
+    IPV4_PROTOCOL, IPV4_SRC, SRC_PORT, TCP_CHECKSUM, UDP_CHECKSUM,
+};
+use super::SiitConfig;
+use crate::ensure;
+use crate::packets::checksum::{self, PseudoHeader};
+use crate::packets::ethernet::{EtherTypes, Ethernet};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::{Flow, ProtocolNumber, ProtocolNumbers};
+use crate::packets::{Mbuf, Packet};
+use anyhow::{anyhow, Result};
+use std::cell::Cell;
+use std::cmp;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// offsets of the IPv4 fields not rewritten by NAT44.
+const IPV4_TOS: usize = 1;
+const IPV4_TOTAL_LEN: usize = 2;
+const IPV4_IDENTIFICATION: usize = 4;
+const IPV4_TTL: usize = 8;
+
+// offsets of the fields within the IPv6 header.
+const IPV6_HEADER_LEN: usize = 40;
+const IPV6_PAYLOAD_LEN: usize = 4;
+const IPV6_NEXT_HEADER: usize = 6;
+const IPV6_HOP_LIMIT: usize = 7;
+const IPV6_SRC: usize = 8;
+const IPV6_DST: usize = 24;
+const FRAGMENT_HEADER_LEN: usize = 8;
+
+const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+const ICMPV6_TIME_EXCEEDED: u8 = 3;
+const ICMPV6_PARAMETER_PROBLEM: u8 = 4;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// The maximum length of an ICMPv6 error, so it fits the minimum IPv6 MTU.
+const ICMPV6_ERROR_MAX_LEN: usize = 1280;
+
+/// IPv4 packets longer than this are translated with the don't fragment
+/// flag set, as specified in RFC 7915, section 5.1.
+const IPV4_DF_THRESHOLD: usize = 1260;
+
+thread_local! {
+    /// The identification of the next IPv4 packet translated without the
+    /// don't fragment flag. Each core has its own generator, so translating
+    /// doesn't contend on a shared counter.
+    static NEXT_IDENTIFICATION: Cell<u16> = const { Cell::new(0) };
+}
+
+/// Returns a new identification for a translated IPv4 packet.
+fn next_identification() -> u16 {
+    NEXT_IDENTIFICATION.with(|next| {
+        let identification = next.get();
+        next.set(identification.wrapping_add(1));
+        identification
+    })
+}
+
+/// The translated headers of a datagram.
+#[derive(Debug)]
+pub(crate) struct Translation {
+    /// The translated headers.
+    pub(crate) headers: Vec<u8>,
+    /// The length of the original headers they replace.
+    pub(crate) replaced: usize,
+    /// The length of the original datagram, excluding any padding.
+    pub(crate) datagram_len: usize,
+}
+
+/// The fields of an IPv6 datagram's fixed and extension headers.
+#[derive(Debug)]
+struct Ipv6Headers {
+    /// The length of the fixed and extension headers.
+    len: usize,
+    /// The upper-layer protocol.
+    protocol: ProtocolNumber,
+    /// The offset, more fragments flag and identification of the fragment
+    /// header.
+    fragment: Option<(u16, bool, u32)>,
+}
+
+impl Ipv6Headers {
+    fn is_first_fragment(&self) -> bool {
+        match self.fragment {
+            Some((offset, _, _)) => offset == 0,
+            None => true,
+        }
+    }
+
+    fn is_fragmented(&self) -> bool {
+        match self.fragment {
+            Some((offset, more, _)) => offset != 0 || more,
+            None => false,
+        }
+    }
+}
+
+#[inline]
+fn get_ipv6(bytes: &[u8], offset: usize) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(&bytes[offset..offset + 16]);
+    Ipv6Addr::from(octets)
+}
+
+#[inline]
+fn set_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Returns the words of the addresses covered by the IPv4 pseudo-header,
+/// padded to the length of the IPv6 ones.
+fn ipv4_pseudo_words(src: Ipv4Addr, dst: Ipv4Addr) -> [u16; 16] {
+    let src = u32::from(src);
+    let dst = u32::from(dst);
+    let mut words = [0; 16];
+    words[0] = (src >> 16) as u16;
+    words[1] = src as u16;
+    words[2] = (dst >> 16) as u16;
+    words[3] = dst as u16;
+    words
+}
+
+/// Returns the words of the addresses covered by the IPv6 pseudo-header.
+fn ipv6_pseudo_words(src: Ipv6Addr, dst: Ipv6Addr) -> [u16; 16] {
+    let mut words = [0; 16];
+    words[..8].copy_from_slice(&src.segments());
+    words[8..].copy_from_slice(&dst.segments());
+    words
+}
+
+/// Parses the fixed and extension headers of an IPv6 datagram.
+///
+/// Hop-by-hop options, destination options and routing headers without
+/// segments left are skipped, as they have no IPv4 equivalent.
+fn ipv6_headers(bytes: &[u8]) -> Result<Ipv6Headers> {
+    ensure!(
+        bytes.len() >= IPV6_HEADER_LEN,
+        anyhow!("IPv6 header is truncated.")
+    );
+    ensure!(bytes[0] >> 4 == 6, anyhow!("not an IPv6 packet."));
+
+    let mut len = IPV6_HEADER_LEN;
+    let mut protocol = ProtocolNumber::new(bytes[IPV6_NEXT_HEADER]);
+    let mut fragment = None;
+
+    loop {
+        match protocol {
+            ProtocolNumbers::Ipv6HopByHop
+            | ProtocolNumbers::Ipv6Route
+            | ProtocolNumbers::Ipv6Opts => {
+                ensure!(
+                    bytes.len() >= len + 4,
+                    anyhow!("IPv6 extension header is truncated.")
+                );
+                ensure!(
+                    protocol != ProtocolNumbers::Ipv6Route || bytes[len + 3] == 0,
+                    anyhow!("routing header with segments left is not translatable.")
+                );
+                protocol = ProtocolNumber::new(bytes[len]);
+                len += (bytes[len + 1] as usize + 1) * 8;
+            }
+            ProtocolNumbers::Ipv6Frag => {
+                ensure!(
+                    bytes.len() >= len + FRAGMENT_HEADER_LEN,
+                    anyhow!("IPv6 fragment header is truncated.")
+                );
+                let field = get_u16(bytes, len + 2);
+                fragment = Some((field >> 3, field & 1 == 1, get_u32(bytes, len + 4)));
+                protocol = ProtocolNumber::new(bytes[len]);
+                len += FRAGMENT_HEADER_LEN;
+            }
+            _ => break,
+        }
+    }
+
+    ensure!(
+        bytes.len() >= len,
+        anyhow!("IPv6 extension header is truncated.")
+    );
+
+    Ok(Ipv6Headers {
+        len,
+        protocol,
+        fragment,
+    })
+}
+
+/// Returns whether the IPv6 datagram in `bytes` is a fragment.
+pub(crate) fn is_ipv6_fragment(bytes: &[u8]) -> Result<bool> {
+    Ok(ipv6_headers(bytes)?.is_fragmented())
+}
+
+/// Returns the offset of the datagram embedded in the ICMPv6 error, if the
+/// IPv6 datagram in `bytes` is an ICMPv6 error.
+pub(crate) fn icmpv6_error_offset(bytes: &[u8]) -> Result<Option<usize>> {
+    let headers = ipv6_headers(bytes)?;
+    if headers.protocol != ProtocolNumbers::Icmpv6
+        || !headers.is_first_fragment()
+        || bytes.len() < headers.len + ICMP_HEADER_LEN
+    {
+        return Ok(None);
+    }
+
+    match bytes[headers.len + ICMP_TYPE] {
+        ICMPV6_DEST_UNREACHABLE
+        | ICMPV6_PACKET_TOO_BIG
+        | ICMPV6_TIME_EXCEEDED
+        | ICMPV6_PARAMETER_PROBLEM => Ok(Some(headers.len + ICMP_HEADER_LEN)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns the flow of the IPv6 datagram in `bytes`.
+///
+/// Like for IPv4 datagrams, the ICMPv6 echo identifier is used as the
+/// source port of a request and as the destination port of a reply.
+///
+/// # Errors
+///
+/// Returns an error if the protocol or ICMPv6 message is not translatable,
+/// or if the datagram is not the first fragment.
+pub(crate) fn ipv6_datagram_flow(bytes: &[u8]) -> Result<Flow> {
+    let headers = ipv6_headers(bytes)?;
+    ensure!(
+        headers.is_first_fragment(),
+        anyhow!("fragment doesn't have a transport header.")
+    );
+    let src = IpAddr::V6(get_ipv6(bytes, IPV6_SRC));
+    let dst = IpAddr::V6(get_ipv6(bytes, IPV6_DST));
+    let l4 = &bytes[headers.len..];
+
+    match headers.protocol {
+        protocol if protocol == ProtocolNumbers::Tcp || protocol == ProtocolNumbers::Udp => {
+            ensure!(l4.len() >= 4, anyhow!("transport header is truncated."));
+            Ok(Flow::new(
+                src,
+                dst,
+                get_u16(l4, SRC_PORT),
+                get_u16(l4, DST_PORT),
+                protocol,
+            ))
+        }
+        ProtocolNumbers::Icmpv6 => {
+            ensure!(
+                l4.len() >= ICMP_IDENTIFIER + 2,
+                anyhow!("ICMPv6 header is truncated.")
+            );
+            let id = get_u16(l4, ICMP_IDENTIFIER);
+            match l4[ICMP_TYPE] {
+                ICMPV6_ECHO_REQUEST => Ok(Flow::new(src, dst, id, 0, ProtocolNumbers::Icmpv6)),
+                ICMPV6_ECHO_REPLY => Ok(Flow::new(src, dst, 0, id, ProtocolNumbers::Icmpv6)),
+                t => Err(anyhow!("ICMPv6 type {} is not translatable.", t)),
+            }
+        }
+        protocol => Err(anyhow!("protocol {} is not translatable.", protocol)),
+    }
+}
+
+/// Copies the TCP or UDP header up to its checksum, and updates the
+/// checksum for the new pseudo-header addresses.
+///
+/// Returns the translated header.
+fn translate_transport(
+    l4: &[u8],
+    protocol: ProtocolNumber,
+    old: &[u16; 16],
+    new: &[u16; 16],
+) -> Vec<u8> {
+    let offset = if protocol == ProtocolNumbers::Tcp {
+        TCP_CHECKSUM
+    } else {
+        UDP_CHECKSUM
+    };
+    let mut header = l4[..cmp::min(l4.len(), offset + 2)].to_vec();
+
+    if header.len() == offset + 2 {
+        let checksum = get_u16(&header, offset);
+        // a zero UDP checksum is not used.
+        if protocol == ProtocolNumbers::Tcp || checksum != 0 {
+            let mut checksum = checksum::compute_inc(checksum, old, new);
+            if protocol == ProtocolNumbers::Udp && checksum == 0 {
+                checksum = 0xffff;
+            }
+            set_u16(&mut header, offset, checksum);
+        }
+    }
+
+    header
+}
+
+/// Builds an IPv4 header with its checksum.
+///
+/// As specified in RFC 7915, section 5.1, a packet not translated from a
+/// fragment has the don't fragment flag set only when it's longer than
+/// 1260 bytes. Otherwise it may be fragmented on the IPv4 path, and is
+/// given a generated identification.
+fn ipv4_header(
+    tos: u8,
+    total_len: usize,
+    fragment: Option<(u16, bool, u32)>,
+    ttl: u8,
+    protocol: ProtocolNumber,
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+) -> Result<Vec<u8>> {
+    ensure!(
+        total_len <= u16::MAX as usize,
+        anyhow!("translated IPv4 packet is too long.")
+    );
+
+    let (identification, flags) = match fragment {
+        Some((offset, more, identification)) => {
+            let more = if more { 0x2000 } else { 0 };
+            (identification as u16, offset | more)
+        }
+        None if total_len > IPV4_DF_THRESHOLD => (0, 0x4000),
+        None => (next_identification(), 0),
+    };
+
+    let mut header = vec![0; IPV4_MIN_HEADER_LEN];
+    header[0] = 0x45;
+    header[IPV4_TOS] = tos;
+    set_u16(&mut header, IPV4_TOTAL_LEN, total_len as u16);
+    set_u16(&mut header, IPV4_IDENTIFICATION, identification);
+    set_u16(&mut header, IPV4_FRAGMENT, flags);
+    header[IPV4_TTL] = ttl;
+    header[IPV4_PROTOCOL] = protocol.0;
+    header[IPV4_SRC..IPV4_SRC + 4].copy_from_slice(&src.octets());
+    header[IPV4_DST..IPV4_DST + 4].copy_from_slice(&dst.octets());
+    let checksum = checksum::compute(0, &header);
+    set_u16(&mut header, IPV4_CHECKSUM, checksum);
+
+    Ok(header)
+}
+
+/// Returns the ICMPv4 type, code and rest of header of an ICMPv6 error.
+fn icmp_error_6to4(header: &[u8]) -> Result<(u8, u8, [u8; 4])> {
+    let (msg_type, code) = (header[0], header[1]);
+    let not_translatable = || {
+        anyhow!(
+            "ICMPv6 type {} code {} is not translatable.",
+            msg_type,
+            code
+        )
+    };
+
+    match msg_type {
+        ICMPV6_DEST_UNREACHABLE => match code {
+            // no route, beyond scope and address unreachable are all
+            // reported as host unreachable.
+            0 | 2 | 3 => Ok((ICMP_DEST_UNREACHABLE, 1, [0; 4])),
+            1 => Ok((ICMP_DEST_UNREACHABLE, 10, [0; 4])),
+            4 => Ok((ICMP_DEST_UNREACHABLE, 3, [0; 4])),
+            _ => Err(not_translatable()),
+        },
+        ICMPV6_PACKET_TOO_BIG => {
+            let mtu = get_u32(header, 4).saturating_sub(20);
+            let mtu = cmp::min(mtu, u16::MAX as u32) as u16;
+            let mtu = mtu.to_be_bytes();
+            Ok((ICMP_DEST_UNREACHABLE, 4, [0, 0, mtu[0], mtu[1]]))
+        }
+        ICMPV6_TIME_EXCEEDED => Ok((ICMP_TIME_EXCEEDED, code, [0; 4])),
+        ICMPV6_PARAMETER_PROBLEM => match code {
+            0 => {
+                let pointer = match get_u32(header, 4) {
+                    0 => 0,
+                    1 => 1,
+                    4 | 5 => 2,
+                    6 => 9,
+                    7 => 8,
+                    8..=23 => 12,
+                    24..=39 => 16,
+                    _ => return Err(not_translatable()),
+                };
+                Ok((ICMP_PARAMETER_PROBLEM, 0, [pointer, 0, 0, 0]))
+            }
+            // unrecognized next header is reported as protocol unreachable.
+            1 => Ok((ICMP_DEST_UNREACHABLE, 2, [0; 4])),
+            _ => Err(not_translatable()),
+        },
+        _ => Err(not_translatable()),
+    }
+}
+
+/// Returns the ICMPv6 type, code and rest of header of an ICMPv4 error.
+fn icmp_error_4to6(header: &[u8]) -> Result<(u8, u8, [u8; 4])> {
+    let (msg_type, code) = (header[0], header[1]);
+    let not_translatable = || anyhow!("ICMP type {} code {} is not translatable.", msg_type, code);
+
+    match msg_type {
+        ICMP_DEST_UNREACHABLE => match code {
+            0 | 1 | 5 | 6 | 7 | 8 | 11 | 12 => Ok((ICMPV6_DEST_UNREACHABLE, 0, [0; 4])),
+            // protocol unreachable points to the next header field.
+            2 => Ok((
+                ICMPV6_PARAMETER_PROBLEM,
+                1,
+                [0, 0, 0, IPV6_NEXT_HEADER as u8],
+            )),
+            3 => Ok((ICMPV6_DEST_UNREACHABLE, 4, [0; 4])),
+            4 => {
+                let mtu = get_u16(header, 6) as u32 + 20;
+                Ok((ICMPV6_PACKET_TOO_BIG, 0, mtu.to_be_bytes()))
+            }
+            9 | 10 | 13 | 15 => Ok((ICMPV6_DEST_UNREACHABLE, 1, [0; 4])),
+            _ => Err(not_translatable()),
+        },
+        ICMP_TIME_EXCEEDED => Ok((ICMPV6_TIME_EXCEEDED, code, [0; 4])),
+        ICMP_PARAMETER_PROBLEM => match code {
+            0 | 2 => {
+                let pointer: u32 = match header[4] {
+                    0 => 0,
+                    1 => 1,
+                    2 | 3 => 4,
+                    8 => 7,
+                    9 => 6,
+                    12..=15 => 8,
+                    16..=19 => 24,
+                    _ => return Err(not_translatable()),
+                };
+                Ok((ICMPV6_PARAMETER_PROBLEM, 0, pointer.to_be_bytes()))
+            }
+            _ => Err(not_translatable()),
+        },
+        _ => Err(not_translatable()),
+    }
+}
+
+/// Translates the IPv6 datagram in `bytes` to IPv4, mapping its addresses
+/// with `map`.
+///
+/// The TCP and UDP headers are translated up to their checksums, and the
+/// rest of the datagram is left alone. ICMP errors are translated whole,
+/// including the datagrams embedded in them.
+fn datagram_6to4<F>(
+    bytes: &[u8],
+    config: &SiitConfig,
+    map: &F,
+    embedded: bool,
+) -> Result<Translation>
+where
+    F: Fn(Ipv6Addr) -> Result<Ipv4Addr>,
+{
+    let headers = ipv6_headers(bytes)?;
+    let datagram_len = IPV6_HEADER_LEN + get_u16(bytes, IPV6_PAYLOAD_LEN) as usize;
+    ensure!(
+        datagram_len >= headers.len,
+        anyhow!("IPv6 payload length is invalid.")
+    );
+    let bytes = if embedded {
+        bytes
+    } else {
+        ensure!(
+            bytes.len() >= datagram_len,
+            anyhow!("IPv6 packet is truncated.")
+        );
+        &bytes[..datagram_len]
+    };
+
+    let src6 = get_ipv6(bytes, IPV6_SRC);
+    let dst6 = get_ipv6(bytes, IPV6_DST);
+    let src = map(src6)?;
+    let dst = map(dst6)?;
+
+    let mut hop_limit = bytes[IPV6_HOP_LIMIT];
+    if !embedded {
+        ensure!(hop_limit > 1, anyhow!("IPv6 hop limit exceeded."));
+        hop_limit -= 1;
+    }
+
+    let tos = if config.traffic_class {
+        (bytes[0] << 4) | (bytes[1] >> 4)
+    } else {
+        0
+    };
+
+    let l4 = &bytes[headers.len..];
+    let l4_len = datagram_len - headers.len;
+    let mut protocol = headers.protocol;
+    let (translated, replaced) = match protocol {
+        _ if !headers.is_first_fragment() => (vec![], 0),
+        ProtocolNumbers::Tcp | ProtocolNumbers::Udp => {
+            let old = ipv6_pseudo_words(src6, dst6);
+            let new = ipv4_pseudo_words(src, dst);
+            let header = translate_transport(l4, protocol, &old, &new);
+            let len = header.len();
+            (header, len)
+        }
+        ProtocolNumbers::Icmpv6 => {
+            protocol = ProtocolNumbers::Icmpv4;
+            ensure!(
+                l4.len() >= ICMP_HEADER_LEN,
+                anyhow!("ICMPv6 header is truncated.")
+            );
+            ensure!(
+                embedded || !headers.is_fragmented(),
+                anyhow!("fragmented ICMPv6 packets are not translatable.")
+            );
+
+            match l4[ICMP_TYPE] {
+                ICMPV6_ECHO_REQUEST | ICMPV6_ECHO_REPLY => {
+                    let mut header = l4[..ICMP_HEADER_LEN].to_vec();
+                    header[ICMP_TYPE] = if l4[ICMP_TYPE] == ICMPV6_ECHO_REQUEST {
+                        ICMP_ECHO_REQUEST
+                    } else {
+                        ICMP_ECHO_REPLY
+                    };
+
+                    // ICMPv4 checksum doesn't cover a pseudo-header.
+                    let mut old = ipv6_pseudo_words(src6, dst6).to_vec();
+                    old.extend_from_slice(&[
+                        (l4_len >> 16) as u16,
+                        l4_len as u16,
+                        0,
+                        ProtocolNumbers::Icmpv6.0 as u16,
+                        get_u16(l4, ICMP_TYPE),
+                    ]);
+                    let mut new = vec![0; old.len() - 1];
+                    new.push(get_u16(&header, ICMP_TYPE));
+                    let checksum = get_u16(&header, ICMP_CHECKSUM);
+                    let checksum = checksum::compute_inc(checksum, &old, &new);
+                    set_u16(&mut header, ICMP_CHECKSUM, checksum);
+
+                    (header, ICMP_HEADER_LEN)
+                }
+                _ => {
+                    ensure!(
+                        !embedded,
+                        anyhow!("ICMPv6 error embedded in an ICMPv6 error.")
+                    );
+                    let (msg_type, code, rest) = icmp_error_6to4(l4)?;
+                    let inner = datagram_6to4(&l4[ICMP_HEADER_LEN..], config, map, true)?;
+
+                    let mut message = vec![msg_type, code, 0, 0];
+                    message.extend_from_slice(&rest);
+                    message.extend_from_slice(&inner.headers);
+                    message.extend_from_slice(&l4[ICMP_HEADER_LEN + inner.replaced..]);
+                    let checksum = checksum::compute(0, &message);
+                    set_u16(&mut message, ICMP_CHECKSUM, checksum);
+
+                    (message, l4.len())
+                }
+            }
+        }
+        _ => (vec![], 0),
+    };
+
+    let total_len = IPV4_MIN_HEADER_LEN + translated.len() + l4_len.saturating_sub(replaced);
+    let mut translated_headers = ipv4_header(
+        tos,
+        total_len,
+        headers.fragment,
+        hop_limit,
+        protocol,
+        src,
+        dst,
+    )?;
+    translated_headers.extend_from_slice(&translated);
+
+    Ok(Translation {
+        headers: translated_headers,
+        replaced: headers.len + replaced,
+        datagram_len,
+    })
+}
+
+/// Returns a flow label for the IPv4 datagram, hashed from its addresses,
+/// protocol and ports as recommended by [IETF RFC 6437].
+///
+/// [IETF RFC 6437]: https://tools.ietf.org/html/rfc6437#section-3
+fn flow_label(bytes: &[u8], ihl: usize, fragmented: bool) -> u32 {
+    let protocol = ProtocolNumber::new(bytes[IPV4_PROTOCOL]);
+    let mut key = bytes[IPV4_SRC..IPV4_DST + 4].to_vec();
+    key.push(protocol.0);
+    if !fragmented
+        && (protocol == ProtocolNumbers::Tcp || protocol == ProtocolNumbers::Udp)
+        && bytes.len() >= ihl + DST_PORT + 2
+    {
+        key.extend_from_slice(&bytes[ihl + SRC_PORT..ihl + DST_PORT + 2]);
+    }
+
+    // 32-bit FNV-1a.
+    let hash = key.iter().fold(0x811c_9dc5u32, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    (hash ^ (hash >> 20)) & 0xfffff
+}
+
+/// Translates the IPv4 datagram in `bytes` to IPv6, mapping its addresses
+/// with `map`.
+///
+/// The TCP and UDP headers are translated up to their checksums, unless a
+/// UDP checksum must be computed, and the rest of the datagram is left
+/// alone. ICMP errors are translated whole, including the datagrams
+/// embedded in them.
+fn datagram_4to6<F>(
+    bytes: &[u8],
+    config: &SiitConfig,
+    map: &F,
+    embedded: bool,
+) -> Result<Translation>
+where
+    F: Fn(Ipv4Addr) -> Result<Ipv6Addr>,
+{
+    ensure!(
+        bytes.len() >= IPV4_MIN_HEADER_LEN && bytes[0] >> 4 == 4,
+        anyhow!("not an IPv4 packet.")
+    );
+    let ihl = header_len(bytes)?;
+    let datagram_len = get_u16(bytes, IPV4_TOTAL_LEN) as usize;
+    ensure!(
+        datagram_len >= ihl,
+        anyhow!("IPv4 total length is invalid.")
+    );
+    let bytes = if embedded {
+        bytes
+    } else {
+        ensure!(
+            bytes.len() >= datagram_len,
+            anyhow!("IPv4 packet is truncated.")
+        );
+        &bytes[..datagram_len]
+    };
+
+    let src4 = Ipv4Addr::from(get_u32(bytes, IPV4_SRC));
+    let dst4 = Ipv4Addr::from(get_u32(bytes, IPV4_DST));
+    let src = map(src4)?;
+    let dst = map(dst4)?;
+
+    let mut hop_limit = bytes[IPV4_TTL];
+    if !embedded {
+        ensure!(hop_limit > 1, anyhow!("IPv4 TTL exceeded."));
+        hop_limit -= 1;
+    }
+
+    let field = get_u16(bytes, IPV4_FRAGMENT);
+    let offset = field & 0x1fff;
+    let more = field & 0x2000 != 0;
+    let fragmented = offset != 0 || more;
+
+    let traffic_class = if config.traffic_class {
+        bytes[IPV4_TOS]
+    } else {
+        0
+    };
+    let flow_label = if config.flow_label && !embedded {
+        flow_label(bytes, ihl, fragmented)
+    } else {
+        0
+    };
+
+    let l4 = &bytes[ihl..];
+    let l4_len = datagram_len - ihl;
+    let mut protocol = ProtocolNumber::new(bytes[IPV4_PROTOCOL]);
+    let (mut translated, replaced) = match protocol {
+        _ if offset != 0 => (vec![], 0),
+        ProtocolNumbers::Udp
+            if !embedded && l4.len() >= UDP_CHECKSUM + 2 && get_u16(l4, UDP_CHECKSUM) == 0 =>
+        {
+            // UDP checksum is mandatory in IPv6.
+            ensure!(
+                !fragmented,
+                anyhow!("fragmented UDP packets without checksum are not translatable.")
+            );
+            let pseudo = PseudoHeader::V6 {
+                src,
+                dst,
+                packet_len: l4_len as u16,
+                protocol,
+            };
+            let mut datagram = l4.to_vec();
+            let checksum = match checksum::compute(pseudo.sum(), &datagram) {
+                0 => 0xffff,
+                checksum => checksum,
+            };
+            set_u16(&mut datagram, UDP_CHECKSUM, checksum);
+            (datagram, l4.len())
+        }
+        ProtocolNumbers::Tcp | ProtocolNumbers::Udp => {
+            let old = ipv4_pseudo_words(src4, dst4);
+            let new = ipv6_pseudo_words(src, dst);
+            let header = translate_transport(l4, protocol, &old, &new);
+            let len = header.len();
+            (header, len)
+        }
+        ProtocolNumbers::Icmpv4 => {
+            protocol = ProtocolNumbers::Icmpv6;
+            ensure!(
+                l4.len() >= ICMP_HEADER_LEN,
+                anyhow!("ICMP header is truncated.")
+            );
+            ensure!(
+                embedded || !fragmented,
+                anyhow!("fragmented ICMP packets are not translatable.")
+            );
+
+            match l4[ICMP_TYPE] {
+                ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY => {
+                    let mut header = l4[..ICMP_HEADER_LEN].to_vec();
+                    header[ICMP_TYPE] = if l4[ICMP_TYPE] == ICMP_ECHO_REQUEST {
+                        ICMPV6_ECHO_REQUEST
+                    } else {
+                        ICMPV6_ECHO_REPLY
+                    };
+
+                    // ICMPv6 checksum covers a pseudo-header.
+                    let mut new = ipv6_pseudo_words(src, dst).to_vec();
+                    new.extend_from_slice(&[
+                        (l4_len >> 16) as u16,
+                        l4_len as u16,
+                        0,
+                        ProtocolNumbers::Icmpv6.0 as u16,
+                        get_u16(&header, ICMP_TYPE),
+                    ]);
+                    let mut old = vec![0; new.len() - 1];
+                    old.push(get_u16(l4, ICMP_TYPE));
+                    let checksum = get_u16(&header, ICMP_CHECKSUM);
+                    let checksum = checksum::compute_inc(checksum, &old, &new);
+                    set_u16(&mut header, ICMP_CHECKSUM, checksum);
+
+                    (header, ICMP_HEADER_LEN)
+                }
+                _ => {
+                    ensure!(!embedded, anyhow!("ICMP error embedded in an ICMP error."));
+                    let (msg_type, code, rest) = icmp_error_4to6(l4)?;
+                    let inner = datagram_4to6(&l4[ICMP_HEADER_LEN..], config, map, true)?;
+
+                    let mut message = vec![msg_type, code, 0, 0];
+                    message.extend_from_slice(&rest);
+                    message.extend_from_slice(&inner.headers);
+                    message.extend_from_slice(&l4[ICMP_HEADER_LEN + inner.replaced..]);
+                    message.truncate(ICMPV6_ERROR_MAX_LEN - IPV6_HEADER_LEN);
+
+                    let pseudo = PseudoHeader::V6 {
+                        src,
+                        dst,
+                        packet_len: message.len() as u16,
+                        protocol,
+                    };
+                    let checksum = checksum::compute(pseudo.sum(), &message);
+                    set_u16(&mut message, ICMP_CHECKSUM, checksum);
+
+                    (message, l4.len())
+                }
+            }
+        }
+        _ => (vec![], 0),
+    };
+
+    let fragment_len = if fragmented { FRAGMENT_HEADER_LEN } else { 0 };
+    let payload_len = fragment_len + translated.len() + l4_len.saturating_sub(replaced);
+    ensure!(
+        payload_len <= u16::MAX as usize,
+        anyhow!("translated IPv6 packet is too long.")
+    );
+
+    let mut headers = vec![0; IPV6_HEADER_LEN];
+    let version = 0x6000_0000 | u32::from(traffic_class) << 20 | flow_label;
+    headers[..4].copy_from_slice(&version.to_be_bytes());
+    set_u16(&mut headers, IPV6_PAYLOAD_LEN, payload_len as u16);
+    headers[IPV6_NEXT_HEADER] = if fragmented {
+        ProtocolNumbers::Ipv6Frag.0
+    } else {
+        protocol.0
+    };
+    headers[IPV6_HOP_LIMIT] = hop_limit;
+    headers[IPV6_SRC..IPV6_SRC + 16].copy_from_slice(&src.octets());
+    headers[IPV6_DST..IPV6_DST + 16].copy_from_slice(&dst.octets());
+
+    if fragmented {
+        let more = if more { 1 } else { 0 };
+        let identification = u32::from(get_u16(bytes, IPV4_IDENTIFICATION));
+        headers.extend_from_slice(&[protocol.0, 0]);
+        headers.extend_from_slice(&(offset << 3 | more).to_be_bytes());
+        headers.extend_from_slice(&identification.to_be_bytes());
+    }
+    headers.append(&mut translated);
+
+    Ok(Translation {
+        headers,
+        replaced: ihl + replaced,
+        datagram_len,
+    })
+}
+
+/// Replaces the original headers at offset with the translated ones, and
+/// removes any padding after the datagram.
+fn splice(mbuf: &mut Mbuf, offset: usize, translation: &Translation) -> Result<()> {
+    let end = offset + translation.datagram_len;
+    if mbuf.data_len() > end {
+        mbuf.truncate(end)?;
+    }
+
+    let Translation {
+        headers, replaced, ..
+    } = translation;
+    if headers.len() > *replaced {
+        mbuf.extend(offset, headers.len() - replaced)?;
+    } else if headers.len() < *replaced {
+        mbuf.shrink(offset, replaced - headers.len())?;
+    }
+    mbuf.copy_from_slice(offset, headers)
+}
+
+/// Translates an IPv6 packet to IPv4, mapping its addresses, and the
+/// addresses of the datagram embedded in an ICMPv6 error, with `map`.
+pub(crate) fn translate_6to4<F>(ipv6: Ipv6, config: &SiitConfig, map: F) -> Result<Ipv4>
+where
+    F: Fn(Ipv6Addr) -> Result<Ipv4Addr>,
+{
+    let offset = ipv6.offset();
+    let len = ipv6.len();
+    let mut ethernet = ipv6.deparse();
+
+    let translation = {
+        let bytes = ethernet.mbuf().data_slice(offset, len)?;
+        datagram_6to4(&bytes, config, &map, false)?
+    };
+    splice(ethernet.mbuf_mut(), offset, &translation)?;
+
+    ethernet.set_ether_type(EtherTypes::Ipv4);
+    ethernet.parse::<Ipv4>().map_err(|(e, _)| e)
+}
+
+/// Translates an IPv4 packet to IPv6, mapping its addresses, and the
+/// addresses of the datagram embedded in an ICMP error, with `map`.
+pub(crate) fn translate_4to6<F>(ipv4: Ipv4, config: &SiitConfig, map: F) -> Result<Ipv6>
+where
+    F: Fn(Ipv4Addr) -> Result<Ipv6Addr>,
+{
+    let offset = ipv4.offset();
+    let len = ipv4.len();
+    let mut ethernet: Ethernet = ipv4.deparse();
+
+    let translation = {
+        let bytes = ethernet.mbuf().data_slice(offset, len)?;
+        datagram_4to6(&bytes, config, &map, false)?
+    };
+    splice(ethernet.mbuf_mut(), offset, &translation)?;
+
+    ethernet.set_ether_type(EtherTypes::Ipv6);
+    ethernet.parse::<Ipv6>().map_err(|(e, _)| e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_6to4(addr: Ipv6Addr) -> Result<Ipv4Addr> {
+        SiitConfig::default()
+            .prefix
+            .extract(addr)
+            .ok_or_else(|| anyhow!("not embedded."))
+    }
+
+    fn map_4to6(addr: Ipv4Addr) -> Result<Ipv6Addr> {
+        Ok(SiitConfig::default().prefix.embed(addr))
+    }
+
+    /// Returns an IPv4 datagram with valid checksums.
+    fn ipv4_datagram(protocol: u8, l4: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![
+            0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, protocol, 0x00, 0x00, 0xc0, 0x00,
+            0x02, 0x01, 0xc6, 0x33, 0x64, 0x02,
+        ];
+        bytes.extend_from_slice(l4);
+        let len = bytes.len() as u16;
+        set_u16(&mut bytes, IPV4_TOTAL_LEN, len);
+        let checksum = checksum::compute(0, &bytes[..20]);
+        set_u16(&mut bytes, IPV4_CHECKSUM, checksum);
+        bytes
+    }
+
+    fn ipv4_udp_datagram() -> Vec<u8> {
+        let mut udp = vec![
+            0x9c, 0x40, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04,
+        ];
+        let pseudo = PseudoHeader::V4 {
+            src: Ipv4Addr::new(192, 0, 2, 1),
+            dst: Ipv4Addr::new(198, 51, 100, 2),
+            packet_len: udp.len() as u16,
+            protocol: ProtocolNumbers::Udp,
+        };
+        let checksum = checksum::compute(pseudo.sum(), &udp);
+        set_u16(&mut udp, UDP_CHECKSUM, checksum);
+        ipv4_datagram(ProtocolNumbers::Udp.0, &udp)
+    }
+
+    /// Asserts the ICMPv6 message of the datagram has a valid checksum.
+    fn assert_icmpv6_checksum(bytes: &[u8]) {
+        let pseudo = PseudoHeader::V6 {
+            src: get_ipv6(bytes, IPV6_SRC),
+            dst: get_ipv6(bytes, IPV6_DST),
+            packet_len: (bytes.len() - IPV6_HEADER_LEN) as u16,
+            protocol: ProtocolNumbers::Icmpv6,
+        };
+        assert_eq!(
+            0,
+            checksum::compute(pseudo.sum(), &bytes[IPV6_HEADER_LEN..])
+        );
+    }
+
+    #[test]
+    fn translate_icmp_echo_round_trip() {
+        let mut echo = vec![
+            ICMP_ECHO_REQUEST,
+            0,
+            0,
+            0,
+            0x12,
+            0x34,
+            0x00,
+            0x01,
+            0xaa,
+            0xbb,
+        ];
+        let checksum = checksum::compute(0, &echo);
+        set_u16(&mut echo, ICMP_CHECKSUM, checksum);
+        let original = ipv4_datagram(ProtocolNumbers::Icmpv4.0, &echo);
+
+        let config = SiitConfig::default();
+        let translation = datagram_4to6(&original, &config, &map_4to6, false).unwrap();
+        assert_eq!(20 + ICMP_HEADER_LEN, translation.replaced);
+        let mut ipv6 = translation.headers;
+        ipv6.extend_from_slice(&original[translation.replaced..]);
+
+        assert_eq!(ProtocolNumbers::Icmpv6.0, ipv6[IPV6_NEXT_HEADER]);
+        assert_eq!(0x3f, ipv6[IPV6_HOP_LIMIT]);
+        assert_eq!(ICMPV6_ECHO_REQUEST, ipv6[IPV6_HEADER_LEN]);
+        assert_eq!(echo.len() as u16, get_u16(&ipv6, IPV6_PAYLOAD_LEN));
+        assert_icmpv6_checksum(&ipv6);
+
+        let flow = ipv6_datagram_flow(&ipv6).unwrap();
+        assert_eq!(0x1234, flow.src_port());
+
+        let translation = datagram_6to4(&ipv6, &config, &map_6to4, false).unwrap();
+        let mut ipv4 = translation.headers;
+        ipv4.extend_from_slice(&ipv6[translation.replaced..]);
+
+        // only the TTL and IPv4 checksum differ
+        assert_eq!(0x3e, ipv4[IPV4_TTL]);
+        assert_eq!(0, checksum::compute(0, &ipv4[..20]));
+        assert_eq!(&original[20..], &ipv4[20..]);
+    }
+
+    #[test]
+    fn translate_icmp_error_with_embedded_datagram() {
+        let udp = ipv4_udp_datagram();
+
+        // port unreachable, sent back by the destination.
+        let mut error = vec![ICMP_DEST_UNREACHABLE, 3, 0, 0, 0, 0, 0, 0];
+        error.extend_from_slice(&udp);
+        let checksum = checksum::compute(0, &error);
+        set_u16(&mut error, ICMP_CHECKSUM, checksum);
+        let mut original = ipv4_datagram(ProtocolNumbers::Icmpv4.0, &error);
+        original[IPV4_SRC..IPV4_DST + 4].rotate_left(4);
+
+        let config = SiitConfig::default();
+        let translation = datagram_4to6(&original, &config, &map_4to6, false).unwrap();
+        assert_eq!(original.len(), translation.replaced);
+        let ipv6 = translation.headers;
+
+        assert_eq!(ICMPV6_DEST_UNREACHABLE, ipv6[IPV6_HEADER_LEN]);
+        assert_eq!(4, ipv6[IPV6_HEADER_LEN + 1]);
+        assert_eq!(
+            (ipv6.len() - IPV6_HEADER_LEN) as u16,
+            get_u16(&ipv6, IPV6_PAYLOAD_LEN)
+        );
+        assert_icmpv6_checksum(&ipv6);
+
+        // the embedded datagram is translated, and its checksum updated
+        let inner = &ipv6[IPV6_HEADER_LEN + ICMP_HEADER_LEN..];
+        assert_eq!(
+            Some(IPV6_HEADER_LEN + ICMP_HEADER_LEN),
+            icmpv6_error_offset(&ipv6).unwrap()
+        );
+        assert_eq!(udp.len() - 20, get_u16(inner, IPV6_PAYLOAD_LEN) as usize);
+        assert_eq!(0x40, inner[IPV6_HOP_LIMIT]);
+        let pseudo = PseudoHeader::V6 {
+            src: get_ipv6(inner, IPV6_SRC),
+            dst: get_ipv6(inner, IPV6_DST),
+            packet_len: (inner.len() - IPV6_HEADER_LEN) as u16,
+            protocol: ProtocolNumbers::Udp,
+        };
+        assert_eq!(
+            0,
+            checksum::compute(pseudo.sum(), &inner[IPV6_HEADER_LEN..])
+        );
+
+        let flow = ipv6_datagram_flow(inner).unwrap();
+        assert_eq!(40000, flow.src_port());
+        assert_eq!(53, flow.dst_port());
+
+        // and back, to the original error
+        let translation = datagram_6to4(&ipv6, &config, &map_6to4, false).unwrap();
+        let ipv4 = translation.headers;
+        assert_eq!(0, checksum::compute(0, &ipv4[..20]));
+        assert_eq!(&original[20..], &ipv4[20..]);
+    }
+
+    #[test]
+    fn translate_fragment_header() {
+        let mut original = ipv4_udp_datagram();
+        set_u16(&mut original, IPV4_IDENTIFICATION, 0xbeef);
+        set_u16(&mut original, IPV4_FRAGMENT, 0x2000);
+
+        let config = SiitConfig::default();
+        let translation = datagram_4to6(&original, &config, &map_4to6, false).unwrap();
+        let mut ipv6 = translation.headers;
+        ipv6.extend_from_slice(&original[translation.replaced..]);
+
+        let headers = ipv6_headers(&ipv6).unwrap();
+        assert_eq!(ProtocolNumbers::Udp, headers.protocol);
+        assert_eq!(Some((0, true, 0xbeef)), headers.fragment);
+        assert!(is_ipv6_fragment(&ipv6).unwrap());
+
+        let translation = datagram_6to4(&ipv6, &config, &map_6to4, false).unwrap();
+        let ipv4 = translation.headers;
+        assert_eq!(0xbeef, get_u16(&ipv4, IPV4_IDENTIFICATION));
+        assert_eq!(0x2000, get_u16(&ipv4, IPV4_FRAGMENT));
+        assert_eq!(original.len(), get_u16(&ipv4, IPV4_TOTAL_LEN) as usize);
+    }
+
+    #[test]
+    fn set_dont_fragment_and_identification() {
+        let src = Ipv4Addr::new(192, 0, 2, 1);
+        let dst = Ipv4Addr::new(198, 51, 100, 1);
+        let udp = ProtocolNumbers::Udp;
+
+        // may be fragmented, so each packet has its own identification.
+        let first = ipv4_header(0, 1260, None, 64, udp, src, dst).unwrap();
+        let second = ipv4_header(0, 1260, None, 64, udp, src, dst).unwrap();
+        assert_eq!(0, get_u16(&first, IPV4_FRAGMENT));
+        assert_ne!(
+            get_u16(&first, IPV4_IDENTIFICATION),
+            get_u16(&second, IPV4_IDENTIFICATION)
+        );
+
+        let large = ipv4_header(0, 1261, None, 64, udp, src, dst).unwrap();
+        assert_eq!(0x4000, get_u16(&large, IPV4_FRAGMENT));
+        assert_eq!(0, checksum::compute(0, &large));
+    }
+
+    #[test]
+    fn translate_traffic_class_and_flow_label() {
+        let mut original = ipv4_udp_datagram();
+        original[IPV4_TOS] = 0xb8;
+
+        let config = SiitConfig {
+            flow_label: true,
+            ..Default::default()
+        };
+        let ipv6 = datagram_4to6(&original, &config, &map_4to6, false)
+            .unwrap()
+            .headers;
+        assert_eq!(0x6b, ipv6[0]);
+        assert_eq!(0x80, ipv6[1] & 0xf0);
+        let label = get_u32(&ipv6, 0) & 0xfffff;
+        assert_ne!(0, label);
+
+        // the same flow has the same label
+        let again = datagram_4to6(&original, &config, &map_4to6, false)
+            .unwrap()
+            .headers;
+        assert_eq!(label, get_u32(&again, 0) & 0xfffff);
+
+        let config = SiitConfig {
+            traffic_class: false,
+            ..Default::default()
+        };
+        let ipv6 = datagram_4to6(&original, &config, &map_4to6, false)
+            .unwrap()
+            .headers;
+        assert_eq!(0x6000_0000, get_u32(&ipv6, 0));
+    }
+
+    #[test]
+    fn translate_icmp_error_types() {
+        // packet too big
+        let header = [ICMPV6_PACKET_TOO_BIG, 0, 0, 0, 0, 0, 0x05, 0x00];
+        assert_eq!(
+            (ICMP_DEST_UNREACHABLE, 4, [0, 0, 0x04, 0xec]),
+            icmp_error_6to4(&header).unwrap()
+        );
+        let header = [ICMP_DEST_UNREACHABLE, 4, 0, 0, 0, 0, 0x04, 0xec];
+        assert_eq!(
+            (ICMPV6_PACKET_TOO_BIG, 0, [0, 0, 0x05, 0x00]),
+            icmp_error_4to6(&header).unwrap()
+        );
+
+        // parameter problem pointing to the destination address
+        let header = [ICMPV6_PARAMETER_PROBLEM, 0, 0, 0, 0, 0, 0, 30];
+        assert_eq!(
+            (ICMP_PARAMETER_PROBLEM, 0, [16, 0, 0, 0]),
+            icmp_error_6to4(&header).unwrap()
+        );
+        let header = [ICMP_PARAMETER_PROBLEM, 0, 0, 0, 16, 0, 0, 0];
+        assert_eq!(
+            (ICMPV6_PARAMETER_PROBLEM, 0, [0, 0, 0, 24]),
+            icmp_error_4to6(&header).unwrap()
+        );
+
+        // no counterpart
+        let header = [ICMPV6_PARAMETER_PROBLEM, 2, 0, 0, 0, 0, 0, 0];
+        assert!(icmp_error_6to4(&header).is_err());
+        let header = [ICMP_DEST_UNREACHABLE, 14, 0, 0, 0, 0, 0, 0];
+        assert!(icmp_error_4to6(&header).is_err());
+    }
+}
@@ -0,0 +1,539 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::net::MacAddr;
+use crate::packets::arp::{Arp4, OperationCodes};
+use crate::packets::ethernet::Ethernet;
+use crate::packets::{Mbuf, Packet, Postmark};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// Builds an ARP request from the sender for the target address.
+fn request(mac: MacAddr, sender: Ipv4Addr, target: Ipv4Addr) -> Result<Mbuf> {
+    let mut ethernet = Mbuf::new()?.push::<Ethernet>()?;
+    ethernet.set_src(mac);
+    ethernet.set_dst(MacAddr::BROADCAST);
+
+    let mut arp = ethernet.push::<Arp4>()?;
+    arp.set_operation_code(OperationCodes::Request);
+    arp.set_sender_hardware_addr(mac);
+    arp.set_sender_protocol_addr(sender);
+    arp.set_target_protocol_addr(target);
+    Ok(arp.reset())
+}
+
+/// Turns an ARP request into the reply from the MAC address, in place.
+fn reply(arp: &mut Arp4, mac: MacAddr) {
+    let sender_mac = arp.sender_hardware_addr();
+    let sender_ip = arp.sender_protocol_addr();
+    let target_ip = arp.target_protocol_addr();
+
+    arp.set_operation_code(OperationCodes::Reply);
+    arp.set_sender_hardware_addr(mac);
+    arp.set_sender_protocol_addr(target_ip);
+    arp.set_target_hardware_addr(sender_mac);
+    arp.set_target_protocol_addr(sender_ip);
+
+    let ethernet = arp.envelope_mut();
+    ethernet.set_src(mac);
+    ethernet.set_dst(sender_mac);
+}
+
+/// Answers ARP requests for the addresses of an interface.
+#[derive(Clone, Debug)]
+pub struct ArpResponder {
+    mac: MacAddr,
+    addrs: Vec<Ipv4Addr>,
+}
+
+impl ArpResponder {
+    /// Creates a responder for the interface addresses.
+    pub fn new(mac: MacAddr, addrs: Vec<Ipv4Addr>) -> Self {
+        ArpResponder { mac, addrs }
+    }
+
+    /// Returns the MAC address of the interface.
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    /// Returns the IPv4 addresses of the interface.
+    pub fn addrs(&self) -> &[Ipv4Addr] {
+        &self.addrs
+    }
+
+    /// Answers the packet if it is an ARP request for one of the interface
+    /// addresses, turning it into the reply.
+    ///
+    /// Returns the packet unchanged as the error if it is not such a
+    /// request, so it can continue through the pipeline.
+    pub fn respond(&self, packet: Mbuf) -> std::result::Result<Arp4, Mbuf> {
+        let ethernet = packet.parse::<Ethernet>().map_err(|(_, mbuf)| mbuf)?;
+        let mut arp = ethernet
+            .parse::<Arp4>()
+            .map_err(|(_, ethernet)| ethernet.reset())?;
+
+        if arp.operation_code() == OperationCodes::Request
+            && self.addrs.contains(&arp.target_protocol_addr())
+        {
+            reply(&mut arp, self.mac);
+            Ok(arp)
+        } else {
+            Err(arp.reset())
+        }
+    }
+}
+
+/// Settings of an ARP cache.
+#[derive(Clone, Copy, Debug)]
+pub struct ArpCacheConfig {
+    /// How long a resolved address is used before it is resolved again.
+    pub reachable_time: Duration,
+    /// Interval between the requests for an unresolved address.
+    pub retrans_time: Duration,
+    /// Number of requests sent before an address is deemed unreachable.
+    pub max_requests: u32,
+    /// Maximum number of packets held for an unresolved address.
+    pub max_pending: usize,
+    /// Maximum number of addresses in the cache.
+    pub max_entries: usize,
+}
+
+impl Default for ArpCacheConfig {
+    fn default() -> Self {
+        ArpCacheConfig {
+            reachable_time: Duration::from_secs(60),
+            retrans_time: Duration::from_secs(1),
+            max_requests: 3,
+            max_pending: 16,
+            max_entries: 1024,
+        }
+    }
+}
+
+/// An address in the cache.
+#[derive(Debug)]
+enum Entry {
+    /// The address is being resolved. Packets to the address are held
+    /// until it is.
+    Incomplete {
+        pending: VecDeque<Mbuf>,
+        requests: u32,
+        sent: Instant,
+    },
+    /// The address is resolved.
+    Resolved { mac: MacAddr, updated: Instant },
+}
+
+/// IPv4 neighbor cache of an interface.
+///
+/// Packets are sent to a next hop with [`resolve`], which addresses the
+/// packet if the next hop is resolved, or holds it and requests the next
+/// hop's MAC address otherwise. The ARP packets received on the interface
+/// are handed to [`receive`], which answers the requests for the interface
+/// address and releases the held packets once a next hop replies.
+/// [`poll`] must be called periodically to retransmit the requests and age
+/// the cache.
+///
+/// [`resolve`]: ArpCache::resolve
+/// [`receive`]: ArpCache::receive
+/// [`poll`]: ArpCache::poll
+#[derive(Debug)]
+pub struct ArpCache {
+    mac: MacAddr,
+    addr: Ipv4Addr,
+    config: ArpCacheConfig,
+    entries: HashMap<Ipv4Addr, Entry>,
+}
+
+impl ArpCache {
+    /// Creates an empty cache for the interface.
+    pub fn new(mac: MacAddr, addr: Ipv4Addr, config: ArpCacheConfig) -> Self {
+        ArpCache {
+            mac,
+            addr,
+            config,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of addresses in the cache, resolved or not.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the MAC address of a resolved address.
+    pub fn lookup(&self, addr: Ipv4Addr) -> Option<MacAddr> {
+        match self.entries.get(&addr) {
+            Some(Entry::Resolved { mac, updated })
+                if updated.elapsed() < self.config.reachable_time =>
+            {
+                Some(*mac)
+            }
+            _ => None,
+        }
+    }
+
+    /// Sends the packet to the next hop.
+    ///
+    /// If the next hop is resolved, the packet is addressed and emitted.
+    /// Otherwise the packet is held, and an ARP request is emitted if the
+    /// next hop was not already being resolved. The packet is dropped if
+    /// too many packets are held for the next hop, or if the cache is full
+    /// of unresolved addresses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ARP request cannot be allocated.
+    pub fn resolve(&mut self, next_hop: Ipv4Addr, mut packet: Ethernet) -> Result<Postmark> {
+        let now = Instant::now();
+        match self.entries.get_mut(&next_hop) {
+            Some(Entry::Resolved { mac, updated })
+                if now.duration_since(*updated) < self.config.reachable_time =>
+            {
+                packet.set_src(self.mac);
+                packet.set_dst(*mac);
+                Ok(Postmark::emit(packet))
+            }
+            Some(Entry::Incomplete { pending, .. }) => {
+                if pending.len() < self.config.max_pending {
+                    pending.push_back(packet.reset());
+                    Ok(Postmark::emit_multi(vec![]))
+                } else {
+                    Ok(Postmark::drop(packet))
+                }
+            }
+            entry => {
+                if entry.is_none() && !self.make_room() {
+                    return Ok(Postmark::drop(packet));
+                }
+
+                let request = request(self.mac, self.addr, next_hop)?;
+                let mut pending = VecDeque::with_capacity(self.config.max_pending);
+                pending.push_back(packet.reset());
+                self.entries.insert(
+                    next_hop,
+                    Entry::Incomplete {
+                        pending,
+                        requests: 1,
+                        sent: now,
+                    },
+                );
+                Ok(Postmark::emit_multi(vec![request]))
+            }
+        }
+    }
+
+    /// Processes an ARP packet received on the interface.
+    ///
+    /// As in RFC 826, the sender's address is learned if it is already in
+    /// the cache or the packet is for the interface address, and the held
+    /// packets to the sender are emitted. A request for the interface
+    /// address is turned into the reply and emitted as well. Other packets
+    /// are dropped.
+    pub fn receive(&mut self, mut arp: Arp4) -> Postmark {
+        let sender_ip = arp.sender_protocol_addr();
+        let for_us = arp.target_protocol_addr() == self.addr;
+
+        let mut emit = vec![];
+        if !sender_ip.is_unspecified()
+            && sender_ip != self.addr
+            && (for_us || self.entries.contains_key(&sender_ip))
+        {
+            emit = self.learn(sender_ip, arp.sender_hardware_addr());
+        }
+
+        if for_us && arp.operation_code() == OperationCodes::Request {
+            reply(&mut arp, self.mac);
+            emit.push(arp.reset());
+            Postmark::emit_multi(emit)
+        } else {
+            Postmark::emit_multi_and_drop(emit, arp.reset())
+        }
+    }
+
+    /// Builds a gratuitous ARP announcing the interface address, so the
+    /// neighbors update their caches.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet cannot be allocated.
+    pub fn gratuitous(&self) -> Result<Mbuf> {
+        request(self.mac, self.addr, self.addr)
+    }
+
+    /// Retransmits the requests for unresolved addresses, and removes the
+    /// resolved addresses that aged out and the unresolved addresses that
+    /// did not reply. The packets held for those are dropped.
+    ///
+    /// Returns the requests to emit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request cannot be allocated.
+    pub fn poll(&mut self) -> Result<Vec<Mbuf>> {
+        let now = Instant::now();
+        let config = self.config;
+        let mut targets = vec![];
+
+        self.entries.retain(|&addr, entry| match entry {
+            Entry::Resolved { updated, .. } => now.duration_since(*updated) < config.reachable_time,
+            Entry::Incomplete { requests, sent, .. } => {
+                if now.duration_since(*sent) < config.retrans_time {
+                    true
+                } else if *requests < config.max_requests {
+                    *requests += 1;
+                    *sent = now;
+                    targets.push(addr);
+                    true
+                } else {
+                    false
+                }
+            }
+        });
+
+        targets
+            .into_iter()
+            .map(|target| request(self.mac, self.addr, target))
+            .collect()
+    }
+
+    /// Records the MAC address of a neighbor, returning the packets that
+    /// were held for it, addressed.
+    fn learn(&mut self, addr: Ipv4Addr, mac: MacAddr) -> Vec<Mbuf> {
+        if !self.entries.contains_key(&addr) && !self.make_room() {
+            return vec![];
+        }
+
+        let entry = Entry::Resolved {
+            mac,
+            updated: Instant::now(),
+        };
+
+        match self.entries.insert(addr, entry) {
+            Some(Entry::Incomplete { pending, .. }) => pending
+                .into_iter()
+                .filter_map(|mbuf| {
+                    let mut ethernet = mbuf.parse::<Ethernet>().ok()?;
+                    ethernet.set_src(self.mac);
+                    ethernet.set_dst(mac);
+                    Some(ethernet.reset())
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Makes room for a new address when the cache is full, by removing
+    /// the resolved address that was updated the longest time ago.
+    ///
+    /// Returns `false` if the cache is full and no address is resolved.
+    fn make_room(&mut self) -> bool {
+        if self.entries.len() < self.config.max_entries {
+            return true;
+        }
+
+        let oldest = self
+            .entries
+            .iter()
+            .filter_map(|(&addr, entry)| match entry {
+                Entry::Resolved { updated, .. } => Some((addr, *updated)),
+                Entry::Incomplete { .. } => None,
+            })
+            .min_by_key(|&(_, updated)| updated)
+            .map(|(addr, _)| addr);
+
+        match oldest {
+            Some(addr) => {
+                self.entries.remove(&addr);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::arp::OperationCode;
+    use crate::testils::byte_arrays::ARP4_PACKET;
+
+    const OUR_MAC: MacAddr = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+    const OUR_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const PEER_MAC: MacAddr = MacAddr::new(0x02, 0, 0, 0, 0, 0x02);
+    const PEER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn arp(operation: OperationCode, target_ip: Ipv4Addr) -> Arp4 {
+        let mut arp = Mbuf::new()
+            .unwrap()
+            .push::<Ethernet>()
+            .unwrap()
+            .push::<Arp4>()
+            .unwrap();
+        arp.set_operation_code(operation);
+        arp.set_sender_hardware_addr(PEER_MAC);
+        arp.set_sender_protocol_addr(PEER_IP);
+        arp.set_target_protocol_addr(target_ip);
+        arp
+    }
+
+    fn parse_arp(mbuf: Mbuf) -> Arp4 {
+        mbuf.parse::<Ethernet>().unwrap().parse::<Arp4>().unwrap()
+    }
+
+    #[capsule::test]
+    fn respond_to_request() {
+        let target = Ipv4Addr::new(139, 133, 233, 2);
+        let responder = ArpResponder::new(OUR_MAC, vec![target]);
+
+        let packet = Mbuf::from_bytes(&ARP4_PACKET).unwrap();
+        let reply = responder.respond(packet).unwrap();
+        assert_eq!(OperationCodes::Reply, reply.operation_code());
+        assert_eq!(OUR_MAC, reply.sender_hardware_addr());
+        assert_eq!(target, reply.sender_protocol_addr());
+        assert_eq!(MacAddr::new(0, 0, 0, 0, 0, 1), reply.target_hardware_addr());
+        assert_eq!(
+            Ipv4Addr::new(139, 133, 217, 110),
+            reply.target_protocol_addr()
+        );
+        assert_eq!(OUR_MAC, reply.envelope().src());
+        assert_eq!(MacAddr::new(0, 0, 0, 0, 0, 1), reply.envelope().dst());
+
+        // requests for other addresses are passed through
+        let responder = ArpResponder::new(OUR_MAC, vec![OUR_IP]);
+        let packet = Mbuf::from_bytes(&ARP4_PACKET).unwrap();
+        assert!(responder.respond(packet).is_err());
+    }
+
+    #[capsule::test]
+    fn hold_packets_until_resolved() {
+        let mut cache = ArpCache::new(OUR_MAC, OUR_IP, ArpCacheConfig::default());
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let postmark = cache.resolve(PEER_IP, packet).unwrap();
+        assert_eq!(1, postmark.emit.len());
+        let request = parse_arp(postmark.emit.into_iter().next().unwrap());
+        assert_eq!(OperationCodes::Request, request.operation_code());
+        assert_eq!(OUR_IP, request.sender_protocol_addr());
+        assert_eq!(PEER_IP, request.target_protocol_addr());
+        assert_eq!(MacAddr::BROADCAST, request.envelope().dst());
+
+        // the request is not repeated for the next packet
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let postmark = cache.resolve(PEER_IP, packet).unwrap();
+        assert!(postmark.emit.is_empty());
+        assert!(postmark.drop.is_none());
+        assert_eq!(None, cache.lookup(PEER_IP));
+
+        // the reply releases the held packets
+        let postmark = cache.receive(arp(OperationCodes::Reply, OUR_IP));
+        assert_eq!(2, postmark.emit.len());
+        assert!(postmark.drop.is_some());
+        for mbuf in postmark.emit {
+            let ethernet = mbuf.parse::<Ethernet>().unwrap();
+            assert_eq!(OUR_MAC, ethernet.src());
+            assert_eq!(PEER_MAC, ethernet.dst());
+        }
+        assert_eq!(Some(PEER_MAC), cache.lookup(PEER_IP));
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let postmark = cache.resolve(PEER_IP, packet).unwrap();
+        assert_eq!(1, postmark.emit.len());
+    }
+
+    #[capsule::test]
+    fn answer_request_and_learn_sender() {
+        let mut cache = ArpCache::new(OUR_MAC, OUR_IP, ArpCacheConfig::default());
+
+        let postmark = cache.receive(arp(OperationCodes::Request, OUR_IP));
+        assert!(postmark.drop.is_none());
+        let reply = parse_arp(postmark.emit.into_iter().next().unwrap());
+        assert_eq!(OperationCodes::Reply, reply.operation_code());
+        assert_eq!(OUR_MAC, reply.sender_hardware_addr());
+        assert_eq!(PEER_IP, reply.target_protocol_addr());
+        assert_eq!(Some(PEER_MAC), cache.lookup(PEER_IP));
+
+        // requests for other hosts are not learned from
+        let mut cache = ArpCache::new(OUR_MAC, OUR_IP, ArpCacheConfig::default());
+        let postmark = cache.receive(arp(OperationCodes::Request, Ipv4Addr::new(10, 0, 0, 3)));
+        assert!(postmark.emit.is_empty());
+        assert!(cache.is_empty());
+    }
+
+    #[capsule::test]
+    fn gratuitous_arp() {
+        let cache = ArpCache::new(OUR_MAC, OUR_IP, ArpCacheConfig::default());
+        let arp = parse_arp(cache.gratuitous().unwrap());
+        assert_eq!(OUR_IP, arp.sender_protocol_addr());
+        assert_eq!(OUR_IP, arp.target_protocol_addr());
+        assert_eq!(MacAddr::BROADCAST, arp.envelope().dst());
+    }
+
+    #[capsule::test]
+    fn give_up_unresolved_address() {
+        let config = ArpCacheConfig {
+            retrans_time: Duration::from_secs(0),
+            max_requests: 2,
+            ..Default::default()
+        };
+        let mut cache = ArpCache::new(OUR_MAC, OUR_IP, config);
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let _ = cache.resolve(PEER_IP, packet).unwrap();
+
+        assert_eq!(1, cache.poll().unwrap().len());
+        assert!(cache.poll().unwrap().is_empty());
+        assert!(cache.is_empty());
+    }
+
+    #[capsule::test]
+    fn limit_addresses() {
+        let config = ArpCacheConfig {
+            max_entries: 1,
+            ..Default::default()
+        };
+        let mut cache = ArpCache::new(OUR_MAC, OUR_IP, config);
+        let other = Ipv4Addr::new(10, 0, 0, 3);
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let _ = cache.resolve(PEER_IP, packet).unwrap();
+
+        // the cache is full of unresolved addresses
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert!(cache.resolve(other, packet).unwrap().drop.is_some());
+        assert_eq!(1, cache.len());
+
+        // a resolved address makes room for the new one
+        let _ = cache.receive(arp(OperationCodes::Reply, OUR_IP));
+        assert_eq!(Some(PEER_MAC), cache.lookup(PEER_IP));
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert_eq!(1, cache.resolve(other, packet).unwrap().emit.len());
+        assert_eq!(None, cache.lookup(PEER_IP));
+        assert_eq!(1, cache.len());
+    }
+}
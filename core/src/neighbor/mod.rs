@@ -0,0 +1,57 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Neighbor resolution, mapping the IP addresses of next hops on the link
+//! to their MAC addresses.
+//!
+//! An [`ArpCache`] resolves IPv4 next hops with [IETF RFC 826] ARP. Packets
+//! to an unresolved next hop are held until the neighbor replies, and
+//! resolved addresses age out so they are refreshed periodically. An
+//! [`ArpResponder`] answers the requests for the addresses of an interface.
+//!
+//! An [`NdpCache`] resolves IPv6 next hops with the neighbor discovery of
+//! [IETF RFC 4861], tracking the reachability of each neighbor, and
+//! verifies the interface addresses are unique on the link. An
+//! [`NdpResponder`] answers the neighbor solicitations for the addresses of
+//! an interface, and a [`RouterAdvertiser`] announces the interface as a
+//! router, along with the link's prefixes and MTU.
+//!
+//! Ports configured with addresses answer ARP requests and neighbor
+//! solicitations on their own.
+//!
+//! Like the flow table, the caches are not synchronized and should be
+//! owned by a single lcore.
+//!
+//! [`ArpCache`]: crate::neighbor::ArpCache
+//! [`ArpResponder`]: crate::neighbor::ArpResponder
+//! [`NdpCache`]: crate::neighbor::NdpCache
+//! [`NdpResponder`]: crate::neighbor::NdpResponder
+//! [`RouterAdvertiser`]: crate::neighbor::RouterAdvertiser
+//! [IETF RFC 826]: https://tools.ietf.org/html/rfc826
+//! [IETF RFC 4861]: https://tools.ietf.org/html/rfc4861
+
+mod arp;
+mod ndp;
+mod router;
+
+#[allow(unreachable_pub)] // https://github.com/rust-lang/rust/issues/57411
+pub use self::arp::{ArpCache, ArpCacheConfig, ArpResponder};
+#[allow(unreachable_pub)]
+pub use self::ndp::{AddressState, NdpCache, NdpCacheConfig, NdpResponder, NeighborState};
+#[allow(unreachable_pub)]
+pub use self::router::{AdvertisedPrefix, RouterAdvertConfig, RouterAdvertiser};
@@ -0,0 +1,1040 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::net::MacAddr;
+use crate::packets::ethernet::Ethernet;
+use crate::packets::icmp::v6::ndp::{
+    LinkLayerAddress, NdpOptionType, NdpOptionTypes, NdpPacket, NeighborAdvertisement,
+    NeighborSolicitation,
+};
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Types};
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::{Mbuf, Packet, Postmark};
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+/// Hop limit of NDP messages. Receivers discard messages with a lower hop
+/// limit, since they were forwarded by a router.
+pub(super) const NDP_HOP_LIMIT: u8 = 255;
+
+/// The all-nodes multicast address.
+pub(super) const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// Returns the solicited-node multicast address of an address.
+fn solicited_node(addr: Ipv6Addr) -> Ipv6Addr {
+    let octets = addr.octets();
+    Ipv6Addr::new(
+        0xff02,
+        0,
+        0,
+        0,
+        0,
+        1,
+        0xff00 | octets[13] as u16,
+        (octets[14] as u16) << 8 | octets[15] as u16,
+    )
+}
+
+/// Returns the MAC address a multicast address is mapped to, as defined
+/// in RFC 2464.
+pub(super) fn multicast_mac(addr: Ipv6Addr) -> MacAddr {
+    let octets = addr.octets();
+    MacAddr::new(0x33, 0x33, octets[12], octets[13], octets[14], octets[15])
+}
+
+/// Returns whether the address is a link-local unicast address.
+pub(super) fn is_link_local(addr: Ipv6Addr) -> bool {
+    addr.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// Returns the address of the first link-layer address option of the type.
+fn link_layer_addr<T: NdpPacket>(packet: &T, option_type: NdpOptionType) -> Option<MacAddr> {
+    let mut iter = packet.options_iter();
+    while let Ok(Some(mut option)) = iter.next() {
+        if option.option_type() == option_type {
+            return option
+                .downcast::<LinkLayerAddress<'_>>()
+                .ok()
+                .map(|option| option.addr());
+        }
+    }
+    None
+}
+
+/// Parses the packet as an NDP message, returning the packet unchanged as
+/// the error if it is not one.
+fn parse<T: Packet<Envelope = Ipv6>>(packet: Mbuf) -> std::result::Result<T, Mbuf> {
+    let ethernet = packet.parse::<Ethernet>().map_err(|(_, mbuf)| mbuf)?;
+    let ipv6 = ethernet
+        .parse::<Ipv6>()
+        .map_err(|(_, ethernet)| ethernet.reset())?;
+    ipv6.parse::<T>().map_err(|(_, ipv6)| ipv6.reset())
+}
+
+/// Returns whether the solicitation passes the validation of RFC 4861,
+/// section 7.1.1.
+fn is_valid(solicit: &NeighborSolicitation<Ipv6>) -> bool {
+    solicit.envelope().hop_limit() == NDP_HOP_LIMIT
+        && solicit.code() == 0
+        && !solicit.target().is_multicast()
+}
+
+/// Builds a neighbor solicitation for the target address.
+///
+/// The solicitation is sent to the target's solicited-node multicast
+/// address, or to the target itself when probing a known neighbor. A
+/// solicitation from the unspecified address is a DAD probe, and carries
+/// no source link-layer address.
+fn solicitation(
+    mac: MacAddr,
+    src: Ipv6Addr,
+    target: Ipv6Addr,
+    neighbor: Option<MacAddr>,
+) -> Result<Mbuf> {
+    let (dst, dst_mac) = match neighbor {
+        Some(dst_mac) => (target, dst_mac),
+        None => {
+            let dst = solicited_node(target);
+            (dst, multicast_mac(dst))
+        }
+    };
+
+    let mut ethernet = Mbuf::new()?.push::<Ethernet>()?;
+    ethernet.set_src(mac);
+    ethernet.set_dst(dst_mac);
+
+    let mut ipv6 = ethernet.push::<Ipv6>()?;
+    ipv6.set_src(src);
+    ipv6.set_dst(dst);
+    ipv6.set_hop_limit(NDP_HOP_LIMIT);
+
+    let mut solicit = ipv6.push::<NeighborSolicitation<Ipv6>>()?;
+    solicit.set_target(target);
+    if !src.is_unspecified() {
+        let mut options = solicit.options_mut();
+        let mut source = options.append::<LinkLayerAddress<'_>>()?;
+        source.set_option_type_source();
+        source.set_addr(mac);
+    }
+
+    solicit.reconcile_all();
+    Ok(solicit.reset())
+}
+
+/// Builds the advertisement answering a solicitation for one of the
+/// interface addresses, as in RFC 4861, section 7.2.4.
+fn advertisement(
+    mac: MacAddr,
+    solicit: &NeighborSolicitation<Ipv6>,
+) -> Result<NeighborAdvertisement<Ipv6>> {
+    let src = solicit.envelope().src();
+    let (dst, dst_mac) = if src.is_unspecified() {
+        // answers a DAD probe to all nodes.
+        (ALL_NODES, multicast_mac(ALL_NODES))
+    } else {
+        let dst_mac = link_layer_addr(solicit, NdpOptionTypes::SourceLinkLayerAddress)
+            .unwrap_or_else(|| solicit.envelope().envelope().src());
+        (src, dst_mac)
+    };
+
+    let mut ethernet = Mbuf::new()?.push::<Ethernet>()?;
+    ethernet.set_src(mac);
+    ethernet.set_dst(dst_mac);
+
+    let mut ipv6 = ethernet.push::<Ipv6>()?;
+    ipv6.set_src(solicit.target());
+    ipv6.set_dst(dst);
+    ipv6.set_hop_limit(NDP_HOP_LIMIT);
+
+    let mut advert = ipv6.push::<NeighborAdvertisement<Ipv6>>()?;
+    advert.set_target(solicit.target());
+    advert.set_override();
+    if !src.is_unspecified() {
+        advert.set_solicited();
+    }
+    {
+        let mut options = advert.options_mut();
+        let mut target = options.append::<LinkLayerAddress<'_>>()?;
+        target.set_option_type_target();
+        target.set_addr(mac);
+    }
+
+    advert.reconcile_all();
+    Ok(advert)
+}
+
+/// Answers neighbor solicitations for the addresses of an interface.
+#[derive(Clone, Debug)]
+pub struct NdpResponder {
+    mac: MacAddr,
+    addrs: Vec<Ipv6Addr>,
+}
+
+impl NdpResponder {
+    /// Creates a responder for the interface addresses.
+    pub fn new(mac: MacAddr, addrs: Vec<Ipv6Addr>) -> Self {
+        NdpResponder { mac, addrs }
+    }
+
+    /// Returns the MAC address of the interface.
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    /// Returns the IPv6 addresses of the interface.
+    pub fn addrs(&self) -> &[Ipv6Addr] {
+        &self.addrs
+    }
+
+    /// Answers the packet if it is a neighbor solicitation for one of the
+    /// interface addresses. The solicitation is freed and the advertisement
+    /// returned.
+    ///
+    /// Returns the packet unchanged as the error if it is not such a
+    /// solicitation, so it can continue through the pipeline.
+    pub fn respond(&self, packet: Mbuf) -> std::result::Result<NeighborAdvertisement<Ipv6>, Mbuf> {
+        let solicit = parse::<NeighborSolicitation<Ipv6>>(packet)?;
+        if is_valid(&solicit) && self.addrs.contains(&solicit.target()) {
+            advertisement(self.mac, &solicit).map_err(|_| solicit.reset())
+        } else {
+            Err(solicit.reset())
+        }
+    }
+}
+
+/// Settings of an NDP neighbor cache. The defaults are the protocol
+/// constants of RFC 4861, section 10.
+#[derive(Clone, Copy, Debug)]
+pub struct NdpCacheConfig {
+    /// How long a neighbor is reachable after a reachability confirmation.
+    pub reachable_time: Duration,
+    /// Interval between the solicitations for a neighbor.
+    pub retrans_timer: Duration,
+    /// How long a stale neighbor is used before it is probed.
+    pub delay_first_probe_time: Duration,
+    /// Number of multicast solicitations sent to resolve a neighbor.
+    pub max_multicast_solicit: u32,
+    /// Number of unicast solicitations sent to probe a neighbor.
+    pub max_unicast_solicit: u32,
+    /// Number of solicitations sent to detect a duplicate address. Zero
+    /// disables duplicate address detection.
+    pub dup_addr_detect_transmits: u32,
+    /// Maximum number of packets held for an unresolved neighbor.
+    pub max_pending: usize,
+    /// Maximum number of neighbors in the cache.
+    pub max_entries: usize,
+    /// How long a stale neighbor is kept without being used.
+    pub stale_timeout: Duration,
+}
+
+impl Default for NdpCacheConfig {
+    fn default() -> Self {
+        NdpCacheConfig {
+            reachable_time: Duration::from_secs(30),
+            retrans_timer: Duration::from_secs(1),
+            delay_first_probe_time: Duration::from_secs(5),
+            max_multicast_solicit: 3,
+            max_unicast_solicit: 3,
+            dup_addr_detect_transmits: 1,
+            max_pending: 16,
+            max_entries: 1024,
+            stale_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Reachability state of a neighbor, as defined in RFC 4861, section
+/// 7.3.2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeighborState {
+    /// Address resolution is in progress.
+    Incomplete,
+    /// The neighbor was recently reachable.
+    Reachable,
+    /// The neighbor is no longer known to be reachable.
+    Stale,
+    /// The neighbor is no longer known to be reachable, and a packet was
+    /// recently sent to it. Probing is delayed to give upper layers a
+    /// chance to confirm reachability.
+    Delay,
+    /// The neighbor is being probed.
+    Probe,
+}
+
+/// State of an interface address, as defined in RFC 4862.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressState {
+    /// Duplicate address detection is in progress.
+    Tentative,
+    /// The address is unique on the link and in use.
+    Preferred,
+    /// Another node on the link uses the address.
+    Duplicate,
+}
+
+/// A neighbor in the cache.
+#[derive(Debug)]
+struct Neighbor {
+    state: NeighborState,
+    mac: MacAddr,
+    pending: VecDeque<Mbuf>,
+    probes: u32,
+    updated: Instant,
+}
+
+impl Neighbor {
+    fn new(state: NeighborState, mac: MacAddr, now: Instant) -> Self {
+        Neighbor {
+            state,
+            mac,
+            pending: VecDeque::new(),
+            probes: 0,
+            updated: now,
+        }
+    }
+
+    fn set_state(&mut self, state: NeighborState, now: Instant) {
+        self.state = state;
+        self.probes = 0;
+        self.updated = now;
+    }
+
+    /// Returns the packets held for the neighbor, addressed.
+    fn release(&mut self, src: MacAddr) -> Vec<Mbuf> {
+        let dst = self.mac;
+        self.pending
+            .drain(..)
+            .filter_map(|mbuf| {
+                let mut ethernet = mbuf.parse::<Ethernet>().ok()?;
+                ethernet.set_src(src);
+                ethernet.set_dst(dst);
+                Some(ethernet.reset())
+            })
+            .collect()
+    }
+}
+
+/// An address of the interface.
+#[derive(Debug)]
+struct Address {
+    addr: Ipv6Addr,
+    state: AddressState,
+    probes: u32,
+    sent: Option<Instant>,
+}
+
+/// IPv6 neighbor cache of an interface, implementing the neighbor
+/// unreachability detection and the duplicate address detection of
+/// [IETF RFC 4861] and [IETF RFC 4862].
+///
+/// Packets are sent to a next hop with [`resolve`], which addresses the
+/// packet if the next hop's MAC address is known, or holds it and
+/// solicits the next hop otherwise. The neighbor solicitations and
+/// advertisements received on the interface are handed to [`receive`],
+/// which answers the solicitations for the interface addresses and updates
+/// the neighbors. [`poll`] must be called periodically to drive the timers
+/// of the neighbors and of the addresses being verified.
+///
+/// [IETF RFC 4861]: https://tools.ietf.org/html/rfc4861
+/// [IETF RFC 4862]: https://tools.ietf.org/html/rfc4862
+/// [`resolve`]: NdpCache::resolve
+/// [`receive`]: NdpCache::receive
+/// [`poll`]: NdpCache::poll
+#[derive(Debug)]
+pub struct NdpCache {
+    mac: MacAddr,
+    config: NdpCacheConfig,
+    addrs: Vec<Address>,
+    entries: HashMap<Ipv6Addr, Neighbor>,
+}
+
+impl NdpCache {
+    /// Creates an empty cache for the interface.
+    pub fn new(mac: MacAddr, config: NdpCacheConfig) -> Self {
+        NdpCache {
+            mac,
+            config,
+            addrs: vec![],
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Adds an address to the interface.
+    ///
+    /// The address is tentative until [`poll`] has verified that no other
+    /// node on the link uses it, unless duplicate address detection is
+    /// disabled.
+    ///
+    /// [`poll`]: NdpCache::poll
+    pub fn add_address(&mut self, addr: Ipv6Addr) {
+        if self.addrs.iter().all(|address| address.addr != addr) {
+            let state = if self.config.dup_addr_detect_transmits == 0 {
+                AddressState::Preferred
+            } else {
+                AddressState::Tentative
+            };
+            self.addrs.push(Address {
+                addr,
+                state,
+                probes: 0,
+                sent: None,
+            });
+        }
+    }
+
+    /// Returns the state of an interface address.
+    pub fn address_state(&self, addr: Ipv6Addr) -> Option<AddressState> {
+        self.addrs
+            .iter()
+            .find(|address| address.addr == addr)
+            .map(|address| address.state)
+    }
+
+    /// Returns the source address of the solicitations, preferring a
+    /// link-local address.
+    fn source(&self) -> Option<Ipv6Addr> {
+        let mut preferred = self
+            .addrs
+            .iter()
+            .filter(|address| address.state == AddressState::Preferred)
+            .map(|address| address.addr);
+        let first = preferred.next()?;
+        if is_link_local(first) {
+            Some(first)
+        } else {
+            Some(preferred.find(|&addr| is_link_local(addr)).unwrap_or(first))
+        }
+    }
+
+    /// Returns the number of neighbors in the cache.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the state of a neighbor.
+    pub fn state(&self, addr: Ipv6Addr) -> Option<NeighborState> {
+        self.entries.get(&addr).map(|neighbor| neighbor.state)
+    }
+
+    /// Returns the MAC address of a neighbor, if it is resolved.
+    pub fn lookup(&self, addr: Ipv6Addr) -> Option<MacAddr> {
+        match self.entries.get(&addr) {
+            Some(neighbor) if neighbor.state != NeighborState::Incomplete => Some(neighbor.mac),
+            _ => None,
+        }
+    }
+
+    /// Confirms that a neighbor is reachable, for instance because a TCP
+    /// peer acknowledged new data.
+    pub fn confirm(&mut self, addr: Ipv6Addr) {
+        if let Some(neighbor) = self.entries.get_mut(&addr) {
+            if neighbor.state != NeighborState::Incomplete {
+                neighbor.set_state(NeighborState::Reachable, Instant::now());
+            }
+        }
+    }
+
+    /// Sends the packet to the next hop.
+    ///
+    /// If the next hop's MAC address is known, the packet is addressed and
+    /// emitted. Otherwise the packet is held, and a solicitation is emitted
+    /// if the next hop was not already being resolved. The packet is
+    /// dropped if too many packets are held for the next hop, or if the
+    /// cache is full and no neighbor is stale.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the interface has no preferred address to
+    /// solicit from, or the solicitation cannot be allocated.
+    pub fn resolve(&mut self, next_hop: Ipv6Addr, mut packet: Ethernet) -> Result<Postmark> {
+        let now = Instant::now();
+        let config = self.config;
+
+        if let Some(neighbor) = self.entries.get_mut(&next_hop) {
+            match neighbor.state {
+                NeighborState::Incomplete => {
+                    return if neighbor.pending.len() < config.max_pending {
+                        neighbor.pending.push_back(packet.reset());
+                        Ok(Postmark::emit_multi(vec![]))
+                    } else {
+                        Ok(Postmark::drop(packet))
+                    };
+                }
+                NeighborState::Reachable
+                    if now.duration_since(neighbor.updated) < config.reachable_time => {}
+                NeighborState::Reachable | NeighborState::Stale => {
+                    neighbor.set_state(NeighborState::Delay, now)
+                }
+                NeighborState::Delay | NeighborState::Probe => (),
+            }
+
+            packet.set_src(self.mac);
+            packet.set_dst(neighbor.mac);
+            return Ok(Postmark::emit(packet));
+        }
+
+        if !self.make_room() {
+            return Ok(Postmark::drop(packet));
+        }
+
+        let src = self
+            .source()
+            .ok_or_else(|| anyhow!("no IPv6 address to solicit from."))?;
+        let solicit = solicitation(self.mac, src, next_hop, None)?;
+
+        let mut neighbor = Neighbor::new(NeighborState::Incomplete, MacAddr::UNSPECIFIED, now);
+        neighbor.probes = 1;
+        neighbor.pending.push_back(packet.reset());
+        self.entries.insert(next_hop, neighbor);
+        Ok(Postmark::emit_multi(vec![solicit]))
+    }
+
+    /// Processes an ICMPv6 packet received on the interface.
+    ///
+    /// Neighbor solicitations for the interface addresses are answered,
+    /// and the sender is learned. Neighbor advertisements update the state
+    /// of the neighbor, and emit the packets held for it once it is
+    /// resolved. Solicitations and advertisements for a tentative address
+    /// mark it as a duplicate. All other packets are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the advertisement cannot be allocated.
+    pub fn receive(&mut self, icmp: Icmpv6<Ipv6>) -> Result<Postmark> {
+        if icmp.envelope().hop_limit() != NDP_HOP_LIMIT || icmp.code() != 0 {
+            return Ok(Postmark::drop(icmp));
+        }
+
+        match icmp.msg_type() {
+            Icmpv6Types::NeighborSolicitation => {
+                let solicit = icmp
+                    .downcast::<NeighborSolicitation<Ipv6>>()
+                    .map_err(|(e, _)| e)?;
+                self.receive_solicitation(solicit)
+            }
+            Icmpv6Types::NeighborAdvertisement => {
+                let advert = icmp
+                    .downcast::<NeighborAdvertisement<Ipv6>>()
+                    .map_err(|(e, _)| e)?;
+                Ok(self.receive_advertisement(advert))
+            }
+            _ => Ok(Postmark::drop(icmp)),
+        }
+    }
+
+    /// Processes a neighbor solicitation, as in RFC 4861, section 7.2.3.
+    fn receive_solicitation(&mut self, solicit: NeighborSolicitation<Ipv6>) -> Result<Postmark> {
+        let src = solicit.envelope().src();
+        let address = match self
+            .addrs
+            .iter_mut()
+            .find(|address| address.addr == solicit.target())
+        {
+            Some(address) if !solicit.target().is_multicast() => address,
+            _ => return Ok(Postmark::drop(solicit)),
+        };
+
+        match address.state {
+            AddressState::Tentative => {
+                // another node is verifying the same address.
+                if src.is_unspecified() {
+                    address.state = AddressState::Duplicate;
+                }
+                return Ok(Postmark::drop(solicit));
+            }
+            AddressState::Duplicate => return Ok(Postmark::drop(solicit)),
+            AddressState::Preferred => (),
+        }
+
+        let mut emit = vec![];
+        if !src.is_unspecified() {
+            if let Some(mac) = link_layer_addr(&solicit, NdpOptionTypes::SourceLinkLayerAddress) {
+                emit = self.learn(src, mac);
+            }
+        }
+
+        let advert = advertisement(self.mac, &solicit)?;
+        emit.push(advert.reset());
+        Ok(Postmark::emit_multi_and_drop(emit, solicit.reset()))
+    }
+
+    /// Records the MAC address of a neighbor that sent a solicitation,
+    /// returning the packets that were held for it.
+    fn learn(&mut self, addr: Ipv6Addr, mac: MacAddr) -> Vec<Mbuf> {
+        let now = Instant::now();
+        match self.entries.get_mut(&addr) {
+            Some(neighbor) => {
+                if neighbor.state == NeighborState::Incomplete || neighbor.mac != mac {
+                    neighbor.mac = mac;
+                    neighbor.set_state(NeighborState::Stale, now);
+                    neighbor.release(self.mac)
+                } else {
+                    vec![]
+                }
+            }
+            None => {
+                if self.make_room() {
+                    self.entries
+                        .insert(addr, Neighbor::new(NeighborState::Stale, mac, now));
+                }
+                vec![]
+            }
+        }
+    }
+
+    /// Makes room for a new neighbor when the cache is full, by removing
+    /// the stale neighbor that was used the longest time ago.
+    ///
+    /// Returns `false` if the cache is full and no neighbor is stale.
+    fn make_room(&mut self) -> bool {
+        if self.entries.len() < self.config.max_entries {
+            return true;
+        }
+
+        let oldest = self
+            .entries
+            .iter()
+            .filter(|(_, neighbor)| neighbor.state == NeighborState::Stale)
+            .min_by_key(|(_, neighbor)| neighbor.updated)
+            .map(|(&addr, _)| addr);
+
+        match oldest {
+            Some(addr) => {
+                self.entries.remove(&addr);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Processes a neighbor advertisement, as in RFC 4861, section 7.2.5.
+    fn receive_advertisement(&mut self, advert: NeighborAdvertisement<Ipv6>) -> Postmark {
+        let target = advert.target();
+        if let Some(address) = self.addrs.iter_mut().find(|address| address.addr == target) {
+            if address.state == AddressState::Tentative {
+                address.state = AddressState::Duplicate;
+            }
+            return Postmark::drop(advert);
+        }
+
+        let now = Instant::now();
+        let mac = link_layer_addr(&advert, NdpOptionTypes::TargetLinkLayerAddress);
+        let neighbor = match self.entries.get_mut(&target) {
+            Some(neighbor) => neighbor,
+            None => return Postmark::drop(advert),
+        };
+
+        let mut emit = vec![];
+        if neighbor.state == NeighborState::Incomplete {
+            if let Some(mac) = mac {
+                neighbor.mac = mac;
+                if advert.solicited() {
+                    neighbor.set_state(NeighborState::Reachable, now);
+                } else {
+                    neighbor.set_state(NeighborState::Stale, now);
+                }
+                emit = neighbor.release(self.mac);
+            }
+        } else {
+            let changed = match mac {
+                Some(mac) => mac != neighbor.mac,
+                None => false,
+            };
+
+            if changed && !advert.r#override() {
+                if neighbor.state == NeighborState::Reachable {
+                    neighbor.set_state(NeighborState::Stale, now);
+                }
+            } else {
+                if let Some(mac) = mac {
+                    neighbor.mac = mac;
+                }
+                if advert.solicited() {
+                    neighbor.set_state(NeighborState::Reachable, now);
+                } else if changed {
+                    neighbor.set_state(NeighborState::Stale, now);
+                }
+            }
+        }
+
+        Postmark::emit_multi_and_drop(emit, advert.reset())
+    }
+
+    /// Drives the timers of the cache.
+    ///
+    /// Sends the duplicate address detection probes of the tentative
+    /// addresses, and marks them preferred once no other node claimed them.
+    /// Retransmits the solicitations of unresolved neighbors and probes the
+    /// neighbors in the delay state, removing those that did not answer.
+    /// The packets held for those are dropped. Reachable neighbors that
+    /// were not confirmed in time become stale, and stale neighbors that
+    /// were not used in time are removed.
+    ///
+    /// Returns the solicitations to emit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a solicitation cannot be allocated.
+    pub fn poll(&mut self) -> Result<Vec<Mbuf>> {
+        let now = Instant::now();
+        let config = self.config;
+
+        let mut tentative = vec![];
+        for address in self
+            .addrs
+            .iter_mut()
+            .filter(|address| address.state == AddressState::Tentative)
+        {
+            match address.sent {
+                Some(sent) if now.duration_since(sent) < config.retrans_timer => (),
+                _ if address.probes < config.dup_addr_detect_transmits => {
+                    address.probes += 1;
+                    address.sent = Some(now);
+                    tentative.push(address.addr);
+                }
+                _ => address.state = AddressState::Preferred,
+            }
+        }
+
+        let mut probes = vec![];
+        self.entries.retain(|&addr, neighbor| {
+            let elapsed = now.duration_since(neighbor.updated);
+            match neighbor.state {
+                NeighborState::Incomplete | NeighborState::Probe => {
+                    let (max, target) = if neighbor.state == NeighborState::Incomplete {
+                        (config.max_multicast_solicit, None)
+                    } else {
+                        (config.max_unicast_solicit, Some(neighbor.mac))
+                    };
+
+                    if elapsed < config.retrans_timer {
+                        true
+                    } else if neighbor.probes < max {
+                        neighbor.probes += 1;
+                        neighbor.updated = now;
+                        probes.push((addr, target));
+                        true
+                    } else {
+                        false
+                    }
+                }
+                NeighborState::Reachable => {
+                    if elapsed >= config.reachable_time {
+                        neighbor.set_state(NeighborState::Stale, now);
+                    }
+                    true
+                }
+                NeighborState::Delay => {
+                    if elapsed >= config.delay_first_probe_time {
+                        neighbor.set_state(NeighborState::Probe, now);
+                        neighbor.probes = 1;
+                        probes.push((addr, Some(neighbor.mac)));
+                    }
+                    true
+                }
+                NeighborState::Stale => elapsed < config.stale_timeout,
+            }
+        });
+
+        let mut packets = tentative
+            .into_iter()
+            .map(|addr| solicitation(self.mac, Ipv6Addr::UNSPECIFIED, addr, None))
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(src) = self.source() {
+            for (addr, neighbor) in probes {
+                packets.push(solicitation(self.mac, src, addr, neighbor)?);
+            }
+        }
+
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v6::ndp::NdpOptionTypes;
+    use crate::packets::icmp::v6::Icmpv6Message;
+
+    const OUR_MAC: MacAddr = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+    const OUR_IP: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    const PEER_MAC: MacAddr = MacAddr::new(0x02, 0, 0, 0, 0, 0x02);
+    const PEER_IP: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+
+    fn icmp(mbuf: Mbuf) -> Icmpv6<Ipv6> {
+        mbuf.parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv6>()
+            .unwrap()
+            .parse::<Icmpv6<Ipv6>>()
+            .unwrap()
+    }
+
+    fn advert(target: Ipv6Addr, solicited: bool, r#override: bool) -> Icmpv6<Ipv6> {
+        let mut ipv6 = Mbuf::new()
+            .unwrap()
+            .push::<Ethernet>()
+            .unwrap()
+            .push::<Ipv6>()
+            .unwrap();
+        ipv6.set_src(target);
+        ipv6.set_dst(OUR_IP);
+        ipv6.set_hop_limit(NDP_HOP_LIMIT);
+
+        let mut advert = ipv6.push::<NeighborAdvertisement<Ipv6>>().unwrap();
+        advert.set_target(target);
+        if solicited {
+            advert.set_solicited();
+        }
+        if r#override {
+            advert.set_override();
+        }
+        {
+            let mut options = advert.options_mut();
+            let mut option = options.append::<LinkLayerAddress<'_>>().unwrap();
+            option.set_option_type_target();
+            option.set_addr(PEER_MAC);
+        }
+        advert.reconcile_all();
+        advert.into_icmp()
+    }
+
+    fn cache(config: NdpCacheConfig) -> NdpCache {
+        let mut cache = NdpCache::new(OUR_MAC, config);
+        cache.add_address(OUR_IP);
+        cache
+    }
+
+    fn no_dad() -> NdpCacheConfig {
+        NdpCacheConfig {
+            dup_addr_detect_transmits: 0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn multicast_addresses() {
+        let addr = "2001:db8::4:5678:9abc".parse().unwrap();
+        assert_eq!(
+            "ff02::1:ff78:9abc".parse::<Ipv6Addr>().unwrap(),
+            solicited_node(addr)
+        );
+        assert_eq!(
+            MacAddr::new(0x33, 0x33, 0xff, 0x78, 0x9a, 0xbc),
+            multicast_mac(solicited_node(addr))
+        );
+    }
+
+    #[capsule::test]
+    fn respond_to_solicitation() {
+        let responder = NdpResponder::new(OUR_MAC, vec![OUR_IP]);
+
+        let solicit = solicitation(PEER_MAC, PEER_IP, OUR_IP, None).unwrap();
+        let advert = responder.respond(solicit).unwrap();
+        assert_eq!(OUR_IP, advert.target());
+        assert!(advert.solicited());
+        assert!(advert.r#override());
+        assert_eq!(OUR_IP, advert.envelope().src());
+        assert_eq!(PEER_IP, advert.envelope().dst());
+        assert_eq!(PEER_MAC, advert.envelope().envelope().dst());
+        assert_eq!(
+            Some(OUR_MAC),
+            link_layer_addr(&advert, NdpOptionTypes::TargetLinkLayerAddress)
+        );
+
+        // solicitations for other addresses are passed through
+        let solicit = solicitation(PEER_MAC, PEER_IP, PEER_IP, None).unwrap();
+        assert!(responder.respond(solicit).is_err());
+    }
+
+    #[capsule::test]
+    fn resolve_neighbor() {
+        let mut cache = cache(no_dad());
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let postmark = cache.resolve(PEER_IP, packet).unwrap();
+        assert_eq!(Some(NeighborState::Incomplete), cache.state(PEER_IP));
+        let solicit = icmp(postmark.emit.into_iter().next().unwrap())
+            .downcast::<NeighborSolicitation<Ipv6>>()
+            .unwrap();
+        assert_eq!(PEER_IP, solicit.target());
+        assert_eq!(solicited_node(PEER_IP), solicit.envelope().dst());
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert!(cache.resolve(PEER_IP, packet).unwrap().emit.is_empty());
+
+        // the advertisement releases the held packets
+        let postmark = cache.receive(advert(PEER_IP, true, true)).unwrap();
+        assert_eq!(2, postmark.emit.len());
+        for mbuf in postmark.emit {
+            assert_eq!(PEER_MAC, mbuf.parse::<Ethernet>().unwrap().dst());
+        }
+        assert_eq!(Some(NeighborState::Reachable), cache.state(PEER_IP));
+        assert_eq!(Some(PEER_MAC), cache.lookup(PEER_IP));
+    }
+
+    #[capsule::test]
+    fn probe_stale_neighbor() {
+        let config = NdpCacheConfig {
+            reachable_time: Duration::from_secs(0),
+            retrans_timer: Duration::from_secs(0),
+            delay_first_probe_time: Duration::from_secs(0),
+            max_unicast_solicit: 1,
+            ..no_dad()
+        };
+        let mut cache = cache(config);
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let _ = cache.resolve(PEER_IP, packet).unwrap();
+        let _ = cache.receive(advert(PEER_IP, true, true)).unwrap();
+
+        // the reachable time expires
+        assert!(cache.poll().unwrap().is_empty());
+        assert_eq!(Some(NeighborState::Stale), cache.state(PEER_IP));
+
+        // sending a packet delays the probe
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert_eq!(1, cache.resolve(PEER_IP, packet).unwrap().emit.len());
+        assert_eq!(Some(NeighborState::Delay), cache.state(PEER_IP));
+
+        let probes = cache.poll().unwrap();
+        assert_eq!(Some(NeighborState::Probe), cache.state(PEER_IP));
+        let solicit = icmp(probes.into_iter().next().unwrap())
+            .downcast::<NeighborSolicitation<Ipv6>>()
+            .unwrap();
+        assert_eq!(PEER_IP, solicit.envelope().dst());
+        assert_eq!(PEER_MAC, solicit.envelope().envelope().dst());
+
+        // the neighbor didn't answer
+        assert!(cache.poll().unwrap().is_empty());
+        assert!(cache.is_empty());
+    }
+
+    #[capsule::test]
+    fn remove_unused_stale_neighbor() {
+        let config = NdpCacheConfig {
+            stale_timeout: Duration::from_secs(0),
+            ..no_dad()
+        };
+        let mut cache = cache(config);
+
+        let solicit = icmp(solicitation(PEER_MAC, PEER_IP, OUR_IP, None).unwrap());
+        let _ = cache.receive(solicit).unwrap();
+        assert_eq!(Some(NeighborState::Stale), cache.state(PEER_IP));
+
+        assert!(cache.poll().unwrap().is_empty());
+        assert!(cache.is_empty());
+    }
+
+    #[capsule::test]
+    fn limit_neighbors() {
+        let config = NdpCacheConfig {
+            max_entries: 1,
+            ..no_dad()
+        };
+        let mut cache = cache(config);
+        let other = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 3);
+
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let _ = cache.resolve(PEER_IP, packet).unwrap();
+
+        // the cache is full of neighbors in use
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert!(cache.resolve(other, packet).unwrap().drop.is_some());
+        assert_eq!(None, cache.state(other));
+
+        // a stale neighbor makes room for the new one
+        let _ = cache.receive(advert(PEER_IP, false, false)).unwrap();
+        assert_eq!(Some(NeighborState::Stale), cache.state(PEER_IP));
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert_eq!(1, cache.resolve(other, packet).unwrap().emit.len());
+        assert_eq!(Some(NeighborState::Incomplete), cache.state(other));
+        assert_eq!(None, cache.state(PEER_IP));
+    }
+
+    #[capsule::test]
+    fn unsolicited_advertisement() {
+        let mut cache = cache(no_dad());
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        let _ = cache.resolve(PEER_IP, packet).unwrap();
+
+        let _ = cache.receive(advert(PEER_IP, false, false)).unwrap();
+        assert_eq!(Some(NeighborState::Stale), cache.state(PEER_IP));
+
+        // advertisements for unknown neighbors are ignored
+        let other = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 3);
+        let _ = cache.receive(advert(other, true, true)).unwrap();
+        assert_eq!(None, cache.state(other));
+    }
+
+    #[capsule::test]
+    fn answer_solicitation_and_learn_sender() {
+        let mut cache = cache(no_dad());
+
+        let solicit = icmp(solicitation(PEER_MAC, PEER_IP, OUR_IP, None).unwrap());
+        let postmark = cache.receive(solicit).unwrap();
+        assert!(postmark.drop.is_some());
+        let advert = icmp(postmark.emit.into_iter().next().unwrap())
+            .downcast::<NeighborAdvertisement<Ipv6>>()
+            .unwrap();
+        assert_eq!(OUR_IP, advert.target());
+        assert_eq!(Some(NeighborState::Stale), cache.state(PEER_IP));
+        assert_eq!(Some(PEER_MAC), cache.lookup(PEER_IP));
+    }
+
+    #[capsule::test]
+    fn detect_duplicate_address() {
+        let config = NdpCacheConfig {
+            retrans_timer: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let mut cache = cache(config);
+        assert_eq!(Some(AddressState::Tentative), cache.address_state(OUR_IP));
+
+        // tentative addresses can't be used to solicit
+        let packet = Mbuf::new().unwrap().push::<Ethernet>().unwrap();
+        assert!(cache.resolve(PEER_IP, packet).is_err());
+
+        let probes = cache.poll().unwrap();
+        let solicit = icmp(probes.into_iter().next().unwrap())
+            .downcast::<NeighborSolicitation<Ipv6>>()
+            .unwrap();
+        assert_eq!(OUR_IP, solicit.target());
+        assert!(solicit.envelope().src().is_unspecified());
+
+        let _ = cache.receive(advert(OUR_IP, false, true)).unwrap();
+        assert_eq!(Some(AddressState::Duplicate), cache.address_state(OUR_IP));
+
+        // no answer to the probe
+        let addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 4);
+        cache.add_address(addr);
+        assert_eq!(1, cache.poll().unwrap().len());
+        assert!(cache.poll().unwrap().is_empty());
+        assert_eq!(Some(AddressState::Preferred), cache.address_state(addr));
+    }
+}
@@ -0,0 +1,306 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::ndp::{is_link_local, multicast_mac, ALL_NODES, NDP_HOP_LIMIT};
+use crate::ensure;
+use crate::net::{Cidr, Ipv6Cidr, MacAddr};
+use crate::packets::ethernet::Ethernet;
+use crate::packets::icmp::v6::ndp::{
+    LinkLayerAddress, Mtu, NdpPacket, PrefixInformation, RouterAdvertisement, RouterSolicitation,
+};
+use crate::packets::icmp::v6::Icmpv6Packet;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::{Mbuf, Packet, Postmark};
+use anyhow::{anyhow, Result};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+/// Minimum interval between advertisements sent to all nodes, as defined
+/// in RFC 4861, section 10.
+const MIN_DELAY_BETWEEN_RAS: Duration = Duration::from_secs(3);
+
+/// A prefix announced in router advertisements.
+#[derive(Clone, Copy, Debug)]
+pub struct AdvertisedPrefix {
+    /// The prefix.
+    pub cidr: Ipv6Cidr,
+    /// Whether the addresses of the prefix are on the link.
+    pub on_link: bool,
+    /// Whether hosts can configure addresses from the prefix on their own.
+    pub autonomous: bool,
+    /// How long the prefix is valid, in seconds.
+    pub valid_lifetime: u32,
+    /// How long the addresses configured from the prefix are preferred, in
+    /// seconds.
+    pub preferred_lifetime: u32,
+}
+
+impl AdvertisedPrefix {
+    /// Creates an on-link prefix for autonomous address configuration,
+    /// with the default lifetimes of RFC 4861.
+    pub fn new(cidr: Ipv6Cidr) -> Self {
+        AdvertisedPrefix {
+            cidr,
+            on_link: true,
+            autonomous: true,
+            valid_lifetime: 2_592_000,
+            preferred_lifetime: 604_800,
+        }
+    }
+}
+
+/// Settings of a router advertiser. The defaults follow RFC 4861,
+/// section 6.2.1.
+#[derive(Clone, Debug)]
+pub struct RouterAdvertConfig {
+    /// Interval between unsolicited advertisements.
+    pub interval: Duration,
+    /// Hop limit hosts should use.
+    pub current_hop_limit: u8,
+    /// Whether addresses are available through DHCPv6.
+    pub managed_addr_cfg: bool,
+    /// Whether other configuration is available through DHCPv6.
+    pub other_cfg: bool,
+    /// How long the router can be used as a default router, in seconds.
+    /// Zero means the router is not a default router.
+    pub router_lifetime: u16,
+    /// Reachable time hosts should use, in milliseconds. Zero means
+    /// unspecified.
+    pub reachable_time: u32,
+    /// Retransmission timer hosts should use, in milliseconds. Zero means
+    /// unspecified.
+    pub retrans_timer: u32,
+    /// MTU of the link, advertised with the MTU option.
+    pub mtu: Option<u32>,
+    /// Prefixes advertised with prefix information options.
+    pub prefixes: Vec<AdvertisedPrefix>,
+}
+
+impl Default for RouterAdvertConfig {
+    fn default() -> Self {
+        RouterAdvertConfig {
+            interval: Duration::from_secs(200),
+            current_hop_limit: 64,
+            managed_addr_cfg: false,
+            other_cfg: false,
+            router_lifetime: 1800,
+            reachable_time: 0,
+            retrans_timer: 0,
+            mtu: None,
+            prefixes: vec![],
+        }
+    }
+}
+
+/// Sends the router advertisements of an interface, periodically and in
+/// response to router solicitations.
+#[derive(Debug)]
+pub struct RouterAdvertiser {
+    mac: MacAddr,
+    src: Ipv6Addr,
+    config: RouterAdvertConfig,
+    sent: Option<Instant>,
+}
+
+impl RouterAdvertiser {
+    /// Creates an advertiser for the interface.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `src` is not a link-local address.
+    pub fn new(mac: MacAddr, src: Ipv6Addr, config: RouterAdvertConfig) -> Result<Self> {
+        ensure!(
+            is_link_local(src),
+            anyhow!("router advertisements must be sent from a link-local address.")
+        );
+
+        Ok(RouterAdvertiser {
+            mac,
+            src,
+            config,
+            sent: None,
+        })
+    }
+
+    /// Returns the settings of the advertiser.
+    pub fn config(&self) -> &RouterAdvertConfig {
+        &self.config
+    }
+
+    /// Builds an advertisement to all nodes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the advertisement cannot be allocated.
+    pub fn advertise(&mut self) -> Result<Mbuf> {
+        let mut ethernet = Mbuf::new()?.push::<Ethernet>()?;
+        ethernet.set_src(self.mac);
+        ethernet.set_dst(multicast_mac(ALL_NODES));
+
+        let mut ipv6 = ethernet.push::<Ipv6>()?;
+        ipv6.set_src(self.src);
+        ipv6.set_dst(ALL_NODES);
+        ipv6.set_hop_limit(NDP_HOP_LIMIT);
+
+        let config = &self.config;
+        let mut advert = ipv6.push::<RouterAdvertisement<Ipv6>>()?;
+        advert.set_current_hop_limit(config.current_hop_limit);
+        if config.managed_addr_cfg {
+            advert.set_managed_addr_cfg();
+        }
+        if config.other_cfg {
+            advert.set_other_cfg();
+        }
+        advert.set_router_lifetime(config.router_lifetime);
+        advert.set_reachable_time(config.reachable_time);
+        advert.set_retrans_timer(config.retrans_timer);
+
+        let mut options = advert.options_mut();
+        let mut source = options.append::<LinkLayerAddress<'_>>()?;
+        source.set_option_type_source();
+        source.set_addr(self.mac);
+
+        if let Some(mtu) = config.mtu {
+            let mut options = advert.options_mut();
+            let mut option = options.append::<Mtu<'_>>()?;
+            option.set_mtu(mtu);
+        }
+
+        for prefix in config.prefixes.iter() {
+            let mut options = advert.options_mut();
+            let mut option = options.append::<PrefixInformation<'_>>()?;
+            option.set_prefix_length(prefix.cidr.length() as u8);
+            option.set_prefix(prefix.cidr.network());
+            if prefix.on_link {
+                option.set_on_link();
+            }
+            if prefix.autonomous {
+                option.set_autonomous();
+            }
+            option.set_valid_lifetime(prefix.valid_lifetime);
+            option.set_preferred_lifetime(prefix.preferred_lifetime);
+        }
+
+        advert.reconcile_all();
+        self.sent = Some(Instant::now());
+        Ok(advert.reset())
+    }
+
+    /// Returns the unsolicited advertisement to emit, if one is due.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the advertisement cannot be allocated.
+    pub fn poll(&mut self) -> Result<Option<Mbuf>> {
+        match self.sent {
+            Some(sent) if sent.elapsed() < self.config.interval => Ok(None),
+            _ => self.advertise().map(Some),
+        }
+    }
+
+    /// Answers a router solicitation with an advertisement to all nodes.
+    ///
+    /// Advertisements are not sent more often than every 3 seconds, the
+    /// solicitations received in between are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the advertisement cannot be allocated.
+    pub fn receive(&mut self, solicit: RouterSolicitation<Ipv6>) -> Result<Postmark> {
+        let valid = solicit.envelope().hop_limit() == NDP_HOP_LIMIT && solicit.code() == 0;
+        let due = match self.sent {
+            Some(sent) => sent.elapsed() >= MIN_DELAY_BETWEEN_RAS,
+            None => true,
+        };
+
+        if valid && due {
+            let advert = self.advertise()?;
+            Ok(Postmark::emit_multi_and_drop(vec![advert], solicit.reset()))
+        } else {
+            Ok(Postmark::drop(solicit))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v6::ndp::NdpOptionTypes;
+    use std::str::FromStr;
+
+    const MAC: MacAddr = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+    const SRC: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+
+    #[test]
+    fn require_link_local_source() {
+        let src = Ipv6Addr::from_str("2001:db8::1").unwrap();
+        assert!(RouterAdvertiser::new(MAC, src, Default::default()).is_err());
+        assert!(RouterAdvertiser::new(MAC, SRC, Default::default()).is_ok());
+    }
+
+    #[capsule::test]
+    fn advertise_prefixes_and_mtu() {
+        let config = RouterAdvertConfig {
+            mtu: Some(1500),
+            prefixes: vec![AdvertisedPrefix::new(
+                Ipv6Cidr::from_str("2001:db8:1::/64").unwrap(),
+            )],
+            ..Default::default()
+        };
+        let mut advertiser = RouterAdvertiser::new(MAC, SRC, config).unwrap();
+
+        let mbuf = advertiser.advertise().unwrap();
+        let advert = mbuf
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv6>()
+            .unwrap()
+            .parse::<RouterAdvertisement<Ipv6>>()
+            .unwrap();
+        assert_eq!(ALL_NODES, advert.envelope().dst());
+        assert_eq!(NDP_HOP_LIMIT, advert.envelope().hop_limit());
+        assert_eq!(1800, advert.router_lifetime());
+
+        let mut types = vec![];
+        let mut iter = advert.options_iter();
+        while let Some(mut option) = iter.next().unwrap() {
+            types.push(option.option_type());
+            if let Ok(prefix) = option.downcast::<PrefixInformation<'_>>() {
+                assert_eq!(64, prefix.prefix_length());
+                assert_eq!(Ipv6Addr::from_str("2001:db8:1::").unwrap(), prefix.prefix());
+                assert!(prefix.on_link());
+                assert!(prefix.autonomous());
+            }
+        }
+        assert_eq!(
+            vec![
+                NdpOptionTypes::SourceLinkLayerAddress,
+                NdpOptionTypes::Mtu,
+                NdpOptionTypes::PrefixInformation
+            ],
+            types
+        );
+    }
+
+    #[capsule::test]
+    fn send_periodically() {
+        let mut advertiser = RouterAdvertiser::new(MAC, SRC, Default::default()).unwrap();
+        assert!(advertiser.poll().unwrap().is_some());
+        assert!(advertiser.poll().unwrap().is_none());
+    }
+}
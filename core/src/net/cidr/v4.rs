@@ -131,7 +131,7 @@ impl Cidr for Ipv4Cidr {
     #[inline]
     fn new(address: Self::Addr, length: usize) -> Result<Self, CidrError> {
         let mask = match length {
-            0 => u32::max_value(),
+            0 => 0,
             1..=IPV4ADDR_BITS => u32::max_value() << (IPV4ADDR_BITS - length),
             _ => return Err(CidrError::Malformed("Not a valid length".to_owned())),
         };
@@ -226,6 +226,14 @@ mod tests {
         assert_eq!(cidr.netmask(), Ipv4Addr::from_str("255.255.240.0").unwrap());
     }
 
+    #[test]
+    fn zero_length_cidr() {
+        let cidr = Ipv4Cidr::new(Ipv4Addr::from_str("10.1.0.10").unwrap(), 0).unwrap();
+        assert_eq!(0, cidr.length());
+        assert_eq!(cidr.network(), Ipv4Addr::UNSPECIFIED);
+        assert!(cidr.contains(Ipv4Addr::from_str("192.0.2.1").unwrap()));
+    }
+
     #[test]
     fn cidr_network_address() {
         let cidr = Ipv4Cidr::new(Ipv4Addr::from_str("10.1.0.10").unwrap(), 20).unwrap();
@@ -131,7 +131,7 @@ impl Cidr for Ipv6Cidr {
     #[inline]
     fn new(address: Self::Addr, length: usize) -> Result<Self, CidrError> {
         let mask = match length {
-            0 => u128::max_value(),
+            0 => 0,
             1..=IPV6ADDR_BITS => u128::max_value() << (IPV6ADDR_BITS - length),
             _ => return Err(CidrError::Malformed("Not a valid length".to_owned())),
         };
@@ -0,0 +1,282 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Longest prefix match tables for routing lookups.
+//!
+//! Both tables are backed by a multibit trie, similar to DPDK's DIR-24-8
+//! but with a 16-bit first stride followed by 8-bit strides so the same
+//! structure serves IPv4 and IPv6. A lookup takes one memory access per
+//! stride beyond the first 16 bits of the matched prefix.
+
+mod v4;
+mod v6;
+
+#[allow(unreachable_pub)] // https://github.com/rust-lang/rust/issues/57411
+pub use self::v4::Ipv4Lpm;
+#[allow(unreachable_pub)]
+pub use self::v6::Ipv6Lpm;
+
+use std::collections::HashMap;
+
+/// Number of bits indexing the root table.
+const ROOT_BITS: usize = 16;
+
+/// Number of bits indexing a group table.
+const GROUP_BITS: usize = 8;
+
+/// Number of entries in a group table.
+const GROUP_SIZE: usize = 1 << GROUP_BITS;
+
+/// An entry of a trie table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Entry<T> {
+    /// No prefix covers the entry.
+    Empty,
+    /// The next hop of the longest prefix covering the entry, and the
+    /// length of that prefix.
+    Hop(u8, T),
+    /// The entry is split further by a group table.
+    Group(u32),
+}
+
+/// Returns the key with the bits beyond the prefix length cleared.
+#[inline]
+fn mask(key: u128, length: u8) -> u128 {
+    if length == 0 {
+        0
+    } else {
+        key & (!0u128 << (128 - length as u32))
+    }
+}
+
+/// A multibit trie keyed by left-aligned 128-bit addresses.
+///
+/// The rules are kept alongside the tables so deleting a prefix can fall
+/// back to the next longest prefix covering it.
+pub(crate) struct Trie<T> {
+    tables: Vec<Vec<Entry<T>>>,
+    free: Vec<u32>,
+    rules: HashMap<(u128, u8), T>,
+}
+
+impl<T: Copy + PartialEq> Trie<T> {
+    /// Creates an empty trie.
+    pub(crate) fn new() -> Self {
+        Trie {
+            tables: vec![vec![Entry::Empty; 1 << ROOT_BITS]],
+            free: vec![],
+            rules: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of prefixes in the trie.
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Returns the number of group tables in use.
+    #[cfg(test)]
+    fn groups(&self) -> usize {
+        self.tables.len() - 1 - self.free.len()
+    }
+
+    /// Inserts a prefix, returning the previous next hop of the same
+    /// prefix if there was one.
+    pub(crate) fn insert(&mut self, key: u128, length: u8, next_hop: T) -> Option<T> {
+        let key = mask(key, length);
+        let previous = self.rules.insert((key, length), next_hop);
+        let replace = |entry: &Entry<T>| match *entry {
+            Entry::Empty => true,
+            Entry::Hop(depth, _) => depth <= length,
+            Entry::Group(_) => false,
+        };
+        self.update(0, 0, key, length, Entry::Hop(length, next_hop), &replace);
+        previous
+    }
+
+    /// Deletes a prefix, returning its next hop if it was in the trie.
+    ///
+    /// The addresses covered by the prefix fall back to the next longest
+    /// prefix covering them.
+    pub(crate) fn delete(&mut self, key: u128, length: u8) -> Option<T> {
+        let key = mask(key, length);
+        let next_hop = self.rules.remove(&(key, length))?;
+        let fallback = (0..length)
+            .rev()
+            .find_map(|depth| {
+                self.rules
+                    .get(&(mask(key, depth), depth))
+                    .map(|&hop| Entry::Hop(depth, hop))
+            })
+            .unwrap_or(Entry::Empty);
+        let replace = |entry: &Entry<T>| match *entry {
+            Entry::Hop(depth, _) => depth == length,
+            _ => false,
+        };
+        self.update(0, 0, key, length, fallback, &replace);
+        Some(next_hop)
+    }
+
+    /// Returns the next hop of the longest prefix matching the key.
+    #[inline]
+    pub(crate) fn lookup(&self, key: u128) -> Option<T> {
+        let mut shift = 128 - ROOT_BITS;
+        let mut entry = self.tables[0][(key >> shift) as usize];
+        loop {
+            match entry {
+                Entry::Empty => return None,
+                Entry::Hop(_, next_hop) => return Some(next_hop),
+                Entry::Group(group) => {
+                    shift -= GROUP_BITS;
+                    let index = (key >> shift) as usize & (GROUP_SIZE - 1);
+                    entry = self.tables[group as usize][index];
+                }
+            }
+        }
+    }
+
+    /// Writes `new` to the entries of the table at `level` covered by the
+    /// prefix, descending into group tables for prefixes longer than the
+    /// level's stride. Only the entries accepted by `replace` are written.
+    fn update<F>(
+        &mut self,
+        table: usize,
+        level: usize,
+        key: u128,
+        length: u8,
+        new: Entry<T>,
+        replace: &F,
+    ) where
+        F: Fn(&Entry<T>) -> bool,
+    {
+        let (bits, end) = if level == 0 {
+            (ROOT_BITS, ROOT_BITS)
+        } else {
+            (GROUP_BITS, ROOT_BITS + level * GROUP_BITS)
+        };
+        let index = (key >> (128 - end)) as usize & ((1 << bits) - 1);
+
+        if length as usize <= end {
+            for index in index..index + (1 << (end - length as usize)) {
+                self.fill(table, index, new, replace);
+            }
+        } else {
+            let group = self.expand(table, index);
+            self.update(group, level + 1, key, length, new, replace);
+            self.collapse(table, index);
+        }
+    }
+
+    /// Writes `new` to the entry, or to every entry of its group table.
+    fn fill<F>(&mut self, table: usize, index: usize, new: Entry<T>, replace: &F)
+    where
+        F: Fn(&Entry<T>) -> bool,
+    {
+        let entry = self.tables[table][index];
+        match entry {
+            Entry::Group(group) => {
+                for child in 0..GROUP_SIZE {
+                    self.fill(group as usize, child, new, replace);
+                }
+                self.collapse(table, index);
+            }
+            _ if replace(&entry) => self.tables[table][index] = new,
+            _ => (),
+        }
+    }
+
+    /// Splits the entry with a group table, returning the group's index.
+    ///
+    /// The group's entries inherit the entry being split.
+    fn expand(&mut self, table: usize, index: usize) -> usize {
+        let entry = self.tables[table][index];
+        if let Entry::Group(group) = entry {
+            return group as usize;
+        }
+
+        let group = match self.free.pop() {
+            Some(group) => {
+                let group = group as usize;
+                self.tables[group].iter_mut().for_each(|e| *e = entry);
+                group
+            }
+            None => {
+                self.tables.push(vec![entry; GROUP_SIZE]);
+                self.tables.len() - 1
+            }
+        };
+        self.tables[table][index] = Entry::Group(group as u32);
+        group
+    }
+
+    /// Merges the entry's group table back into the entry when all of the
+    /// group's entries are the same.
+    fn collapse(&mut self, table: usize, index: usize) {
+        if let Entry::Group(group) = self.tables[table][index] {
+            let entries = &self.tables[group as usize];
+            let first = entries[0];
+            if !matches!(first, Entry::Group(_)) && entries.iter().all(|&e| e == first) {
+                self.tables[table][index] = first;
+                self.free.push(group);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapse_groups() {
+        let mut trie = Trie::new();
+        let key = 0x0a00_0000u128 << 96;
+
+        trie.insert(key, 24, 1);
+        assert_eq!(1, trie.groups());
+        trie.insert(key, 32, 2);
+        assert_eq!(2, trie.groups());
+
+        trie.delete(key, 32);
+        assert_eq!(1, trie.groups());
+        trie.delete(key, 24);
+        assert_eq!(0, trie.groups());
+        assert_eq!(None, trie.lookup(key));
+
+        // freed groups are reused
+        trie.insert(key, 32, 3);
+        assert_eq!(2, trie.groups());
+        assert_eq!(3, trie.tables.len());
+    }
+
+    #[test]
+    fn delete_merged_prefix() {
+        let mut trie = Trie::new();
+        let key = 0x0a00_0000u128 << 96;
+
+        // two halves with the same next hop collapse into one entry
+        trie.insert(key, 25, 1);
+        trie.insert(key | 0x80 << 96, 25, 1);
+        assert_eq!(1, trie.groups());
+
+        trie.delete(key, 25);
+        assert_eq!(None, trie.lookup(key));
+        assert_eq!(Some(1), trie.lookup(key | 0x80 << 96));
+    }
+}
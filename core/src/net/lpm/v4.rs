@@ -0,0 +1,182 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::Trie;
+use crate::net::{Cidr, Ipv4Cidr};
+use std::fmt;
+use std::net::Ipv4Addr;
+
+#[inline]
+fn key(addr: Ipv4Addr) -> u128 {
+    (u32::from(addr) as u128) << 96
+}
+
+/// Longest prefix match table for IPv4 routes.
+///
+/// Maps [`Ipv4Cidr`] prefixes to a next hop of type `T`, typically a small
+/// index into a table of neighbors or ports.
+///
+/// [`Ipv4Cidr`]: crate::net::Ipv4Cidr
+pub struct Ipv4Lpm<T> {
+    trie: Trie<T>,
+}
+
+impl<T: Copy + PartialEq> Ipv4Lpm<T> {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Ipv4Lpm { trie: Trie::new() }
+    }
+
+    /// Returns the number of routes in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    /// Returns whether the table has no routes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds a route, returning the previous next hop if the prefix was
+    /// already in the table.
+    ///
+    /// The host bits of the CIDR's address are ignored.
+    pub fn insert(&mut self, cidr: Ipv4Cidr, next_hop: T) -> Option<T> {
+        self.trie
+            .insert(key(cidr.address()), cidr.length() as u8, next_hop)
+    }
+
+    /// Removes a route, returning its next hop if the prefix was in the
+    /// table.
+    pub fn delete(&mut self, cidr: Ipv4Cidr) -> Option<T> {
+        self.trie.delete(key(cidr.address()), cidr.length() as u8)
+    }
+
+    /// Returns the next hop of the longest prefix matching the address.
+    #[inline]
+    pub fn lookup(&self, addr: Ipv4Addr) -> Option<T> {
+        self.trie.lookup(key(addr))
+    }
+
+    /// Looks up the next hops of a burst of addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `addrs` and `next_hops` are not the same length.
+    pub fn lookup_batch(&self, addrs: &[Ipv4Addr], next_hops: &mut [Option<T>]) {
+        assert_eq!(addrs.len(), next_hops.len());
+        for (addr, next_hop) in addrs.iter().zip(next_hops.iter_mut()) {
+            *next_hop = self.lookup(*addr);
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> Default for Ipv4Lpm<T> {
+    fn default() -> Self {
+        Ipv4Lpm::new()
+    }
+}
+
+impl<T> fmt::Debug for Ipv4Lpm<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ipv4Lpm")
+            .field("len", &self.trie.rules.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn cidr(s: &str) -> Ipv4Cidr {
+        Ipv4Cidr::from_str(s).unwrap()
+    }
+
+    fn addr(s: &str) -> Ipv4Addr {
+        Ipv4Addr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn lookup_longest_prefix() {
+        let mut lpm = Ipv4Lpm::new();
+        assert_eq!(None, lpm.insert(cidr("0.0.0.0/0"), 0));
+        assert_eq!(None, lpm.insert(cidr("10.0.0.0/8"), 1));
+        assert_eq!(None, lpm.insert(cidr("10.1.0.0/16"), 2));
+        assert_eq!(None, lpm.insert(cidr("10.1.2.0/24"), 3));
+        assert_eq!(None, lpm.insert(cidr("10.1.2.3/32"), 4));
+        assert_eq!(None, lpm.insert(cidr("10.1.2.128/25"), 5));
+        assert_eq!(6, lpm.len());
+
+        assert_eq!(Some(0), lpm.lookup(addr("192.0.2.1")));
+        assert_eq!(Some(1), lpm.lookup(addr("10.2.0.1")));
+        assert_eq!(Some(2), lpm.lookup(addr("10.1.3.1")));
+        assert_eq!(Some(3), lpm.lookup(addr("10.1.2.4")));
+        assert_eq!(Some(4), lpm.lookup(addr("10.1.2.3")));
+        assert_eq!(Some(5), lpm.lookup(addr("10.1.2.200")));
+
+        // a shorter prefix added later doesn't override longer ones
+        assert_eq!(None, lpm.insert(cidr("10.1.2.0/23"), 6));
+        assert_eq!(Some(3), lpm.lookup(addr("10.1.2.4")));
+        assert_eq!(Some(6), lpm.lookup(addr("10.1.3.1")));
+    }
+
+    #[test]
+    fn replace_route() {
+        let mut lpm = Ipv4Lpm::new();
+        lpm.insert(cidr("10.1.2.0/24"), 1);
+        assert_eq!(Some(1), lpm.insert(cidr("10.1.2.99/24"), 2));
+        assert_eq!(1, lpm.len());
+        assert_eq!(Some(2), lpm.lookup(addr("10.1.2.1")));
+    }
+
+    #[test]
+    fn delete_falls_back_to_shorter_prefix() {
+        let mut lpm = Ipv4Lpm::new();
+        lpm.insert(cidr("10.0.0.0/8"), 1);
+        lpm.insert(cidr("10.1.2.0/24"), 2);
+        lpm.insert(cidr("10.1.2.3/32"), 3);
+
+        assert_eq!(Some(2), lpm.delete(cidr("10.1.2.0/24")));
+        assert_eq!(None, lpm.delete(cidr("10.1.2.0/24")));
+        assert_eq!(Some(1), lpm.lookup(addr("10.1.2.4")));
+        assert_eq!(Some(3), lpm.lookup(addr("10.1.2.3")));
+
+        assert_eq!(Some(3), lpm.delete(cidr("10.1.2.3/32")));
+        assert_eq!(Some(1), lpm.lookup(addr("10.1.2.3")));
+
+        assert_eq!(Some(1), lpm.delete(cidr("10.0.0.0/8")));
+        assert_eq!(None, lpm.lookup(addr("10.1.2.3")));
+        assert!(lpm.is_empty());
+    }
+
+    #[test]
+    fn lookup_batch() {
+        let mut lpm = Ipv4Lpm::new();
+        lpm.insert(cidr("10.0.0.0/8"), 1);
+        lpm.insert(cidr("172.16.0.0/12"), 2);
+
+        let addrs = [addr("10.0.0.1"), addr("172.31.0.1"), addr("192.0.2.1")];
+        let mut next_hops = [None; 3];
+        lpm.lookup_batch(&addrs, &mut next_hops);
+        assert_eq!([Some(1), Some(2), None], next_hops);
+    }
+}
@@ -0,0 +1,151 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::Trie;
+use crate::net::{Cidr, Ipv6Cidr};
+use std::fmt;
+use std::net::Ipv6Addr;
+
+#[inline]
+fn key(addr: Ipv6Addr) -> u128 {
+    u128::from(addr)
+}
+
+/// Longest prefix match table for IPv6 routes.
+///
+/// Maps [`Ipv6Cidr`] prefixes to a next hop of type `T`, typically a small
+/// index into a table of neighbors or ports.
+///
+/// [`Ipv6Cidr`]: crate::net::Ipv6Cidr
+pub struct Ipv6Lpm<T> {
+    trie: Trie<T>,
+}
+
+impl<T: Copy + PartialEq> Ipv6Lpm<T> {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Ipv6Lpm { trie: Trie::new() }
+    }
+
+    /// Returns the number of routes in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    /// Returns whether the table has no routes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds a route, returning the previous next hop if the prefix was
+    /// already in the table.
+    ///
+    /// The host bits of the CIDR's address are ignored.
+    pub fn insert(&mut self, cidr: Ipv6Cidr, next_hop: T) -> Option<T> {
+        self.trie
+            .insert(key(cidr.address()), cidr.length() as u8, next_hop)
+    }
+
+    /// Removes a route, returning its next hop if the prefix was in the
+    /// table.
+    pub fn delete(&mut self, cidr: Ipv6Cidr) -> Option<T> {
+        self.trie.delete(key(cidr.address()), cidr.length() as u8)
+    }
+
+    /// Returns the next hop of the longest prefix matching the address.
+    #[inline]
+    pub fn lookup(&self, addr: Ipv6Addr) -> Option<T> {
+        self.trie.lookup(key(addr))
+    }
+
+    /// Looks up the next hops of a burst of addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `addrs` and `next_hops` are not the same length.
+    pub fn lookup_batch(&self, addrs: &[Ipv6Addr], next_hops: &mut [Option<T>]) {
+        assert_eq!(addrs.len(), next_hops.len());
+        for (addr, next_hop) in addrs.iter().zip(next_hops.iter_mut()) {
+            *next_hop = self.lookup(*addr);
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> Default for Ipv6Lpm<T> {
+    fn default() -> Self {
+        Ipv6Lpm::new()
+    }
+}
+
+impl<T> fmt::Debug for Ipv6Lpm<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ipv6Lpm")
+            .field("len", &self.trie.rules.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn cidr(s: &str) -> Ipv6Cidr {
+        Ipv6Cidr::from_str(s).unwrap()
+    }
+
+    fn addr(s: &str) -> Ipv6Addr {
+        Ipv6Addr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn lookup_longest_prefix() {
+        let mut lpm = Ipv6Lpm::new();
+        lpm.insert(cidr("::/0"), 0);
+        lpm.insert(cidr("2001:db8::/32"), 1);
+        lpm.insert(cidr("2001:db8:1::/48"), 2);
+        lpm.insert(cidr("2001:db8:1:2::/63"), 3);
+        lpm.insert(cidr("2001:db8:1:2::1/128"), 4);
+        assert_eq!(5, lpm.len());
+
+        assert_eq!(Some(0), lpm.lookup(addr("fe80::1")));
+        assert_eq!(Some(1), lpm.lookup(addr("2001:db8:2::1")));
+        assert_eq!(Some(2), lpm.lookup(addr("2001:db8:1:4::1")));
+        assert_eq!(Some(3), lpm.lookup(addr("2001:db8:1:3::1")));
+        assert_eq!(Some(4), lpm.lookup(addr("2001:db8:1:2::1")));
+        assert_eq!(Some(3), lpm.lookup(addr("2001:db8:1:2::2")));
+
+        assert_eq!(Some(4), lpm.delete(cidr("2001:db8:1:2::1/128")));
+        assert_eq!(Some(3), lpm.lookup(addr("2001:db8:1:2::1")));
+        assert_eq!(Some(3), lpm.delete(cidr("2001:db8:1:2::/63")));
+        assert_eq!(Some(2), lpm.lookup(addr("2001:db8:1:2::1")));
+    }
+
+    #[test]
+    fn lookup_batch() {
+        let mut lpm = Ipv6Lpm::new();
+        lpm.insert(cidr("2001:db8::/32"), 1);
+
+        let addrs = [addr("2001:db8::1"), addr("2001:db9::1")];
+        let mut next_hops = [None; 2];
+        lpm.lookup_batch(&addrs, &mut next_hops);
+        assert_eq!([Some(1), None], next_hops);
+    }
+}
@@ -30,6 +30,9 @@ impl MacAddr {
     /// A MAC address representing an unspecified address: 00:00:00:00:00:00.
     pub const UNSPECIFIED: Self = MacAddr([0, 0, 0, 0, 0, 0]);
 
+    /// The broadcast MAC address: ff:ff:ff:ff:ff:ff.
+    pub const BROADCAST: Self = MacAddr([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
     /// Creates a MAC address from 6 octets.
     #[allow(clippy::many_single_char_names)]
     pub const fn new(a: u8, b: u8, c: u8, d: u8, e: u8, f: u8) -> Self {
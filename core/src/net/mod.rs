@@ -19,7 +19,9 @@
 //! Common network utilities.
 
 mod cidr;
+mod lpm;
 mod mac;
 
 pub use self::cidr::{Cidr, CidrError, Ipv4Cidr, Ipv6Cidr};
+pub use self::lpm::{Ipv4Lpm, Ipv6Lpm};
 pub use self::mac::{MacAddr, MacParseError};
@@ -219,6 +219,41 @@ pub fn compute_with_ipaddr(
     }
 }
 
+/// Lookup table of the reflected CRC32c polynomial `0x1EDC6F41`.
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Computes the CRC32c checksum as defined in [IETF RFC 3309].
+///
+/// The checksum is used by SCTP instead of the Internet checksum, and
+/// does not cover a pseudo header.
+///
+/// [IETF RFC 3309]: https://tools.ietf.org/html/rfc3309
+pub fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ u32::from(byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -227,4 +262,10 @@ mod tests {
     fn compute_checksum_incrementally() {
         assert_eq!(0x0000, compute_inc(0xdd2f, &[0x5555], &[0x3285]));
     }
+
+    #[test]
+    fn compute_crc32c() {
+        assert_eq!(0x0000_0000, crc32c(&[]));
+        assert_eq!(0xE306_9283, crc32c(b"123456789"));
+    }
 }
@@ -21,7 +21,7 @@
 use crate::ensure;
 use crate::net::MacAddr;
 use crate::packets::types::u16be;
-use crate::packets::{Internal, Mbuf, Packet, SizeOf};
+use crate::packets::{Datalink, Internal, Mbuf, Packet, SizeOf};
 use anyhow::{anyhow, Result, Error};
 use std::fmt;
 use std::ptr::NonNull;
@@ -117,15 +117,23 @@ const VLAN_802_1AD: u16 = 0x88a8;
 /// S-TAG, or service tag, comes first, followed by the inner C-TAG, or customer
 /// tag. In such cases, 802.1ad specifies a TPID of `0x88a8` for S-TAG.
 ///
+/// # Inner frames
+///
+/// The frame is normally the outermost packet of the [`Mbuf`]. Overlay
+/// protocols such as [VXLAN] carry another Ethernet frame as their payload,
+/// in which case the envelope is the tunnel packet instead. `Ethernet`
+/// without a type parameter always refers to the outermost frame.
+///
 /// [IEEE 802.1Q]: https://en.wikipedia.org/wiki/IEEE_802.1Q
 /// [IEEE 802.1ad]: https://en.wikipedia.org/wiki/IEEE_802.1ad
-pub struct Ethernet {
-    envelope: Mbuf,
+/// [VXLAN]: crate::packets::vxlan::Vxlan
+pub struct Ethernet<E: Packet = Mbuf> {
+    envelope: E,
     header: NonNull<EthernetHeader>,
     offset: usize,
 }
 
-impl Ethernet {
+impl<E: Packet> Ethernet<E> {
     #[inline]
     fn header(&self) -> &EthernetHeader {
         unsafe { self.header.as_ref() }
@@ -204,6 +212,113 @@ impl Ethernet {
         self.vlan_marker() == VLAN_802_1AD
     }
 
+    /// Returns the outermost VLAN tag.
+    ///
+    /// For Dot1q frames, this is the only tag. For QinQ frames, this is
+    /// the S-TAG. Returns `None` if the frame is not tagged.
+    #[inline]
+    pub fn outer_tag(&self) -> Option<&VlanTag> {
+        let header = self.header();
+        unsafe {
+            match self.vlan_marker() {
+                VLAN_802_1Q => Some(&header.chunk.dot1q.tag),
+                VLAN_802_1AD => Some(&header.chunk.qinq.stag),
+                _ => None,
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the outermost VLAN tag.
+    #[inline]
+    pub fn outer_tag_mut(&mut self) -> Option<&mut VlanTag> {
+        let marker = self.vlan_marker();
+        let header = self.header_mut();
+        unsafe {
+            match marker {
+                VLAN_802_1Q => Some(&mut header.chunk.dot1q.tag),
+                VLAN_802_1AD => Some(&mut header.chunk.qinq.stag),
+                _ => None,
+            }
+        }
+    }
+
+    /// Returns the inner C-TAG of a QinQ frame.
+    ///
+    /// Returns `None` if the frame is not double tagged.
+    #[inline]
+    pub fn inner_tag(&self) -> Option<&VlanTag> {
+        if self.is_qinq() {
+            unsafe { Some(&self.header().chunk.qinq.ctag) }
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the inner C-TAG of a QinQ frame.
+    #[inline]
+    pub fn inner_tag_mut(&mut self) -> Option<&mut VlanTag> {
+        if self.is_qinq() {
+            unsafe { Some(&mut self.header_mut().chunk.qinq.ctag) }
+        } else {
+            None
+        }
+    }
+
+    /// Pushes a new outermost VLAN tag with the VLAN identifier.
+    ///
+    /// An untagged frame becomes a Dot1q frame. A Dot1q frame becomes a
+    /// QinQ frame, with the new tag as the S-TAG and the existing tag as
+    /// the C-TAG. The header is extended by 4 octets, shifting the payload
+    /// down.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame is already QinQ tagged. Returns an
+    /// error if the buffer does not have enough free space.
+    #[inline]
+    pub fn push_tag(&mut self, identifier: u16) -> Result<()> {
+        let tpid = match self.vlan_marker() {
+            VLAN_802_1Q => VLAN_802_1AD,
+            VLAN_802_1AD => return Err(anyhow!("frame is already QinQ tagged.")),
+            _ => VLAN_802_1Q,
+        };
+
+        // the tag goes in between the source MAC and the current tag or
+        // ether type.
+        let offset = self.offset + MacAddr::size_of() * 2;
+        let mbuf = self.mbuf_mut();
+        mbuf.extend(offset, VlanTag::size_of())?;
+
+        let mut tag = VlanTag {
+            tpid: tpid.into(),
+            tci: u16be::default(),
+        };
+        tag.set_identifier(identifier);
+        let _ = mbuf.write_data(offset, &tag)?;
+
+        Ok(())
+    }
+
+    /// Pops the outermost VLAN tag.
+    ///
+    /// A Dot1q frame becomes an untagged frame. A QinQ frame becomes a
+    /// Dot1q frame with the C-TAG. The header is shrunk by 4 octets,
+    /// shifting the payload up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame is not VLAN tagged.
+    #[inline]
+    pub fn pop_tag(&mut self) -> Result<()> {
+        ensure!(
+            self.is_dot1q() || self.is_qinq(),
+            anyhow!("frame is not VLAN tagged.")
+        );
+
+        let offset = self.offset + MacAddr::size_of() * 2;
+        self.mbuf_mut().shrink(offset, VlanTag::size_of())
+    }
+
     /// Swaps the source MAC address with the destination MAC address.
     #[inline]
     pub fn swap_addresses(&mut self) {
@@ -214,13 +329,15 @@ impl Ethernet {
     }
 }
 
-impl fmt::Debug for Ethernet {
+impl<E: Packet> fmt::Debug for Ethernet<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ethernet")
             .field("src", &format!("{}", self.src()))
             .field("dst", &format!("{}", self.dst()))
             .field("ether_type", &format!("{}", self.ether_type()))
             .field("vlan", &(self.is_dot1q() || self.is_qinq()))
+            .field("outer_tag", &self.outer_tag())
+            .field("inner_tag", &self.inner_tag())
             .field("$offset", &self.offset())
             .field("$len", &self.len())
             .field("$header_len", &self.header_len())
@@ -228,9 +345,10 @@ impl fmt::Debug for Ethernet {
     }
 }
 
-impl Packet for Ethernet {
-    /// The preceding type for Ethernet must be `Mbuf`.
-    type Envelope = Mbuf;
+impl<E: Packet> Packet for Ethernet<E> {
+    /// The preceding type for an outermost frame is `Mbuf`, or the tunnel
+    /// packet for an inner frame.
+    type Envelope = E;
 
     #[inline]
     fn envelope(&self) -> &Self::Envelope {
@@ -270,7 +388,7 @@ impl Packet for Ethernet {
         }
     }
 
-    /// Parses the mbuf's payload as `Ethernet`.
+    /// Parses the envelope's payload as `Ethernet`.
     ///
     /// # Errors
     ///
@@ -334,6 +452,18 @@ impl Packet for Ethernet {
     }
 }
 
+impl<E: Packet> Datalink for Ethernet<E> {
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        self.ether_type()
+    }
+
+    #[inline]
+    fn set_protocol_type(&mut self, ether_type: EtherType) {
+        self.set_ether_type(ether_type)
+    }
+}
+
 /// The protocol identifier of the Ethernet frame payload.
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 #[repr(C, packed)]
@@ -358,6 +488,13 @@ pub mod EtherTypes {
     pub const Ipv4: EtherType = EtherType(0x0800);
     /// Internet Protocol version 6.
     pub const Ipv6: EtherType = EtherType(0x86DD);
+    /// Transparent Ethernet bridging, an Ethernet frame carried by a
+    /// tunnel.
+    pub const TransparentEthernetBridging: EtherType = EtherType(0x6558);
+    /// Multiprotocol label switching unicast.
+    pub const Mpls: EtherType = EtherType(0x8847);
+    /// Multiprotocol label switching multicast.
+    pub const MplsMulticast: EtherType = EtherType(0x8848);
 }
 
 impl fmt::Display for EtherType {
@@ -369,6 +506,9 @@ impl fmt::Display for EtherType {
                 EtherTypes::Arp => "ARP".to_string(),
                 EtherTypes::Ipv4 => "IPv4".to_string(),
                 EtherTypes::Ipv6 => "IPv6".to_string(),
+                EtherTypes::TransparentEthernetBridging => "TEB".to_string(),
+                EtherTypes::Mpls => "MPLS".to_string(),
+                EtherTypes::MplsMulticast => "MPLS multicast".to_string(),
                 _ => {
                     let t = self.0;
                     format!("0x{:04x}", t)
@@ -379,9 +519,13 @@ impl fmt::Display for EtherType {
 }
 
 /// VLAN tag.
+///
+/// The tag is made of the tag protocol identifier (TPID) and the tag
+/// control information (TCI). The TCI holds the priority code point (PCP),
+/// the drop eligible indicator (DEI) and the VLAN identifier (VID).
 #[derive(Clone, Copy, Debug, Default, SizeOf)]
 #[repr(C, packed)]
-struct VlanTag {
+pub struct VlanTag {
     tpid: u16be,
     tci: u16be,
 }
@@ -389,34 +533,54 @@ struct VlanTag {
 #[allow(clippy::trivially_copy_pass_by_ref)]
 impl VlanTag {
     /// Returns the tag protocol identifier, either 802.1q (Dot1q) or 802.1ad (QinQ).
-    #[allow(dead_code)]
     #[inline]
-    fn tag_id(&self) -> u16 {
+    pub fn tag_id(&self) -> u16 {
         self.tpid.into()
     }
 
     /// Returns the priority code point.
-    #[allow(dead_code)]
     #[inline]
-    fn priority(&self) -> u8 {
+    pub fn priority(&self) -> u8 {
         let tci: u16 = self.tci.into();
         (tci >> 13) as u8
     }
 
+    /// Sets the priority code point.
+    #[inline]
+    pub fn set_priority(&mut self, priority: u8) {
+        self.tci = (self.tci & u16be::from(0x1fff)) | u16be::from(u16::from(priority & 0x07) << 13);
+    }
+
     /// Returns whether the frame is eligible to be dropped in the presence
     /// of congestion.
-    #[allow(dead_code)]
     #[inline]
-    fn drop_eligible(&self) -> bool {
+    pub fn drop_eligible(&self) -> bool {
         self.tci & u16be::from(0x1000) > u16be::MIN
     }
 
+    /// Sets the drop eligible indicator.
+    #[inline]
+    pub fn set_drop_eligible(&mut self) {
+        self.tci |= u16be::from(0x1000);
+    }
+
+    /// Unsets the drop eligible indicator.
+    #[inline]
+    pub fn unset_drop_eligible(&mut self) {
+        self.tci &= !u16be::from(0x1000);
+    }
+
     /// Returns the VLAN identifier.
-    #[allow(dead_code)]
     #[inline]
-    fn identifier(&self) -> u16 {
+    pub fn identifier(&self) -> u16 {
         (self.tci & u16be::from(0x0fff)).into()
     }
+
+    /// Sets the VLAN identifier.
+    #[inline]
+    pub fn set_identifier(&mut self, identifier: u16) {
+        self.tci = (self.tci & u16be::from(0xf000)) | u16be::from(identifier & 0x0fff);
+    }
 }
 
 /// Dot1q chunk for a VLAN header.
@@ -482,6 +646,7 @@ impl SizeOf for EthernetHeader {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::packets::ip::v4::Ipv4;
     use crate::testils::byte_arrays::{IPV4_UDP_PACKET, VLAN_DOT1Q_PACKET, VLAN_QINQ_PACKET};
 
     #[test]
@@ -494,6 +659,8 @@ mod tests {
         assert_eq!("ARP", EtherTypes::Arp.to_string());
         assert_eq!("IPv4", EtherTypes::Ipv4.to_string());
         assert_eq!("IPv6", EtherTypes::Ipv6.to_string());
+        assert_eq!("TEB", EtherTypes::TransparentEthernetBridging.to_string());
+        assert_eq!("MPLS", EtherTypes::Mpls.to_string());
         assert_eq!("0x0000", EtherType::new(0).to_string());
     }
 
@@ -531,6 +698,102 @@ mod tests {
         assert_eq!(22, ethernet.header_len());
     }
 
+    #[capsule::test]
+    fn dot1q_tag_accessors() {
+        let packet = Mbuf::from_bytes(&VLAN_DOT1Q_PACKET).unwrap();
+        let mut ethernet = packet.parse::<Ethernet>().unwrap();
+
+        let tag = ethernet.outer_tag().unwrap();
+        assert_eq!(VLAN_802_1Q, tag.tag_id());
+        assert_eq!(0, tag.priority());
+        assert!(!tag.drop_eligible());
+        assert_eq!(123, tag.identifier());
+        assert!(ethernet.inner_tag().is_none());
+
+        let tag = ethernet.outer_tag_mut().unwrap();
+        tag.set_priority(5);
+        tag.set_drop_eligible();
+        tag.set_identifier(4000);
+
+        let tag = ethernet.outer_tag().unwrap();
+        assert_eq!(5, tag.priority());
+        assert!(tag.drop_eligible());
+        assert_eq!(4000, tag.identifier());
+
+        ethernet.outer_tag_mut().unwrap().unset_drop_eligible();
+        assert!(!ethernet.outer_tag().unwrap().drop_eligible());
+        assert_eq!(EtherTypes::Arp, ethernet.ether_type());
+    }
+
+    #[capsule::test]
+    fn qinq_tag_accessors() {
+        let packet = Mbuf::from_bytes(&VLAN_QINQ_PACKET).unwrap();
+        let mut ethernet = packet.parse::<Ethernet>().unwrap();
+
+        let stag = ethernet.outer_tag().unwrap();
+        assert_eq!(VLAN_802_1AD, stag.tag_id());
+        assert_eq!(30, stag.identifier());
+
+        let ctag = ethernet.inner_tag().unwrap();
+        assert_eq!(VLAN_802_1Q, ctag.tag_id());
+        assert_eq!(1, ctag.priority());
+        assert!(!ctag.drop_eligible());
+        assert_eq!(101, ctag.identifier());
+
+        ethernet.inner_tag_mut().unwrap().set_identifier(200);
+        assert_eq!(200, ethernet.inner_tag().unwrap().identifier());
+        assert_eq!(30, ethernet.outer_tag().unwrap().identifier());
+    }
+
+    #[capsule::test]
+    fn untagged_frame_has_no_tags() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let mut ethernet = packet.parse::<Ethernet>().unwrap();
+
+        assert!(ethernet.outer_tag().is_none());
+        assert!(ethernet.outer_tag_mut().is_none());
+        assert!(ethernet.inner_tag().is_none());
+        assert!(ethernet.pop_tag().is_err());
+    }
+
+    #[capsule::test]
+    fn push_and_pop_vlan_tags() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let mut ethernet = packet.parse::<Ethernet>().unwrap();
+
+        ethernet.push_tag(100).unwrap();
+        assert!(ethernet.is_dot1q());
+        assert_eq!(18, ethernet.header_len());
+        assert_eq!(100, ethernet.outer_tag().unwrap().identifier());
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+
+        ethernet.push_tag(200).unwrap();
+        assert!(ethernet.is_qinq());
+        assert_eq!(22, ethernet.header_len());
+        assert_eq!(200, ethernet.outer_tag().unwrap().identifier());
+        assert_eq!(100, ethernet.inner_tag().unwrap().identifier());
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+
+        // only two tags are supported
+        assert!(ethernet.push_tag(300).is_err());
+
+        // the payload is intact after the tags
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert_eq!("139.133.217.110", ipv4.src().to_string());
+        let mut ethernet = ipv4.deparse();
+
+        ethernet.pop_tag().unwrap();
+        assert!(ethernet.is_dot1q());
+        assert_eq!(100, ethernet.outer_tag().unwrap().identifier());
+
+        ethernet.pop_tag().unwrap();
+        assert!(!ethernet.is_dot1q());
+        assert!(!ethernet.is_qinq());
+        assert_eq!(14, ethernet.header_len());
+        assert_eq!(IPV4_UDP_PACKET.len(), ethernet.len());
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+    }
+
     #[capsule::test]
     fn swap_addresses() {
         let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
@@ -0,0 +1,664 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Generic Network Virtualization Encapsulation.
+
+use crate::ensure;
+use crate::packets::ethernet::{EtherType, EtherTypes};
+use crate::packets::ip::IpPacket;
+use crate::packets::types::{u16be, u32be};
+use crate::packets::udp::Udp;
+use crate::packets::{Datalink, Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// The IANA assigned UDP destination port of Geneve.
+pub const GENEVE_PORT: u16 = 6081;
+
+/// The flag that indicates a control packet.
+const FLAGS_O: u8 = 0x80;
+
+/// The flag that indicates critical options are present.
+const FLAGS_C: u8 = 0x40;
+
+/// The bit of the option type that indicates a critical option.
+const CRITICAL_TYPE: u8 = 0x80;
+
+/// Length of the option header.
+const OPTION_HEADER_LEN: usize = 4;
+
+/// The maximum length of the options, measured in octets.
+const MAX_OPTIONS_LEN: usize = 0x3f * 4;
+
+/// The maximum length of an option's data, measured in octets.
+const MAX_OPTION_DATA_LEN: usize = 0x1f * 4;
+
+/// Generic Network Virtualization Encapsulation packet based on
+/// [IETF RFC 8926].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |Ver|  Opt Len  |O|C|    Rsvd.  |          Protocol Type        |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |        Virtual Network Identifier (VNI)       |    Reserved   |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// ~                    Variable-Length Options                    ~
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Version*: (2 bits)
+///      The current version number is 0.
+///
+/// - *Opt Len*: (6 bits)
+///      The length of the option fields, expressed in 4-byte multiples,
+///      not including the 8-byte fixed tunnel header.
+///
+/// - *O*: (1 bit)
+///      Control packet. This packet contains a control message. Control
+///      messages are sent between tunnel endpoints.
+///
+/// - *C*: (1 bit)
+///      Critical options present. One or more options has the critical
+///      bit set.
+///
+/// - *Protocol Type*: (16 bits)
+///      The type of protocol data unit appearing after the Geneve header,
+///      using the EtherType values.
+///
+/// - *Virtual Network Identifier (VNI)*: (24 bits)
+///      An identifier for a unique element of a virtual network.
+///
+/// Each option is a type-length-value encoded as follows.
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |          Option Class         |      Type     |R|R|R| Length  |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// ~                  Variable-Length Option Data                  ~
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Option Class*: (16 bits)
+///      Namespace for the Type field.
+///
+/// - *Type*: (8 bits)
+///      Type indicating the format of the data contained in this option.
+///      The high-order bit is the critical bit.
+///
+/// - *Length*: (5 bits)
+///      Length of the option, expressed in 4-byte multiples, excluding the
+///      option header.
+///
+/// The payload is usually an inner [Ethernet] frame, enveloped by the
+/// Geneve packet, with the protocol type set to
+/// [`EtherTypes::TransparentEthernetBridging`].
+///
+/// # Example
+///
+/// ```
+/// let geneve = udp.parse::<Geneve<Ipv4>>()?;
+/// let mut iter = geneve.options_iter();
+/// while let Some(option) = iter.next()? {
+///     println!("{:?}", option);
+/// }
+/// let inner = geneve.parse::<Ethernet<Geneve<Ipv4>>>()?;
+/// ```
+///
+/// [IETF RFC 8926]: https://tools.ietf.org/html/rfc8926#section-3.4
+/// [Ethernet]: crate::packets::ethernet::Ethernet
+/// [`EtherTypes::TransparentEthernetBridging`]: EtherTypes::TransparentEthernetBridging
+pub struct Geneve<E: IpPacket> {
+    envelope: Udp<E>,
+    header: NonNull<GeneveHeader>,
+    offset: usize,
+}
+
+impl<E: IpPacket> Geneve<E> {
+    #[inline]
+    fn header(&self) -> &GeneveHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut GeneveHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the version number.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.header().version_opt_len >> 6
+    }
+
+    /// Returns the length of the options, measured in octets.
+    #[inline]
+    pub fn options_len(&self) -> usize {
+        (self.header().version_opt_len & 0x3f) as usize * 4
+    }
+
+    #[inline]
+    fn set_options_len(&mut self, len: usize) {
+        let version = self.header().version_opt_len & 0xc0;
+        self.header_mut().version_opt_len = version | (len / 4) as u8;
+    }
+
+    /// Returns a flag indicating whether the packet is a control packet.
+    #[inline]
+    pub fn oam(&self) -> bool {
+        self.header().flags & FLAGS_O != 0
+    }
+
+    /// Sets the control packet flag.
+    #[inline]
+    pub fn set_oam(&mut self) {
+        self.header_mut().flags |= FLAGS_O;
+    }
+
+    /// Unsets the control packet flag.
+    #[inline]
+    pub fn unset_oam(&mut self) {
+        self.header_mut().flags &= !FLAGS_O;
+    }
+
+    /// Returns a flag indicating whether critical options are present.
+    #[inline]
+    pub fn critical(&self) -> bool {
+        self.header().flags & FLAGS_C != 0
+    }
+
+    /// Sets the critical options present flag.
+    #[inline]
+    pub fn set_critical(&mut self) {
+        self.header_mut().flags |= FLAGS_C;
+    }
+
+    /// Unsets the critical options present flag.
+    #[inline]
+    pub fn unset_critical(&mut self) {
+        self.header_mut().flags &= !FLAGS_C;
+    }
+
+    /// Returns the virtual network identifier.
+    #[inline]
+    pub fn vni(&self) -> u32 {
+        u32::from(self.header().vni) >> 8
+    }
+
+    /// Sets the virtual network identifier. Only the lower 24 bits of the
+    /// identifier are used.
+    #[inline]
+    pub fn set_vni(&mut self, vni: u32) {
+        self.header_mut().vni = (vni << 8).into();
+    }
+
+    /// Returns an iterator that iterates through the options.
+    #[inline]
+    pub fn options_iter(&self) -> GeneveOptionsIterator<'_> {
+        let offset = self.offset + GeneveHeader::size_of();
+        GeneveOptionsIterator {
+            mbuf: self.mbuf(),
+            offset,
+            end: offset + self.options_len(),
+        }
+    }
+
+    /// Appends an option after the existing options.
+    ///
+    /// If the option is critical, the critical options present flag is
+    /// set as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the length of the data is not a multiple of 4
+    /// octets or exceeds 124 octets, or if the options would exceed 252
+    /// octets. Returns an error if the buffer does not have enough free
+    /// space.
+    pub fn push_option(&mut self, class: u16, option_type: u8, data: &[u8]) -> Result<()> {
+        ensure!(
+            data.len() % 4 == 0 && data.len() <= MAX_OPTION_DATA_LEN,
+            anyhow!("invalid Geneve option length {}.", data.len())
+        );
+        let len = self.options_len() + OPTION_HEADER_LEN + data.len();
+        ensure!(
+            len <= MAX_OPTIONS_LEN,
+            anyhow!("Geneve options length {} exceeds the maximum.", len)
+        );
+
+        let offset = self.payload_offset();
+        let mbuf = self.mbuf_mut();
+        mbuf.extend(offset, OPTION_HEADER_LEN + data.len())?;
+        let class = class.to_be_bytes();
+        let header = [class[0], class[1], option_type, (data.len() / 4) as u8];
+        let _ = mbuf.write_data_slice(offset, &header)?;
+        // an option without data may end the buffer.
+        if !data.is_empty() {
+            let _ = mbuf.write_data_slice(offset + OPTION_HEADER_LEN, data)?;
+        }
+
+        self.set_options_len(len);
+        if option_type & CRITICAL_TYPE != 0 {
+            self.set_critical();
+        }
+        Ok(())
+    }
+
+    /// Removes all the options, and unsets the critical options present
+    /// flag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    pub fn remove_options(&mut self) -> Result<()> {
+        let len = self.options_len();
+        if len > 0 {
+            let offset = self.offset + GeneveHeader::size_of();
+            self.mbuf_mut().shrink(offset, len)?;
+            self.set_options_len(0);
+        }
+        self.unset_critical();
+        Ok(())
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for Geneve<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("geneve")
+            .field("version", &self.version())
+            .field("options_len", &self.options_len())
+            .field("oam", &self.oam())
+            .field("critical", &self.critical())
+            .field("protocol_type", &format!("{}", self.protocol_type()))
+            .field("vni", &self.vni())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for Geneve<E> {
+    /// The preceding type for a Geneve packet must be UDP.
+    type Envelope = Udp<E>;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the length of the header including the options.
+    #[inline]
+    fn header_len(&self) -> usize {
+        GeneveHeader::size_of() + self.options_len()
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Geneve::<E> {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+        }
+    }
+
+    /// Parses the UDP packet's payload as a Geneve packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the UDP destination port is not [`GENEVE_PORT`].
+    /// Returns an error if the version is not `0`. Returns an error if the
+    /// payload does not have sufficient data for the header and the
+    /// options.
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.dst_port() == GENEVE_PORT,
+            (anyhow!("not a Geneve packet."), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        let packet = Geneve {
+            envelope,
+            header,
+            offset,
+        };
+
+        ensure!(
+            packet.version() == 0,
+            (
+                anyhow!("unsupported Geneve version {}.", packet.version()),
+                packet.deparse()
+            )
+        );
+        if let Err(e) = packet
+            .mbuf()
+            .read_data_slice::<u8>(offset, packet.header_len())
+        {
+            return Err((e, packet.deparse()));
+        }
+
+        Ok(packet)
+    }
+
+    /// Prepends a Geneve packet to the beginning of the UDP packet's
+    /// payload.
+    ///
+    /// The UDP destination port is set to [`GENEVE_PORT`], and the
+    /// protocol type to [`EtherTypes::TransparentEthernetBridging`]. There
+    /// are no options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    ///
+    /// [`EtherTypes::TransparentEthernetBridging`]: EtherTypes::TransparentEthernetBridging
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, GeneveHeader::size_of())?;
+        let header = mbuf.write_data(
+            offset,
+            &GeneveHeader {
+                protocol_type: EtherTypes::TransparentEthernetBridging.0.into(),
+                ..Default::default()
+            },
+        )?;
+
+        envelope.set_dst_port(GENEVE_PORT);
+
+        Ok(Geneve {
+            envelope,
+            header,
+            offset,
+        })
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+impl<E: IpPacket> Datalink for Geneve<E> {
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        EtherType::new(self.header().protocol_type.into())
+    }
+
+    #[inline]
+    fn set_protocol_type(&mut self, ether_type: EtherType) {
+        self.header_mut().protocol_type = ether_type.0.into();
+    }
+}
+
+/// A Geneve option.
+pub struct GeneveOption<'a> {
+    class: u16,
+    option_type: u8,
+    data: &'a [u8],
+}
+
+impl GeneveOption<'_> {
+    /// Returns the option class.
+    #[inline]
+    pub fn class(&self) -> u16 {
+        self.class
+    }
+
+    /// Returns the option type.
+    #[inline]
+    pub fn option_type(&self) -> u8 {
+        self.option_type
+    }
+
+    /// Returns a flag indicating whether the option is critical. A tunnel
+    /// endpoint that does not recognize a critical option must drop the
+    /// packet.
+    #[inline]
+    pub fn critical(&self) -> bool {
+        self.option_type & CRITICAL_TYPE != 0
+    }
+
+    /// Returns the option data.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl fmt::Debug for GeneveOption<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneveOption")
+            .field("class", &format!("0x{:04x}", self.class()))
+            .field("option_type", &format!("0x{:02x}", self.option_type()))
+            .field("critical", &self.critical())
+            .field("length", &self.data().len())
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in the Geneve header.
+pub struct GeneveOptionsIterator<'a> {
+    mbuf: &'a Mbuf,
+    offset: usize,
+    end: usize,
+}
+
+impl<'a> GeneveOptionsIterator<'a> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// Returns `Ok(None)` when iteration is finished; returns `Err` when
+    /// an option exceeds the options length.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<GeneveOption<'a>>> {
+        if self.offset >= self.end {
+            return Ok(None);
+        }
+
+        ensure!(
+            self.offset + OPTION_HEADER_LEN <= self.end,
+            anyhow!("option header exceeds the options length.")
+        );
+        let header = self
+            .mbuf
+            .read_data_slice::<u8>(self.offset, OPTION_HEADER_LEN)?;
+        let header = unsafe { &*header.as_ptr() };
+
+        let data_offset = self.offset + OPTION_HEADER_LEN;
+        let len = (header[3] & 0x1f) as usize * 4;
+        ensure!(
+            data_offset + len <= self.end,
+            anyhow!("option size exceeds the options length.")
+        );
+        // an option without data may end the buffer.
+        let data = if len > 0 {
+            let data = self.mbuf.read_data_slice::<u8>(data_offset, len)?;
+            unsafe { &*data.as_ptr() }
+        } else {
+            &[]
+        };
+
+        self.offset = data_offset + len;
+        Ok(Some(GeneveOption {
+            class: u16::from_be_bytes([header[0], header[1]]),
+            option_type: header[2],
+            data,
+        }))
+    }
+}
+
+impl fmt::Debug for GeneveOptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneveOptionsIterator")
+            .field("offset", &self.offset)
+            .field("end", &self.end)
+            .finish()
+    }
+}
+
+/// Geneve header.
+///
+/// The header only include the fixed portion of the Geneve header. The
+/// options are handled separately.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C)]
+struct GeneveHeader {
+    version_opt_len: u8,
+    flags: u8,
+    protocol_type: u16be,
+    vni: u32be,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::testils::byte_arrays::{GENEVE_PACKET, VXLAN_PACKET};
+
+    #[test]
+    fn size_of_geneve_header() {
+        assert_eq!(8, GeneveHeader::size_of());
+    }
+
+    #[capsule::test]
+    fn parse_geneve_packet() {
+        let packet = Mbuf::from_bytes(&GENEVE_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        let geneve = udp.parse::<Geneve<Ipv4>>().unwrap();
+
+        assert_eq!(0, geneve.version());
+        assert_eq!(8, geneve.options_len());
+        assert!(!geneve.oam());
+        assert!(geneve.critical());
+        assert_eq!(
+            EtherTypes::TransparentEthernetBridging,
+            geneve.protocol_type()
+        );
+        assert_eq!(5678, geneve.vni());
+
+        let mut iter = geneve.options_iter();
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(0x0102, option.class());
+        assert_eq!(0x80, option.option_type());
+        assert!(option.critical());
+        assert_eq!(&[0xde, 0xad, 0xbe, 0xef], option.data());
+        assert!(iter.next().unwrap().is_none());
+
+        let inner = geneve.parse::<Ethernet<Geneve<Ipv4>>>().unwrap();
+        assert_eq!("00:00:00:00:00:03", inner.dst().to_string());
+        assert_eq!(EtherTypes::Ipv4, inner.ether_type());
+    }
+
+    #[capsule::test]
+    fn parse_non_geneve_packet() {
+        let packet = Mbuf::from_bytes(&VXLAN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+
+        assert!(udp.parse::<Geneve<Ipv4>>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_geneve_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let udp = ipv6.push::<Udp<Ipv6>>().unwrap();
+        let mut geneve = udp.push::<Geneve<Ipv6>>().unwrap();
+
+        assert_eq!(GeneveHeader::size_of(), geneve.len());
+        assert_eq!(GENEVE_PORT, geneve.envelope().dst_port());
+        assert_eq!(
+            EtherTypes::TransparentEthernetBridging,
+            geneve.protocol_type()
+        );
+
+        geneve.set_vni(0x00ab_cdef);
+        geneve.set_oam();
+        assert_eq!(0x00ab_cdef, geneve.vni());
+        assert!(geneve.oam());
+
+        let inner = geneve.push::<Ethernet<Geneve<Ipv6>>>().unwrap();
+        assert_eq!(GeneveHeader::size_of(), inner.envelope().len() - inner.len());
+    }
+
+    #[capsule::test]
+    fn push_and_remove_options() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let udp = ipv4.push::<Udp<Ipv4>>().unwrap();
+        let mut geneve = udp.push::<Geneve<Ipv4>>().unwrap();
+
+        geneve.push_option(0x0101, 0x01, &[1, 2, 3, 4]).unwrap();
+        assert!(!geneve.critical());
+        geneve.push_option(0x0102, 0x82, &[]).unwrap();
+        assert!(geneve.critical());
+        assert_eq!(12, geneve.options_len());
+        assert_eq!(20, geneve.header_len());
+
+        // invalid data lengths
+        assert!(geneve.push_option(0x0103, 0x01, &[1, 2, 3]).is_err());
+        assert!(geneve.push_option(0x0103, 0x01, &[0; 128]).is_err());
+
+        let mut iter = geneve.options_iter();
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(0x0101, option.class());
+        assert_eq!(&[1, 2, 3, 4], option.data());
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(0x82, option.option_type());
+        assert!(option.data().is_empty());
+        assert!(iter.next().unwrap().is_none());
+
+        geneve.remove_options().unwrap();
+        assert_eq!(0, geneve.options_len());
+        assert!(!geneve.critical());
+        assert_eq!(GeneveHeader::size_of(), geneve.len());
+    }
+}
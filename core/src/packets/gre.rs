@@ -0,0 +1,605 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Generic Routing Encapsulation.
+
+use crate::ensure;
+use crate::packets::ethernet::EtherType;
+use crate::packets::ip::{IpPacket, ProtocolNumbers};
+use crate::packets::types::u16be;
+use crate::packets::{Datalink, Internal, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// The checksum present flag.
+const FLAGS_C: u16be = u16be(u16::to_be(0x8000));
+
+/// The key present flag.
+const FLAGS_K: u16be = u16be(u16::to_be(0x2000));
+
+/// The sequence number present flag.
+const FLAGS_S: u16be = u16be(u16::to_be(0x1000));
+
+/// The routing present and strict source route flags, and the recursion
+/// control of RFC 1701, which are not supported.
+const FLAGS_RFC1701: u16be = u16be(u16::to_be(0x4c00));
+
+const VERSION: u16be = u16be(u16::to_be(0x0007));
+
+/// Length of each optional field.
+const FIELD_LEN: usize = 4;
+
+/// Generic Routing Encapsulation packet based on [IETF RFC 2784], with the
+/// key and sequence number extensions of [IETF RFC 2890].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |C| |K|S| Reserved0       | Ver |         Protocol Type         |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |      Checksum (optional)      |       Reserved1 (Optional)    |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                         Key (optional)                        |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                 Sequence Number (Optional)                    |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Checksum Present*: (bit 0)
+///      If the Checksum Present bit is set to one, then the Checksum and
+///      the Reserved1 fields are present and the Checksum field contains
+///      valid information.
+///
+/// - *Key Present*: (bit 2)
+///      If the Key Present bit is set to 1, then it indicates that the Key
+///      field is present in the GRE header.
+///
+/// - *Sequence Number Present*: (bit 3)
+///      If the Sequence Number Present bit is set to 1, then it indicates
+///      that the Sequence Number field is present.
+///
+/// - *Version Number*: (bits 13-15)
+///      The Version Number field must contain the value zero.
+///
+/// - *Protocol Type*: (2 octets)
+///      The Protocol Type field contains the protocol type of the payload
+///      packet. These Protocol Types are defined as "ETHER TYPES".
+///
+/// - *Checksum*: (2 octets)
+///      The Checksum field contains the IP (one's complement) checksum sum
+///      of the all the 16 bit words in the GRE header and the payload
+///      packet.
+///
+/// - *Key*: (4 octets)
+///      The Key field contains a four octet number which was inserted by
+///      the encapsulator. It may be used by the receiver to authenticate
+///      the source of the packet.
+///
+/// - *Sequence Number*: (4 octets)
+///      The Sequence Number field contains an unsigned 32 bit integer
+///      which is inserted by the encapsulator. It may be used by the
+///      receiver to establish the order in which packets have been
+///      transmitted from the encapsulator to the receiver.
+///
+/// The payload can be an inner [IPv4] or [IPv6] packet, enveloped by the
+/// GRE packet. With the protocol type set to
+/// [`EtherTypes::TransparentEthernetBridging`], the payload is an inner
+/// [Ethernet] frame.
+///
+/// # Example
+///
+/// ```
+/// let gre = ipv4.parse::<Gre<Ipv4>>()?;
+/// let key = gre.key();
+/// let inner = gre.parse::<Ipv4<Gre<Ipv4>>>()?;
+/// ```
+///
+/// [IETF RFC 2784]: https://tools.ietf.org/html/rfc2784
+/// [IETF RFC 2890]: https://tools.ietf.org/html/rfc2890
+/// [IPv4]: crate::packets::ip::v4::Ipv4
+/// [IPv6]: crate::packets::ip::v6::Ipv6
+/// [Ethernet]: crate::packets::ethernet::Ethernet
+/// [`EtherTypes::TransparentEthernetBridging`]: crate::packets::ethernet::EtherTypes::TransparentEthernetBridging
+pub struct Gre<E: IpPacket> {
+    envelope: E,
+    header: NonNull<GreHeader>,
+    offset: usize,
+}
+
+impl<E: IpPacket> Gre<E> {
+    #[inline]
+    fn header(&self) -> &GreHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut GreHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn has_field(&self, flag: u16be) -> bool {
+        self.header().flags_version & flag != u16be::MIN
+    }
+
+    /// Returns the buffer offset of an optional field, which follows the
+    /// fields present before it.
+    #[inline]
+    fn field_offset(&self, flag: u16be) -> usize {
+        let mut offset = self.offset + GreHeader::size_of();
+        for &before in [FLAGS_C, FLAGS_K, FLAGS_S].iter() {
+            if before == flag {
+                break;
+            }
+            if self.has_field(before) {
+                offset += FIELD_LEN;
+            }
+        }
+        offset
+    }
+
+    #[inline]
+    fn field(&self, flag: u16be) -> Option<u32> {
+        if self.has_field(flag) {
+            let mut bytes = [0; FIELD_LEN];
+            if self
+                .mbuf()
+                .copy_to_slice(self.field_offset(flag), &mut bytes)
+                .is_err()
+            {
+                // the fields present are part of the parsed header
+                unreachable!()
+            }
+            Some(u32::from_be_bytes(bytes))
+        } else {
+            None
+        }
+    }
+
+    /// Sets an optional field, inserting it if not present.
+    #[inline]
+    fn set_field(&mut self, flag: u16be, value: u32) -> Result<()> {
+        let offset = self.field_offset(flag);
+        if !self.has_field(flag) {
+            self.mbuf_mut().extend(offset, FIELD_LEN)?;
+            self.header_mut().flags_version |= flag;
+        }
+        let _ = self
+            .mbuf_mut()
+            .write_data_slice(offset, &value.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Removes an optional field if present.
+    #[inline]
+    fn remove_field(&mut self, flag: u16be) -> Result<()> {
+        if self.has_field(flag) {
+            let offset = self.field_offset(flag);
+            self.mbuf_mut().shrink(offset, FIELD_LEN)?;
+            self.header_mut().flags_version &= !flag;
+        }
+        Ok(())
+    }
+
+    /// Returns the version number.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        u16::from(self.header().flags_version & VERSION) as u8
+    }
+
+    /// Returns the checksum, or `None` if the checksum is not present.
+    #[inline]
+    pub fn checksum(&self) -> Option<u16> {
+        self.field(FLAGS_C).map(|field| (field >> 16) as u16)
+    }
+
+    /// Adds the checksum field if not present. The checksum is computed
+    /// when the packet is reconciled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    pub fn add_checksum(&mut self) -> Result<()> {
+        if self.has_field(FLAGS_C) {
+            Ok(())
+        } else {
+            self.set_field(FLAGS_C, 0)
+        }
+    }
+
+    /// Removes the checksum field if present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    #[inline]
+    pub fn remove_checksum(&mut self) -> Result<()> {
+        self.remove_field(FLAGS_C)
+    }
+
+    /// Returns the key, or `None` if the key is not present.
+    #[inline]
+    pub fn key(&self) -> Option<u32> {
+        self.field(FLAGS_K)
+    }
+
+    /// Sets the key, adding the field if not present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    pub fn set_key(&mut self, key: u32) -> Result<()> {
+        self.set_field(FLAGS_K, key)
+    }
+
+    /// Removes the key field if present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    #[inline]
+    pub fn remove_key(&mut self) -> Result<()> {
+        self.remove_field(FLAGS_K)
+    }
+
+    /// Returns the sequence number, or `None` if the sequence number is
+    /// not present.
+    #[inline]
+    pub fn sequence(&self) -> Option<u32> {
+        self.field(FLAGS_S)
+    }
+
+    /// Sets the sequence number, adding the field if not present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    pub fn set_sequence(&mut self, sequence: u32) -> Result<()> {
+        self.set_field(FLAGS_S, sequence)
+    }
+
+    /// Removes the sequence number field if present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    #[inline]
+    pub fn remove_sequence(&mut self) -> Result<()> {
+        self.remove_field(FLAGS_S)
+    }
+
+    /// Computes the checksum over the header and the payload.
+    #[inline]
+    fn compute_checksum(&mut self) {
+        if self.set_field(FLAGS_C, 0).is_err() {
+            // the checksum is present
+            unreachable!()
+        }
+
+        let offset = self.offset;
+        let len = self.len();
+        if let Ok(checksum) = self.mbuf_mut().checksum(0, offset, len) {
+            let _ = self.set_field(FLAGS_C, u32::from(checksum) << 16);
+        } else {
+            // we are reading till the end of buffer, should never run out
+            unreachable!()
+        }
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for Gre<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("gre")
+            .field("version", &self.version())
+            .field("protocol_type", &format!("{}", self.protocol_type()))
+            .field("checksum", &self.checksum().map(|c| format!("0x{:04x}", c)))
+            .field("key", &self.key())
+            .field("sequence", &self.sequence())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for Gre<E> {
+    /// The preceding type for a GRE packet can be either an [IPv4] packet,
+    /// an [IPv6] packet, or any IPv6 extension packets.
+    ///
+    /// [IPv4]: crate::packets::ip::v4::Ipv4
+    /// [IPv6]: crate::packets::ip::v6::Ipv6
+    type Envelope = E;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the length of the header including the optional fields
+    /// present.
+    #[inline]
+    fn header_len(&self) -> usize {
+        let fields = [FLAGS_C, FLAGS_K, FLAGS_S]
+            .iter()
+            .filter(|&&flag| self.has_field(flag))
+            .count();
+        GreHeader::size_of() + fields * FIELD_LEN
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Gre::<E> {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+        }
+    }
+
+    /// Parses the envelope's payload as a GRE packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the envelope's next protocol is not
+    /// [`ProtocolNumbers::Gre`]. Returns an error if the version is not
+    /// `0`, or if any of the routing flags of RFC 1701 are set. Returns an
+    /// error if the payload does not have sufficient data for the header
+    /// and the optional fields present.
+    ///
+    /// [`ProtocolNumbers::Gre`]: crate::packets::ip::ProtocolNumbers::Gre
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.next_protocol() == ProtocolNumbers::Gre,
+            (anyhow!("not a GRE packet."), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        let packet = Gre {
+            envelope,
+            header,
+            offset,
+        };
+
+        ensure!(
+            packet.version() == 0 && !packet.has_field(FLAGS_RFC1701),
+            (anyhow!("unsupported GRE header."), packet.deparse())
+        );
+        if let Err(e) = packet
+            .mbuf()
+            .read_data_slice::<u8>(offset, packet.header_len())
+        {
+            return Err((e, packet.deparse()));
+        }
+
+        Ok(packet)
+    }
+
+    /// Prepends a GRE packet to the beginning of the envelope's payload.
+    ///
+    /// The envelope's next protocol is set to [`ProtocolNumbers::Gre`].
+    /// None of the optional fields is present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    ///
+    /// [`ProtocolNumbers::Gre`]: crate::packets::ip::ProtocolNumbers::Gre
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, GreHeader::size_of())?;
+        let header = mbuf.write_data(offset, &GreHeader::default())?;
+
+        envelope.set_next_protocol(ProtocolNumbers::Gre);
+
+        Ok(Gre {
+            envelope,
+            header,
+            offset,
+        })
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+
+    /// Reconciles the derivable header fields against the changes made to
+    /// the packet.
+    ///
+    /// * [`checksum`] is computed over the header and the payload, if
+    /// present.
+    ///
+    /// [`checksum`]: Gre::checksum
+    #[inline]
+    fn reconcile(&mut self) {
+        if self.has_field(FLAGS_C) {
+            self.compute_checksum();
+        }
+    }
+}
+
+impl<E: IpPacket> Datalink for Gre<E> {
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        EtherType::new(self.header().protocol_type.into())
+    }
+
+    #[inline]
+    fn set_protocol_type(&mut self, ether_type: EtherType) {
+        self.header_mut().protocol_type = ether_type.0.into();
+    }
+}
+
+/// GRE header.
+///
+/// The header only include the fixed portion of the GRE header. The
+/// optional fields are handled separately.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C)]
+struct GreHeader {
+    flags_version: u16be,
+    protocol_type: u16be,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::checksum;
+    use crate::packets::ethernet::{EtherTypes, Ethernet};
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::udp::Udp;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::{GRE_PACKET, IPV4_UDP_PACKET};
+
+    #[test]
+    fn size_of_gre_header() {
+        assert_eq!(4, GreHeader::size_of());
+    }
+
+    #[capsule::test]
+    fn parse_gre_packet() {
+        let packet = Mbuf::from_bytes(&GRE_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let gre = ipv4.parse::<Gre<Ipv4>>().unwrap();
+
+        assert_eq!(0, gre.version());
+        assert_eq!(EtherTypes::Ipv4, gre.protocol_type());
+        assert_eq!(None, gre.checksum());
+        assert_eq!(Some(100), gre.key());
+        assert_eq!(Some(1), gre.sequence());
+        assert_eq!(12, gre.header_len());
+
+        let inner = gre.parse::<Ipv4<Gre<Ipv4>>>().unwrap();
+        assert_eq!("192.168.0.1", inner.src().to_string());
+        assert_eq!("192.168.0.2", inner.dst().to_string());
+        let udp = inner.parse::<Udp<Ipv4<Gre<Ipv4>>>>().unwrap();
+        assert_eq!(53, udp.dst_port());
+    }
+
+    #[capsule::test]
+    fn parse_non_gre_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        assert!(ipv4.parse::<Gre<Ipv4>>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_gre_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let gre = ipv4.push::<Gre<Ipv4>>().unwrap();
+
+        assert_eq!(GreHeader::size_of(), gre.len());
+        assert_eq!(ProtocolNumbers::Gre, gre.envelope().protocol());
+
+        let inner = gre.push::<Ipv6<Gre<Ipv4>>>().unwrap();
+        assert_eq!(EtherTypes::Ipv6, inner.envelope().protocol_type());
+    }
+
+    #[capsule::test]
+    fn add_and_remove_fields() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let mut gre = ipv4.push::<Gre<Ipv4>>().unwrap();
+
+        gre.set_sequence(9).unwrap();
+        gre.set_key(7).unwrap();
+        gre.add_checksum().unwrap();
+        assert_eq!(16, gre.header_len());
+        assert_eq!(Some(7), gre.key());
+        assert_eq!(Some(9), gre.sequence());
+
+        gre.set_key(8).unwrap();
+        assert_eq!(16, gre.header_len());
+        assert_eq!(Some(8), gre.key());
+
+        gre.remove_key().unwrap();
+        assert_eq!(12, gre.header_len());
+        assert_eq!(None, gre.key());
+        assert_eq!(Some(9), gre.sequence());
+
+        gre.remove_sequence().unwrap();
+        gre.remove_checksum().unwrap();
+        assert_eq!(GreHeader::size_of(), gre.header_len());
+        assert_eq!(GreHeader::size_of(), gre.len());
+    }
+
+    #[capsule::test]
+    fn compute_checksum() {
+        let packet = Mbuf::from_bytes(&GRE_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut gre = ipv4.parse::<Gre<Ipv4>>().unwrap();
+
+        gre.add_checksum().unwrap();
+        gre.reconcile_all();
+        assert!(gre.checksum().is_some());
+        assert_eq!(Some(100), gre.key());
+
+        // the checksum of a valid packet sums to zero
+        let data = gre.mbuf().read_data_slice(gre.offset(), gre.len()).unwrap();
+        assert_eq!(0, checksum::compute(0, unsafe { data.as_ref() }));
+    }
+
+    #[capsule::test]
+    fn push_bridged_frame() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut gre = ipv6.push::<Gre<Ipv6>>().unwrap();
+        gre.set_protocol_type(EtherTypes::TransparentEthernetBridging);
+
+        let inner = gre.push::<Ethernet<Gre<Ipv6>>>().unwrap();
+        let inner = inner.push::<Ipv4<Ethernet<Gre<Ipv6>>>>().unwrap();
+        assert_eq!(EtherTypes::Ipv4, inner.envelope().ether_type());
+        assert_eq!(
+            EtherTypes::TransparentEthernetBridging,
+            inner.envelope().envelope().protocol_type()
+        );
+    }
+}
@@ -0,0 +1,806 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! GPRS Tunnelling Protocol User Plane.
+
+use crate::ensure;
+use crate::packets::ethernet::{EtherType, EtherTypes, Ethernet};
+use crate::packets::ip::{self, IpPacket};
+use crate::packets::types::{u16be, u32be};
+use crate::packets::udp::Udp;
+use crate::packets::{Datalink, Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// The IANA assigned UDP port of GTP-U.
+pub const GTPU_PORT: u16 = 2152;
+
+/// The message type of a packet carrying a user payload.
+pub const G_PDU: u8 = 0xff;
+
+/// The extension header type of the PDU session container.
+pub const PDU_SESSION_CONTAINER: u8 = 0x85;
+
+/// Version 1 with the protocol type set to GTP.
+const VERSION_PT: u8 = 0x30;
+const FLAGS_PT: u8 = 0x10;
+const FLAGS_E: u8 = 0x04;
+const FLAGS_S: u8 = 0x02;
+const FLAGS_PN: u8 = 0x01;
+
+/// The length of the sequence number, the N-PDU number and the next
+/// extension header type fields.
+const OPTIONAL_LEN: usize = 4;
+
+/// GPRS Tunnelling Protocol User Plane packet based on [3GPP TS 29.281].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |Ver  |P|R|E|S|N| Message Type  |            Length             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                 Tunnel Endpoint Identifier                    |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |        Sequence Number        | N-PDU Number  |  Next Ext Hdr |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Version*: (3 bits)
+///      Set to 1 for GTPv1.
+///
+/// - *Protocol Type*: (1 bit)
+///      Set to 1 for GTP, and 0 for GTP'.
+///
+/// - *Flags*: (3 bits)
+///      E, S and N indicate the presence of the next extension header
+///      type, the sequence number and the N-PDU number respectively.
+///      When any of them is set, all three optional fields are present.
+///
+/// - *Message Type*: (8 bits)
+///      The type of the message. [`G_PDU`] for a user payload.
+///
+/// - *Length*: (16 bits)
+///      Length of the payload in octets, including the optional fields
+///      and the extension headers, excluding the mandatory header.
+///
+/// - *Tunnel Endpoint Identifier*: (32 bits)
+///      Unambiguously identifies a tunnel endpoint in the receiving
+///      GTP-U protocol entity.
+///
+/// Each extension header is encoded as follows.
+///
+/// ```
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |    Length     |              Contents                         |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ~                                               |  Next Ext Hdr |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Length*: (8 bits)
+///      Length of the extension header, expressed in 4-byte multiples.
+///
+/// - *Next Extension Header Type*: (8 bits)
+///      The type of the extension header that follows, or `0` if there
+///      are no more extension headers.
+///
+/// The payload of a [`G_PDU`] packet is the user's IP packet, which can be
+/// parsed as an [IPv4] or [IPv6] packet enveloped by the GTP-U packet.
+/// GTP-U does not identify the payload protocol. The protocol type is
+/// inferred from the IP version of the payload instead.
+///
+/// # Example
+///
+/// ```
+/// let gtpu = udp.parse::<GtpU<Ipv4>>()?;
+/// let teid = gtpu.teid();
+/// let mut iter = gtpu.extension_headers_iter();
+/// while let Some(header) = iter.next()? {
+///     println!("{:?}", header);
+/// }
+/// let inner = gtpu.parse::<Ipv4<GtpU<Ipv4>>>()?;
+/// ```
+///
+/// [3GPP TS 29.281]: https://www.3gpp.org/ftp/Specs/archive/29_series/29.281/
+/// [IPv4]: crate::packets::ip::v4::Ipv4
+/// [IPv6]: crate::packets::ip::v6::Ipv6
+pub struct GtpU<E: IpPacket> {
+    envelope: Udp<E>,
+    header: NonNull<GtpUHeader>,
+    offset: usize,
+    header_len: usize,
+}
+
+impl<E: IpPacket> GtpU<E> {
+    #[inline]
+    fn header(&self) -> &GtpUHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut GtpUHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the version number.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.header().flags >> 5
+    }
+
+    /// Returns the message type.
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.header().message_type
+    }
+
+    /// Sets the message type.
+    #[inline]
+    pub fn set_message_type(&mut self, message_type: u8) {
+        self.header_mut().message_type = message_type;
+    }
+
+    /// Returns the length of the payload, including the optional fields
+    /// and the extension headers.
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.header().length.into()
+    }
+
+    #[inline]
+    fn set_length(&mut self, length: u16) {
+        self.header_mut().length = length.into();
+    }
+
+    /// Returns the tunnel endpoint identifier.
+    #[inline]
+    pub fn teid(&self) -> u32 {
+        self.header().teid.into()
+    }
+
+    /// Sets the tunnel endpoint identifier.
+    #[inline]
+    pub fn set_teid(&mut self, teid: u32) {
+        self.header_mut().teid = teid.into();
+    }
+
+    /// Returns the sequence number if present.
+    #[inline]
+    pub fn sequence(&self) -> Option<u16> {
+        if self.header().flags & FLAGS_S != 0 {
+            let offset = self.offset + GtpUHeader::size_of();
+            self.mbuf()
+                .read_data_slice::<u8>(offset, 2)
+                .ok()
+                .map(|seq| {
+                    let seq = unsafe { seq.as_ref() };
+                    u16::from_be_bytes([seq[0], seq[1]])
+                })
+        } else {
+            None
+        }
+    }
+
+    /// Sets the sequence number, and the flag indicating it is present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the optional fields are not present and the
+    /// buffer does not have enough free space to add them.
+    pub fn set_sequence(&mut self, sequence: u16) -> Result<()> {
+        self.add_optional()?;
+        let offset = self.offset + GtpUHeader::size_of();
+        let _ = self
+            .mbuf_mut()
+            .write_data_slice(offset, &sequence.to_be_bytes())?;
+        self.header_mut().flags |= FLAGS_S;
+        Ok(())
+    }
+
+    /// Removes the sequence number.
+    ///
+    /// The optional fields are removed as well if they are no longer
+    /// needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    pub fn remove_sequence(&mut self) -> Result<()> {
+        if self.header().flags & FLAGS_S != 0 {
+            let offset = self.offset + GtpUHeader::size_of();
+            let _ = self.mbuf_mut().write_data_slice(offset, &[0u8; 2])?;
+            self.header_mut().flags &= !FLAGS_S;
+            self.remove_optional()?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator that iterates through the extension headers.
+    #[inline]
+    pub fn extension_headers_iter(&self) -> ExtensionHeadersIterator<'_> {
+        // the iterator can only fail to initialize if the packet was
+        // modified outside of the parsed bounds.
+        ExtensionHeadersIterator::new(self.mbuf(), self.offset).unwrap_or(
+            ExtensionHeadersIterator {
+                mbuf: self.mbuf(),
+                offset: self.payload_offset(),
+                next_type: 0,
+            },
+        )
+    }
+
+    /// Appends an extension header after the existing extension headers.
+    ///
+    /// The contents must be padded so the extension header, including
+    /// the length and the next extension header type fields, is a
+    /// multiple of 4 octets.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header type is `0`, or if the length of the
+    /// extension header is invalid. Returns an error if the buffer does not
+    /// have enough free space.
+    pub fn push_extension_header(&mut self, header_type: u8, contents: &[u8]) -> Result<()> {
+        let len = contents.len() + 2;
+        ensure!(
+            header_type != 0,
+            anyhow!("invalid GTP-U extension header type 0.")
+        );
+        ensure!(
+            len % 4 == 0 && len / 4 <= u8::MAX as usize,
+            anyhow!("invalid GTP-U extension header length {}.", len)
+        );
+
+        self.add_optional()?;
+
+        // the next extension header type of the last extension header,
+        // or of the optional fields, is the last octet before the payload.
+        let offset = self.payload_offset();
+        let mbuf = self.mbuf_mut();
+        mbuf.extend(offset, len)?;
+        let _ = mbuf.write_data(offset - 1, &header_type)?;
+        let _ = mbuf.write_data(offset, &((len / 4) as u8))?;
+        let _ = mbuf.write_data_slice(offset + 1, contents)?;
+        let _ = mbuf.write_data(offset + len - 1, &0u8)?;
+
+        self.header_len += len;
+        self.header_mut().flags |= FLAGS_E;
+        Ok(())
+    }
+
+    /// Removes all the extension headers.
+    ///
+    /// The optional fields are removed as well if they are no longer
+    /// needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    pub fn remove_extension_headers(&mut self) -> Result<()> {
+        if self.header().flags & FLAGS_E != 0 {
+            let offset = self.offset + GtpUHeader::size_of() + OPTIONAL_LEN;
+            let len = self.header_len - GtpUHeader::size_of() - OPTIONAL_LEN;
+            let mbuf = self.mbuf_mut();
+            if len > 0 {
+                mbuf.shrink(offset, len)?;
+            }
+            let _ = mbuf.write_data(offset - 1, &0u8)?;
+
+            self.header_len -= len;
+            self.header_mut().flags &= !FLAGS_E;
+            self.remove_optional()?;
+        }
+        Ok(())
+    }
+
+    /// Adds the optional fields if not present.
+    fn add_optional(&mut self) -> Result<()> {
+        if self.header_len == GtpUHeader::size_of() {
+            let offset = self.payload_offset();
+            let mbuf = self.mbuf_mut();
+            mbuf.extend(offset, OPTIONAL_LEN)?;
+            let _ = mbuf.write_data_slice(offset, &[0u8; OPTIONAL_LEN])?;
+            self.header_len += OPTIONAL_LEN;
+        }
+        Ok(())
+    }
+
+    /// Removes the optional fields if none of the flags indicating their
+    /// presence is set.
+    fn remove_optional(&mut self) -> Result<()> {
+        if self.header().flags & (FLAGS_E | FLAGS_S | FLAGS_PN) == 0
+            && self.header_len == GtpUHeader::size_of() + OPTIONAL_LEN
+        {
+            let offset = self.offset + GtpUHeader::size_of();
+            self.mbuf_mut().shrink(offset, OPTIONAL_LEN)?;
+            self.header_len -= OPTIONAL_LEN;
+        }
+        Ok(())
+    }
+}
+
+impl<E: IpPacket<Envelope = Ethernet>> GtpU<E> {
+    /// Encapsulates the IP packet of the Ethernet frame in a GTP-U packet.
+    ///
+    /// The outer IP, UDP and GTP-U headers are inserted between the
+    /// Ethernet header and the IP packet. The UDP ports are set to
+    /// [`GTPU_PORT`], and the lengths of the outer headers are set. The
+    /// caller is responsible for setting the outer addresses and
+    /// reconciling the packet, which sets the outer checksums. The
+    /// checksums are not computed here, so the inner headers can still be
+    /// reconciled first and claim the NIC's checksum offloads.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame's payload is not an IPv4 or IPv6
+    /// packet. Returns an error if the buffer does not have enough free
+    /// space.
+    pub fn encap(frame: Ethernet, teid: u32) -> Result<Self> {
+        ensure!(
+            frame.ether_type() == EtherTypes::Ipv4 || frame.ether_type() == EtherTypes::Ipv6,
+            anyhow!("not an IP packet.")
+        );
+
+        let ip = frame.push::<E>()?;
+        let mut udp = ip.push::<Udp<E>>()?;
+        udp.set_src_port(GTPU_PORT);
+        let mut gtpu = udp.push::<GtpU<E>>()?;
+        gtpu.set_teid(teid);
+
+        let len = gtpu.len() - GtpUHeader::size_of();
+        gtpu.set_length(len as u16);
+        let len = gtpu.envelope().len();
+        gtpu.envelope_mut().set_length(len as u16);
+        gtpu.envelope_mut().envelope_mut().set_length_from_buffer();
+        Ok(gtpu)
+    }
+
+    /// Decapsulates the inner IP packet.
+    ///
+    /// The outer IP, UDP and GTP-U headers are removed from the buffer,
+    /// and the Ethernet header's [`ether_type`] is set to the protocol type
+    /// of the inner packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is not an IPv4 or IPv6 packet.
+    /// Returns an error if the buffer fails to shrink.
+    ///
+    /// [`ether_type`]: Ethernet::ether_type
+    pub fn decap(self) -> Result<Ethernet> {
+        let ether_type = self.protocol_type();
+        ensure!(
+            ether_type == EtherTypes::Ipv4 || ether_type == EtherTypes::Ipv6,
+            anyhow!("GTP-U payload is not an IP packet.")
+        );
+
+        let offset = self.envelope().envelope().offset();
+        let len = self.payload_offset() - offset;
+        let mut ethernet = self.deparse().deparse().deparse();
+        ethernet.mbuf_mut().shrink(offset, len)?;
+        ethernet.set_ether_type(ether_type);
+        Ok(ethernet)
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for GtpU<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("gtpu")
+            .field("version", &self.version())
+            .field("message_type", &format!("{:#04x}", self.message_type()))
+            .field("length", &self.length())
+            .field("teid", &self.teid())
+            .field("sequence", &self.sequence())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for GtpU<E> {
+    /// The preceding type for a GTP-U packet must be UDP.
+    type Envelope = Udp<E>;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the length of the header including the optional fields and
+    /// the extension headers.
+    #[inline]
+    fn header_len(&self) -> usize {
+        self.header_len
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        GtpU::<E> {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+            header_len: self.header_len,
+        }
+    }
+
+    /// Parses the UDP packet's payload as a GTP-U packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the UDP destination port is not [`GTPU_PORT`].
+    /// Returns an error if the version is not 1 or the protocol type is
+    /// not GTP. Returns an error if the payload does not have sufficient
+    /// data for the header and the extension headers.
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.dst_port() == GTPU_PORT,
+            (anyhow!("not a GTP-U packet."), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data::<GtpUHeader>(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        let flags = unsafe { header.as_ref() }.flags;
+        ensure!(
+            flags >> 5 == 1 && flags & FLAGS_PT != 0,
+            (anyhow!("not a GTPv1 packet."), envelope)
+        );
+
+        let header_len = if flags & (FLAGS_E | FLAGS_S | FLAGS_PN) != 0 {
+            match ExtensionHeadersIterator::new(mbuf, offset).and_then(|mut iter| {
+                while iter.next()?.is_some() {}
+                Ok(iter.offset - offset)
+            }) {
+                Err(e) => return Err((e, envelope)),
+                Ok(len) => len,
+            }
+        } else {
+            GtpUHeader::size_of()
+        };
+
+        Ok(GtpU {
+            envelope,
+            header,
+            offset,
+            header_len,
+        })
+    }
+
+    /// Prepends a GTP-U packet to the beginning of the UDP packet's
+    /// payload.
+    ///
+    /// The UDP destination port is set to [`GTPU_PORT`], and the message
+    /// type is set to [`G_PDU`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, GtpUHeader::size_of())?;
+        let header = mbuf.write_data(offset, &GtpUHeader::default())?;
+
+        envelope.set_dst_port(GTPU_PORT);
+
+        Ok(GtpU {
+            envelope,
+            header,
+            offset,
+            header_len: GtpUHeader::size_of(),
+        })
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+
+    /// Reconciles the derivable header fields against the changes made to
+    /// the packet.
+    ///
+    /// * [`length`] is set to the length of the packet excluding the
+    /// mandatory header.
+    ///
+    /// [`length`]: GtpU::length
+    #[inline]
+    fn reconcile(&mut self) {
+        let len = self.len() - GtpUHeader::size_of();
+        self.set_length(len as u16);
+    }
+}
+
+impl<E: IpPacket> Datalink for GtpU<E> {
+    /// Returns the protocol type of a [`G_PDU`] packet's payload, inferred
+    /// from its IP version. Returns `0` for other message types or if the
+    /// payload is not an IP packet.
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        if self.message_type() == G_PDU {
+            ip::version_ether_type(self.mbuf(), self.payload_offset())
+        } else {
+            EtherType::default()
+        }
+    }
+
+    /// GTP-U does not carry the protocol type of the payload. This is a
+    /// no-op.
+    #[inline]
+    fn set_protocol_type(&mut self, _ether_type: EtherType) {}
+}
+
+/// A GTP-U extension header.
+pub struct ExtensionHeader<'a> {
+    header_type: u8,
+    contents: &'a [u8],
+}
+
+impl ExtensionHeader<'_> {
+    /// Returns the extension header type.
+    #[inline]
+    pub fn header_type(&self) -> u8 {
+        self.header_type
+    }
+
+    /// Returns the contents, excluding the length and the next extension
+    /// header type fields.
+    #[inline]
+    pub fn contents(&self) -> &[u8] {
+        self.contents
+    }
+}
+
+impl fmt::Debug for ExtensionHeader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionHeader")
+            .field("header_type", &format!("0x{:02x}", self.header_type()))
+            .field("length", &self.contents().len())
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the extension headers in the GTP-U
+/// header.
+pub struct ExtensionHeadersIterator<'a> {
+    mbuf: &'a Mbuf,
+    offset: usize,
+    next_type: u8,
+}
+
+impl<'a> ExtensionHeadersIterator<'a> {
+    /// Creates an iterator for the GTP-U header at the offset.
+    fn new(mbuf: &'a Mbuf, offset: usize) -> Result<Self> {
+        let flags = unsafe { *mbuf.read_data::<u8>(offset)?.as_ref() };
+        let mut offset = offset + GtpUHeader::size_of();
+        let mut next_type = 0;
+
+        if flags & (FLAGS_E | FLAGS_S | FLAGS_PN) != 0 {
+            let optional = mbuf.read_data_slice::<u8>(offset, OPTIONAL_LEN)?;
+            if flags & FLAGS_E != 0 {
+                next_type = unsafe { optional.as_ref() }[OPTIONAL_LEN - 1];
+            }
+            offset += OPTIONAL_LEN;
+        }
+
+        Ok(ExtensionHeadersIterator {
+            mbuf,
+            offset,
+            next_type,
+        })
+    }
+
+    /// Advances the iterator and returns the next value.
+    ///
+    /// Returns `Ok(None)` when iteration is finished; returns `Err` when
+    /// an extension header has a zero length or exceeds the buffer.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<ExtensionHeader<'a>>> {
+        if self.next_type == 0 {
+            return Ok(None);
+        }
+
+        let len = unsafe { *self.mbuf.read_data::<u8>(self.offset)?.as_ref() } as usize * 4;
+        ensure!(len > 0, anyhow!("invalid GTP-U extension header length 0."));
+        let data = self.mbuf.read_data_slice::<u8>(self.offset, len)?;
+        let data = unsafe { &*data.as_ptr() };
+
+        let header_type = self.next_type;
+        self.next_type = data[len - 1];
+        self.offset += len;
+        Ok(Some(ExtensionHeader {
+            header_type,
+            contents: &data[1..len - 1],
+        }))
+    }
+}
+
+impl fmt::Debug for ExtensionHeadersIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionHeadersIterator")
+            .field("offset", &self.offset)
+            .field("next_type", &self.next_type)
+            .finish()
+    }
+}
+
+/// GTP-U header.
+///
+/// The header only include the mandatory portion of the GTP-U header. The
+/// optional fields and the extension headers are handled separately.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C)]
+struct GtpUHeader {
+    flags: u8,
+    message_type: u8,
+    length: u16be,
+    teid: u32be,
+}
+
+impl Default for GtpUHeader {
+    fn default() -> GtpUHeader {
+        GtpUHeader {
+            flags: VERSION_PT,
+            message_type: G_PDU,
+            length: u16be::default(),
+            teid: u32be::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::testils::byte_arrays::{GTPU_PACKET, IPV4_UDP_PACKET};
+
+    #[test]
+    fn size_of_gtpu_header() {
+        assert_eq!(8, GtpUHeader::size_of());
+    }
+
+    #[capsule::test]
+    fn parse_gtpu_packet() {
+        let packet = Mbuf::from_bytes(&GTPU_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        let gtpu = udp.parse::<GtpU<Ipv4>>().unwrap();
+
+        assert_eq!(1, gtpu.version());
+        assert_eq!(G_PDU, gtpu.message_type());
+        assert_eq!(41, gtpu.length());
+        assert_eq!(1234, gtpu.teid());
+        assert_eq!(Some(7), gtpu.sequence());
+        assert_eq!(16, gtpu.header_len());
+        assert_eq!(EtherTypes::Ipv4, gtpu.protocol_type());
+
+        let mut iter = gtpu.extension_headers_iter();
+        let header = iter.next().unwrap().unwrap();
+        assert_eq!(PDU_SESSION_CONTAINER, header.header_type());
+        assert_eq!(&[0x10, 0x09], header.contents());
+        assert!(iter.next().unwrap().is_none());
+
+        let inner = gtpu.parse::<Ipv4<GtpU<Ipv4>>>().unwrap();
+        assert_eq!("192.168.0.1", inner.src().to_string());
+        assert_eq!("192.168.0.2", inner.dst().to_string());
+    }
+
+    #[capsule::test]
+    fn parse_non_gtpu_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+
+        assert!(udp.parse::<GtpU<Ipv4>>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_gtpu_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let udp = ipv6.push::<Udp<Ipv6>>().unwrap();
+        let mut gtpu = udp.push::<GtpU<Ipv6>>().unwrap();
+
+        assert_eq!(GtpUHeader::size_of(), gtpu.len());
+        assert_eq!(GTPU_PORT, gtpu.envelope().dst_port());
+        assert_eq!(1, gtpu.version());
+        assert_eq!(G_PDU, gtpu.message_type());
+        assert_eq!(None, gtpu.sequence());
+
+        gtpu.set_teid(0x1234_5678);
+        assert_eq!(0x1234_5678, gtpu.teid());
+
+        gtpu.set_sequence(5).unwrap();
+        assert_eq!(Some(5), gtpu.sequence());
+        assert_eq!(12, gtpu.header_len());
+
+        assert!(gtpu
+            .push_extension_header(PDU_SESSION_CONTAINER, &[0x00])
+            .is_err());
+        gtpu.push_extension_header(PDU_SESSION_CONTAINER, &[0x00, 0x05])
+            .unwrap();
+        assert_eq!(16, gtpu.header_len());
+        let mut iter = gtpu.extension_headers_iter();
+        let header = iter.next().unwrap().unwrap();
+        assert_eq!(PDU_SESSION_CONTAINER, header.header_type());
+        assert_eq!(&[0x00, 0x05], header.contents());
+        assert!(iter.next().unwrap().is_none());
+
+        gtpu.remove_extension_headers().unwrap();
+        assert_eq!(12, gtpu.header_len());
+        assert!(gtpu.extension_headers_iter().next().unwrap().is_none());
+        gtpu.remove_sequence().unwrap();
+        assert_eq!(8, gtpu.header_len());
+        assert_eq!(None, gtpu.sequence());
+
+        let mut inner = gtpu.push::<Ipv4<GtpU<Ipv6>>>().unwrap();
+        inner.reconcile_all();
+        let gtpu = inner.envelope();
+        assert_eq!(20, gtpu.length());
+        assert_eq!(EtherTypes::Ipv4, gtpu.protocol_type());
+    }
+
+    #[capsule::test]
+    fn encap_and_decap_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let frame = packet.parse::<Ethernet>().unwrap();
+        let inner_len = frame.payload_len();
+
+        let gtpu = GtpU::<Ipv4>::encap(frame, 1234).unwrap();
+        assert_eq!(1234, gtpu.teid());
+        assert_eq!(inner_len as u16, gtpu.length());
+        assert_eq!(GTPU_PORT, gtpu.envelope().src_port());
+        assert_eq!(GTPU_PORT, gtpu.envelope().dst_port());
+        assert_eq!((inner_len + 8 + 8) as u16, gtpu.envelope().length());
+        assert_eq!(
+            (inner_len + 8 + 8 + 20) as u16,
+            gtpu.envelope().envelope().total_length()
+        );
+
+        let frame = gtpu.decap().unwrap();
+        assert_eq!(EtherTypes::Ipv4, frame.ether_type());
+        let data = frame
+            .mbuf()
+            .read_data_slice::<u8>(0, frame.mbuf().data_len())
+            .unwrap();
+        assert_eq!(&IPV4_UDP_PACKET[..], unsafe { data.as_ref() });
+    }
+}
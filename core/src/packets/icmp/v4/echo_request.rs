@@ -100,6 +100,13 @@ impl EchoRequest {
     }
 
     /// Returns the data as a `u8` slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data spans more than one segment. Use [`data_to_vec`]
+    /// to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         if let Ok(data) = self
@@ -108,6 +115,20 @@ impl EchoRequest {
             .read_data_slice(self.data_offset(), self.data_len())
         {
             unsafe { &*data.as_ptr() }
+        } else {
+            panic!("data spans more than one segment.")
+        }
+    }
+
+    /// Returns a copy of the data, which may span more than one segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .data_slice(self.data_offset(), self.data_len())
+        {
+            data.into_owned()
         } else {
             unreachable!()
         }
@@ -123,7 +144,7 @@ impl EchoRequest {
         let offset = self.data_offset();
         let len = data.len() as isize - self.data_len() as isize;
         self.icmp_mut().mbuf_mut().resize(offset, len)?;
-        self.icmp_mut().mbuf_mut().write_data_slice(offset, data)?;
+        self.icmp_mut().mbuf_mut().copy_from_slice(offset, data)?;
         Ok(())
     }
 }
@@ -256,4 +277,17 @@ mod tests {
         echo.reconcile_all();
         assert!(echo.checksum() != 0);
     }
+
+    #[capsule::test]
+    fn set_echo_request_data_across_segments() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let mut echo = ipv4.push::<EchoRequest>().unwrap();
+
+        let data = (0..4000).map(|i| i as u8).collect::<Vec<_>>();
+        assert!(echo.set_data(&data).is_ok());
+        assert!(echo.mbuf().is_chained());
+        assert_eq!(data, echo.data_to_vec());
+    }
 }
@@ -20,13 +20,19 @@
 
 mod echo_reply;
 mod echo_request;
+mod parameter_problem;
 mod redirect;
+mod source_quench;
 mod time_exceeded;
+mod unreachable;
 
 pub use self::echo_reply::*;
 pub use self::echo_request::*;
+pub use self::parameter_problem::*;
 pub use self::redirect::*;
+pub use self::source_quench::*;
 pub use self::time_exceeded::*;
+pub use self::unreachable::*;
 pub use capsule_macros::Icmpv4Packet;
 
 use crate::ensure;
@@ -118,9 +124,9 @@ impl Icmpv4 {
     pub fn compute_checksum(&mut self) {
         self.header_mut().checksum = u16be::default();
 
-        if let Ok(data) = self.mbuf().read_data_slice(self.offset(), self.len()) {
-            let data = unsafe { data.as_ref() };
-            let checksum = checksum::compute(0, data);
+        if let Ok(data) = self.mbuf().data_slice(self.offset(), self.len()) {
+            let checksum = checksum::compute(0, &data);
+            drop(data);
             self.header_mut().checksum = checksum.into();
         } else {
             // we are reading till the end of buffer, should never run out
@@ -293,6 +299,21 @@ pub mod Icmpv4Types {
     ///
     /// [Redirect]: crate::packets::icmp::v4::Redirect
     pub const Redirect: Icmpv4Type = Icmpv4Type(5);
+
+    /// Message type for [Destination Unreachable].
+    ///
+    /// [Destination Unreachable]: crate::packets::icmp::v4::DestinationUnreachable
+    pub const DestinationUnreachable: Icmpv4Type = Icmpv4Type(3);
+
+    /// Message type for [Source Quench].
+    ///
+    /// [Source Quench]: crate::packets::icmp::v4::SourceQuench
+    pub const SourceQuench: Icmpv4Type = Icmpv4Type(4);
+
+    /// Message type for [Parameter Problem].
+    ///
+    /// [Parameter Problem]: crate::packets::icmp::v4::ParameterProblem
+    pub const ParameterProblem: Icmpv4Type = Icmpv4Type(12);
 }
 
 impl fmt::Display for Icmpv4Type {
@@ -305,6 +326,9 @@ impl fmt::Display for Icmpv4Type {
                 Icmpv4Types::EchoReply => "Echo Reply".to_string(),
                 Icmpv4Types::TimeExceeded => "Time Exceeded".to_string(),
                 Icmpv4Types::Redirect => "Redirect".to_string(),
+                Icmpv4Types::DestinationUnreachable => "Destination Unreachable".to_string(),
+                Icmpv4Types::SourceQuench => "Source Quench".to_string(),
+                Icmpv4Types::ParameterProblem => "Parameter Problem".to_string(),
                 _ => format!("{}", self.0),
             }
         )
@@ -82,6 +82,11 @@ impl Redirect {
     }
 
     /// Returns the data packet as a `u8` slice.
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         if let Ok(data) = self
@@ -94,6 +99,21 @@ impl Redirect {
             &[]
         }
     }
+
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .data_slice(self.data_offset(), self.data_len())
+        {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 impl fmt::Debug for Redirect {
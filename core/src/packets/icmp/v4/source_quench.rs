@@ -0,0 +1,253 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::packets::icmp::v4::{Icmpv4, Icmpv4Message, Icmpv4Packet, Icmpv4Type, Icmpv4Types};
+use crate::packets::ip::v4::IPV4_MIN_MTU;
+use crate::packets::types::u32be;
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{Result, Error};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// Source Quench Message defined in [IETF RFC 792].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |     Code      |          Checksum             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                             Unused                            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |    Internet Header + 64 bits of Original Data Datagram        |
+/// ```
+///
+/// Source quench was deprecated by [IETF RFC 6633]. Hosts should not
+/// generate it and should silently discard any received.
+///
+/// [IETF RFC 792]: https://tools.ietf.org/html/rfc792
+/// [IETF RFC 6633]: https://tools.ietf.org/html/rfc6633
+#[derive(Icmpv4Packet)]
+pub struct SourceQuench {
+    icmp: Icmpv4,
+    body: NonNull<SourceQuenchBody>,
+}
+
+impl SourceQuench {
+    /// Returns the offset where the data field in the message body starts.
+    #[inline]
+    fn data_offset(&self) -> usize {
+        self.payload_offset() + SourceQuenchBody::size_of()
+    }
+
+    /// Returns the length of the data field in the message body.
+    #[inline]
+    fn data_len(&self) -> usize {
+        self.payload_len() - SourceQuenchBody::size_of()
+    }
+
+    /// Returns the invoking packet as a `u8` slice.
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .read_data_slice(self.data_offset(), self.data_len())
+        {
+            unsafe { &*data.as_ptr() }
+        } else {
+            &[]
+        }
+    }
+
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .data_slice(self.data_offset(), self.data_len())
+        {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl fmt::Debug for SourceQuench {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceQuench")
+            .field("type", &format!("{}", self.msg_type()))
+            .field("code", &self.code())
+            .field("checksum", &format!("0x{:04x}", self.checksum()))
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl Icmpv4Message for SourceQuench {
+    #[inline]
+    fn msg_type() -> Icmpv4Type {
+        Icmpv4Types::SourceQuench
+    }
+
+    #[inline]
+    fn icmp(&self) -> &Icmpv4 {
+        &self.icmp
+    }
+
+    #[inline]
+    fn icmp_mut(&mut self) -> &mut Icmpv4 {
+        &mut self.icmp
+    }
+
+    #[inline]
+    fn into_icmp(self) -> Icmpv4 {
+        self.icmp
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        SourceQuench {
+            icmp: self.icmp.clone(internal),
+            body: self.body,
+        }
+    }
+
+    /// Parses the ICMPv4 packet's payload as source quench.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not have sufficient data for
+    /// the source quench message body.
+    #[inline]
+    fn try_parse(icmp: Icmpv4, _internal: Internal) -> Result<Self, (Error, Icmpv4)> {
+        let mbuf = icmp.mbuf();
+        let offset = icmp.payload_offset();
+        let body = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, icmp)),
+            Ok(body) => body
+        };
+
+        Ok(SourceQuench { icmp, body })
+    }
+
+    /// Prepends a new source quench message to the beginning of the ICMPv4's
+    /// payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut icmp: Icmpv4, _internal: Internal) -> Result<Self> {
+        let offset = icmp.payload_offset();
+        let mbuf = icmp.mbuf_mut();
+
+        mbuf.extend(offset, SourceQuenchBody::size_of())?;
+        let body = mbuf.write_data(offset, &SourceQuenchBody::default())?;
+
+        Ok(SourceQuench { icmp, body })
+    }
+
+    /// Reconciles the derivable header fields against the changes made to
+    /// the packet.
+    ///
+    /// * the data field in the message body is trimmed if it exceeds the
+    /// [minimum IPV4 MTU], as we only need enough for port information.
+    /// * [`checksum`] is computed based on the `SourceQuench` message.
+    ///
+    /// [minimum IPv4 MTU]: IPV4_MIN_MTU
+    /// [`checksum`]: Icmpv4::checksum
+    #[inline]
+    fn reconcile(&mut self) {
+        let len = self.data_len();
+        let offset = self.data_offset();
+
+        if len > IPV4_MIN_MTU {
+            let _ = self
+                .mbuf_mut()
+                .shrink(offset + IPV4_MIN_MTU, len - IPV4_MIN_MTU);
+        }
+
+        self.icmp_mut().compute_checksum();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C, packed)]
+struct SourceQuenchBody {
+    _unused: u32be,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::IPV4_TCP_PACKET;
+
+    #[test]
+    fn size_of_source_quench_body() {
+        assert_eq!(4, SourceQuenchBody::size_of());
+    }
+
+    #[capsule::test]
+    fn push_and_set_source_quench() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let tcp_len = ipv4.payload_len();
+
+        let mut quench = ipv4.push::<SourceQuench>().unwrap();
+
+        assert_eq!(4, quench.header_len());
+        assert_eq!(
+            SourceQuenchBody::size_of() + tcp_len,
+            quench.payload_len()
+        );
+        assert_eq!(Icmpv4Types::SourceQuench, quench.msg_type());
+        assert_eq!(0, quench.code());
+        assert_eq!(tcp_len, quench.data().len());
+
+        quench.reconcile_all();
+        assert!(quench.checksum() != 0);
+    }
+
+    #[capsule::test]
+    fn shrinks_to_ipv4_min_mtu() {
+        // starts with a buffer with a message body larger than min MTU.
+        let packet = Mbuf::from_bytes(&[42; 100]).unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let mut quench = ipv4.push::<SourceQuench>().unwrap();
+        assert!(quench.data_len() > IPV4_MIN_MTU);
+
+        quench.reconcile_all();
+        assert_eq!(IPV4_MIN_MTU, quench.data_len());
+    }
+}
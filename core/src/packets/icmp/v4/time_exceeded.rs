@@ -58,6 +58,11 @@ impl TimeExceeded {
     }
 
     /// Returns the invoking packet as a `u8` slice.
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         if let Ok(data) = self
@@ -70,6 +75,21 @@ impl TimeExceeded {
             &[]
         }
     }
+
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .data_slice(self.data_offset(), self.data_len())
+        {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 impl fmt::Debug for TimeExceeded {
@@ -0,0 +1,381 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::packets::icmp::v4::{Icmpv4, Icmpv4Message, Icmpv4Packet, Icmpv4Type, Icmpv4Types};
+use crate::packets::ip::v4::IPV4_MIN_MTU;
+use crate::packets::types::u16be;
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{Result, Error};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// Destination Unreachable Message defined in [IETF RFC 792].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |     Code      |          Checksum             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |           Unused              |         Next-Hop MTU          |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |      Internet Header + 64 bits of Original Data Datagram      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// *Code*: The reason the datagram could not be delivered. The known
+///         values are under [`UnreachableCodes`].
+///
+/// *Next-Hop MTU*: The MTU of the next-hop network when the code is
+///         [`FragmentationNeeded`], as defined in [IETF RFC 1191].
+///         Otherwise the field is unused and must be zero.
+///
+/// [IETF RFC 792]: https://tools.ietf.org/html/rfc792
+/// [IETF RFC 1191]: https://tools.ietf.org/html/rfc1191#section-4
+/// [`UnreachableCodes`]: UnreachableCodes
+/// [`FragmentationNeeded`]: UnreachableCodes::FragmentationNeeded
+#[derive(Icmpv4Packet)]
+pub struct DestinationUnreachable {
+    icmp: Icmpv4,
+    body: NonNull<DestinationUnreachableBody>,
+}
+
+impl DestinationUnreachable {
+    #[inline]
+    fn body(&self) -> &DestinationUnreachableBody {
+        unsafe { self.body.as_ref() }
+    }
+
+    #[inline]
+    fn body_mut(&mut self) -> &mut DestinationUnreachableBody {
+        unsafe { self.body.as_mut() }
+    }
+
+    /// Returns the next-hop MTU.
+    ///
+    /// Only meaningful when the code is [`FragmentationNeeded`].
+    ///
+    /// [`FragmentationNeeded`]: UnreachableCodes::FragmentationNeeded
+    #[inline]
+    pub fn next_hop_mtu(&self) -> u16 {
+        self.body().next_hop_mtu.into()
+    }
+
+    /// Sets the next-hop MTU.
+    #[inline]
+    pub fn set_next_hop_mtu(&mut self, mtu: u16) {
+        self.body_mut().next_hop_mtu = mtu.into();
+    }
+
+    /// Returns the offset where the data field in the message body starts.
+    #[inline]
+    fn data_offset(&self) -> usize {
+        self.payload_offset() + DestinationUnreachableBody::size_of()
+    }
+
+    /// Returns the length of the data field in the message body.
+    #[inline]
+    fn data_len(&self) -> usize {
+        self.payload_len() - DestinationUnreachableBody::size_of()
+    }
+
+    /// Returns the invoking packet as a `u8` slice.
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .read_data_slice(self.data_offset(), self.data_len())
+        {
+            unsafe { &*data.as_ptr() }
+        } else {
+            &[]
+        }
+    }
+
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .data_slice(self.data_offset(), self.data_len())
+        {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl fmt::Debug for DestinationUnreachable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DestinationUnreachable")
+            .field("type", &format!("{}", self.msg_type()))
+            .field("code", &self.code())
+            .field("checksum", &format!("0x{:04x}", self.checksum()))
+            .field("next_hop_mtu", &self.next_hop_mtu())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl Icmpv4Message for DestinationUnreachable {
+    #[inline]
+    fn msg_type() -> Icmpv4Type {
+        Icmpv4Types::DestinationUnreachable
+    }
+
+    #[inline]
+    fn icmp(&self) -> &Icmpv4 {
+        &self.icmp
+    }
+
+    #[inline]
+    fn icmp_mut(&mut self) -> &mut Icmpv4 {
+        &mut self.icmp
+    }
+
+    #[inline]
+    fn into_icmp(self) -> Icmpv4 {
+        self.icmp
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        DestinationUnreachable {
+            icmp: self.icmp.clone(internal),
+            body: self.body,
+        }
+    }
+
+    /// Parses the ICMPv4 packet's payload as destination unreachable.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not have sufficient data for
+    /// the destination unreachable message body.
+    #[inline]
+    fn try_parse(icmp: Icmpv4, _internal: Internal) -> Result<Self, (Error, Icmpv4)> {
+        let mbuf = icmp.mbuf();
+        let offset = icmp.payload_offset();
+        let body = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, icmp)),
+            Ok(body) => body
+        };
+
+        Ok(DestinationUnreachable { icmp, body })
+    }
+
+    /// Prepends a new destination unreachable message to the beginning of
+    /// the ICMPv4's payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut icmp: Icmpv4, _internal: Internal) -> Result<Self> {
+        let offset = icmp.payload_offset();
+        let mbuf = icmp.mbuf_mut();
+
+        mbuf.extend(offset, DestinationUnreachableBody::size_of())?;
+        let body = mbuf.write_data(offset, &DestinationUnreachableBody::default())?;
+
+        Ok(DestinationUnreachable { icmp, body })
+    }
+
+    /// Reconciles the derivable header fields against the changes made to
+    /// the packet.
+    ///
+    /// * the data field in the message body is trimmed if it exceeds the
+    /// [minimum IPV4 MTU], as we only need enough for port information.
+    /// * [`checksum`] is computed based on the `DestinationUnreachable`
+    /// message.
+    ///
+    /// [minimum IPv4 MTU]: IPV4_MIN_MTU
+    /// [`checksum`]: Icmpv4::checksum
+    #[inline]
+    fn reconcile(&mut self) {
+        let len = self.data_len();
+        let offset = self.data_offset();
+
+        if len > IPV4_MIN_MTU {
+            let _ = self
+                .mbuf_mut()
+                .shrink(offset + IPV4_MIN_MTU, len - IPV4_MIN_MTU);
+        }
+
+        self.icmp_mut().compute_checksum();
+    }
+}
+
+/// Destination unreachable codes defined in [IETF RFC 792], [IETF RFC 1122]
+/// and [IETF RFC 1812].
+///
+/// [IETF RFC 792]: https://tools.ietf.org/html/rfc792
+/// [IETF RFC 1122]: https://tools.ietf.org/html/rfc1122#section-3.2.2.1
+/// [IETF RFC 1812]: https://tools.ietf.org/html/rfc1812#section-5.2.7.1
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod UnreachableCodes {
+    /// Net unreachable.
+    pub const NetUnreachable: u8 = 0;
+
+    /// Host unreachable.
+    pub const HostUnreachable: u8 = 1;
+
+    /// Protocol unreachable.
+    pub const ProtocolUnreachable: u8 = 2;
+
+    /// Port unreachable.
+    pub const PortUnreachable: u8 = 3;
+
+    /// Fragmentation needed and DF set.
+    pub const FragmentationNeeded: u8 = 4;
+
+    /// Source route failed.
+    pub const SourceRouteFailed: u8 = 5;
+
+    /// Destination network unknown.
+    pub const NetUnknown: u8 = 6;
+
+    /// Destination host unknown.
+    pub const HostUnknown: u8 = 7;
+
+    /// Source host isolated.
+    pub const SourceHostIsolated: u8 = 8;
+
+    /// Communication with destination network is administratively
+    /// prohibited.
+    pub const NetProhibited: u8 = 9;
+
+    /// Communication with destination host is administratively
+    /// prohibited.
+    pub const HostProhibited: u8 = 10;
+
+    /// Destination network unreachable for type of service.
+    pub const NetUnreachableForTos: u8 = 11;
+
+    /// Destination host unreachable for type of service.
+    pub const HostUnreachableForTos: u8 = 12;
+
+    /// Communication administratively prohibited.
+    pub const CommunicationProhibited: u8 = 13;
+
+    /// Host precedence violation.
+    pub const HostPrecedenceViolation: u8 = 14;
+
+    /// Precedence cutoff in effect.
+    pub const PrecedenceCutoff: u8 = 15;
+}
+
+/// The ICMPv4 Destination Unreachable message body.
+///
+/// This contains only the fixed portion of the message body.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C, packed)]
+struct DestinationUnreachableBody {
+    _unused: u16be,
+    next_hop_mtu: u16be,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::IPV4_TCP_PACKET;
+
+    #[test]
+    fn size_of_destination_unreachable_body() {
+        assert_eq!(4, DestinationUnreachableBody::size_of());
+    }
+
+    #[capsule::test]
+    fn push_and_set_destination_unreachable() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let tcp_len = ipv4.payload_len();
+
+        let mut unreachable = ipv4.push::<DestinationUnreachable>().unwrap();
+
+        assert_eq!(4, unreachable.header_len());
+        assert_eq!(
+            DestinationUnreachableBody::size_of() + tcp_len,
+            unreachable.payload_len()
+        );
+        assert_eq!(Icmpv4Types::DestinationUnreachable, unreachable.msg_type());
+        assert_eq!(0, unreachable.code());
+        assert_eq!(0, unreachable.next_hop_mtu());
+        assert_eq!(tcp_len, unreachable.data().len());
+
+        unreachable.set_code(UnreachableCodes::PortUnreachable);
+        assert_eq!(UnreachableCodes::PortUnreachable, unreachable.code());
+
+        unreachable.reconcile_all();
+        assert!(unreachable.checksum() != 0);
+    }
+
+    #[capsule::test]
+    fn fragmentation_needed_with_next_hop_mtu() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let mut unreachable = ipv4.push::<DestinationUnreachable>().unwrap();
+        unreachable.set_code(UnreachableCodes::FragmentationNeeded);
+        unreachable.set_next_hop_mtu(1400);
+        unreachable.reconcile_all();
+
+        // reparses the message from the raw bytes.
+        let ipv4 = unreachable.deparse();
+        let mut icmpv4 = ipv4.parse::<Icmpv4>().unwrap();
+        assert_eq!(Icmpv4Types::DestinationUnreachable, icmpv4.msg_type());
+
+        let checksum = icmpv4.checksum();
+        icmpv4.compute_checksum();
+        assert_eq!(checksum, icmpv4.checksum());
+
+        let unreachable = icmpv4.downcast::<DestinationUnreachable>().unwrap();
+        assert_eq!(UnreachableCodes::FragmentationNeeded, unreachable.code());
+        assert_eq!(1400, unreachable.next_hop_mtu());
+    }
+
+    #[capsule::test]
+    fn shrinks_to_ipv4_min_mtu() {
+        // starts with a buffer with a message body larger than min MTU.
+        let packet = Mbuf::from_bytes(&[42; 100]).unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let mut unreachable = ipv4.push::<DestinationUnreachable>().unwrap();
+        assert!(unreachable.data_len() > IPV4_MIN_MTU);
+
+        unreachable.reconcile_all();
+        assert_eq!(IPV4_MIN_MTU, unreachable.data_len());
+    }
+}
@@ -101,6 +101,13 @@ impl<E: Ipv6Packet> EchoRequest<E> {
     }
 
     /// Returns the data as a `u8` slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data spans more than one segment. Use [`data_to_vec`]
+    /// to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         if let Ok(data) = self
@@ -109,6 +116,20 @@ impl<E: Ipv6Packet> EchoRequest<E> {
             .read_data_slice(self.data_offset(), self.data_len())
         {
             unsafe { &*data.as_ptr() }
+        } else {
+            panic!("data spans more than one segment.")
+        }
+    }
+
+    /// Returns a copy of the data, which may span more than one segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .icmp()
+            .mbuf()
+            .data_slice(self.data_offset(), self.data_len())
+        {
+            data.into_owned()
         } else {
             unreachable!()
         }
@@ -124,7 +145,7 @@ impl<E: Ipv6Packet> EchoRequest<E> {
         let offset = self.data_offset();
         let len = data.len() as isize - self.data_len() as isize;
         self.icmp_mut().mbuf_mut().resize(offset, len)?;
-        self.icmp_mut().mbuf_mut().write_data_slice(offset, data)?;
+        self.icmp_mut().mbuf_mut().copy_from_slice(offset, data)?;
         Ok(())
     }
 }
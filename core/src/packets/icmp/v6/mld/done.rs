@@ -0,0 +1,196 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::MldBody;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Message, Icmpv6Packet, Icmpv6Type, Icmpv6Types};
+use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{Result, Error};
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::ptr::NonNull;
+
+/// Multicast Listener Done Message defined in [IETF RFC 2710].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |     Code      |          Checksum             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Maximum Response Delay    |          Reserved             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// +                                                               +
+/// |                                                               |
+/// +                       Multicast Address                       +
+/// |                                                               |
+/// +                                                               +
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Maximum Response Delay*:
+///                     Set to zero by the sender and ignored by the
+///                     receiver.
+///
+/// - *Multicast Address*:
+///                     The IPv6 multicast address to which the sender is
+///                     ceasing to listen.
+///
+/// [IETF RFC 2710]: https://tools.ietf.org/html/rfc2710#section-3
+/// [`Mldv2Query`]: crate::packets::icmp::v6::mld::Mldv2Query
+#[derive(Icmpv6Packet)]
+pub struct MulticastListenerDone<E: Ipv6Packet> {
+    icmp: Icmpv6<E>,
+    body: NonNull<MldBody>,
+}
+
+impl<E: Ipv6Packet> MulticastListenerDone<E> {
+    #[inline]
+    fn body(&self) -> &MldBody {
+        unsafe { self.body.as_ref() }
+    }
+
+    #[inline]
+    fn body_mut(&mut self) -> &mut MldBody {
+        unsafe { self.body.as_mut() }
+    }
+
+    /// Returns the multicast address the sender is ceasing to listen to.
+    #[inline]
+    pub fn multicast_address(&self) -> Ipv6Addr {
+        self.body().multicast_address
+    }
+
+    /// Sets the multicast address the sender is ceasing to listen to.
+    #[inline]
+    pub fn set_multicast_address(&mut self, addr: Ipv6Addr) {
+        self.body_mut().multicast_address = addr;
+    }
+
+}
+
+impl<E: Ipv6Packet> fmt::Debug for MulticastListenerDone<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MulticastListenerDone")
+            .field("type", &format!("{}", self.msg_type()))
+            .field("code", &self.code())
+            .field("checksum", &format!("0x{:04x}", self.checksum()))
+            .field("multicast_address", &self.multicast_address())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: Ipv6Packet> Icmpv6Message for MulticastListenerDone<E> {
+    type Envelope = E;
+
+    #[inline]
+    fn msg_type() -> Icmpv6Type {
+        Icmpv6Types::MulticastListenerDone
+    }
+
+    #[inline]
+    fn icmp(&self) -> &Icmpv6<Self::Envelope> {
+        &self.icmp
+    }
+
+    #[inline]
+    fn icmp_mut(&mut self) -> &mut Icmpv6<Self::Envelope> {
+        &mut self.icmp
+    }
+
+    #[inline]
+    fn into_icmp(self) -> Icmpv6<Self::Envelope> {
+        self.icmp
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        MulticastListenerDone {
+            icmp: self.icmp.clone(internal),
+            body: self.body,
+        }
+    }
+
+    /// Parses the ICMPv6 packet's payload as multicast listener done.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not have sufficient data for
+    /// the multicast listener done message body.
+    #[inline]
+    fn try_parse(icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self, (Error, Icmpv6<Self::Envelope>)> {
+        let mbuf = icmp.mbuf();
+        let offset = icmp.payload_offset();
+        let body = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, icmp)),
+            Ok(body) => body
+        };
+
+        Ok(MulticastListenerDone { icmp, body })
+    }
+
+    /// Prepends a new multicast listener done message to the beginning
+    /// of the ICMPv6's payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self> {
+        let offset = icmp.payload_offset();
+        let mbuf = icmp.mbuf_mut();
+
+        mbuf.extend(offset, MldBody::size_of())?;
+        let body = mbuf.write_data(offset, &MldBody::default())?;
+
+        Ok(MulticastListenerDone { icmp, body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::Mbuf;
+
+    #[capsule::test]
+    fn push_and_set_multicast_listener_done() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut done = ipv6.push::<MulticastListenerDone<Ipv6>>().unwrap();
+
+        assert_eq!(4, done.header_len());
+        assert_eq!(MldBody::size_of(), done.payload_len());
+        assert_eq!(Icmpv6Types::MulticastListenerDone, done.msg_type());
+        assert_eq!(0, done.code());
+
+        let addr = "ff02::1:ff00:1".parse().unwrap();
+        done.set_multicast_address(addr);
+        assert_eq!(addr, done.multicast_address());
+
+        done.reconcile_all();
+        assert!(done.checksum() != 0);
+    }
+}
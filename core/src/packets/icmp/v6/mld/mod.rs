@@ -0,0 +1,93 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Multicast Listener Discovery
+//!
+//! MLD is used by IPv6 routers to discover the presence of multicast
+//! listeners on their directly attached links, and to discover which
+//! multicast addresses are of interest to those listeners. Version 1 is
+//! defined in [IETF RFC 2710] and version 2, which adds source filtering,
+//! is defined in [IETF RFC 3810].
+//!
+//! MLDv1 and MLDv2 queries share the same message type. They are told
+//! apart by the length of the message. An MLDv2 query is at least 28
+//! octets long.
+//!
+//! [IETF RFC 2710]: https://tools.ietf.org/html/rfc2710
+//! [IETF RFC 3810]: https://tools.ietf.org/html/rfc3810
+
+mod done;
+mod query;
+mod report;
+mod v2_query;
+mod v2_report;
+
+pub use self::done::*;
+pub use self::query::*;
+pub use self::report::*;
+pub use self::v2_query::*;
+pub use self::v2_report::*;
+
+use crate::packets::types::u16be;
+use crate::packets::SizeOf;
+use std::net::Ipv6Addr;
+
+/// The MLDv1 message body shared by query, report and done messages.
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Maximum Response Delay    |          Reserved             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// +                                                               +
+/// |                                                               |
+/// +                       Multicast Address                       +
+/// |                                                               |
+/// +                                                               +
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct MldBody {
+    max_response_delay: u16be,
+    _reserved: u16be,
+    multicast_address: Ipv6Addr,
+}
+
+impl Default for MldBody {
+    fn default() -> Self {
+        MldBody {
+            max_response_delay: u16be::default(),
+            _reserved: u16be::default(),
+            multicast_address: Ipv6Addr::UNSPECIFIED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_of_mld_body() {
+        assert_eq!(20, MldBody::size_of());
+    }
+}
@@ -0,0 +1,224 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::MldBody;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Message, Icmpv6Packet, Icmpv6Type, Icmpv6Types};
+use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{Result, Error};
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::ptr::NonNull;
+
+/// Multicast Listener Query Message defined in [IETF RFC 2710].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |     Code      |          Checksum             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Maximum Response Delay    |          Reserved             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// +                                                               +
+/// |                                                               |
+/// +                       Multicast Address                       +
+/// |                                                               |
+/// +                                                               +
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Maximum Response Delay*:
+///                     The maximum allowed delay, in milliseconds, before
+///                     sending a responding report.
+///
+/// - *Multicast Address*:
+///                     Set to zero for a general query, or a specific IPv6
+///                     multicast address for a multicast-address-specific
+///                     query.
+///
+/// An MLDv2 query can also be parsed as a version 1 query. Use
+/// [`Mldv2Query`] to access the version 2 fields.
+///
+/// [IETF RFC 2710]: https://tools.ietf.org/html/rfc2710#section-3
+/// [`Mldv2Query`]: crate::packets::icmp::v6::mld::Mldv2Query
+#[derive(Icmpv6Packet)]
+pub struct MulticastListenerQuery<E: Ipv6Packet> {
+    icmp: Icmpv6<E>,
+    body: NonNull<MldBody>,
+}
+
+impl<E: Ipv6Packet> MulticastListenerQuery<E> {
+    #[inline]
+    fn body(&self) -> &MldBody {
+        unsafe { self.body.as_ref() }
+    }
+
+    #[inline]
+    fn body_mut(&mut self) -> &mut MldBody {
+        unsafe { self.body.as_mut() }
+    }
+
+    /// Returns the maximum response delay in milliseconds.
+    #[inline]
+    pub fn max_response_delay(&self) -> u16 {
+        self.body().max_response_delay.into()
+    }
+
+    /// Sets the maximum response delay in milliseconds.
+    #[inline]
+    pub fn set_max_response_delay(&mut self, delay: u16) {
+        self.body_mut().max_response_delay = delay.into();
+    }
+
+    /// Returns the multicast address being queried.
+    #[inline]
+    pub fn multicast_address(&self) -> Ipv6Addr {
+        self.body().multicast_address
+    }
+
+    /// Sets the multicast address being queried.
+    #[inline]
+    pub fn set_multicast_address(&mut self, addr: Ipv6Addr) {
+        self.body_mut().multicast_address = addr;
+    }
+
+    /// Returns whether the query is a general query.
+    #[inline]
+    pub fn is_general(&self) -> bool {
+        self.multicast_address().is_unspecified()
+    }
+}
+
+impl<E: Ipv6Packet> fmt::Debug for MulticastListenerQuery<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MulticastListenerQuery")
+            .field("type", &format!("{}", self.msg_type()))
+            .field("code", &self.code())
+            .field("checksum", &format!("0x{:04x}", self.checksum()))
+            .field("max_response_delay", &self.max_response_delay())
+            .field("multicast_address", &self.multicast_address())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: Ipv6Packet> Icmpv6Message for MulticastListenerQuery<E> {
+    type Envelope = E;
+
+    #[inline]
+    fn msg_type() -> Icmpv6Type {
+        Icmpv6Types::MulticastListenerQuery
+    }
+
+    #[inline]
+    fn icmp(&self) -> &Icmpv6<Self::Envelope> {
+        &self.icmp
+    }
+
+    #[inline]
+    fn icmp_mut(&mut self) -> &mut Icmpv6<Self::Envelope> {
+        &mut self.icmp
+    }
+
+    #[inline]
+    fn into_icmp(self) -> Icmpv6<Self::Envelope> {
+        self.icmp
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        MulticastListenerQuery {
+            icmp: self.icmp.clone(internal),
+            body: self.body,
+        }
+    }
+
+    /// Parses the ICMPv6 packet's payload as multicast listener query.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not have sufficient data for
+    /// the multicast listener query message body.
+    #[inline]
+    fn try_parse(icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self, (Error, Icmpv6<Self::Envelope>)> {
+        let mbuf = icmp.mbuf();
+        let offset = icmp.payload_offset();
+        let body = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, icmp)),
+            Ok(body) => body
+        };
+
+        Ok(MulticastListenerQuery { icmp, body })
+    }
+
+    /// Prepends a new multicast listener query message to the beginning
+    /// of the ICMPv6's payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self> {
+        let offset = icmp.payload_offset();
+        let mbuf = icmp.mbuf_mut();
+
+        mbuf.extend(offset, MldBody::size_of())?;
+        let body = mbuf.write_data(offset, &MldBody::default())?;
+
+        Ok(MulticastListenerQuery { icmp, body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::Mbuf;
+
+    #[capsule::test]
+    fn push_and_set_multicast_listener_query() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut query = ipv6.push::<MulticastListenerQuery<Ipv6>>().unwrap();
+
+        assert_eq!(4, query.header_len());
+        assert_eq!(MldBody::size_of(), query.payload_len());
+        assert_eq!(Icmpv6Types::MulticastListenerQuery, query.msg_type());
+        assert_eq!(0, query.code());
+        assert_eq!(0, query.max_response_delay());
+        assert!(query.is_general());
+
+        query.set_max_response_delay(10000);
+        assert_eq!(10000, query.max_response_delay());
+
+        let addr = "ff02::1:ff00:1".parse().unwrap();
+        query.set_multicast_address(addr);
+        assert_eq!(addr, query.multicast_address());
+        assert!(!query.is_general());
+
+        query.reconcile_all();
+        assert!(query.checksum() != 0);
+    }
+}
@@ -0,0 +1,490 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Message, Icmpv6Packet, Icmpv6Type, Icmpv6Types};
+use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::types::u16be;
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{anyhow, Result, Error};
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::ptr::NonNull;
+
+/// Version 2 Multicast Listener Query Message defined in [IETF RFC 3810].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |  Type = 130   |      Code     |           Checksum            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |    Maximum Response Code      |           Reserved            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// *                                                               *
+/// |                                                               |
+/// *                       Multicast Address                       *
+/// |                                                               |
+/// *                                                               *
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// | Resv  |S| QRV |     QQIC      |     Number of Sources (N)     |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// *                                                               *
+/// |                                                               |
+/// *                       Source Address [1]                      *
+/// |                                                               |
+/// *                                                               *
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// .                               .                               .
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                       Source Address [N]                      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Maximum Response Code*:
+///                     Encodes the maximum allowed delay before sending a
+///                     responding report. Values of 32768 or more are
+///                     encoded as a floating point value.
+///
+/// - *Multicast Address*:
+///                     Set to zero for a general query, or a specific IPv6
+///                     multicast address otherwise.
+///
+/// - *S Flag*:         Suppress router-side processing.
+///
+/// - *QRV*:            Querier's robustness variable.
+///
+/// - *QQIC*:           Querier's query interval code. Values of 128 or
+///                     more are encoded as a floating point value.
+///
+/// - *Number of Sources (N)*:
+///                     The number of source addresses present in the query.
+///
+/// [IETF RFC 3810]: https://tools.ietf.org/html/rfc3810#section-5.1
+#[derive(Icmpv6Packet)]
+pub struct Mldv2Query<E: Ipv6Packet> {
+    icmp: Icmpv6<E>,
+    body: NonNull<Mldv2QueryBody>,
+}
+
+impl<E: Ipv6Packet> Mldv2Query<E> {
+    #[inline]
+    fn body(&self) -> &Mldv2QueryBody {
+        unsafe { self.body.as_ref() }
+    }
+
+    #[inline]
+    fn body_mut(&mut self) -> &mut Mldv2QueryBody {
+        unsafe { self.body.as_mut() }
+    }
+
+    /// Returns the maximum response code.
+    #[inline]
+    pub fn max_response_code(&self) -> u16 {
+        self.body().max_response_code.into()
+    }
+
+    /// Sets the maximum response code.
+    #[inline]
+    pub fn set_max_response_code(&mut self, code: u16) {
+        self.body_mut().max_response_code = code.into();
+    }
+
+    /// Returns the maximum response delay in milliseconds, decoded from
+    /// the maximum response code.
+    #[inline]
+    pub fn max_response_delay(&self) -> u32 {
+        let code = self.max_response_code();
+        if code < 0x8000 {
+            code as u32
+        } else {
+            let exp = (code >> 12) & 0x07;
+            let mant = code & 0x0fff;
+            ((mant | 0x1000) as u32) << (exp + 3)
+        }
+    }
+
+    /// Returns the multicast address being queried.
+    #[inline]
+    pub fn multicast_address(&self) -> Ipv6Addr {
+        self.body().multicast_address
+    }
+
+    /// Sets the multicast address being queried.
+    #[inline]
+    pub fn set_multicast_address(&mut self, addr: Ipv6Addr) {
+        self.body_mut().multicast_address = addr;
+    }
+
+    /// Returns whether the query is a general query.
+    #[inline]
+    pub fn is_general(&self) -> bool {
+        self.multicast_address().is_unspecified()
+    }
+
+    /// Returns a flag indicating that router-side processing should be
+    /// suppressed.
+    #[inline]
+    pub fn suppress_router_processing(&self) -> bool {
+        self.body().flags & 0x08 != 0
+    }
+
+    /// Sets the suppress router-side processing flag.
+    #[inline]
+    pub fn set_suppress_router_processing(&mut self) {
+        self.body_mut().flags |= 0x08;
+    }
+
+    /// Unsets the suppress router-side processing flag.
+    #[inline]
+    pub fn unset_suppress_router_processing(&mut self) {
+        self.body_mut().flags &= !0x08;
+    }
+
+    /// Returns the querier's robustness variable.
+    #[inline]
+    pub fn robustness(&self) -> u8 {
+        self.body().flags & 0x07
+    }
+
+    /// Sets the querier's robustness variable.
+    #[inline]
+    pub fn set_robustness(&mut self, qrv: u8) {
+        self.body_mut().flags = (self.body().flags & 0xf8) | (qrv & 0x07);
+    }
+
+    /// Returns the querier's query interval code.
+    #[inline]
+    pub fn qqic(&self) -> u8 {
+        self.body().qqic
+    }
+
+    /// Sets the querier's query interval code.
+    #[inline]
+    pub fn set_qqic(&mut self, qqic: u8) {
+        self.body_mut().qqic = qqic;
+    }
+
+    /// Returns the querier's query interval in seconds, decoded from the
+    /// query interval code.
+    #[inline]
+    pub fn query_interval(&self) -> u32 {
+        let qqic = self.qqic();
+        if qqic < 0x80 {
+            qqic as u32
+        } else {
+            let exp = (qqic >> 4) & 0x07;
+            let mant = qqic & 0x0f;
+            ((mant | 0x10) as u32) << (exp + 3)
+        }
+    }
+
+    /// Returns the number of source addresses.
+    #[inline]
+    pub fn num_sources(&self) -> u16 {
+        self.body().num_sources.into()
+    }
+
+    /// Returns the offset where the source addresses start.
+    #[inline]
+    fn sources_offset(&self) -> usize {
+        self.payload_offset() + Mldv2QueryBody::size_of()
+    }
+
+    /// Returns the source addresses.
+    #[inline]
+    pub fn sources(&self) -> &[Ipv6Addr] {
+        let count = self.num_sources() as usize;
+        if count == 0 {
+            return &[];
+        }
+
+        if let Ok(sources) = self
+            .icmp()
+            .mbuf()
+            .read_data_slice(self.sources_offset(), count)
+        {
+            unsafe { &*sources.as_ptr() }
+        } else {
+            &[]
+        }
+    }
+
+    /// Appends a source address to the end of the source list.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    pub fn push_source(&mut self, source: Ipv6Addr) -> Result<()> {
+        let count = self.num_sources();
+        ensure!(count < u16::MAX, anyhow!("too many source addresses."));
+
+        let offset = self.sources_offset() + count as usize * Ipv6Addr::size_of();
+        let mbuf = self.mbuf_mut();
+        mbuf.extend(offset, Ipv6Addr::size_of())?;
+        let _ = mbuf.write_data(offset, &source)?;
+
+        self.body_mut().num_sources = (count + 1).into();
+        Ok(())
+    }
+
+    /// Removes the source address at the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of bounds.
+    #[inline]
+    pub fn remove_source(&mut self, index: usize) -> Result<()> {
+        let count = self.num_sources();
+        ensure!(
+            index < count as usize,
+            anyhow!("source index {} is out of bounds.", index)
+        );
+
+        let offset = self.sources_offset() + index * Ipv6Addr::size_of();
+        self.mbuf_mut().shrink(offset, Ipv6Addr::size_of())?;
+
+        self.body_mut().num_sources = (count - 1).into();
+        Ok(())
+    }
+}
+
+impl<E: Ipv6Packet> fmt::Debug for Mldv2Query<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mldv2Query")
+            .field("type", &format!("{}", self.msg_type()))
+            .field("code", &self.code())
+            .field("checksum", &format!("0x{:04x}", self.checksum()))
+            .field("max_response_code", &self.max_response_code())
+            .field("multicast_address", &self.multicast_address())
+            .field("suppress_router_processing", &self.suppress_router_processing())
+            .field("robustness", &self.robustness())
+            .field("qqic", &self.qqic())
+            .field("sources", &self.sources())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: Ipv6Packet> Icmpv6Message for Mldv2Query<E> {
+    type Envelope = E;
+
+    #[inline]
+    fn msg_type() -> Icmpv6Type {
+        Icmpv6Types::MulticastListenerQuery
+    }
+
+    #[inline]
+    fn icmp(&self) -> &Icmpv6<Self::Envelope> {
+        &self.icmp
+    }
+
+    #[inline]
+    fn icmp_mut(&mut self) -> &mut Icmpv6<Self::Envelope> {
+        &mut self.icmp
+    }
+
+    #[inline]
+    fn into_icmp(self) -> Icmpv6<Self::Envelope> {
+        self.icmp
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Mldv2Query {
+            icmp: self.icmp.clone(internal),
+            body: self.body,
+        }
+    }
+
+    /// Parses the ICMPv6 packet's payload as version 2 multicast listener
+    /// query.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not have sufficient data for
+    /// the version 2 query message body and the source addresses. A
+    /// version 1 query does not have sufficient data.
+    #[inline]
+    fn try_parse(icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self, (Error, Icmpv6<Self::Envelope>)> {
+        let mbuf = icmp.mbuf();
+        let offset = icmp.payload_offset();
+        let body = match mbuf.read_data::<Mldv2QueryBody>(offset) {
+            Err(e) => return Err((e, icmp)),
+            Ok(body) => body
+        };
+
+        let count = unsafe { u16::from(body.as_ref().num_sources) } as usize;
+        if count > 0 {
+            if let Err(e) =
+                mbuf.read_data_slice::<Ipv6Addr>(offset + Mldv2QueryBody::size_of(), count)
+            {
+                return Err((e, icmp));
+            }
+        }
+
+        Ok(Mldv2Query { icmp, body })
+    }
+
+    /// Prepends a new version 2 multicast listener query message to the
+    /// beginning of the ICMPv6's payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self> {
+        let offset = icmp.payload_offset();
+        let mbuf = icmp.mbuf_mut();
+
+        mbuf.extend(offset, Mldv2QueryBody::size_of())?;
+        let body = mbuf.write_data(offset, &Mldv2QueryBody::default())?;
+
+        Ok(Mldv2Query { icmp, body })
+    }
+}
+
+/// The fixed portion of the MLDv2 query message body.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct Mldv2QueryBody {
+    max_response_code: u16be,
+    _reserved: u16be,
+    multicast_address: Ipv6Addr,
+    flags: u8,
+    qqic: u8,
+    num_sources: u16be,
+}
+
+impl Default for Mldv2QueryBody {
+    fn default() -> Self {
+        Mldv2QueryBody {
+            max_response_code: u16be::default(),
+            _reserved: u16be::default(),
+            multicast_address: Ipv6Addr::UNSPECIFIED,
+            flags: 0,
+            qqic: 0,
+            num_sources: u16be::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::icmp::v6::mld::MulticastListenerQuery;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::Mbuf;
+
+    #[test]
+    fn size_of_mldv2_query_body() {
+        assert_eq!(24, Mldv2QueryBody::size_of());
+    }
+
+    #[capsule::test]
+    fn push_and_set_mldv2_query() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut query = ipv6.push::<Mldv2Query<Ipv6>>().unwrap();
+
+        assert_eq!(Icmpv6Types::MulticastListenerQuery, query.msg_type());
+        assert_eq!(Mldv2QueryBody::size_of(), query.payload_len());
+        assert!(query.is_general());
+        assert_eq!(0, query.num_sources());
+        assert!(query.sources().is_empty());
+
+        query.set_max_response_code(10000);
+        assert_eq!(10000, query.max_response_delay());
+        query.set_max_response_code(0x8001);
+        assert_eq!(0x1001 << 3, query.max_response_delay());
+
+        query.set_suppress_router_processing();
+        assert!(query.suppress_router_processing());
+        query.set_robustness(2);
+        assert_eq!(2, query.robustness());
+        assert!(query.suppress_router_processing());
+        query.unset_suppress_router_processing();
+        assert!(!query.suppress_router_processing());
+        assert_eq!(2, query.robustness());
+
+        query.set_qqic(125);
+        assert_eq!(125, query.query_interval());
+        query.set_qqic(0x81);
+        assert_eq!(0x11 << 3, query.query_interval());
+
+        let group = "ff3e::8000:1".parse().unwrap();
+        query.set_multicast_address(group);
+        assert!(!query.is_general());
+    }
+
+    #[capsule::test]
+    fn push_and_remove_mldv2_query_sources() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut query = ipv6.push::<Mldv2Query<Ipv6>>().unwrap();
+
+        let src1: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let src2: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let src3: Ipv6Addr = "2001:db8::3".parse().unwrap();
+        query.push_source(src1).unwrap();
+        query.push_source(src2).unwrap();
+        query.push_source(src3).unwrap();
+
+        assert_eq!(3, query.num_sources());
+        assert_eq!(&[src1, src2, src3], query.sources());
+        assert_eq!(Mldv2QueryBody::size_of() + 48, query.payload_len());
+
+        query.remove_source(1).unwrap();
+        assert_eq!(&[src1, src3], query.sources());
+        assert!(query.remove_source(2).is_err());
+
+        query.reconcile_all();
+
+        // reparses the message from the raw bytes.
+        let ipv6 = query.deparse();
+        let icmpv6 = ipv6.parse::<Icmpv6<Ipv6>>().unwrap();
+        let query = icmpv6.downcast::<Mldv2Query<Ipv6>>().unwrap();
+        assert_eq!(&[src1, src3], query.sources());
+
+        // a version 2 query can be parsed as a version 1 query.
+        let icmpv6 = query.into_icmp();
+        assert!(icmpv6.downcast::<MulticastListenerQuery<Ipv6>>().is_ok());
+    }
+
+    #[capsule::test]
+    fn cannot_parse_mldv1_query_as_mldv2() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut query = ipv6.push::<MulticastListenerQuery<Ipv6>>().unwrap();
+        query.reconcile_all();
+
+        let icmpv6 = query.into_icmp();
+        assert!(icmpv6.downcast::<Mldv2Query<Ipv6>>().is_err());
+    }
+}
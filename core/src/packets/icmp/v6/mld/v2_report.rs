@@ -0,0 +1,476 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Message, Icmpv6Packet, Icmpv6Type, Icmpv6Types};
+use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::types::u16be;
+use crate::packets::{Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Result, Error};
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::ptr::NonNull;
+
+/// Version 2 Multicast Listener Report Message defined in [IETF RFC 3810].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |  Type = 143   |    Reserved   |           Checksum            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |           Reserved            |Nr of Mcast Address Records (M)|
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// .                  Multicast Address Record [1]                 .
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                               .                               |
+/// .                               .                               .
+/// |                               .                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// .                  Multicast Address Record [M]                 .
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Nr of Mcast Address Records (M)*:
+///                     The number of multicast address records present in
+///                     the report.
+///
+/// - *Multicast Address Record*:
+///                     Each record contains information on the sender's
+///                     listening to a single multicast address. See
+///                     [`MulticastAddressRecord`].
+///
+/// [IETF RFC 3810]: https://tools.ietf.org/html/rfc3810#section-5.2
+/// [`MulticastAddressRecord`]: MulticastAddressRecord
+#[derive(Icmpv6Packet)]
+pub struct Mldv2Report<E: Ipv6Packet> {
+    icmp: Icmpv6<E>,
+    body: NonNull<Mldv2ReportBody>,
+}
+
+impl<E: Ipv6Packet> Mldv2Report<E> {
+    #[inline]
+    fn body(&self) -> &Mldv2ReportBody {
+        unsafe { self.body.as_ref() }
+    }
+
+    #[inline]
+    fn body_mut(&mut self) -> &mut Mldv2ReportBody {
+        unsafe { self.body.as_mut() }
+    }
+
+    /// Returns the number of multicast address records.
+    #[inline]
+    pub fn num_records(&self) -> u16 {
+        self.body().num_records.into()
+    }
+
+    /// Returns the offset where the multicast address records start.
+    #[inline]
+    fn records_offset(&self) -> usize {
+        self.payload_offset() + Mldv2ReportBody::size_of()
+    }
+
+    /// Returns the multicast address records.
+    ///
+    /// Any auxiliary data in the records is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough data for the
+    /// number of records indicated.
+    #[inline]
+    pub fn records(&self) -> Result<Vec<MulticastAddressRecord>> {
+        read_records(
+            self.mbuf(),
+            self.records_offset(),
+            self.num_records() as usize,
+        )
+        .map(|(records, _)| records)
+    }
+
+    /// Appends a multicast address record to the end of the report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    pub fn push_record(&mut self, record: &MulticastAddressRecord) -> Result<()> {
+        let count = self.num_records();
+        ensure!(count < u16::MAX, anyhow!("too many multicast address records."));
+        ensure!(
+            record.sources.len() <= u16::MAX as usize,
+            anyhow!("too many source addresses.")
+        );
+
+        let (_, offset) = read_records(self.mbuf(), self.records_offset(), count as usize)?;
+        let len = RecordHeader::size_of() + record.sources.len() * Ipv6Addr::size_of();
+
+        let header = RecordHeader {
+            record_type: record.record_type.0,
+            aux_data_len: 0,
+            num_sources: (record.sources.len() as u16).into(),
+            multicast_address: record.multicast_address,
+        };
+
+        let mbuf = self.mbuf_mut();
+        mbuf.extend(offset, len)?;
+        let _ = mbuf.write_data(offset, &header)?;
+        if !record.sources.is_empty() {
+            let _ = mbuf.write_data_slice(offset + RecordHeader::size_of(), &record.sources)?;
+        }
+
+        self.body_mut().num_records = (count + 1).into();
+        Ok(())
+    }
+}
+
+/// Reads `count` multicast address records starting at `offset`. Returns
+/// the records and the offset immediately after the last record.
+fn read_records(
+    mbuf: &Mbuf,
+    mut offset: usize,
+    count: usize,
+) -> Result<(Vec<MulticastAddressRecord>, usize)> {
+    let mut records = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let header = mbuf.read_data::<RecordHeader>(offset)?;
+        let header = unsafe { header.as_ref() };
+        let num_sources = u16::from(header.num_sources) as usize;
+        let sources_offset = offset + RecordHeader::size_of();
+
+        let sources = if num_sources > 0 {
+            let sources = mbuf.read_data_slice::<Ipv6Addr>(sources_offset, num_sources)?;
+            unsafe { sources.as_ref() }.to_vec()
+        } else {
+            vec![]
+        };
+
+        let aux_len = header.aux_data_len as usize * 4;
+        let next = sources_offset + num_sources * Ipv6Addr::size_of() + aux_len;
+        ensure!(
+            next <= mbuf.data_len(),
+            anyhow!("multicast address record exceeds the buffer.")
+        );
+
+        records.push(MulticastAddressRecord {
+            record_type: Mldv2RecordType(header.record_type),
+            multicast_address: header.multicast_address,
+            sources,
+        });
+        offset = next;
+    }
+
+    Ok((records, offset))
+}
+
+impl<E: Ipv6Packet> fmt::Debug for Mldv2Report<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mldv2Report")
+            .field("type", &format!("{}", self.msg_type()))
+            .field("code", &self.code())
+            .field("checksum", &format!("0x{:04x}", self.checksum()))
+            .field("num_records", &self.num_records())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: Ipv6Packet> Icmpv6Message for Mldv2Report<E> {
+    type Envelope = E;
+
+    #[inline]
+    fn msg_type() -> Icmpv6Type {
+        Icmpv6Types::Mldv2Report
+    }
+
+    #[inline]
+    fn icmp(&self) -> &Icmpv6<Self::Envelope> {
+        &self.icmp
+    }
+
+    #[inline]
+    fn icmp_mut(&mut self) -> &mut Icmpv6<Self::Envelope> {
+        &mut self.icmp
+    }
+
+    #[inline]
+    fn into_icmp(self) -> Icmpv6<Self::Envelope> {
+        self.icmp
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Mldv2Report {
+            icmp: self.icmp.clone(internal),
+            body: self.body,
+        }
+    }
+
+    /// Parses the ICMPv6 packet's payload as version 2 multicast listener
+    /// report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload does not have sufficient data for
+    /// the report message body and all the multicast address records.
+    #[inline]
+    fn try_parse(icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self, (Error, Icmpv6<Self::Envelope>)> {
+        let mbuf = icmp.mbuf();
+        let offset = icmp.payload_offset();
+        let body = match mbuf.read_data::<Mldv2ReportBody>(offset) {
+            Err(e) => return Err((e, icmp)),
+            Ok(body) => body
+        };
+
+        let count = unsafe { u16::from(body.as_ref().num_records) } as usize;
+        if let Err(e) = read_records(mbuf, offset + Mldv2ReportBody::size_of(), count) {
+            return Err((e, icmp));
+        }
+
+        Ok(Mldv2Report { icmp, body })
+    }
+
+    /// Prepends a new version 2 multicast listener report message to the
+    /// beginning of the ICMPv6's payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut icmp: Icmpv6<Self::Envelope>, _internal: Internal) -> Result<Self> {
+        let offset = icmp.payload_offset();
+        let mbuf = icmp.mbuf_mut();
+
+        mbuf.extend(offset, Mldv2ReportBody::size_of())?;
+        let body = mbuf.write_data(offset, &Mldv2ReportBody::default())?;
+
+        Ok(Mldv2Report { icmp, body })
+    }
+}
+
+/// A multicast address record in the version 2 multicast listener report.
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |  Record Type  |  Aux Data Len |     Number of Sources (N)     |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// *                       Multicast Address                       *
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// *                       Source Address [1]                      *
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// .                               .                               .
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                                                               |
+/// .                         Auxiliary Data                        .
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// MLDv2 does not define any auxiliary data. It is skipped when reading
+/// and never written.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MulticastAddressRecord {
+    /// The type of the record.
+    pub record_type: Mldv2RecordType,
+    /// The multicast address the record pertains to.
+    pub multicast_address: Ipv6Addr,
+    /// The source addresses.
+    pub sources: Vec<Ipv6Addr>,
+}
+
+/// Type of multicast address record.
+///
+/// A list of supported types is under [`Mldv2RecordTypes`].
+///
+/// [`Mldv2RecordTypes`]: Mldv2RecordTypes
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct Mldv2RecordType(pub u8);
+
+/// Supported multicast address record types defined in [IETF RFC 3810].
+///
+/// [IETF RFC 3810]: https://tools.ietf.org/html/rfc3810#section-5.2.12
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod Mldv2RecordTypes {
+    use super::Mldv2RecordType;
+
+    /// The interface has a filter mode of include for the address.
+    pub const ModeIsInclude: Mldv2RecordType = Mldv2RecordType(1);
+
+    /// The interface has a filter mode of exclude for the address.
+    pub const ModeIsExclude: Mldv2RecordType = Mldv2RecordType(2);
+
+    /// The filter mode has changed to include for the address.
+    pub const ChangeToIncludeMode: Mldv2RecordType = Mldv2RecordType(3);
+
+    /// The filter mode has changed to exclude for the address.
+    pub const ChangeToExcludeMode: Mldv2RecordType = Mldv2RecordType(4);
+
+    /// The listener wants to hear from the additional sources.
+    pub const AllowNewSources: Mldv2RecordType = Mldv2RecordType(5);
+
+    /// The listener no longer wants to hear from the sources.
+    pub const BlockOldSources: Mldv2RecordType = Mldv2RecordType(6);
+}
+
+impl fmt::Display for Mldv2RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Mldv2RecordTypes::ModeIsInclude => "MODE_IS_INCLUDE".to_string(),
+                Mldv2RecordTypes::ModeIsExclude => "MODE_IS_EXCLUDE".to_string(),
+                Mldv2RecordTypes::ChangeToIncludeMode => "CHANGE_TO_INCLUDE_MODE".to_string(),
+                Mldv2RecordTypes::ChangeToExcludeMode => "CHANGE_TO_EXCLUDE_MODE".to_string(),
+                Mldv2RecordTypes::AllowNewSources => "ALLOW_NEW_SOURCES".to_string(),
+                Mldv2RecordTypes::BlockOldSources => "BLOCK_OLD_SOURCES".to_string(),
+                _ => format!("{}", self.0),
+            }
+        )
+    }
+}
+
+/// The fixed portion of the MLDv2 report message body.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C, packed)]
+struct Mldv2ReportBody {
+    _reserved: u16be,
+    num_records: u16be,
+}
+
+/// The fixed portion of a multicast address record.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct RecordHeader {
+    record_type: u8,
+    aux_data_len: u8,
+    num_sources: u16be,
+    multicast_address: Ipv6Addr,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v6::Ipv6;
+
+    #[test]
+    fn size_of_mldv2_report_body() {
+        assert_eq!(4, Mldv2ReportBody::size_of());
+        assert_eq!(20, RecordHeader::size_of());
+    }
+
+    #[test]
+    fn record_type_to_string() {
+        assert_eq!("MODE_IS_EXCLUDE", Mldv2RecordTypes::ModeIsExclude.to_string());
+        assert_eq!("42", Mldv2RecordType(42).to_string());
+    }
+
+    #[capsule::test]
+    fn push_and_parse_mldv2_report() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut report = ipv6.push::<Mldv2Report<Ipv6>>().unwrap();
+
+        assert_eq!(Icmpv6Types::Mldv2Report, report.msg_type());
+        assert_eq!(0, report.num_records());
+        assert!(report.records().unwrap().is_empty());
+
+        let exclude = MulticastAddressRecord {
+            record_type: Mldv2RecordTypes::ChangeToExcludeMode,
+            multicast_address: "ff02::1:ff00:1".parse().unwrap(),
+            sources: vec![],
+        };
+        let include = MulticastAddressRecord {
+            record_type: Mldv2RecordTypes::ModeIsInclude,
+            multicast_address: "ff3e::8000:1".parse().unwrap(),
+            sources: vec!["2001:db8::1".parse().unwrap(), "2001:db8::2".parse().unwrap()],
+        };
+
+        report.push_record(&exclude).unwrap();
+        report.push_record(&include).unwrap();
+
+        assert_eq!(2, report.num_records());
+        assert_eq!(4 + 20 + 20 + 32, report.payload_len());
+        assert_eq!(vec![exclude.clone(), include.clone()], report.records().unwrap());
+
+        report.reconcile_all();
+        assert!(report.checksum() != 0);
+
+        // reparses the message from the raw bytes.
+        let ipv6 = report.deparse();
+        let icmpv6 = ipv6.parse::<Icmpv6<Ipv6>>().unwrap();
+        let report = icmpv6.downcast::<Mldv2Report<Ipv6>>().unwrap();
+        assert_eq!(vec![exclude, include], report.records().unwrap());
+    }
+
+    #[capsule::test]
+    fn parse_mldv2_report_with_aux_data() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut report = ipv6.push::<Mldv2Report<Ipv6>>().unwrap();
+
+        let record = MulticastAddressRecord {
+            record_type: Mldv2RecordTypes::AllowNewSources,
+            multicast_address: "ff3e::8000:1".parse().unwrap(),
+            sources: vec!["2001:db8::1".parse().unwrap()],
+        };
+        report.push_record(&record).unwrap();
+
+        // appends one word of auxiliary data to the record.
+        let offset = report.records_offset();
+        let end = offset + RecordHeader::size_of() + Ipv6Addr::size_of();
+        report.mbuf_mut().extend(end, 4).unwrap();
+        report.mbuf_mut().write_data(offset + 1, &1u8).unwrap();
+
+        report.push_record(&record).unwrap();
+        assert_eq!(vec![record.clone(), record], report.records().unwrap());
+    }
+
+    #[capsule::test]
+    fn parse_truncated_mldv2_report() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut report = ipv6.push::<Mldv2Report<Ipv6>>().unwrap();
+
+        // claims a record that isn't there.
+        report.body_mut().num_records = 1.into();
+        assert!(report.records().is_err());
+
+        let icmpv6 = report.into_icmp();
+        assert!(icmpv6.downcast::<Mldv2Report<Ipv6>>().is_err());
+    }
+}
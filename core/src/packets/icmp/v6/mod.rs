@@ -20,13 +20,16 @@
 
 mod echo_reply;
 mod echo_request;
+pub mod mld;
 pub mod ndp;
+mod parameter_problem;
 mod time_exceeded;
 mod too_big;
 mod unreachable;
 
 pub use self::echo_reply::*;
 pub use self::echo_request::*;
+pub use self::parameter_problem::*;
 pub use self::time_exceeded::*;
 pub use self::too_big::*;
 pub use self::unreachable::*;
@@ -123,13 +126,13 @@ impl<E: Ipv6Packet> Icmpv6<E> {
     pub fn compute_checksum(&mut self) {
         self.header_mut().checksum = u16be::default();
 
-        if let Ok(data) = self.mbuf().read_data_slice(self.offset(), self.len()) {
-            let data = unsafe { data.as_ref() };
+        if let Ok(data) = self.mbuf().data_slice(self.offset(), self.len()) {
             let pseudo_header_sum = self
                 .envelope()
                 .pseudo_header(data.len() as u16, ProtocolNumbers::Icmpv6)
                 .sum();
-            let checksum = checksum::compute(pseudo_header_sum, data);
+            let checksum = checksum::compute(pseudo_header_sum, &data);
+            drop(data);
             self.header_mut().checksum = checksum.into();
         } else {
             // we are reading till the end of buffer, should never run out
@@ -337,6 +340,33 @@ pub mod Icmpv6Types {
     ///
     /// [Redirect]: crate::packets::icmp::v6::ndp::Redirect
     pub const Redirect: Icmpv6Type = Icmpv6Type(137);
+
+    /// Message type for [Parameter Problem].
+    ///
+    /// [Parameter Problem]: crate::packets::icmp::v6::ParameterProblem
+    pub const ParameterProblem: Icmpv6Type = Icmpv6Type(4);
+
+    /// Message type for [Multicast Listener Query], both version 1 and
+    /// [version 2].
+    ///
+    /// [Multicast Listener Query]: crate::packets::icmp::v6::mld::MulticastListenerQuery
+    /// [version 2]: crate::packets::icmp::v6::mld::Mldv2Query
+    pub const MulticastListenerQuery: Icmpv6Type = Icmpv6Type(130);
+
+    /// Message type for [Multicast Listener Report].
+    ///
+    /// [Multicast Listener Report]: crate::packets::icmp::v6::mld::MulticastListenerReport
+    pub const MulticastListenerReport: Icmpv6Type = Icmpv6Type(131);
+
+    /// Message type for [Multicast Listener Done].
+    ///
+    /// [Multicast Listener Done]: crate::packets::icmp::v6::mld::MulticastListenerDone
+    pub const MulticastListenerDone: Icmpv6Type = Icmpv6Type(132);
+
+    /// Message type for [Version 2 Multicast Listener Report].
+    ///
+    /// [Version 2 Multicast Listener Report]: crate::packets::icmp::v6::mld::Mldv2Report
+    pub const Mldv2Report: Icmpv6Type = Icmpv6Type(143);
 }
 
 impl fmt::Display for Icmpv6Type {
@@ -354,6 +384,11 @@ impl fmt::Display for Icmpv6Type {
                 Icmpv6Types::NeighborSolicitation => "Neighbor Solicitation".to_string(),
                 Icmpv6Types::NeighborAdvertisement => "Neighbor Advertisement".to_string(),
                 Icmpv6Types::Redirect => "Redirect".to_string(),
+                Icmpv6Types::ParameterProblem => "Parameter Problem".to_string(),
+                Icmpv6Types::MulticastListenerQuery => "Multicast Listener Query".to_string(),
+                Icmpv6Types::MulticastListenerReport => "Multicast Listener Report".to_string(),
+                Icmpv6Types::MulticastListenerDone => "Multicast Listener Done".to_string(),
+                Icmpv6Types::Mldv2Report => "Version 2 Multicast Listener Report".to_string(),
                 _ => format!("{}", self.0),
             }
         )
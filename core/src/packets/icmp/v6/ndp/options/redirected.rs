@@ -103,6 +103,11 @@ impl RedirectedHeader<'_> {
     /// remainder of the buffer is the data.
     ///
     /// [Redirect]: crate::packets::icmp::v6::ndp::Redirect
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         let offset = self.offset + RedirectedHeaderFields::size_of();
@@ -115,6 +120,20 @@ impl RedirectedHeader<'_> {
         }
     }
 
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        let offset = self.offset + RedirectedHeaderFields::size_of();
+        let len = self.mbuf.data_len() - offset;
+
+        if let Ok(data) = self.mbuf.data_slice(offset, len) {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
+
     /// Sets the length of the option based on the buffer length.
     ///
     /// This option must be the last option in the [Redirect] message. The
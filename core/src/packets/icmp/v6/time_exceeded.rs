@@ -39,7 +39,11 @@ use std::ptr::NonNull;
 /// |               exceeding the minimum IPv6 MTU [IPv6]           |
 /// ```
 ///
+/// - *Code*:       The kind of time exceeded. The known values are under
+///                 [`TimeExceededCodes`].
+///
 /// [IETF RFC 4443]: https://tools.ietf.org/html/rfc4443#section-3.3
+/// [`TimeExceededCodes`]: TimeExceededCodes
 #[derive(Icmpv6Packet)]
 pub struct TimeExceeded<E: Ipv6Packet> {
     icmp: Icmpv6<E>,
@@ -48,6 +52,11 @@ pub struct TimeExceeded<E: Ipv6Packet> {
 
 impl<E: Ipv6Packet> TimeExceeded<E> {
     /// Returns the invoking packet as a `u8` slice.
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         let offset = self.payload_offset() + TimeExceededBody::size_of();
@@ -59,6 +68,20 @@ impl<E: Ipv6Packet> TimeExceeded<E> {
             &[]
         }
     }
+
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        let offset = self.payload_offset() + TimeExceededBody::size_of();
+        let len = self.payload_len() - TimeExceededBody::size_of();
+
+        if let Ok(data) = self.icmp().mbuf().data_slice(offset, len) {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 impl<E: Ipv6Packet> fmt::Debug for TimeExceeded<E> {
@@ -157,6 +180,19 @@ impl<E: Ipv6Packet> Icmpv6Message for TimeExceeded<E> {
     }
 }
 
+/// Time exceeded codes defined in [IETF RFC 4443].
+///
+/// [IETF RFC 4443]: https://tools.ietf.org/html/rfc4443#section-3.3
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod TimeExceededCodes {
+    /// Hop limit exceeded in transit.
+    pub const HopLimitExceeded: u8 = 0;
+
+    /// Fragment reassembly time exceeded.
+    pub const FragmentReassemblyTimeExceeded: u8 = 1;
+}
+
 #[derive(Clone, Copy, Debug, Default, SizeOf)]
 #[repr(C, packed)]
 struct TimeExceededBody {
@@ -48,6 +48,11 @@ pub struct DestinationUnreachable<E: Ipv6Packet> {
 
 impl<E: Ipv6Packet> DestinationUnreachable<E> {
     /// Returns the invoking packet as a `u8` slice.
+    ///
+    /// Returns an empty slice if the data spans more than one segment. Use
+    /// [`data_to_vec`] to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         let offset = self.payload_offset() + DestinationUnreachableBody::size_of();
@@ -59,6 +64,20 @@ impl<E: Ipv6Packet> DestinationUnreachable<E> {
             &[]
         }
     }
+
+    /// Returns a copy of the invoking packet, which may span more than one
+    /// segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        let offset = self.payload_offset() + DestinationUnreachableBody::size_of();
+        let len = self.payload_len() - DestinationUnreachableBody::size_of();
+
+        if let Ok(data) = self.icmp().mbuf().data_slice(offset, len) {
+            data.into_owned()
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 impl<E: Ipv6Packet> fmt::Debug for DestinationUnreachable<E> {
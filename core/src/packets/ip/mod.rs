@@ -20,9 +20,13 @@
 
 pub mod v4;
 pub mod v6;
+mod reassembly;
+
+pub use self::reassembly::ReassemblyConfig;
 
 use crate::packets::checksum::PseudoHeader;
-use crate::packets::Packet;
+use crate::packets::ethernet::{EtherType, EtherTypes};
+use crate::packets::{Mbuf, Packet};
 use anyhow::Result;
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr};
@@ -55,23 +59,41 @@ impl ProtocolNumber {
 pub mod ProtocolNumbers {
     use super::ProtocolNumber;
 
+    /// IPv6 Hop-by-Hop Option.
+    pub const Ipv6HopByHop: ProtocolNumber = ProtocolNumber(0x00);
+
+    /// IPv4 encapsulation.
+    pub const Ipip: ProtocolNumber = ProtocolNumber(0x04);
+
     /// Transmission Control Protocol.
     pub const Tcp: ProtocolNumber = ProtocolNumber(0x06);
 
     /// User Datagram Protocol.
     pub const Udp: ProtocolNumber = ProtocolNumber(0x11);
 
+    /// IPv6 encapsulation.
+    pub const Ipv6: ProtocolNumber = ProtocolNumber(0x29);
+
     /// Routing Header for IPv6.
     pub const Ipv6Route: ProtocolNumber = ProtocolNumber(0x2B);
 
     /// Fragment Header for IPv6.
     pub const Ipv6Frag: ProtocolNumber = ProtocolNumber(0x2C);
 
+    /// Destination Options for IPv6.
+    pub const Ipv6Opts: ProtocolNumber = ProtocolNumber(0x3C);
+
     /// Internet Control Message Protocol for IPv6.
     pub const Icmpv6: ProtocolNumber = ProtocolNumber(0x3A);
 
     /// Internet Control Message Protocol for IPv4.
     pub const Icmpv4: ProtocolNumber = ProtocolNumber(0x01);
+
+    /// Generic Routing Encapsulation.
+    pub const Gre: ProtocolNumber = ProtocolNumber(0x2F);
+
+    /// Stream Control Transmission Protocol.
+    pub const Sctp: ProtocolNumber = ProtocolNumber(0x84);
 }
 
 impl fmt::Display for ProtocolNumber {
@@ -80,12 +102,18 @@ impl fmt::Display for ProtocolNumber {
             f,
             "{}",
             match *self {
+                ProtocolNumbers::Ipv6HopByHop => "IPv6 Hop-by-Hop".to_string(),
+                ProtocolNumbers::Ipip => "IPIP".to_string(),
                 ProtocolNumbers::Tcp => "TCP".to_string(),
                 ProtocolNumbers::Udp => "UDP".to_string(),
+                ProtocolNumbers::Ipv6 => "IPv6".to_string(),
                 ProtocolNumbers::Ipv6Route => "IPv6 Route".to_string(),
                 ProtocolNumbers::Ipv6Frag => "IPv6 Frag".to_string(),
+                ProtocolNumbers::Ipv6Opts => "IPv6 Opts".to_string(),
                 ProtocolNumbers::Icmpv6 => "ICMPv6".to_string(),
                 ProtocolNumbers::Icmpv4 => "ICMPv4".to_string(),
+                ProtocolNumbers::Gre => "GRE".to_string(),
+                ProtocolNumbers::Sctp => "SCTP".to_string(),
                 _ => format!("0x{:02x}", self.0),
             }
         )
@@ -145,6 +173,48 @@ pub trait IpPacket: Packet {
 
     /// Truncates the IP packet to MTU. The data exceeds MTU is lost.
     fn truncate(&mut self, mtu: usize) -> Result<()>;
+
+    /// Sets the length field of the IP header to the length of the packet
+    /// in the buffer. Unlike [`reconcile`], the checksum is not updated.
+    ///
+    /// [`reconcile`]: crate::packets::Packet::reconcile
+    fn set_length_from_buffer(&mut self);
+}
+
+/// Returns the ether type of the packet encapsulated in an IP-in-IP
+/// tunnel. Returns `0` if the protocol is not an IP encapsulation.
+pub(crate) fn tunnel_ether_type(protocol: ProtocolNumber) -> EtherType {
+    match protocol {
+        ProtocolNumbers::Ipip => EtherTypes::Ipv4,
+        ProtocolNumbers::Ipv6 => EtherTypes::Ipv6,
+        _ => EtherType::default(),
+    }
+}
+
+/// Returns the IP-in-IP tunnel protocol that encapsulates a packet of the
+/// ether type. Returns `None` if the ether type is not IP.
+pub(crate) fn tunnel_protocol(ether_type: EtherType) -> Option<ProtocolNumber> {
+    match ether_type {
+        EtherTypes::Ipv4 => Some(ProtocolNumbers::Ipip),
+        EtherTypes::Ipv6 => Some(ProtocolNumbers::Ipv6),
+        _ => None,
+    }
+}
+
+/// Returns the ether type of the IP packet at the offset, inferred from
+/// its version. Returns `0` if the data is not an IP packet.
+///
+/// Used by the encapsulations that do not carry the protocol type of
+/// their payload.
+pub(crate) fn version_ether_type(mbuf: &Mbuf, offset: usize) -> EtherType {
+    match mbuf.read_data::<u8>(offset) {
+        Ok(version) => match unsafe { *version.as_ref() } >> 4 {
+            4 => EtherTypes::Ipv4,
+            6 => EtherTypes::Ipv6,
+            _ => EtherType::default(),
+        },
+        Err(_) => EtherType::default(),
+    }
 }
 
 /// The common attributes (5-tuple) used to identify an IP based network
@@ -284,6 +354,11 @@ mod tests {
         assert_eq!("UDP", ProtocolNumbers::Udp.to_string());
         assert_eq!("IPv6 Route", ProtocolNumbers::Ipv6Route.to_string());
         assert_eq!("ICMPv6", ProtocolNumbers::Icmpv6.to_string());
-        assert_eq!("0x00", ProtocolNumber::new(0).to_string());
+        assert_eq!("GRE", ProtocolNumbers::Gre.to_string());
+        assert_eq!("SCTP", ProtocolNumbers::Sctp.to_string());
+        assert_eq!("IPIP", ProtocolNumbers::Ipip.to_string());
+        assert_eq!("IPv6", ProtocolNumbers::Ipv6.to_string());
+        assert_eq!("IPv6 Hop-by-Hop", ProtocolNumbers::Ipv6HopByHop.to_string());
+        assert_eq!("0xfd", ProtocolNumber::new(0xfd).to_string());
     }
 }
@@ -0,0 +1,472 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Fragment bookkeeping shared by the IPv4 and IPv6 reassemblers.
+//!
+//! The fragments of each incomplete datagram are kept in a queue sorted
+//! by offset. The queues are bounded by a timeout measured from the
+//! arrival of the first fragment, and by limits on the number of
+//! datagrams and the number of octets held. The current time is passed
+//! in by the caller, so the expiration is deterministic.
+
+use crate::ensure;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Configuration of an IP reassembler.
+#[derive(Clone, Copy, Debug)]
+pub struct ReassemblyConfig {
+    /// Maximum time to wait for all the fragments of a datagram, measured
+    /// from the arrival of its first fragment.
+    pub timeout: Duration,
+    /// Maximum number of octets held across all incomplete datagrams.
+    /// When exceeded, the oldest datagrams are evicted.
+    pub max_bytes: usize,
+    /// Maximum number of incomplete datagrams. When exceeded, the oldest
+    /// datagram is evicted.
+    pub max_datagrams: usize,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            max_bytes: 4 * 1024 * 1024,
+            max_datagrams: 1024,
+        }
+    }
+}
+
+/// A received fragment covering the octets `start..end` of the original
+/// datagram's fragmentable part.
+#[derive(Debug)]
+pub(crate) struct FragmentEntry<P> {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) bytes: usize,
+    pub(crate) packet: P,
+}
+
+/// The fragments received so far for a single datagram, sorted by offset.
+#[derive(Debug)]
+pub(crate) struct FragmentQueue<P> {
+    created: Instant,
+    fragments: Vec<FragmentEntry<P>>,
+    total: Option<usize>,
+    received: usize,
+    bytes: usize,
+}
+
+impl<P> FragmentQueue<P> {
+    fn new(now: Instant) -> Self {
+        FragmentQueue {
+            created: now,
+            fragments: vec![],
+            total: None,
+            received: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Inserts the fragment in order.
+    ///
+    /// Returns `false` if the fragment is an exact duplicate of a fragment
+    /// already received. Any other overlap is an error, and the whole
+    /// datagram should be discarded.
+    fn insert(&mut self, fragment: FragmentEntry<P>, last: bool) -> Result<bool> {
+        let (start, end) = (fragment.start, fragment.end);
+        let index = self.fragments.partition_point(|f| f.start < start);
+
+        if let Some(f) = self.fragments.get(index) {
+            if f.start == start && f.end == end {
+                return Ok(false);
+            }
+        }
+
+        if last {
+            if let Some(total) = self.total {
+                ensure!(
+                    total == end,
+                    anyhow!("last fragment ends at {}, expected {}.", end, total)
+                );
+            }
+            if let Some(f) = self.fragments.last() {
+                ensure!(
+                    f.end <= end,
+                    anyhow!("fragment extends beyond the last fragment.")
+                );
+            }
+        } else {
+            ensure!(
+                end > start && (end - start) % 8 == 0,
+                anyhow!("fragment length {} is not a multiple of 8.", end - start)
+            );
+            if let Some(total) = self.total {
+                ensure!(
+                    end <= total,
+                    anyhow!("fragment extends beyond the last fragment.")
+                );
+            }
+        }
+
+        if index > 0 {
+            ensure!(
+                self.fragments[index - 1].end <= start,
+                anyhow!("fragment at offset {} overlaps.", start)
+            );
+        }
+        if let Some(f) = self.fragments.get(index) {
+            ensure!(
+                f.start >= end,
+                anyhow!("fragment at offset {} overlaps.", start)
+            );
+        }
+
+        if last {
+            self.total = Some(end);
+        }
+        self.received += end - start;
+        self.bytes += fragment.bytes;
+        self.fragments.insert(index, fragment);
+        Ok(true)
+    }
+
+    /// Returns whether all the fragments are received.
+    fn is_complete(&self) -> bool {
+        self.total == Some(self.received)
+    }
+
+    /// Returns the fragments sorted by offset.
+    pub(crate) fn into_fragments(self) -> Vec<FragmentEntry<P>> {
+        self.fragments
+    }
+}
+
+/// Fragment queues of incomplete datagrams keyed by `K`, bounded by a
+/// timeout and memory limits.
+#[derive(Debug)]
+pub(crate) struct Reassembly<K, P> {
+    config: ReassemblyConfig,
+    queues: HashMap<K, FragmentQueue<P>>,
+    bytes: usize,
+}
+
+impl<K: Clone + Eq + Hash, P> Reassembly<K, P> {
+    pub(crate) fn new(config: ReassemblyConfig) -> Self {
+        Reassembly {
+            config,
+            queues: HashMap::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Returns the number of incomplete datagrams.
+    pub(crate) fn len(&self) -> usize {
+        self.queues.len()
+    }
+
+    /// Returns the number of octets held.
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    fn remove(&mut self, key: &K) -> Option<FragmentQueue<P>> {
+        let queue = self.queues.remove(key)?;
+        self.bytes -= queue.bytes;
+        Some(queue)
+    }
+
+    /// Evicts the datagram with the oldest first arrival, other than the
+    /// datagram identified by `current`.
+    fn evict_oldest(&mut self, current: &K) -> bool {
+        let oldest = self
+            .queues
+            .iter()
+            .filter(|(key, _)| *key != current)
+            .min_by_key(|(_, queue)| queue.created)
+            .map(|(key, _)| key.clone());
+
+        match oldest {
+            Some(key) => self.remove(&key).is_some(),
+            None => false,
+        }
+    }
+
+    /// Adds a fragment, received at `now`, to the datagram identified by
+    /// `key`.
+    ///
+    /// Returns the fragments sorted by offset once the datagram is
+    /// complete. Other datagrams are evicted oldest first to make room
+    /// for the fragment. If the fragment overlaps, or if it still does not
+    /// fit in the memory limit, the whole datagram is discarded and an
+    /// error is returned.
+    pub(crate) fn insert(
+        &mut self,
+        key: K,
+        fragment: FragmentEntry<P>,
+        last: bool,
+        now: Instant,
+    ) -> Result<Option<Vec<FragmentEntry<P>>>> {
+        ensure!(
+            fragment.bytes <= self.config.max_bytes,
+            anyhow!("fragment exceeds the reassembly memory limit.")
+        );
+
+        if let Some(queue) = self.queues.get(&key) {
+            if now.saturating_duration_since(queue.created) >= self.config.timeout {
+                self.remove(&key);
+            }
+        }
+
+        if !self.queues.contains_key(&key) {
+            while self.queues.len() >= self.config.max_datagrams && self.evict_oldest(&key) {}
+        }
+        while self.bytes + fragment.bytes > self.config.max_bytes && self.evict_oldest(&key) {}
+
+        if self.bytes + fragment.bytes > self.config.max_bytes {
+            self.remove(&key);
+            return Err(anyhow!("datagram exceeds the reassembly memory limit."));
+        }
+
+        let bytes = fragment.bytes;
+        let queue = self
+            .queues
+            .entry(key.clone())
+            .or_insert_with(|| FragmentQueue::new(now));
+
+        match queue.insert(fragment, last) {
+            Ok(false) => Ok(None),
+            Ok(true) => {
+                self.bytes += bytes;
+                if queue.is_complete() {
+                    Ok(self.remove(&key).map(FragmentQueue::into_fragments))
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(e) => {
+                self.remove(&key);
+                Err(e)
+            }
+        }
+    }
+
+    /// Removes the datagrams that have timed out at `now`.
+    pub(crate) fn evict_expired(&mut self, now: Instant) -> Vec<FragmentQueue<P>> {
+        let timeout = self.config.timeout;
+        let expired = self
+            .queues
+            .iter()
+            .filter(|(_, queue)| now.saturating_duration_since(queue.created) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        expired.iter().filter_map(|key| self.remove(key)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start: usize, end: usize) -> FragmentEntry<usize> {
+        FragmentEntry {
+            start,
+            end,
+            bytes: end - start,
+            packet: start,
+        }
+    }
+
+    fn offsets(fragments: Vec<FragmentEntry<usize>>) -> Vec<usize> {
+        fragments.into_iter().map(|f| f.packet).collect()
+    }
+
+    #[test]
+    fn insert_out_of_order_fragments() {
+        let mut reassembly = Reassembly::new(ReassemblyConfig::default());
+        let now = Instant::now();
+
+        assert!(reassembly
+            .insert(1, entry(16, 20), true, now)
+            .unwrap()
+            .is_none());
+        assert!(reassembly
+            .insert(1, entry(0, 8), false, now)
+            .unwrap()
+            .is_none());
+        assert_eq!(1, reassembly.len());
+        assert_eq!(12, reassembly.bytes());
+
+        let fragments = reassembly.insert(1, entry(8, 16), false, now).unwrap();
+        assert_eq!(vec![0, 8, 16], offsets(fragments.unwrap()));
+        assert_eq!(0, reassembly.len());
+        assert_eq!(0, reassembly.bytes());
+    }
+
+    #[test]
+    fn drop_duplicate_fragment() {
+        let mut reassembly = Reassembly::new(ReassemblyConfig::default());
+        let now = Instant::now();
+
+        assert!(reassembly
+            .insert(1, entry(0, 8), false, now)
+            .unwrap()
+            .is_none());
+        assert!(reassembly
+            .insert(1, entry(0, 8), false, now)
+            .unwrap()
+            .is_none());
+        assert_eq!(8, reassembly.bytes());
+    }
+
+    #[test]
+    fn discard_overlapping_fragment() {
+        let mut reassembly = Reassembly::new(ReassemblyConfig::default());
+        let now = Instant::now();
+
+        assert!(reassembly
+            .insert(1, entry(0, 16), false, now)
+            .unwrap()
+            .is_none());
+        assert!(reassembly.insert(1, entry(8, 24), true, now).is_err());
+        assert_eq!(0, reassembly.len());
+        assert_eq!(0, reassembly.bytes());
+    }
+
+    #[test]
+    fn reject_misaligned_fragment() {
+        let mut reassembly = Reassembly::new(ReassemblyConfig::default());
+        let now = Instant::now();
+
+        assert!(reassembly.insert(1, entry(0, 12), false, now).is_err());
+    }
+
+    #[test]
+    fn evict_expired_datagrams() {
+        let config = ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let mut reassembly = Reassembly::new(config);
+        let now = Instant::now();
+
+        assert!(reassembly
+            .insert(1, entry(0, 8), false, now)
+            .unwrap()
+            .is_none());
+        let later = now + Duration::from_secs(20);
+        assert!(reassembly
+            .insert(2, entry(0, 8), false, later)
+            .unwrap()
+            .is_none());
+
+        assert!(reassembly.evict_expired(later).is_empty());
+        assert_eq!(
+            1,
+            reassembly
+                .evict_expired(now + Duration::from_secs(30))
+                .len()
+        );
+        assert_eq!(1, reassembly.len());
+        assert_eq!(8, reassembly.bytes());
+    }
+
+    #[test]
+    fn restart_expired_datagram() {
+        let mut reassembly = Reassembly::new(ReassemblyConfig::default());
+        let now = Instant::now();
+
+        assert!(reassembly
+            .insert(1, entry(0, 8), false, now)
+            .unwrap()
+            .is_none());
+
+        // the stale first fragment is discarded instead of completing the
+        // datagram.
+        let later = now + Duration::from_secs(30);
+        assert!(reassembly
+            .insert(1, entry(8, 16), true, later)
+            .unwrap()
+            .is_none());
+        assert_eq!(1, reassembly.len());
+        assert_eq!(8, reassembly.bytes());
+    }
+
+    #[test]
+    fn evict_oldest_over_datagram_limit() {
+        let config = ReassemblyConfig {
+            max_datagrams: 2,
+            ..Default::default()
+        };
+        let mut reassembly = Reassembly::new(config);
+        let now = Instant::now();
+
+        assert!(reassembly
+            .insert(1, entry(0, 8), false, now)
+            .unwrap()
+            .is_none());
+        let later = now + Duration::from_secs(1);
+        assert!(reassembly
+            .insert(2, entry(0, 8), false, later)
+            .unwrap()
+            .is_none());
+        assert!(reassembly
+            .insert(3, entry(0, 8), false, later)
+            .unwrap()
+            .is_none());
+
+        assert_eq!(2, reassembly.len());
+        assert!(!reassembly.queues.contains_key(&1));
+    }
+
+    #[test]
+    fn evict_oldest_over_memory_limit_skips_current_datagram() {
+        let config = ReassemblyConfig {
+            max_bytes: 32,
+            ..Default::default()
+        };
+        let mut reassembly = Reassembly::new(config);
+        let now = Instant::now();
+
+        // the datagram being added to is the oldest.
+        assert!(reassembly
+            .insert(1, entry(0, 16), false, now)
+            .unwrap()
+            .is_none());
+        let later = now + Duration::from_secs(1);
+        assert!(reassembly
+            .insert(2, entry(0, 8), false, later)
+            .unwrap()
+            .is_none());
+
+        assert!(reassembly
+            .insert(1, entry(16, 32), false, later)
+            .unwrap()
+            .is_none());
+        assert!(!reassembly.queues.contains_key(&2));
+        assert_eq!(32, reassembly.bytes());
+
+        let fragments = reassembly.insert(1, entry(32, 36), true, later);
+        assert!(fragments.is_err());
+        assert_eq!(0, reassembly.len());
+        assert_eq!(0, reassembly.bytes());
+    }
+}
@@ -0,0 +1,206 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::ethernet::Ethernet;
+use crate::packets::ip::v4::{Ipv4, IPV4_MIN_MTU};
+use crate::packets::{Mbuf, Packet};
+use anyhow::{anyhow, Result};
+use std::cmp;
+
+impl Ipv4 {
+    /// Splits the packet into fragments that fit in the MTU.
+    ///
+    /// The packet itself becomes the first fragment and keeps all its
+    /// options. Each subsequent fragment is a new buffer with a copy of
+    /// the link layer and IPv4 headers, keeping only the options that have
+    /// the [`copied`] flag set. The fragment payloads, except the last, are
+    /// multiples of 8 octets. If the packet is already a fragment, the new
+    /// fragments are offset accordingly and the last one inherits the
+    /// `more fragments` flag. A packet that fits in the MTU is returned
+    /// unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the MTU is less than [`IPV4_MIN_MTU`], if the
+    /// packet exceeds the MTU but the `don't fragment` flag is set, or if
+    /// a new buffer cannot be allocated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let fragments = ipv4.fragment(1500)?;
+    /// Ok(Postmark::emit_multi(fragments.into_iter().map(Packet::reset).collect()))
+    /// ```
+    ///
+    /// [`copied`]: crate::packets::ip::v4::Ipv4OptionType::copied
+    /// [`IPV4_MIN_MTU`]: IPV4_MIN_MTU
+    pub fn fragment(mut self, mtu: usize) -> Result<Vec<Ipv4>> {
+        ensure!(
+            mtu >= IPV4_MIN_MTU,
+            anyhow!("MTU {} must be greater than {}.", mtu, IPV4_MIN_MTU)
+        );
+
+        let total_len = self.total_length() as usize;
+        if total_len <= mtu {
+            return Ok(vec![self]);
+        }
+
+        ensure!(
+            !self.dont_fragment(),
+            anyhow!(
+                "packet of {} octets exceeds MTU {} with DF set.",
+                total_len,
+                mtu
+            )
+        );
+
+        let data_offset = self.payload_offset();
+        let data_len = total_len - self.header_len();
+        let base = self.fragment_offset() as usize * 8;
+        let more_fragments = self.more_fragments();
+        let first_len = (mtu - self.header_len()) & !7;
+
+        let mut fragments = vec![];
+        let mut offset = first_len;
+        while offset < data_len {
+            let mut fragment = self.copy_headers()?;
+            let len = cmp::min((mtu - fragment.header_len()) & !7, data_len - offset);
+
+            let mut data = vec![0u8; len];
+            self.mbuf().copy_to_slice(data_offset + offset, &mut data)?;
+            let end = fragment.payload_offset();
+            fragment.mbuf_mut().extend(end, len)?;
+            fragment.mbuf_mut().copy_from_slice(end, &data)?;
+
+            fragment.set_fragment_offset(((base + offset) / 8) as u16);
+            if more_fragments || offset + len < data_len {
+                fragment.set_more_fragments();
+            } else {
+                fragment.unset_more_fragments();
+            }
+            fragment.reconcile();
+
+            fragments.push(fragment);
+            offset += len;
+        }
+
+        self.mbuf_mut().truncate(data_offset + first_len)?;
+        self.set_more_fragments();
+        self.reconcile();
+        fragments.insert(0, self);
+
+        Ok(fragments)
+    }
+
+    /// Copies the link layer and IPv4 headers into a new buffer, keeping
+    /// only the options with the `copied` flag set.
+    fn copy_headers(&self) -> Result<Ipv4> {
+        let len = self.payload_offset();
+        let headers = self.mbuf().read_data_slice::<u8>(0, len)?;
+
+        let mut mbuf = Mbuf::new()?;
+        mbuf.extend(0, len)?;
+        mbuf.write_data_slice(0, unsafe { headers.as_ref() })?;
+
+        let ethernet = mbuf.parse::<Ethernet>().map_err(|(e, _)| e)?;
+        let mut ipv4 = ethernet.parse::<Ipv4>().map_err(|(e, _)| e)?;
+        ipv4.options_mut()
+            .retain(|option| option.option_type().copied())?;
+        Ok(ipv4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4OptionTypes;
+    use crate::testils::byte_arrays::{IPV4_OPTIONS_PACKET, IPV4_TCP_PACKET};
+    use crate::testils::ipv4_with_payload;
+
+    #[capsule::test]
+    fn fragment_packet() {
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+        assert_eq!(1044, ipv4.total_length());
+
+        let fragments = ipv4.fragment(500).unwrap();
+        assert_eq!(3, fragments.len());
+
+        assert_eq!(500, fragments[0].total_length());
+        assert_eq!(0, fragments[0].fragment_offset());
+        assert!(fragments[0].more_fragments());
+
+        assert_eq!(500, fragments[1].total_length());
+        assert_eq!(60, fragments[1].fragment_offset());
+        assert!(fragments[1].more_fragments());
+
+        assert_eq!(84, fragments[2].total_length());
+        assert_eq!(120, fragments[2].fragment_offset());
+        assert!(!fragments[2].more_fragments());
+
+        for fragment in fragments.iter() {
+            assert_eq!(2232, fragment.identification());
+            assert_eq!(fragment.len(), fragment.total_length() as usize);
+        }
+    }
+
+    #[capsule::test]
+    fn fragment_packet_within_mtu() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let fragments = ipv4.fragment(1500).unwrap();
+        assert_eq!(1, fragments.len());
+        assert!(!fragments[0].more_fragments());
+    }
+
+    #[capsule::test]
+    fn fragment_packet_with_dont_fragment() {
+        let mut ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+        ipv4.set_dont_fragment();
+
+        assert!(ipv4.fragment(500).is_err());
+    }
+
+    #[capsule::test]
+    fn fragment_packet_below_min_mtu() {
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+        assert!(ipv4.fragment(60).is_err());
+    }
+
+    #[capsule::test]
+    fn fragment_copies_only_copied_options() {
+        let ipv4 = ipv4_with_payload(&IPV4_OPTIONS_PACKET, 100);
+        let fragments = ipv4.fragment(100).unwrap();
+        assert_eq!(2, fragments.len());
+
+        // record route and router alert
+        assert_eq!(12, fragments[0].options_len());
+        assert_eq!(96, fragments[0].total_length());
+
+        // router alert only
+        assert_eq!(4, fragments[1].options_len());
+        let mut iter = fragments[1].options_iter();
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(Ipv4OptionTypes::RouterAlert, option.option_type());
+        assert_eq!(8, fragments[1].fragment_offset());
+        assert_eq!(73, fragments[1].total_length());
+    }
+}
@@ -18,12 +18,19 @@
 
 //! Internet Protocol v4.
 
+mod fragmentation;
+mod options;
+mod reassembly;
+
+pub use self::options::*;
+pub use self::reassembly::*;
+
 use crate::ensure;
 use crate::packets::checksum::{self, PseudoHeader};
-use crate::packets::ethernet::{EtherTypes, Ethernet};
-use crate::packets::ip::{IpPacket, ProtocolNumber, DEFAULT_IP_TTL};
+use crate::packets::ethernet::{EtherType, EtherTypes, Ethernet};
+use crate::packets::ip::{self, IpPacket, ProtocolNumber, DEFAULT_IP_TTL};
 use crate::packets::types::u16be;
-use crate::packets::{Internal, Packet, SizeOf};
+use crate::packets::{Datalink, Internal, Packet, SizeOf};
 use anyhow::{anyhow, Result, Error};
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr};
@@ -140,16 +147,30 @@ const FLAGS_MF: u16be = u16be(u16::to_be(0b0010_0000_0000_0000));
 ///      is their transmission in any particular datagram, not their
 ///      implementation.
 ///
+/// The packet is normally carried by an [Ethernet] frame. An inner packet
+/// of a tunnel is carried by the tunnel packet instead, which can be any
+/// [`Datalink`] packet, including an outer IPv4 or IPv6 packet of an
+/// IP-in-IP tunnel.
+///
+/// # Example
+///
+/// ```
+/// let outer = ethernet.parse::<Ipv4>()?;
+/// let inner = outer.parse::<Ipv4<Ipv4>>()?;
+/// ```
+///
 /// [IETF RFC 791]: https://tools.ietf.org/html/rfc791#section-3.1
 /// [IETF RFC 2474]: https://tools.ietf.org/html/rfc2474
 /// [IETF RFC 3168]: https://tools.ietf.org/html/rfc3168
-pub struct Ipv4 {
-    envelope: Ethernet,
+/// [Ethernet]: crate::packets::ethernet::Ethernet
+/// [`Datalink`]: crate::packets::Datalink
+pub struct Ipv4<E: Datalink = Ethernet> {
+    envelope: E,
     header: NonNull<Ipv4Header>,
     offset: usize,
 }
 
-impl Ipv4 {
+impl<E: Datalink> Ipv4<E> {
     #[inline]
     fn header(&self) -> &Ipv4Header {
         unsafe { self.header.as_ref() }
@@ -176,7 +197,7 @@ impl Ipv4 {
     #[allow(dead_code)]
     #[inline]
     fn set_ihl(&mut self, ihl: u8) {
-        self.header_mut().version_ihl = (self.header().version_ihl & 0x0f) | (ihl & 0x0f);
+        self.header_mut().version_ihl = (self.header().version_ihl & 0xf0) | (ihl & 0x0f);
     }
 
     /// Returns the differentiated services codepoint.
@@ -363,9 +384,59 @@ impl Ipv4 {
         self.set_src(self.dst());
         self.set_dst(src_addr)
     }
+
+    /// Encapsulates the IP packet carried by the envelope in an Ipv4
+    /// packet.
+    ///
+    /// The outer Ipv4 header is inserted between the envelope and the
+    /// IP packet, and [`protocol`] is set to the IP-in-IP protocol of the
+    /// inner packet. The caller is responsible for setting the outer
+    /// addresses and reconciling the packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the envelope does not carry an IPv4 or IPv6
+    /// packet. Returns an error if the buffer does not have enough free
+    /// space.
+    ///
+    /// [`protocol`]: Ipv4::protocol
+    #[inline]
+    pub fn encap(envelope: E) -> Result<Self> {
+        let protocol = ip::tunnel_protocol(envelope.protocol_type())
+            .ok_or_else(|| anyhow!("not an IP packet."))?;
+        let mut outer = envelope.push::<Self>()?;
+        outer.set_protocol(protocol);
+        Ok(outer)
+    }
+
+    /// Decapsulates the inner IP packet of an IP-in-IP tunnel.
+    ///
+    /// The Ipv4 header is removed from the buffer, and the envelope's
+    /// protocol type is set to the type of the inner packet. The inner
+    /// packet can then be parsed from the envelope.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is not an IP-in-IP tunnel packet.
+    /// Returns an error if the buffer fails to shrink.
+    #[inline]
+    pub fn decap(self) -> Result<E> {
+        let ether_type = self.protocol_type();
+        ensure!(
+            ether_type == EtherTypes::Ipv4 || ether_type == EtherTypes::Ipv6,
+            anyhow!("not an IP-in-IP packet.")
+        );
+
+        let offset = self.offset;
+        let len = self.header_len();
+        let mut envelope = self.deparse();
+        envelope.mbuf_mut().shrink(offset, len)?;
+        envelope.set_protocol_type(ether_type);
+        Ok(envelope)
+    }
 }
 
-impl fmt::Debug for Ipv4 {
+impl<E: Datalink> fmt::Debug for Ipv4<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ipv4")
             .field("src", &format!("{}", self.src()))
@@ -388,9 +459,10 @@ impl fmt::Debug for Ipv4 {
     }
 }
 
-impl Packet for Ipv4 {
-    /// The preceding type for an IPv4 packet must be Ethernet.
-    type Envelope = Ethernet;
+impl<E: Datalink> Packet for Ipv4<E> {
+    /// The preceding type for an IPv4 packet is Ethernet, or a tunnel
+    /// packet for an inner IPv4 packet.
+    type Envelope = E;
 
     #[inline]
     fn envelope(&self) -> &Self::Envelope {
@@ -407,9 +479,11 @@ impl Packet for Ipv4 {
         self.offset
     }
 
+    /// Returns the length of the header including the options, as
+    /// indicated by the IHL.
     #[inline]
     fn header_len(&self) -> usize {
-        Ipv4Header::size_of()
+        self.ihl() as usize * 4
     }
 
     #[inline]
@@ -421,30 +495,41 @@ impl Packet for Ipv4 {
         }
     }
 
-    /// Parses the Ethernet's payload as an IPv4 packet.
+    /// Parses the envelope's payload as an IPv4 packet.
     ///
     /// # Errors
     ///
-    /// Returns an error if [`ether_type`] is not set to [`EtherTypes::Ipv4`].
+    /// Returns an error if [`protocol_type`] is not set to [`EtherTypes::Ipv4`].
     /// Returns an error if the payload does not have sufficient data for the
-    /// IPv4 header.
+    /// IPv4 header. Returns an error if the IHL is less than `5` or if the
+    /// payload does not have sufficient data for the options.
     ///
-    /// [`ether_type`]: Ethernet::ether_type
+    /// [`protocol_type`]: Datalink::protocol_type
     /// [`EtherTypes::Ipv4`]: EtherTypes::Ipv4
     #[inline]
     fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
         ensure!(
-            envelope.ether_type() == EtherTypes::Ipv4,
+            envelope.protocol_type() == EtherTypes::Ipv4,
             (anyhow!("not an IPv4 packet."), envelope)
         );
 
         let mbuf = envelope.mbuf();
         let offset = envelope.payload_offset();
-        let header = match mbuf.read_data(offset) {
+        let header = match mbuf.read_data::<Ipv4Header>(offset) {
             Err(e) => return Err((e, envelope)),
             Ok(header) => header
         };
 
+        // the options are part of the header as indicated by the IHL.
+        let header_len = (unsafe { header.as_ref() }.version_ihl & 0x0f) as usize * 4;
+        ensure!(
+            header_len >= Ipv4Header::size_of(),
+            (anyhow!("invalid IPv4 header length {}.", header_len), envelope)
+        );
+        if let Err(e) = mbuf.read_data_slice::<u8>(offset, header_len) {
+            return Err((e, envelope));
+        }
+
         Ok(Ipv4 {
             envelope,
             header,
@@ -452,15 +537,15 @@ impl Packet for Ipv4 {
         })
     }
 
-    /// Prepends an IPv4 packet to the beginning of the Ethernet's payload.
+    /// Prepends an IPv4 packet to the beginning of the envelope's payload.
     ///
-    /// [`ether_type`] is set to [`EtherTypes::Ipv4`].
+    /// [`protocol_type`] is set to [`EtherTypes::Ipv4`].
     ///
     /// # Errors
     ///
     /// Returns an error if the buffer does not have enough free space.
     ///
-    /// [`ether_type`]: Ethernet::ether_type
+    /// [`protocol_type`]: Datalink::protocol_type
     /// [`EtherTypes::Ipv4`]: EtherTypes::Ipv4
     #[inline]
     fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
@@ -470,7 +555,7 @@ impl Packet for Ipv4 {
         mbuf.extend(offset, Ipv4Header::size_of())?;
         let header = mbuf.write_data(offset, &Ipv4Header::default())?;
 
-        envelope.set_ether_type(EtherTypes::Ipv4);
+        envelope.set_protocol_type(EtherTypes::Ipv4);
 
         Ok(Ipv4 {
             envelope,
@@ -496,11 +581,41 @@ impl Packet for Ipv4 {
     #[inline]
     fn reconcile(&mut self) {
         self.set_total_length(self.len() as u16);
-        self.compute_checksum();
+
+        let offset = self.offset;
+        let header_len = self.header_len();
+        if self.mbuf_mut().defer_l3_offloads(offset, header_len, true) {
+            self.set_checksum(0);
+        } else {
+            self.compute_checksum();
+        }
     }
 }
 
-impl IpPacket for Ipv4 {
+impl<E: Datalink> Datalink for Ipv4<E> {
+    /// Returns the protocol type of the inner packet of an IP-in-IP tunnel,
+    /// mapped from [`protocol`]. Returns `0` if the packet is not an
+    /// IP-in-IP tunnel packet.
+    ///
+    /// [`protocol`]: Ipv4::protocol
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        ip::tunnel_ether_type(self.protocol())
+    }
+
+    /// Sets [`protocol`] to the IP-in-IP protocol of the inner packet.
+    /// Protocol types other than IPv4 and IPv6 are ignored.
+    ///
+    /// [`protocol`]: Ipv4::protocol
+    #[inline]
+    fn set_protocol_type(&mut self, ether_type: EtherType) {
+        if let Some(protocol) = ip::tunnel_protocol(ether_type) {
+            self.set_protocol(protocol);
+        }
+    }
+}
+
+impl<E: Datalink> IpPacket for Ipv4<E> {
     #[inline]
     fn next_protocol(&self) -> ProtocolNumber {
         self.protocol()
@@ -577,10 +692,15 @@ impl IpPacket for Ipv4 {
             anyhow!("MTU {} must be greater than {}.", mtu, IPV4_MIN_MTU)
         );
 
-        // accounts for the Ethernet frame length.
+        // accounts for the headers preceding the packet.
         let to_len = mtu + self.offset();
         self.mbuf_mut().truncate(to_len)
     }
+
+    #[inline]
+    fn set_length_from_buffer(&mut self) {
+        self.set_total_length(self.len() as u16);
+    }
 }
 
 /// IPv4 header.
@@ -624,7 +744,9 @@ mod tests {
     use super::*;
     use crate::packets::ip::ProtocolNumbers;
     use crate::packets::Mbuf;
-    use crate::testils::byte_arrays::{IPV4_UDP_PACKET, IPV6_TCP_PACKET};
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::udp::Udp;
+    use crate::testils::byte_arrays::{IPIP_PACKET, IPV4_UDP_PACKET, IPV6_TCP_PACKET};
 
     #[test]
     fn size_of_ipv4_header() {
@@ -737,4 +859,79 @@ mod tests {
         ipv4.reconcile_all();
         assert_eq!(expected, ipv4.checksum());
     }
+
+    #[capsule::test]
+    fn parse_ipip_packet() {
+        let packet = Mbuf::from_bytes(&IPIP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let outer = ethernet.parse::<Ipv4>().unwrap();
+
+        assert_eq!(ProtocolNumbers::Ipip, outer.protocol());
+        assert_eq!(EtherTypes::Ipv4, outer.protocol_type());
+        assert!(outer.peek::<Ipv6<Ipv4>>().is_err());
+
+        let inner = outer.parse::<Ipv4<Ipv4>>().unwrap();
+        assert_eq!("192.168.0.1", inner.src().to_string());
+        assert_eq!("192.168.0.2", inner.dst().to_string());
+        assert_eq!(33, inner.total_length());
+
+        let udp = inner.parse::<Udp<Ipv4<Ipv4>>>().unwrap();
+        assert_eq!(1024, udp.src_port());
+        assert_eq!(53, udp.dst_port());
+    }
+
+    #[capsule::test]
+    fn encap_and_decap_4in4() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let inner_len = ethernet.payload_len();
+
+        let mut outer = Ipv4::encap(ethernet).unwrap();
+        outer.set_src(Ipv4Addr::new(10, 0, 0, 1));
+        outer.set_dst(Ipv4Addr::new(10, 0, 0, 2));
+        outer.reconcile_all();
+
+        assert_eq!(EtherTypes::Ipv4, outer.envelope().ether_type());
+        assert_eq!(ProtocolNumbers::Ipip, outer.protocol());
+        assert_eq!((inner_len + 20) as u16, outer.total_length());
+        let inner = outer.peek::<Ipv4<Ipv4>>().unwrap();
+        assert_eq!("139.133.217.110", inner.src().to_string());
+
+        let ethernet = outer.decap().unwrap();
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+        let data = ethernet
+            .mbuf()
+            .read_data_slice::<u8>(0, ethernet.mbuf().data_len())
+            .unwrap();
+        assert_eq!(&IPV4_UDP_PACKET[..], unsafe { data.as_ref() });
+    }
+
+    #[capsule::test]
+    fn encap_and_decap_6in4() {
+        let packet = Mbuf::from_bytes(&IPV6_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let inner_len = ethernet.payload_len();
+
+        let mut outer = Ipv4::encap(ethernet).unwrap();
+        outer.reconcile_all();
+        assert_eq!(EtherTypes::Ipv4, outer.envelope().ether_type());
+        assert_eq!(ProtocolNumbers::Ipv6, outer.protocol());
+        assert_eq!((inner_len + 20) as u16, outer.total_length());
+        assert_eq!(EtherTypes::Ipv6, outer.protocol_type());
+        let inner = outer.peek::<Ipv6<Ipv4>>().unwrap();
+        assert_eq!("2001:db8:85a3::1", inner.src().to_string());
+
+        let ethernet = outer.decap().unwrap();
+        assert_eq!(EtherTypes::Ipv6, ethernet.ether_type());
+        assert!(ethernet.parse::<Ipv6>().is_ok());
+    }
+
+    #[capsule::test]
+    fn decap_non_ipip_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        assert!(ipv4.decap().is_err());
+    }
 }
@@ -0,0 +1,1501 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Internet Protocol v4 header options.
+
+use super::{Ipv4, Ipv4Header};
+use crate::ensure;
+use crate::packets::tlv::{self, Cursor, Tlv, TlvType};
+use crate::packets::types::u16be;
+use crate::packets::{Datalink, Immutable, Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::Ipv4Addr;
+use std::ptr::NonNull;
+
+/// The maximum length of the options, measured in octets.
+///
+/// The IHL field can describe a header of at most 15 32-bit words, 5 of
+/// which are taken by the fixed portion of the header.
+pub const IPV4_MAX_OPTIONS_LEN: usize = 40;
+
+impl<E: Datalink> Ipv4<E> {
+    /// Returns the buffer offset where the options begin.
+    #[inline]
+    fn options_offset(&self) -> usize {
+        self.offset + Ipv4Header::size_of()
+    }
+
+    /// Returns the length of the options including the padding, measured
+    /// in octets.
+    #[inline]
+    pub fn options_len(&self) -> usize {
+        self.header_len() - Ipv4Header::size_of()
+    }
+
+    /// Returns an iterator to read the options in the header.
+    ///
+    /// The options cannot be modified. To modify the values while iterating
+    /// over the options, use [`options_mut().iter()`] instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut iter = ipv4.options_iter();
+    ///
+    /// while let Some(option) = iter.next()? {
+    ///     println!("{:?}", option);
+    /// }
+    /// ```
+    ///
+    /// [`options_mut().iter()`]: Ipv4Options::iter
+    #[inline]
+    pub fn options_iter(&self) -> ImmutableIpv4OptionsIterator<'_> {
+        let mbuf = unsafe { self.mbuf().clone(Internal(())) };
+        ImmutableIpv4OptionsIterator {
+            mbuf,
+            cursor: Cursor {
+                offset: self.options_offset(),
+                end: self.payload_offset(),
+            },
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a mutable reference to the options in the header.
+    #[inline]
+    pub fn options_mut(&mut self) -> Ipv4Options<'_> {
+        let header = self.header;
+        let offset = self.options_offset();
+        Ipv4Options {
+            mbuf: self.mbuf_mut(),
+            header,
+            offset,
+        }
+    }
+}
+
+/// [IANA] assigned IPv4 option type.
+///
+/// The option type octet is made of three fields: the copied flag, the
+/// option class and the option number. A list of supported types is
+/// under [`Ipv4OptionTypes`].
+///
+/// [IANA]: https://www.iana.org/assignments/ip-parameters/ip-parameters.xhtml#ip-parameters-1
+/// [`Ipv4OptionTypes`]: Ipv4OptionTypes
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct Ipv4OptionType(pub u8);
+
+impl Ipv4OptionType {
+    /// Returns a flag indicating whether the option must be copied into
+    /// all fragments on fragmentation.
+    #[inline]
+    pub fn copied(self) -> bool {
+        self.0 & 0x80 != 0
+    }
+
+    /// Returns the option class.
+    #[inline]
+    pub fn class(self) -> u8 {
+        (self.0 >> 5) & 0x03
+    }
+
+    /// Returns the option number.
+    #[inline]
+    pub fn number(self) -> u8 {
+        self.0 & 0x1f
+    }
+}
+
+/// Supported IPv4 option types.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod Ipv4OptionTypes {
+    use super::Ipv4OptionType;
+
+    /// End of the option list. Also used as the padding after the last
+    /// option.
+    pub const EndOfOptionList: Ipv4OptionType = Ipv4OptionType(0);
+
+    /// No operation. Used between options to align the next option.
+    pub const NoOperation: Ipv4OptionType = Ipv4OptionType(1);
+
+    /// Option type for [Record Route].
+    ///
+    /// [Record Route]: crate::packets::ip::v4::RecordRoute
+    pub const RecordRoute: Ipv4OptionType = Ipv4OptionType(7);
+
+    /// Option type for [Timestamp].
+    ///
+    /// [Timestamp]: crate::packets::ip::v4::Timestamp
+    pub const Timestamp: Ipv4OptionType = Ipv4OptionType(68);
+
+    /// Option type for [Router Alert].
+    ///
+    /// [Router Alert]: crate::packets::ip::v4::RouterAlert
+    pub const RouterAlert: Ipv4OptionType = Ipv4OptionType(148);
+}
+
+impl fmt::Display for Ipv4OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Ipv4OptionTypes::EndOfOptionList => "End of Option List".to_string(),
+                Ipv4OptionTypes::NoOperation => "No Operation".to_string(),
+                Ipv4OptionTypes::RecordRoute => "Record Route".to_string(),
+                Ipv4OptionTypes::Timestamp => "Timestamp".to_string(),
+                Ipv4OptionTypes::RouterAlert => "Router Alert".to_string(),
+                _ => format!("{}", self.0),
+            }
+        )
+    }
+}
+
+impl TlvType for Ipv4OptionType {
+    const ALIGNMENT: usize = 4;
+    const MAX_LEN: usize = IPV4_MAX_OPTIONS_LEN;
+
+    #[inline]
+    fn from_octet(octet: u8) -> Self {
+        Ipv4OptionType(octet)
+    }
+
+    #[inline]
+    fn is_single_octet(self) -> bool {
+        self == Ipv4OptionTypes::EndOfOptionList || self == Ipv4OptionTypes::NoOperation
+    }
+
+    #[inline]
+    fn option_len(length: u8) -> Result<usize> {
+        // the length includes the type and length octets.
+        ensure!(length >= 2, anyhow!("invalid option length."));
+        Ok(length as usize)
+    }
+
+    #[inline]
+    fn is_end_of_list(self) -> bool {
+        self == Ipv4OptionTypes::EndOfOptionList
+    }
+}
+
+/// An immutable generic IPv4 option that can be casted to a more specific
+/// option.
+pub struct ImmutableIpv4Option<'a> {
+    mbuf: &'a mut Mbuf,
+    option_type: Ipv4OptionType,
+    length: u8,
+    offset: usize,
+}
+
+impl<'a> ImmutableIpv4Option<'a> {
+    /// Creates a new immutable untyped IPv4 option.
+    #[inline]
+    fn new(mbuf: &'a mut Mbuf, tlv: Tlv<Ipv4OptionType>) -> Self {
+        ImmutableIpv4Option {
+            mbuf,
+            option_type: tlv.option_type,
+            length: tlv.len as u8,
+            offset: tlv.offset,
+        }
+    }
+
+    /// Returns the option type.
+    #[inline]
+    pub fn option_type(&self) -> Ipv4OptionType {
+        self.option_type
+    }
+
+    /// Returns the length of the option measured in octets. Single octet
+    /// options have a length of `1`.
+    #[inline]
+    pub fn length(&self) -> u8 {
+        self.length
+    }
+
+    /// Casts the immutable generic option to a specific option `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut iter = ipv4.options_iter();
+    ///
+    /// while let Some(mut option) = iter.next()? {
+    ///     let route = option.downcast::<RecordRoute<'_>>()?;
+    ///     println!("{:?}", route);
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<'b, T: Ipv4Option<'b>>(&'b mut self) -> Result<Immutable<'b, T>> {
+        T::try_parse(self.mbuf, self.offset, Internal(())).map(Immutable::new)
+    }
+}
+
+impl fmt::Debug for ImmutableIpv4Option<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImmutableIpv4Option")
+            .field("option_type", &format!("{}", self.option_type()))
+            .field("length", &self.length())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in the IPv4 header
+/// immutably.
+pub struct ImmutableIpv4OptionsIterator<'a> {
+    mbuf: Mbuf,
+    cursor: Cursor,
+    _phantom: PhantomData<&'a Mbuf>,
+}
+
+impl ImmutableIpv4OptionsIterator<'_> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// The iteration stops at the end of option list marker. Returns
+    /// `Ok(None)` when iteration is finished; returns `Err` when a parse
+    /// error is encountered during iteration.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<ImmutableIpv4Option<'_>>> {
+        match self.cursor.next::<Ipv4OptionType>(&self.mbuf)? {
+            Some(tlv) => Ok(Some(ImmutableIpv4Option::new(&mut self.mbuf, tlv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for ImmutableIpv4OptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImmutableIpv4OptionsIterator")
+            .field("offset", &self.cursor.offset)
+            .field("end", &self.cursor.end)
+            .finish()
+    }
+}
+
+/// A mutable generic IPv4 option that can be casted to a more specific
+/// option.
+pub struct MutableIpv4Option<'a> {
+    mbuf: &'a mut Mbuf,
+    option_type: Ipv4OptionType,
+    length: u8,
+    offset: usize,
+}
+
+impl<'a> MutableIpv4Option<'a> {
+    /// Creates a new mutable untyped IPv4 option.
+    #[inline]
+    fn new(mbuf: &'a mut Mbuf, tlv: Tlv<Ipv4OptionType>) -> Self {
+        MutableIpv4Option {
+            mbuf,
+            option_type: tlv.option_type,
+            length: tlv.len as u8,
+            offset: tlv.offset,
+        }
+    }
+
+    /// Returns the option type.
+    #[inline]
+    pub fn option_type(&self) -> Ipv4OptionType {
+        self.option_type
+    }
+
+    /// Returns the length of the option measured in octets. Single octet
+    /// options have a length of `1`.
+    #[inline]
+    pub fn length(&self) -> u8 {
+        self.length
+    }
+
+    /// Casts the mutable generic option to a specific option `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut options = ipv4.options_mut();
+    /// let mut iter = options.iter();
+    ///
+    /// while let Some(mut option) = iter.next()? {
+    ///     if let Ok(mut route) = option.downcast::<RecordRoute<'_>>() {
+    ///         route.record(Ipv4Addr::LOCALHOST)?;
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<'b, T: Ipv4Option<'b>>(&'b mut self) -> Result<T> {
+        T::try_parse(self.mbuf, self.offset, Internal(()))
+    }
+}
+
+impl fmt::Debug for MutableIpv4Option<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutableIpv4Option")
+            .field("option_type", &format!("{}", self.option_type()))
+            .field("length", &self.length())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in the IPv4 header
+/// mutably.
+pub struct MutableIpv4OptionsIterator<'a> {
+    mbuf: &'a mut Mbuf,
+    cursor: Cursor,
+}
+
+impl MutableIpv4OptionsIterator<'_> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// The iteration stops at the end of option list marker. Returns
+    /// `Ok(None)` when iteration is finished; returns `Err` when a parse
+    /// error is encountered during iteration.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<MutableIpv4Option<'_>>> {
+        match self.cursor.next::<Ipv4OptionType>(self.mbuf)? {
+            Some(tlv) => Ok(Some(MutableIpv4Option::new(self.mbuf, tlv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for MutableIpv4OptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutableIpv4OptionsIterator")
+            .field("offset", &self.cursor.offset)
+            .field("end", &self.cursor.end)
+            .finish()
+    }
+}
+
+/// Options in the IPv4 header.
+///
+/// Adding or removing options resizes the header. The options are padded
+/// with end of option list markers to a 32-bit boundary and the IHL is
+/// updated accordingly. The total length and the checksum are fixed when
+/// the packet is reconciled.
+pub struct Ipv4Options<'a> {
+    mbuf: &'a mut Mbuf,
+    header: NonNull<Ipv4Header>,
+    offset: usize,
+}
+
+impl Ipv4Options<'_> {
+    #[inline]
+    fn header_mut(&mut self) -> &mut Ipv4Header {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the buffer offset where the options end, including the
+    /// padding.
+    #[inline]
+    fn end(&self) -> usize {
+        let ihl = unsafe { self.header.as_ref() }.version_ihl & 0x0f;
+        self.offset + (ihl as usize * 4).saturating_sub(Ipv4Header::size_of())
+    }
+
+    /// Returns the buffer offset where the padding after the last option
+    /// begins.
+    fn padding_offset(&self) -> Result<usize> {
+        tlv::padding_offset::<Ipv4OptionType>(self.mbuf, self.offset, self.end())
+    }
+
+    /// Sets the IHL to cover the options of `aligned_len` octets.
+    #[inline]
+    fn set_ihl(&mut self, aligned_len: usize) {
+        let ihl = ((Ipv4Header::size_of() + aligned_len) / 4) as u8;
+        let header = self.header_mut();
+        header.version_ihl = (header.version_ihl & 0xf0) | ihl;
+    }
+
+    /// Inserts `len` octets of room for a new option at `at`, resizes the
+    /// padding so the options end on a 32-bit boundary, and updates the
+    /// IHL. The buffer and the IHL are left unchanged if it fails.
+    fn insert(&mut self, at: usize, len: usize) -> Result<()> {
+        let end = self.end();
+        let padding = self.padding_offset()?;
+        let aligned_len =
+            tlv::insert::<Ipv4OptionType>(self.mbuf, self.offset, at, padding, end, len)?;
+        self.set_ihl(aligned_len);
+        Ok(())
+    }
+
+    /// Returns an iterator that allows modifying each option.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut options = ipv4.options_mut();
+    /// let mut iter = options.iter();
+    ///
+    /// while let Some(option) = iter.next()? {
+    ///     println!("{:?}", option);
+    /// }
+    /// ```
+    #[inline]
+    pub fn iter(&mut self) -> MutableIpv4OptionsIterator<'_> {
+        let cursor = Cursor {
+            offset: self.offset,
+            end: self.end(),
+        };
+        MutableIpv4OptionsIterator {
+            mbuf: self.mbuf,
+            cursor,
+        }
+    }
+
+    /// Prepends a new option `T` at the beginning of the options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options would exceed [`IPV4_MAX_OPTIONS_LEN`]
+    /// or if the buffer does not have enough free space. The options are
+    /// left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut options = ipv4.options_mut();
+    /// let mut alert = options.prepend::<RouterAlert<'_>>()?;
+    /// alert.set_value(0);
+    /// ```
+    ///
+    /// [`IPV4_MAX_OPTIONS_LEN`]: IPV4_MAX_OPTIONS_LEN
+    pub fn prepend<'a, T: Ipv4Option<'a>>(&'a mut self) -> Result<T> {
+        let offset = self.offset;
+        self.insert(offset, T::push_len())?;
+        T::try_push(self.mbuf, offset, Internal(()))
+    }
+
+    /// Appends a new option `T` at the end of the options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options would exceed [`IPV4_MAX_OPTIONS_LEN`]
+    /// or if the buffer does not have enough free space. The options are
+    /// left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut options = ipv4.options_mut();
+    /// let mut route = options.append::<RecordRoute<'_>>()?;
+    /// route.record(Ipv4Addr::LOCALHOST)?;
+    /// ```
+    ///
+    /// [`IPV4_MAX_OPTIONS_LEN`]: IPV4_MAX_OPTIONS_LEN
+    pub fn append<'a, T: Ipv4Option<'a>>(&'a mut self) -> Result<T> {
+        let padding = self.padding_offset()?;
+        self.insert(padding, T::push_len())?;
+        T::try_push(self.mbuf, padding, Internal(()))
+    }
+
+    /// Retains only the options specified by the predicate.
+    ///
+    /// In other words, remove all options `o` such that `f(o)` returns false.
+    /// If an error occurs, all removals done prior to the error cannot be
+    /// undone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut ipv4 = ethernet.parse::<Ipv4>()?;
+    /// let mut options = ipv4.options_mut();
+    /// let _ = options.retain(|option| option.option_type().copied());
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut ImmutableIpv4Option<'_>) -> bool,
+    {
+        let (padding, end) =
+            tlv::retain::<Ipv4OptionType, _>(self.mbuf, self.offset, self.end(), |mbuf, tlv| {
+                f(&mut ImmutableIpv4Option::new(mbuf, tlv))
+            })?;
+
+        let aligned_len =
+            tlv::realign::<Ipv4OptionType>(self.mbuf, self.offset, padding, end, 0)?;
+        self.set_ihl(aligned_len);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Ipv4Options<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ipv4Options")
+            .field("offset", &self.offset)
+            .field("end", &self.end())
+            .finish()
+    }
+}
+
+/// A trait that all IPv4 options must implement.
+///
+/// The trait is used for conversion between the generic IPv4 option
+/// and the more specific options. Implementors can use this trait to
+/// add custom IPv4 options. This trait should not be imported and used
+/// directly.
+pub trait Ipv4Option<'a> {
+    /// Returns the option type.
+    fn option_type(&self) -> Ipv4OptionType;
+
+    /// Returns the length of the option measured in octets.
+    fn length(&self) -> u8;
+
+    /// Returns the length of the option in octets when it's pushed by
+    /// [`try_push`].
+    ///
+    /// The room for the option is inserted and the options are realigned
+    /// to a 32-bit boundary before the push.
+    ///
+    /// [`try_push`]: Ipv4Option::try_push
+    fn push_len() -> usize
+    where
+        Self: Sized;
+
+    /// Parses the buffer at offset as this IPv4 option.
+    ///
+    /// The buffer offset includes `option_type` and `length` fields. The
+    /// implementation should verify that `option_type` matches the expected
+    /// type code. Otherwise parse should fail. When applicable, the
+    /// implementation should also verify that `length` is consistent with
+    /// the expected length for the given option type as well.
+    ///
+    /// # Remarks
+    ///
+    /// This function cannot be invoked directly. It is internally used by
+    /// [`ImmutableIpv4Option::downcast`] and [`MutableIpv4Option::downcast`].
+    ///
+    /// [`ImmutableIpv4Option::downcast`]: ImmutableIpv4Option::downcast
+    /// [`MutableIpv4Option::downcast`]: MutableIpv4Option::downcast
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, internal: Internal) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Writes a new IPv4 option to the [`push_len`] octets of room
+    /// already inserted in the buffer at offset.
+    ///
+    /// # Remarks
+    ///
+    /// This function cannot be invoked directly. It is internally used by
+    /// [`Ipv4Options::prepend`] and [`Ipv4Options::append`].
+    ///
+    /// [`push_len`]: Ipv4Option::push_len
+    /// [`Ipv4Options::prepend`]: Ipv4Options::prepend
+    /// [`Ipv4Options::append`]: Ipv4Options::append
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, internal: Internal) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+/// Router Alert option defined in [IETF RFC 2113].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |    Length     |             Value             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Type*:            148
+///
+/// - *Length*:          4
+///
+/// - *Value*:           A 2 octet code. `0` means the router shall
+///                      examine the packet. Other values are reserved.
+///
+/// [IETF RFC 2113]: https://tools.ietf.org/html/rfc2113#section-2.1
+pub struct RouterAlert<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<RouterAlertFields>,
+    offset: usize,
+}
+
+impl RouterAlert<'_> {
+    #[inline]
+    fn fields(&self) -> &RouterAlertFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut RouterAlertFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the router alert value.
+    #[inline]
+    pub fn value(&self) -> u16 {
+        self.fields().value.into()
+    }
+
+    /// Sets the router alert value.
+    #[inline]
+    pub fn set_value(&mut self, value: u16) {
+        self.fields_mut().value = value.into();
+    }
+}
+
+impl fmt::Debug for RouterAlert<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouterAlert")
+            .field("type", &self.option_type())
+            .field("length", &self.length())
+            .field("value", &self.value())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> Ipv4Option<'a> for RouterAlert<'a> {
+    /// Returns the option type. Should always be `148`.
+    #[inline]
+    fn option_type(&self) -> Ipv4OptionType {
+        Ipv4OptionType(self.fields().option_type)
+    }
+
+    /// Returns the length of the option. Should always be `4`.
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        RouterAlertFields::size_of()
+    }
+
+    /// Parses the buffer at offset as router alert option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `option_type` is not set to `RouterAlert`.
+    /// Returns an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<RouterAlert<'a>> {
+        let fields = mbuf.read_data::<RouterAlertFields>(offset)?;
+        let option = RouterAlert {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.option_type() == Ipv4OptionTypes::RouterAlert,
+            anyhow!("not router alert.")
+        );
+
+        ensure!(
+            option.length() as usize == RouterAlertFields::size_of(),
+            anyhow!("invalid router alert option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new router alert option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<RouterAlert<'a>> {
+        let fields = mbuf.write_data(offset, &RouterAlertFields::default())?;
+        Ok(RouterAlert {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// Router alert option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct RouterAlertFields {
+    option_type: u8,
+    length: u8,
+    value: u16be,
+}
+
+impl Default for RouterAlertFields {
+    fn default() -> RouterAlertFields {
+        RouterAlertFields {
+            option_type: Ipv4OptionTypes::RouterAlert.0,
+            length: 4,
+            value: u16be::default(),
+        }
+    }
+}
+
+/// The number of addresses a pushed record route option has room for.
+const RECORD_ROUTE_SLOTS: usize = 9;
+
+/// Record Route option defined in [IETF RFC 791].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |    Length     |    Pointer    |  Route Data   |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                          Route Data                           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Type*:            7
+///
+/// - *Length*:          The option length in octets, including the type,
+///                      length and pointer octets.
+///
+/// - *Pointer*:         The octet offset, relative to the option, of the
+///                      next area to store a route address. The smallest
+///                      legal value is 4.
+///
+/// - *Route Data*:      A list of internet addresses recorded by the
+///                      modules the packet traverses.
+///
+/// A pushed option has room for 9 addresses, the most that fits in the
+/// header.
+///
+/// [IETF RFC 791]: https://tools.ietf.org/html/rfc791#page-20
+pub struct RecordRoute<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<RecordRouteFields>,
+    route: NonNull<[Ipv4Addr]>,
+    offset: usize,
+}
+
+impl RecordRoute<'_> {
+    #[inline]
+    fn fields(&self) -> &RecordRouteFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut RecordRouteFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the pointer to the next area to store a route address.
+    #[inline]
+    pub fn pointer(&self) -> u8 {
+        self.fields().pointer
+    }
+
+    /// Sets the pointer.
+    #[inline]
+    pub fn set_pointer(&mut self, pointer: u8) {
+        self.fields_mut().pointer = pointer;
+    }
+
+    /// Returns all the route address slots, including the ones not yet
+    /// recorded.
+    #[inline]
+    pub fn route(&self) -> &[Ipv4Addr] {
+        unsafe { self.route.as_ref() }
+    }
+
+    /// Returns the route addresses recorded so far.
+    #[inline]
+    pub fn recorded(&self) -> &[Ipv4Addr] {
+        let count = (self.pointer().saturating_sub(4) / 4) as usize;
+        let route = self.route();
+        &route[..count.min(route.len())]
+    }
+
+    /// Records an address in the next slot and advances the pointer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the route data is already full.
+    #[inline]
+    pub fn record(&mut self, addr: Ipv4Addr) -> Result<()> {
+        let index = (self.pointer().saturating_sub(4) / 4) as usize;
+        ensure!(
+            index < self.route().len(),
+            anyhow!("record route option is full.")
+        );
+
+        unsafe {
+            self.route.as_mut()[index] = addr;
+        }
+        self.set_pointer(self.pointer() + 4);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for RecordRoute<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordRoute")
+            .field("type", &self.option_type())
+            .field("length", &self.length())
+            .field("pointer", &self.pointer())
+            .field("recorded", &self.recorded())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> Ipv4Option<'a> for RecordRoute<'a> {
+    /// Returns the option type. Should always be `7`.
+    #[inline]
+    fn option_type(&self) -> Ipv4OptionType {
+        Ipv4OptionType(self.fields().option_type)
+    }
+
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        RecordRouteFields::size_of() + Ipv4Addr::size_of() * RECORD_ROUTE_SLOTS
+    }
+
+    /// Parses the buffer at offset as record route option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `option_type` is not set to `RecordRoute`.
+    /// Returns an error if the option length is not a multiple of the
+    /// address size.
+    #[inline]
+    fn try_parse(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<RecordRoute<'a>> {
+        let fields = mbuf.read_data::<RecordRouteFields>(offset)?;
+        let (option_type, length) =
+            unsafe { (fields.as_ref().option_type, fields.as_ref().length) };
+
+        ensure!(
+            Ipv4OptionType(option_type) == Ipv4OptionTypes::RecordRoute,
+            anyhow!("not record route.")
+        );
+
+        let data_len = (length as usize).saturating_sub(RecordRouteFields::size_of());
+        ensure!(
+            length as usize >= RecordRouteFields::size_of() && data_len % 4 == 0,
+            anyhow!("invalid record route option length.")
+        );
+
+        let route =
+            mbuf.read_data_slice::<Ipv4Addr>(offset + RecordRouteFields::size_of(), data_len / 4)?;
+
+        Ok(RecordRoute {
+            _mbuf: mbuf,
+            fields,
+            route,
+            offset,
+        })
+    }
+
+    /// Writes a new record route option with empty slots to the room at
+    /// offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<RecordRoute<'a>> {
+        let fields = mbuf.write_data(
+            offset,
+            &RecordRouteFields {
+                length: Self::push_len() as u8,
+                ..RecordRouteFields::default()
+            },
+        )?;
+        let route = mbuf.write_data_slice(
+            offset + RecordRouteFields::size_of(),
+            &[Ipv4Addr::UNSPECIFIED; RECORD_ROUTE_SLOTS],
+        )?;
+
+        Ok(RecordRoute {
+            _mbuf: mbuf,
+            fields,
+            route,
+            offset,
+        })
+    }
+}
+
+/// Record route option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct RecordRouteFields {
+    option_type: u8,
+    length: u8,
+    pointer: u8,
+}
+
+impl Default for RecordRouteFields {
+    fn default() -> RecordRouteFields {
+        RecordRouteFields {
+            option_type: Ipv4OptionTypes::RecordRoute.0,
+            length: 3,
+            pointer: 4,
+        }
+    }
+}
+
+/// The length of the data area of a pushed timestamp option.
+const TIMESTAMP_DATA_LEN: usize = 36;
+
+/// Timestamps only.
+pub const TIMESTAMP_ONLY: u8 = 0;
+
+/// Each timestamp is preceded with the internet address of the
+/// registering entity.
+pub const TIMESTAMP_WITH_ADDRESS: u8 = 1;
+
+/// The internet address fields are prespecified, and an entity only
+/// registers its timestamp if it matches the next address.
+pub const TIMESTAMP_PRESPECIFIED: u8 = 3;
+
+/// Internet Timestamp option defined in [IETF RFC 791].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Type      |    Length     |    Pointer    | Oflw  |  Flg  |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                       Internet Address                        |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                           Timestamp                           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                               .                               |
+/// ```
+///
+/// - *Type*:            68
+///
+/// - *Length*:          The option length in octets, including the type,
+///                      length, pointer and overflow/flag octets.
+///
+/// - *Pointer*:         The octet offset, relative to the option, of the
+///                      end of the timestamps plus one. The smallest legal
+///                      value is 5.
+///
+/// - *Oflw*:            The number of modules that cannot register
+///                      timestamps due to lack of space.
+///
+/// - *Flg*:             0 for [timestamps only], 1 for [timestamps with
+///                      addresses] and 3 for [prespecified addresses].
+///
+/// - *Timestamp*:       A 32-bit timestamp in milliseconds since midnight
+///                      UT.
+///
+/// A pushed option records timestamps only and has room for 9 of them,
+/// the most that fits in the header.
+///
+/// [IETF RFC 791]: https://tools.ietf.org/html/rfc791#page-22
+/// [timestamps only]: TIMESTAMP_ONLY
+/// [timestamps with addresses]: TIMESTAMP_WITH_ADDRESS
+/// [prespecified addresses]: TIMESTAMP_PRESPECIFIED
+pub struct Timestamp<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<TimestampFields>,
+    data: NonNull<[u8]>,
+    offset: usize,
+}
+
+impl Timestamp<'_> {
+    #[inline]
+    fn fields(&self) -> &TimestampFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut TimestampFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the pointer to the next area to store a timestamp.
+    #[inline]
+    pub fn pointer(&self) -> u8 {
+        self.fields().pointer
+    }
+
+    /// Sets the pointer.
+    #[inline]
+    pub fn set_pointer(&mut self, pointer: u8) {
+        self.fields_mut().pointer = pointer;
+    }
+
+    /// Returns the number of modules that could not register timestamps.
+    #[inline]
+    pub fn overflow(&self) -> u8 {
+        self.fields().oflw_flg >> 4
+    }
+
+    /// Sets the overflow count.
+    #[inline]
+    pub fn set_overflow(&mut self, overflow: u8) {
+        self.fields_mut().oflw_flg = (self.fields().oflw_flg & 0x0f) | (overflow << 4);
+    }
+
+    /// Returns the flag that determines the layout of the entries.
+    #[inline]
+    pub fn flag(&self) -> u8 {
+        self.fields().oflw_flg & 0x0f
+    }
+
+    /// Sets the flag.
+    #[inline]
+    pub fn set_flag(&mut self, flag: u8) {
+        self.fields_mut().oflw_flg = (self.fields().oflw_flg & 0xf0) | (flag & 0x0f);
+    }
+
+    /// Returns the length of each entry in octets.
+    #[inline]
+    fn entry_len(&self) -> usize {
+        if self.flag() == TIMESTAMP_ONLY {
+            4
+        } else {
+            8
+        }
+    }
+
+    /// Returns the entries recorded so far, as pairs of an optional
+    /// address and the timestamp.
+    pub fn entries(&self) -> Vec<(Option<Ipv4Addr>, u32)> {
+        let data = unsafe { self.data.as_ref() };
+        let recorded = (self.pointer() as usize)
+            .saturating_sub(TimestampFields::size_of() + 1)
+            .min(data.len());
+
+        data[..recorded]
+            .chunks_exact(self.entry_len())
+            .map(|entry| {
+                if entry.len() == 4 {
+                    (
+                        None,
+                        u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]),
+                    )
+                } else {
+                    (
+                        Some(Ipv4Addr::new(entry[0], entry[1], entry[2], entry[3])),
+                        u32::from_be_bytes([entry[4], entry[5], entry[6], entry[7]]),
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Records an entry in the next slot and advances the pointer.
+    ///
+    /// The address is ignored when the option records timestamps only.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data area is already full. The caller
+    /// should increment the [`overflow`] count instead.
+    ///
+    /// [`overflow`]: Timestamp::overflow
+    pub fn record(&mut self, addr: Ipv4Addr, timestamp: u32) -> Result<()> {
+        let entry_len = self.entry_len();
+        let start = (self.pointer() as usize).saturating_sub(TimestampFields::size_of() + 1);
+        let data = unsafe { self.data.as_mut() };
+        ensure!(
+            start + entry_len <= data.len(),
+            anyhow!("timestamp option is full.")
+        );
+
+        let entry = &mut data[start..start + entry_len];
+        if entry_len == 4 {
+            entry.copy_from_slice(&timestamp.to_be_bytes());
+        } else {
+            entry[..4].copy_from_slice(&addr.octets());
+            entry[4..].copy_from_slice(&timestamp.to_be_bytes());
+        }
+
+        self.set_pointer(self.pointer() + entry_len as u8);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Timestamp<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timestamp")
+            .field("type", &self.option_type())
+            .field("length", &self.length())
+            .field("pointer", &self.pointer())
+            .field("overflow", &self.overflow())
+            .field("flag", &self.flag())
+            .field("entries", &self.entries())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> Ipv4Option<'a> for Timestamp<'a> {
+    /// Returns the option type. Should always be `68`.
+    #[inline]
+    fn option_type(&self) -> Ipv4OptionType {
+        Ipv4OptionType(self.fields().option_type)
+    }
+
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        TimestampFields::size_of() + TIMESTAMP_DATA_LEN
+    }
+
+    /// Parses the buffer at offset as timestamp option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `option_type` is not set to `Timestamp`.
+    /// Returns an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Timestamp<'a>> {
+        let fields = mbuf.read_data::<TimestampFields>(offset)?;
+        let (option_type, length) =
+            unsafe { (fields.as_ref().option_type, fields.as_ref().length) };
+
+        ensure!(
+            Ipv4OptionType(option_type) == Ipv4OptionTypes::Timestamp,
+            anyhow!("not timestamp.")
+        );
+
+        ensure!(
+            length as usize >= TimestampFields::size_of(),
+            anyhow!("invalid timestamp option length.")
+        );
+
+        let data = mbuf.read_data_slice::<u8>(
+            offset + TimestampFields::size_of(),
+            length as usize - TimestampFields::size_of(),
+        )?;
+
+        Ok(Timestamp {
+            _mbuf: mbuf,
+            fields,
+            data,
+            offset,
+        })
+    }
+
+    /// Writes a new timestamp option with an empty data area to the room
+    /// at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Timestamp<'a>> {
+        let fields = mbuf.write_data(
+            offset,
+            &TimestampFields {
+                length: Self::push_len() as u8,
+                ..TimestampFields::default()
+            },
+        )?;
+        let data = mbuf.write_data_slice(
+            offset + TimestampFields::size_of(),
+            &[0u8; TIMESTAMP_DATA_LEN],
+        )?;
+
+        Ok(Timestamp {
+            _mbuf: mbuf,
+            fields,
+            data,
+            offset,
+        })
+    }
+}
+
+/// Timestamp option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct TimestampFields {
+    option_type: u8,
+    length: u8,
+    pointer: u8,
+    oflw_flg: u8,
+}
+
+impl Default for TimestampFields {
+    fn default() -> TimestampFields {
+        TimestampFields {
+            option_type: Ipv4OptionTypes::Timestamp.0,
+            length: 4,
+            pointer: 5,
+            oflw_flg: TIMESTAMP_ONLY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::udp::Udp4;
+    use crate::testils::byte_arrays::{IPV4_OPTIONS_PACKET, IPV4_UDP_PACKET};
+    use crate::testils::{bytes_of, ipv4_with_payload};
+
+    #[test]
+    fn size_of_option_fields() {
+        assert_eq!(4, RouterAlertFields::size_of());
+        assert_eq!(3, RecordRouteFields::size_of());
+        assert_eq!(4, TimestampFields::size_of());
+    }
+
+    #[capsule::test]
+    fn iterate_immutable_ipv4_options() {
+        let packet = Mbuf::from_bytes(&IPV4_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        assert_eq!(12, ipv4.options_len());
+
+        let mut iter = ipv4.options_iter();
+        let mut types = vec![];
+        while let Some(option) = iter.next().unwrap() {
+            types.push(option.option_type());
+        }
+
+        assert_eq!(
+            vec![
+                Ipv4OptionTypes::RecordRoute,
+                Ipv4OptionTypes::NoOperation,
+                Ipv4OptionTypes::RouterAlert
+            ],
+            types
+        );
+    }
+
+    #[capsule::test]
+    fn downcast_immutable_ipv4_option() {
+        let packet = Mbuf::from_bytes(&IPV4_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut iter = ipv4.options_iter();
+
+        let mut option = iter.next().unwrap().unwrap();
+        let route = option.downcast::<RecordRoute<'_>>().unwrap();
+        assert_eq!(7, route.length());
+        assert_eq!(8, route.pointer());
+        assert_eq!(&[Ipv4Addr::new(192, 168, 1, 1)], route.recorded());
+
+        // can't downcast to the wrong type
+        let mut option = iter.next().unwrap().unwrap();
+        assert!(option.downcast::<RouterAlert<'_>>().is_err());
+
+        let mut option = iter.next().unwrap().unwrap();
+        let alert = option.downcast::<RouterAlert<'_>>().unwrap();
+        assert_eq!(0, alert.value());
+
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn invalid_ipv4_option_length() {
+        let packet = Mbuf::from_bytes(&IPV4_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        // the record route length now runs past the header
+        let offset = ipv4.options_offset();
+        ipv4.mbuf_mut().write_data(offset + 1, &40u8).unwrap();
+
+        let mut iter = ipv4.options_iter();
+        assert!(iter.next().is_err());
+    }
+
+    #[capsule::test]
+    fn modify_ipv4_option() {
+        let packet = Mbuf::from_bytes(&IPV4_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut options = ipv4.options_mut();
+        let mut iter = options.iter();
+
+        let mut option = iter.next().unwrap().unwrap();
+        let mut route = option.downcast::<RecordRoute<'_>>().unwrap();
+
+        // the only slot is already taken
+        assert!(route.record(Ipv4Addr::new(10, 0, 0, 1)).is_err());
+
+        route.set_pointer(4);
+        route.record(Ipv4Addr::new(10, 0, 0, 1)).unwrap();
+        assert_eq!(&[Ipv4Addr::new(10, 0, 0, 1)], route.recorded());
+    }
+
+    #[capsule::test]
+    fn append_ipv4_option() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let mut options = ipv4.options_mut();
+        let mut alert = options.append::<RouterAlert<'_>>().unwrap();
+        alert.set_value(1);
+
+        assert_eq!(6, ipv4.ihl());
+        assert_eq!(24, ipv4.header_len());
+
+        ipv4.reconcile();
+        assert_eq!(42, ipv4.total_length());
+
+        // the payload is intact after the options
+        let udp = ipv4.parse::<Udp4>().unwrap();
+        assert_eq!(39376, udp.src_port());
+        assert_eq!(1087, udp.dst_port());
+    }
+
+    #[capsule::test]
+    fn prepend_ipv4_option() {
+        let packet = Mbuf::from_bytes(&IPV4_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let mut options = ipv4.options_mut();
+        let _ = options.prepend::<RouterAlert<'_>>().unwrap();
+
+        // 4 + 7 + 1 + 4 = 16, no padding needed
+        assert_eq!(9, ipv4.ihl());
+
+        let mut iter = ipv4.options_iter();
+        assert_eq!(
+            Ipv4OptionTypes::RouterAlert,
+            iter.next().unwrap().unwrap().option_type()
+        );
+        assert_eq!(
+            Ipv4OptionTypes::RecordRoute,
+            iter.next().unwrap().unwrap().option_type()
+        );
+    }
+
+    #[capsule::test]
+    fn append_ipv4_option_exceeds_max_len() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let mut options = ipv4.options_mut();
+        let mut route = options.append::<RecordRoute<'_>>().unwrap();
+        route.record(Ipv4Addr::new(10, 0, 0, 1)).unwrap();
+        assert_eq!(15, ipv4.ihl());
+
+        let mut options = ipv4.options_mut();
+        assert!(options.append::<RouterAlert<'_>>().is_err());
+        assert_eq!(15, ipv4.ihl());
+    }
+
+    #[capsule::test]
+    fn append_ipv4_option_exhausted_mempool() {
+        // fills the first segment, so the option needs a new one.
+        let len = 2048 - IPV4_UDP_PACKET.len();
+        let mut ipv4 = ipv4_with_payload(&IPV4_UDP_PACKET, len);
+        assert_eq!(1, ipv4.mbuf().num_segments());
+        let bytes = bytes_of(&ipv4);
+
+        // holds on to the rest of the mempool
+        let mut held = vec![];
+        while let Ok(other) = Mbuf::new() {
+            held.push(other);
+        }
+
+        // the options are left unchanged
+        let mut options = ipv4.options_mut();
+        assert!(options.append::<RouterAlert<'_>>().is_err());
+        assert!(options.prepend::<RouterAlert<'_>>().is_err());
+        assert_eq!(5, ipv4.ihl());
+        assert_eq!(bytes, bytes_of(&ipv4));
+
+        held.clear();
+        assert!(ipv4.options_mut().append::<RouterAlert<'_>>().is_ok());
+        assert_eq!(6, ipv4.ihl());
+    }
+
+    #[capsule::test]
+    fn record_ipv4_timestamp() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let mut options = ipv4.options_mut();
+        let mut timestamp = options.append::<Timestamp<'_>>().unwrap();
+        assert_eq!(TIMESTAMP_ONLY, timestamp.flag());
+
+        timestamp.record(Ipv4Addr::LOCALHOST, 1000).unwrap();
+        timestamp.record(Ipv4Addr::LOCALHOST, 2000).unwrap();
+        assert_eq!(vec![(None, 1000), (None, 2000)], timestamp.entries());
+
+        timestamp.set_flag(TIMESTAMP_WITH_ADDRESS);
+        timestamp.set_pointer(5);
+        timestamp.record(Ipv4Addr::LOCALHOST, 3000).unwrap();
+        assert_eq!(vec![(Some(Ipv4Addr::LOCALHOST), 3000)], timestamp.entries());
+        assert_eq!(15, ipv4.ihl());
+    }
+
+    #[capsule::test]
+    fn retain_ipv4_options() {
+        let packet = Mbuf::from_bytes(&IPV4_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let mut options = ipv4.options_mut();
+        options
+            .retain(|option| option.option_type() != Ipv4OptionTypes::RouterAlert)
+            .unwrap();
+
+        // 7 + 1 = 8, no padding needed
+        assert_eq!(7, ipv4.ihl());
+
+        let mut options = ipv4.options_mut();
+        options
+            .retain(|option| option.option_type() != Ipv4OptionTypes::NoOperation)
+            .unwrap();
+
+        // 7 + 1 octet of padding
+        assert_eq!(7, ipv4.ihl());
+
+        let mut options = ipv4.options_mut();
+        options.retain(|_| false).unwrap();
+        assert_eq!(5, ipv4.ihl());
+
+        ipv4.reconcile();
+        assert_eq!(33, ipv4.total_length());
+
+        let udp = ipv4.parse::<Udp4>().unwrap();
+        assert_eq!(12345, udp.src_port());
+        assert_eq!(53, udp.dst_port());
+    }
+}
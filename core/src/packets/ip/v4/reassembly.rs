@@ -0,0 +1,321 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::ip::reassembly::{FragmentEntry, Reassembly};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::{ProtocolNumber, ReassemblyConfig};
+use crate::packets::Packet;
+use anyhow::{anyhow, Result};
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+/// Maximum length of an IPv4 packet.
+const IPV4_MAX_LEN: usize = u16::MAX as usize;
+
+/// The fields that identify the fragments of the same datagram.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Ipv4FragmentKey {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: ProtocolNumber,
+    identification: u16,
+}
+
+/// IPv4 fragment reassembler based on [IETF RFC 791].
+///
+/// Fragments are grouped by source, destination, protocol and
+/// identification. Once all the fragments of a datagram are received, the
+/// payloads are copied in order into the buffer of the first fragment, so
+/// the reassembled packet keeps the first fragment's headers and options.
+///
+/// An exact duplicate of a received fragment is dropped. A fragment that
+/// partially overlaps any other fragment causes the whole datagram to be
+/// discarded, in the spirit of [IETF RFC 5722]. Incomplete datagrams are
+/// evicted when they time out, or oldest first when the configured memory
+/// limits are reached.
+///
+/// The reassembler is not thread-safe. Each lcore should have its own
+/// instance, for example as a pipeline thread local.
+///
+/// [IETF RFC 791]: https://tools.ietf.org/html/rfc791#section-3.2
+/// [IETF RFC 5722]: https://tools.ietf.org/html/rfc5722
+#[derive(Debug)]
+pub struct Ipv4Reassembler {
+    inner: Reassembly<Ipv4FragmentKey, Ipv4>,
+}
+
+impl Ipv4Reassembler {
+    /// Creates a new reassembler.
+    pub fn new(config: ReassemblyConfig) -> Self {
+        Ipv4Reassembler {
+            inner: Reassembly::new(config),
+        }
+    }
+
+    /// Returns the number of incomplete datagrams.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns whether there are no incomplete datagrams.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of octets held by the incomplete datagrams.
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.inner.bytes()
+    }
+
+    /// Adds the packet to the reassembler.
+    ///
+    /// Returns the reassembled packet if the packet completes a datagram,
+    /// or `None` if more fragments are needed. A packet that is not a
+    /// fragment is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fragment is malformed, if it overlaps with
+    /// another fragment of the same datagram, or if the reassembled packet
+    /// does not fit in the first fragment's buffer. The datagram is
+    /// discarded in the last two cases.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let ipv4 = ethernet.parse::<Ipv4>()?;
+    /// match reassembler.reassemble(ipv4)? {
+    ///     Some(ipv4) => Ok(Postmark::emit(ipv4)),
+    ///     None => Ok(Postmark::emit_multi(vec![])),
+    /// }
+    /// ```
+    pub fn reassemble(&mut self, packet: Ipv4) -> Result<Option<Ipv4>> {
+        if packet.fragment_offset() == 0 && !packet.more_fragments() {
+            return Ok(Some(packet));
+        }
+
+        let header_len = packet.header_len();
+        let total_len = packet.total_length() as usize;
+        ensure!(
+            total_len >= header_len && total_len <= packet.len(),
+            anyhow!("invalid IPv4 total length {}.", total_len)
+        );
+
+        let start = packet.fragment_offset() as usize * 8;
+        let end = start + total_len - header_len;
+        ensure!(
+            end + header_len <= IPV4_MAX_LEN,
+            anyhow!("fragment extends beyond the maximum IPv4 packet length.")
+        );
+
+        let key = Ipv4FragmentKey {
+            src: packet.src(),
+            dst: packet.dst(),
+            protocol: packet.protocol(),
+            identification: packet.identification(),
+        };
+        let last = !packet.more_fragments();
+        let fragment = FragmentEntry {
+            start,
+            end,
+            bytes: packet.mbuf().data_len(),
+            packet,
+        };
+
+        match self.inner.insert(key, fragment, last, Instant::now())? {
+            Some(fragments) => assemble(fragments).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Evicts the incomplete datagrams that have timed out, and returns the
+    /// number of datagrams evicted.
+    pub fn evict_expired(&mut self) -> usize {
+        self.evict_expired_at(Instant::now())
+    }
+
+    /// Evicts the incomplete datagrams that have timed out at `now`, and
+    /// returns the number of datagrams evicted.
+    pub(crate) fn evict_expired_at(&mut self, now: Instant) -> usize {
+        self.inner.evict_expired(now).len()
+    }
+}
+
+/// Copies the fragment payloads, sorted by offset, into the buffer of the
+/// first fragment.
+fn assemble(fragments: Vec<FragmentEntry<Ipv4>>) -> Result<Ipv4> {
+    let mut fragments = fragments.into_iter();
+    let mut packet = match fragments.next() {
+        Some(first) => first.packet,
+        None => return Err(anyhow!("no fragments to reassemble.")),
+    };
+
+    // removes the link layer padding, if any.
+    let len = packet.offset() + packet.total_length() as usize;
+    if len < packet.mbuf().data_len() {
+        packet.mbuf_mut().truncate(len)?;
+    }
+
+    for fragment in fragments {
+        let len = fragment.end - fragment.start;
+        let mut data = vec![0u8; len];
+        fragment
+            .packet
+            .mbuf()
+            .copy_to_slice(fragment.packet.payload_offset(), &mut data)?;
+        let offset = packet.mbuf().data_len();
+        packet.mbuf_mut().extend(offset, len)?;
+        packet.mbuf_mut().copy_from_slice(offset, &data)?;
+    }
+
+    ensure!(
+        packet.len() <= IPV4_MAX_LEN,
+        anyhow!("reassembled IPv4 packet exceeds {} octets.", IPV4_MAX_LEN)
+    );
+
+    packet.unset_more_fragments();
+    packet.set_fragment_offset(0);
+    packet.reconcile();
+    Ok(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::IPV4_TCP_PACKET;
+    use crate::testils::{bytes_of, ipv4_with_payload};
+    use std::time::Duration;
+
+    #[capsule::test]
+    fn reassemble_unfragmented_packet() {
+        let mut reassembler = Ipv4Reassembler::new(ReassemblyConfig::default());
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 100);
+
+        assert!(reassembler.reassemble(ipv4).unwrap().is_some());
+        assert!(reassembler.is_empty());
+    }
+
+    #[capsule::test]
+    fn reassemble_out_of_order_fragments() {
+        let mut reassembler = Ipv4Reassembler::new(ReassemblyConfig::default());
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+        let expected = bytes_of(&ipv4);
+
+        let mut fragments = ipv4.fragment(300).unwrap();
+        assert_eq!(4, fragments.len());
+        fragments.reverse();
+
+        let last = fragments.pop().unwrap();
+        for fragment in fragments {
+            assert!(reassembler.reassemble(fragment).unwrap().is_none());
+        }
+        assert_eq!(1, reassembler.len());
+        assert!(reassembler.bytes() > 0);
+
+        let packet = reassembler.reassemble(last).unwrap().unwrap();
+        assert!(reassembler.is_empty());
+        assert_eq!(0, reassembler.bytes());
+        assert_eq!(expected, bytes_of(&packet));
+    }
+
+    #[capsule::test]
+    fn reassemble_with_duplicate_fragment() {
+        let mut reassembler = Ipv4Reassembler::new(ReassemblyConfig::default());
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+
+        let mut fragments = ipv4.fragment(600).unwrap();
+        let last = fragments.pop().unwrap();
+        let first = fragments.pop().unwrap();
+        let duplicate = bytes_of(&first);
+
+        assert!(reassembler.reassemble(first).unwrap().is_none());
+        let duplicate = Mbuf::from_bytes(&duplicate).unwrap();
+        let duplicate = duplicate
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap();
+        assert!(reassembler.reassemble(duplicate).unwrap().is_none());
+
+        assert!(reassembler.reassemble(last).unwrap().is_some());
+    }
+
+    #[capsule::test]
+    fn reassemble_with_overlapping_fragment() {
+        let mut reassembler = Ipv4Reassembler::new(ReassemblyConfig::default());
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+
+        let mut fragments = ipv4.fragment(600).unwrap();
+        let mut last = fragments.pop().unwrap();
+        let first = fragments.pop().unwrap();
+        assert!(reassembler.reassemble(first).unwrap().is_none());
+
+        last.set_fragment_offset(last.fragment_offset() - 1);
+        assert!(reassembler.reassemble(last).is_err());
+        assert!(reassembler.is_empty());
+    }
+
+    #[capsule::test]
+    fn evict_expired_fragments() {
+        let config = ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let mut reassembler = Ipv4Reassembler::new(config);
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+
+        let mut fragments = ipv4.fragment(600).unwrap();
+        let _ = fragments.pop();
+        let first = fragments.pop().unwrap();
+        assert!(reassembler.reassemble(first).unwrap().is_none());
+
+        let now = Instant::now();
+        assert_eq!(0, reassembler.evict_expired_at(now));
+        assert_eq!(
+            1,
+            reassembler.evict_expired_at(now + Duration::from_secs(30))
+        );
+        assert!(reassembler.is_empty());
+    }
+
+    #[capsule::test]
+    fn evict_oldest_over_datagram_limit() {
+        let config = ReassemblyConfig {
+            max_datagrams: 1,
+            ..Default::default()
+        };
+        let mut reassembler = Ipv4Reassembler::new(config);
+
+        let mut a = ipv4_with_payload(&IPV4_TCP_PACKET, 1000).fragment(600).unwrap();
+        let mut b = ipv4_with_payload(&IPV4_TCP_PACKET, 1000);
+        b.set_identification(1);
+        let mut b = b.fragment(600).unwrap();
+
+        let _ = a.pop();
+        assert!(reassembler.reassemble(a.pop().unwrap()).unwrap().is_none());
+        let _ = b.pop();
+        assert!(reassembler.reassemble(b.pop().unwrap()).unwrap().is_none());
+        assert_eq!(1, reassembler.len());
+    }
+}
@@ -0,0 +1,547 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::checksum::PseudoHeader;
+use crate::packets::icmp::v6::Icmpv6;
+use crate::packets::ip::v6::{Ipv6, Ipv6Packet, OptionsHeader};
+use crate::packets::ip::{IpPacket, ProtocolNumber, ProtocolNumbers};
+use crate::packets::tcp::Tcp;
+use crate::packets::udp::Udp;
+use crate::packets::{Datalink, Internal, Mbuf, Packet};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::net::IpAddr;
+use std::ptr::NonNull;
+
+/// Offset of the `next_header` field within the IPv6 header.
+const IPV6_NEXT_HEADER_OFFSET: usize = 6;
+
+/// Length of the IPv6 fragment extension header.
+const FRAGMENT_HEADER_LEN: usize = 8;
+
+/// State of a walk through an IPv6 extension header chain.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Walk {
+    /// The protocol of the header at `offset`.
+    pub(crate) protocol: ProtocolNumber,
+    /// The offset of the current header.
+    pub(crate) offset: usize,
+    /// The offset of the `next_header` field that carries the protocol.
+    pub(crate) field: usize,
+}
+
+/// Walks the known extension headers starting at `offset`.
+///
+/// Hop-by-hop options, routing and destination options headers are
+/// skipped over using their `hdr_ext_len` field. A fragment header is
+/// skipped over only if it's the first fragment. For subsequent fragments,
+/// the upper-layer header is not present, and the walk stops at the
+/// fragment header instead.
+///
+/// `stop` is invoked before skipping over each extension header. The walk
+/// stops at that header if it returns `true`.
+pub(crate) fn walk<F>(
+    mbuf: &Mbuf,
+    protocol: ProtocolNumber,
+    field: usize,
+    offset: usize,
+    mut stop: F,
+) -> Result<Walk>
+where
+    F: FnMut(&Walk) -> bool,
+{
+    let mut walk = Walk {
+        protocol,
+        offset,
+        field,
+    };
+
+    loop {
+        let len = match walk.protocol {
+            ProtocolNumbers::Ipv6HopByHop
+            | ProtocolNumbers::Ipv6Route
+            | ProtocolNumbers::Ipv6Opts => {
+                let header = mbuf.read_data::<OptionsHeader>(walk.offset)?;
+                unsafe { header.as_ref() }.len()
+            }
+            ProtocolNumbers::Ipv6Frag => {
+                let frag_os = mbuf.read_data::<[u8; 2]>(walk.offset + 2)?;
+                if u16::from_be_bytes(unsafe { *frag_os.as_ref() }) >> 3 != 0 {
+                    break;
+                }
+                FRAGMENT_HEADER_LEN
+            }
+            _ => break,
+        };
+
+        if stop(&walk) {
+            break;
+        }
+
+        ensure!(
+            walk.offset + len <= mbuf.data_len(),
+            anyhow!("extension header at offset {} is truncated.", walk.offset)
+        );
+
+        let header = mbuf.read_data::<OptionsHeader>(walk.offset)?;
+        walk.protocol = ProtocolNumber(unsafe { header.as_ref() }.next_header);
+        walk.field = walk.offset;
+        walk.offset += len;
+    }
+
+    Ok(walk)
+}
+
+/// Walks the extension headers of the IPv6 packet.
+pub(crate) fn walk_ipv6<E, F>(ipv6: &Ipv6<E>, stop: F) -> Result<Walk>
+where
+    E: Datalink,
+    F: FnMut(&Walk) -> bool,
+{
+    walk(
+        ipv6.mbuf(),
+        ipv6.next_header(),
+        ipv6.offset() + IPV6_NEXT_HEADER_OFFSET,
+        ipv6.payload_offset(),
+        stop,
+    )
+}
+
+impl<E: Datalink> Ipv6<E> {
+    /// Returns the upper-layer protocol and the offset of its header.
+    ///
+    /// Walks the `next_header` field through any sequence of hop-by-hop
+    /// options, routing, fragment and destination options extension
+    /// headers. For a fragment that is not the first fragment, the
+    /// upper-layer header is not present, and [`ProtocolNumbers::Ipv6Frag`]
+    /// is returned with the offset of the fragment header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an extension header is truncated.
+    ///
+    /// [`ProtocolNumbers::Ipv6Frag`]: ProtocolNumbers::Ipv6Frag
+    pub fn upper_layer(&self) -> Result<(ProtocolNumber, usize)> {
+        let walk = walk_ipv6(self, |_| false)?;
+        Ok((walk.protocol, walk.offset))
+    }
+}
+
+impl Ipv6 {
+    /// Parses the payload as a dynamically typed upper-layer packet.
+    ///
+    /// The extension headers, if any, are wrapped in an [`ExtensionChain`]
+    /// which becomes the envelope of the upper-layer packet. A single
+    /// pipeline can then process TCP, UDP and ICMPv6 packets regardless of
+    /// the extension headers present.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an extension header is truncated, or if the
+    /// upper-layer packet fails to parse. The IPv6 packet is returned
+    /// along with the error.
+    ///
+    /// [`ExtensionChain`]: ExtensionChain
+    pub fn parse_upper_layer(self) -> Result<UpperLayer, (Error, Self)> {
+        let chain = self.parse::<ExtensionChain>()?;
+        let upper = match chain.next_header() {
+            ProtocolNumbers::Tcp => chain.parse().map(UpperLayer::Tcp),
+            ProtocolNumbers::Udp => chain.parse().map(UpperLayer::Udp),
+            ProtocolNumbers::Icmpv6 => chain.parse().map(UpperLayer::Icmpv6),
+            _ => Ok(UpperLayer::Other(chain)),
+        };
+        upper.map_err(|(e, chain)| (e, chain.deparse()))
+    }
+}
+
+/// The sequence of IPv6 extension headers preceding the upper layer.
+///
+/// Covers every known extension header between the IPv6 header and the
+/// upper-layer header as a single packet, so the upper-layer packet has the
+/// same type no matter how many extension headers are present. The chain
+/// can be empty. [`next_header`] is the `next_header` field of the last
+/// extension header, or of the IPv6 header if the chain is empty.
+///
+/// [`next_header`]: Ipv6Packet::next_header
+pub struct ExtensionChain {
+    envelope: Ipv6,
+    next_header: NonNull<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl ExtensionChain {
+    /// Parses the envelope's payload as an extension chain that ends at
+    /// the first extension header for which `stop` returns `true`.
+    pub(crate) fn parse_until<F>(envelope: Ipv6, stop: F) -> Result<Self, (Error, Ipv6)>
+    where
+        F: FnMut(&Walk) -> bool,
+    {
+        let offset = envelope.payload_offset();
+        let walk = match walk_ipv6(&envelope, stop) {
+            Err(e) => return Err((e, envelope)),
+            Ok(walk) => walk,
+        };
+
+        let next_header = match envelope.mbuf().read_data(walk.field) {
+            Err(e) => return Err((e, envelope)),
+            Ok(next_header) => next_header,
+        };
+
+        Ok(ExtensionChain {
+            envelope,
+            next_header,
+            offset,
+            len: walk.offset - offset,
+        })
+    }
+
+    /// Returns whether the chain has no extension headers.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl fmt::Debug for ExtensionChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("extension_chain")
+            .field("next_header", &format!("{}", self.next_header()))
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl Packet for ExtensionChain {
+    /// The preceding type for an extension chain must be IPv6.
+    type Envelope = Ipv6;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        ExtensionChain {
+            envelope: self.envelope.clone(internal),
+            next_header: self.next_header,
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+
+    /// Parses the envelope's payload as an extension chain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an extension header is truncated.
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ExtensionChain::parse_until(envelope, |_| false)
+    }
+
+    /// Creates an empty extension chain at the beginning of the envelope's
+    /// payload.
+    ///
+    /// No data is written to the buffer. The chain refers to the
+    /// `next_header` field of the envelope.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have sufficient data.
+    #[inline]
+    fn try_push(envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let next_header = envelope
+            .mbuf()
+            .read_data(envelope.offset() + IPV6_NEXT_HEADER_OFFSET)?;
+
+        Ok(ExtensionChain {
+            envelope,
+            next_header,
+            offset,
+            len: 0,
+        })
+    }
+
+    /// Removes all the extension headers from the message buffer.
+    ///
+    /// The envelope's [`next_header`] field is set to the value of the
+    /// `next_header` field of the last extension header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have sufficient data to
+    /// remove.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let next_header = self.next_header();
+        if !self.is_empty() {
+            let offset = self.offset();
+            let len = self.header_len();
+            self.mbuf_mut().shrink(offset, len)?;
+        }
+        self.envelope_mut().set_next_header(next_header);
+        Ok(self.envelope)
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+impl IpPacket for ExtensionChain {
+    #[inline]
+    fn next_protocol(&self) -> ProtocolNumber {
+        self.next_header()
+    }
+
+    #[inline]
+    fn set_next_protocol(&mut self, proto: ProtocolNumber) {
+        self.set_next_header(proto);
+    }
+
+    #[inline]
+    fn src(&self) -> IpAddr {
+        IpPacket::src(self.envelope())
+    }
+
+    #[inline]
+    fn set_src(&mut self, src: IpAddr) -> Result<()> {
+        IpPacket::set_src(self.envelope_mut(), src)
+    }
+
+    #[inline]
+    fn dst(&self) -> IpAddr {
+        IpPacket::dst(self.envelope())
+    }
+
+    #[inline]
+    fn set_dst(&mut self, dst: IpAddr) -> Result<()> {
+        IpPacket::set_dst(self.envelope_mut(), dst)
+    }
+
+    #[inline]
+    fn pseudo_header(&self, packet_len: u16, protocol: ProtocolNumber) -> PseudoHeader {
+        self.envelope().pseudo_header(packet_len, protocol)
+    }
+
+    #[inline]
+    fn truncate(&mut self, mtu: usize) -> Result<()> {
+        self.envelope_mut().truncate(mtu)
+    }
+
+    #[inline]
+    fn set_length_from_buffer(&mut self) {
+        self.envelope_mut().set_length_from_buffer()
+    }
+}
+
+impl Ipv6Packet for ExtensionChain {
+    #[inline]
+    fn next_header(&self) -> ProtocolNumber {
+        ProtocolNumber(unsafe { *self.next_header.as_ref() })
+    }
+
+    #[inline]
+    fn set_next_header(&mut self, next_header: ProtocolNumber) {
+        unsafe {
+            *self.next_header.as_mut() = next_header.0;
+        }
+    }
+}
+
+/// A dynamically typed upper-layer packet of IPv6.
+///
+/// Returned by [`Ipv6::parse_upper_layer`]. Protocols other than TCP, UDP
+/// and ICMPv6 are returned as the extension chain itself.
+///
+/// [`Ipv6::parse_upper_layer`]: Ipv6::parse_upper_layer
+#[derive(Debug)]
+pub enum UpperLayer {
+    /// A TCP packet.
+    Tcp(Tcp<ExtensionChain>),
+    /// A UDP packet.
+    Udp(Udp<ExtensionChain>),
+    /// An ICMPv6 packet.
+    Icmpv6(Icmpv6<ExtensionChain>),
+    /// Any other upper-layer protocol, or a non-first fragment.
+    Other(ExtensionChain),
+}
+
+impl UpperLayer {
+    /// Returns the upper-layer protocol.
+    #[inline]
+    pub fn protocol(&self) -> ProtocolNumber {
+        match self {
+            UpperLayer::Tcp(_) => ProtocolNumbers::Tcp,
+            UpperLayer::Udp(_) => ProtocolNumbers::Udp,
+            UpperLayer::Icmpv6(_) => ProtocolNumbers::Icmpv6,
+            UpperLayer::Other(chain) => chain.next_header(),
+        }
+    }
+
+    /// Returns the extension chain preceding the upper layer.
+    #[inline]
+    pub fn chain(&self) -> &ExtensionChain {
+        match self {
+            UpperLayer::Tcp(tcp) => tcp.envelope(),
+            UpperLayer::Udp(udp) => udp.envelope(),
+            UpperLayer::Icmpv6(icmpv6) => icmpv6.envelope(),
+            UpperLayer::Other(chain) => chain,
+        }
+    }
+
+    /// Deparses the upper-layer packet back to the extension chain.
+    #[inline]
+    pub fn deparse(self) -> ExtensionChain {
+        match self {
+            UpperLayer::Tcp(tcp) => tcp.deparse(),
+            UpperLayer::Udp(udp) => udp.deparse(),
+            UpperLayer::Icmpv6(icmpv6) => icmpv6.deparse(),
+            UpperLayer::Other(chain) => chain,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::{
+        ICMPV6_PACKET, IPV6_FRAGMENT_PACKET, IPV6_OPTIONS_PACKET, IPV6_TCP_PACKET, SR_TCP_PACKET,
+    };
+
+    #[capsule::test]
+    fn upper_layer_without_extensions() {
+        let packet = Mbuf::from_bytes(&IPV6_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert_eq!((ProtocolNumbers::Tcp, 54), ipv6.upper_layer().unwrap());
+
+        let upper = ipv6.parse_upper_layer().unwrap();
+        assert!(upper.chain().is_empty());
+        match upper {
+            UpperLayer::Tcp(tcp) => {
+                assert_eq!(54, tcp.offset());
+                assert_eq!(36869, tcp.src_port());
+            }
+            _ => panic!("not a TCP packet."),
+        }
+    }
+
+    #[capsule::test]
+    fn upper_layer_through_options_chain() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert_eq!((ProtocolNumbers::Udp, 70), ipv6.upper_layer().unwrap());
+
+        match ipv6.parse_upper_layer().unwrap() {
+            UpperLayer::Udp(udp) => {
+                assert_eq!(16, udp.envelope().header_len());
+                assert_eq!(1234, udp.src_port());
+                assert_eq!(5678, udp.dst_port());
+            }
+            _ => panic!("not a UDP packet."),
+        }
+    }
+
+    #[capsule::test]
+    fn upper_layer_through_routing_header() {
+        let packet = Mbuf::from_bytes(&SR_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert_eq!((ProtocolNumbers::Tcp, 110), ipv6.upper_layer().unwrap());
+
+        let upper = ipv6.parse_upper_layer().unwrap();
+        assert_eq!(ProtocolNumbers::Tcp, upper.protocol());
+        assert_eq!(56, upper.chain().header_len());
+    }
+
+    #[capsule::test]
+    fn upper_layer_of_icmpv6_packet() {
+        let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        let upper = ipv6.parse_upper_layer().unwrap();
+        assert!(matches!(upper, UpperLayer::Icmpv6(_)));
+    }
+
+    #[capsule::test]
+    fn upper_layer_of_non_first_fragment() {
+        let packet = Mbuf::from_bytes(&IPV6_FRAGMENT_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert_eq!((ProtocolNumbers::Ipv6Frag, 54), ipv6.upper_layer().unwrap());
+
+        let upper = ipv6.parse_upper_layer().unwrap();
+        assert_eq!(ProtocolNumbers::Ipv6Frag, upper.protocol());
+        assert!(upper.chain().is_empty());
+    }
+
+    #[capsule::test]
+    fn truncated_extension_header() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET[..60]).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert!(ipv6.upper_layer().is_err());
+        assert!(ipv6.parse::<ExtensionChain>().is_err());
+    }
+
+    #[capsule::test]
+    fn remove_extension_chain() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let chain = ipv6.parse::<ExtensionChain>().unwrap();
+        let ipv6 = chain.remove().unwrap();
+
+        assert_eq!(ProtocolNumbers::Udp, ipv6.next_header());
+        assert_eq!((ProtocolNumbers::Udp, 54), ipv6.upper_layer().unwrap());
+    }
+}
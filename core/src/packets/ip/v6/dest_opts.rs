@@ -0,0 +1,125 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::{OptionsExtension, OptionsExtensionKind};
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+
+/// The kind of the [`DestinationOptions`] options extension header.
+///
+/// [`DestinationOptions`]: DestinationOptions
+#[derive(Debug)]
+pub enum DestinationOptionsKind {}
+
+impl OptionsExtensionKind for DestinationOptionsKind {
+    const PROTOCOL: ProtocolNumber = ProtocolNumbers::Ipv6Opts;
+    const NAME: &'static str = "destination_options";
+}
+
+/// IPv6 Destination Options Extension packet based on [IETF RFC 8200].
+///
+/// The destination options header carries optional information that needs
+/// to be examined only by the packet's destination node or nodes. It may
+/// appear before a routing header, to be processed by each listed node,
+/// or before the upper-layer header, to be processed only by the final
+/// destination.
+///
+/// The header format is described in [`OptionsExtension`].
+///
+/// [IETF RFC 8200]: https://tools.ietf.org/html/rfc8200#section-4.6
+/// [`OptionsExtension`]: OptionsExtension
+pub type DestinationOptions<E> = OptionsExtension<E, DestinationOptionsKind>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v6::{HopByHop, Ipv6};
+    use crate::packets::ip::v6::{Ipv6OptionTypes, Ipv6Packet};
+    use crate::packets::udp::Udp;
+    use crate::packets::Mbuf;
+    use crate::packets::Packet;
+    use crate::testils::byte_arrays::{IPV6_OPTIONS_PACKET, IPV6_TCP_PACKET};
+
+    #[capsule::test]
+    fn parse_destination_options_packet() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>().unwrap();
+        let options = hop_by_hop
+            .parse::<DestinationOptions<HopByHop<Ipv6>>>()
+            .unwrap();
+
+        assert_eq!(ProtocolNumbers::Udp, options.next_header());
+        assert_eq!(8, options.header_len());
+
+        let mut iter = options.options_iter();
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(Ipv6OptionTypes::PadN, option.option_type());
+        assert_eq!(4, option.data_len());
+        assert!(iter.next().unwrap().is_none());
+
+        // parses all the way down to udp.
+        let udp = options
+            .parse::<Udp<DestinationOptions<HopByHop<Ipv6>>>>()
+            .unwrap();
+        assert_eq!(1234, udp.src_port());
+        assert_eq!(5678, udp.dst_port());
+        assert_eq!(0x458d, udp.checksum());
+    }
+
+    #[capsule::test]
+    fn parse_non_destination_options_packet() {
+        let packet = Mbuf::from_bytes(&IPV6_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert!(ipv6.parse::<DestinationOptions<Ipv6>>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_destination_options_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let next_header = ipv6.next_header();
+        let options = ipv6.push::<DestinationOptions<Ipv6>>().unwrap();
+
+        assert_eq!(8, options.len());
+        assert_eq!(next_header, options.next_header());
+        assert_eq!(ProtocolNumbers::Ipv6Opts, options.envelope().next_header());
+    }
+
+    #[capsule::test]
+    fn compute_checksum_with_extension_headers() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>().unwrap();
+        let options = hop_by_hop
+            .parse::<DestinationOptions<HopByHop<Ipv6>>>()
+            .unwrap();
+        let mut udp = options
+            .parse::<Udp<DestinationOptions<HopByHop<Ipv6>>>>()
+            .unwrap();
+
+        let expected = udp.checksum();
+        udp.reconcile_all();
+        assert_eq!(expected, udp.checksum());
+    }
+}
@@ -0,0 +1,234 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::ethernet::Ethernet;
+use crate::packets::ip::v6::{walk_ipv6, ExtensionChain, Fragment, Ipv6, IPV6_MIN_MTU};
+use crate::packets::ip::ProtocolNumbers;
+use crate::packets::{Mbuf, Packet};
+use anyhow::{anyhow, Result};
+use std::cmp;
+
+impl Ipv6 {
+    /// Splits the packet into fragments that fit in the path MTU.
+    ///
+    /// The packet is divided into the unfragmentable part, made of the
+    /// IPv6 header and any hop-by-hop options and routing headers along
+    /// with the destination options that precede them, and the fragmentable
+    /// part, made of everything else. A [`Fragment`] header carrying the
+    /// `identification` is inserted after the unfragmentable part, which is
+    /// repeated in every fragment. The packet itself becomes the first
+    /// fragment. The fragment payloads, except the last, are multiples of 8
+    /// octets. A packet that fits in the MTU is returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the MTU is less than [`IPV6_MIN_MTU`], if the
+    /// packet already has a fragment header, if the unfragmentable part
+    /// leaves no room for data, or if a new buffer cannot be allocated.
+    ///
+    /// [`Fragment`]: Fragment
+    /// [`IPV6_MIN_MTU`]: IPV6_MIN_MTU
+    pub fn fragment(self, mtu: usize, identification: u32) -> Result<Vec<Ipv6>> {
+        ensure!(
+            mtu >= IPV6_MIN_MTU,
+            anyhow!("MTU {} must be greater than {}.", mtu, IPV6_MIN_MTU)
+        );
+
+        let end = self.payload_offset() + self.payload_length() as usize;
+        if end - self.offset() <= mtu {
+            return Ok(vec![self]);
+        }
+
+        // the unfragmentable part ends after the last hop-by-hop options
+        // or routing header.
+        let mut count = 0;
+        let mut unfragmentable = 0;
+        let walk = walk_ipv6(&self, |walk| {
+            count += 1;
+            if walk.protocol == ProtocolNumbers::Ipv6HopByHop
+                || walk.protocol == ProtocolNumbers::Ipv6Route
+            {
+                unfragmentable = count;
+            }
+            walk.protocol == ProtocolNumbers::Ipv6Frag
+        })?;
+        ensure!(
+            walk.protocol != ProtocolNumbers::Ipv6Frag,
+            anyhow!("packet is already fragmented.")
+        );
+
+        let chain = parse_unfragmentable(self, unfragmentable)?;
+        let unfragmentable_len = chain.payload_offset() - chain.envelope().offset();
+        let data_len = end - chain.payload_offset();
+        let max_len = mtu.saturating_sub(unfragmentable_len + 8) & !7;
+        ensure!(
+            max_len > 0,
+            anyhow!("unfragmentable part exceeds MTU {}.", mtu)
+        );
+
+        let mut first = chain.push::<Fragment<ExtensionChain>>()?;
+        first.set_identification(identification);
+        first.set_more_fragments();
+        let data_offset = first.payload_offset();
+
+        let mut fragments = vec![];
+        let mut offset = max_len;
+        while offset < data_len {
+            let len = cmp::min(max_len, data_len - offset);
+            let mut fragment = first.copy_headers(unfragmentable)?;
+
+            let mut data = vec![0u8; len];
+            first
+                .mbuf()
+                .copy_to_slice(data_offset + offset, &mut data)?;
+            let end = fragment.payload_offset();
+            fragment.mbuf_mut().extend(end, len)?;
+            fragment.mbuf_mut().copy_from_slice(end, &data)?;
+
+            fragment.set_fragment_offset((offset / 8) as u16);
+            if offset + len < data_len {
+                fragment.set_more_fragments();
+            } else {
+                fragment.unset_more_fragments();
+            }
+            fragment.reconcile_all();
+
+            fragments.push(fragment.deparse().deparse());
+            offset += len;
+        }
+
+        first.mbuf_mut().truncate(data_offset + max_len)?;
+        first.reconcile_all();
+        fragments.insert(0, first.deparse().deparse());
+
+        Ok(fragments)
+    }
+}
+
+/// Parses the first `count` extension headers as the unfragmentable part.
+fn parse_unfragmentable(ipv6: Ipv6, count: usize) -> Result<ExtensionChain> {
+    let mut index = 0;
+    ExtensionChain::parse_until(ipv6, |_| {
+        index += 1;
+        index > count
+    })
+    .map_err(|(e, _)| e)
+}
+
+impl Fragment<ExtensionChain> {
+    /// Copies the headers up to and including the fragment header into a
+    /// new buffer.
+    fn copy_headers(&self, unfragmentable: usize) -> Result<Self> {
+        let len = self.payload_offset();
+        let headers = self.mbuf().read_data_slice::<u8>(0, len)?;
+
+        let mut mbuf = Mbuf::new()?;
+        mbuf.extend(0, len)?;
+        mbuf.write_data_slice(0, unsafe { headers.as_ref() })?;
+
+        let ethernet = mbuf.parse::<Ethernet>().map_err(|(e, _)| e)?;
+        let ipv6 = ethernet.parse::<Ipv6>().map_err(|(e, _)| e)?;
+        let chain = parse_unfragmentable(ipv6, unfragmentable)?;
+        chain
+            .parse::<Fragment<ExtensionChain>>()
+            .map_err(|(e, _)| e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v6::{HopByHop, Ipv6Packet};
+    use crate::packets::udp::Udp;
+    use crate::testils::byte_arrays::{IPV6_OPTIONS_PACKET, IPV6_TCP_PACKET};
+    use crate::testils::ipv6_with_payload;
+
+    #[capsule::test]
+    fn fragment_packet() {
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 1500);
+        assert_eq!(1524, ipv6.payload_length());
+
+        let fragments = ipv6.fragment(1280, 0xabcd).unwrap();
+        assert_eq!(2, fragments.len());
+
+        // 8 + 1232 and 8 + 292
+        assert_eq!(1240, fragments[0].payload_length());
+        assert_eq!(300, fragments[1].payload_length());
+
+        let first = fragments[0].peek::<Fragment<Ipv6>>().unwrap();
+        assert_eq!(ProtocolNumbers::Tcp, first.next_header());
+        assert_eq!(0, first.fragment_offset());
+        assert!(first.more_fragments());
+        assert_eq!(0xabcd, first.identification());
+
+        let last = fragments[1].peek::<Fragment<Ipv6>>().unwrap();
+        assert_eq!(ProtocolNumbers::Tcp, last.next_header());
+        assert_eq!(154, last.fragment_offset());
+        assert!(!last.more_fragments());
+        assert_eq!(0xabcd, last.identification());
+    }
+
+    #[capsule::test]
+    fn fragment_packet_within_mtu() {
+        let packet = Mbuf::from_bytes(&IPV6_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        let fragments = ipv6.fragment(1280, 1).unwrap();
+        assert_eq!(1, fragments.len());
+        assert_eq!(ProtocolNumbers::Tcp, fragments[0].next_header());
+    }
+
+    #[capsule::test]
+    fn fragment_packet_below_min_mtu() {
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 1500);
+        assert!(ipv6.fragment(1000, 1).is_err());
+    }
+
+    #[capsule::test]
+    fn fragment_keeps_unfragmentable_part() {
+        let ipv6 = ipv6_with_payload(&IPV6_OPTIONS_PACKET, 1500);
+        let fragments = ipv6.fragment(1280, 7).unwrap();
+        assert_eq!(2, fragments.len());
+
+        // hop-by-hop is repeated, destination options is only in the first.
+        let mut fragments = fragments.into_iter();
+        let first = fragments.next().unwrap();
+        let hop_by_hop = first.parse::<HopByHop<Ipv6>>().unwrap();
+        let fragment = hop_by_hop.parse::<Fragment<HopByHop<Ipv6>>>().unwrap();
+        assert_eq!(ProtocolNumbers::Ipv6Opts, fragment.next_header());
+        assert_eq!(0, fragment.fragment_offset());
+
+        let last = fragments.next().unwrap();
+        let hop_by_hop = last.parse::<HopByHop<Ipv6>>().unwrap();
+        let fragment = hop_by_hop.parse::<Fragment<HopByHop<Ipv6>>>().unwrap();
+        assert_eq!(ProtocolNumbers::Ipv6Opts, fragment.next_header());
+        assert_eq!(153, fragment.fragment_offset());
+        assert!(fragment.parse::<Udp<Fragment<HopByHop<Ipv6>>>>().is_err());
+    }
+
+    #[capsule::test]
+    fn fragment_fragmented_packet() {
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 1500);
+        let mut fragment = ipv6.push::<Fragment<Ipv6>>().unwrap();
+        fragment.reconcile_all();
+
+        assert!(fragment.deparse().fragment(1280, 1).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::{OptionsExtension, OptionsExtensionKind};
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+
+/// The kind of the [`HopByHop`] options extension header.
+///
+/// [`HopByHop`]: HopByHop
+#[derive(Debug)]
+pub enum HopByHopKind {}
+
+impl OptionsExtensionKind for HopByHopKind {
+    const PROTOCOL: ProtocolNumber = ProtocolNumbers::Ipv6HopByHop;
+    const NAME: &'static str = "hop_by_hop";
+}
+
+/// IPv6 Hop-by-Hop Options Extension packet based on [IETF RFC 8200].
+///
+/// The hop-by-hop options header carries optional information that must
+/// be examined by every node along the packet's delivery path. It must
+/// immediately follow the IPv6 header.
+///
+/// The header format is described in [`OptionsExtension`].
+///
+/// [IETF RFC 8200]: https://tools.ietf.org/html/rfc8200#section-4.3
+/// [`OptionsExtension`]: OptionsExtension
+pub type HopByHop<E> = OptionsExtension<E, HopByHopKind>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v6::{Ipv6, Ipv6Option, JumboPayload, RouterAlert};
+    use crate::packets::ip::v6::{Ipv6OptionTypes, Ipv6Packet};
+    use crate::packets::Mbuf;
+    use crate::packets::Packet;
+    use crate::testils::byte_arrays::{IPV6_OPTIONS_PACKET, IPV6_TCP_PACKET};
+    use crate::testils::{bytes_of, ipv6_with_payload};
+
+    #[capsule::test]
+    fn parse_hop_by_hop_packet() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>().unwrap();
+
+        assert_eq!(ProtocolNumbers::Ipv6Opts, hop_by_hop.next_header());
+        assert_eq!(0, hop_by_hop.hdr_ext_len());
+        assert_eq!(8, hop_by_hop.header_len());
+
+        let mut iter = hop_by_hop.options_iter();
+
+        let mut option = iter.next().unwrap().unwrap();
+        assert_eq!(Ipv6OptionTypes::RouterAlert, option.option_type());
+        assert_eq!(2, option.data_len());
+        let alert = option.downcast::<RouterAlert<'_>>().unwrap();
+        assert_eq!(0, alert.value());
+
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(Ipv6OptionTypes::PadN, option.option_type());
+        assert_eq!(0, option.data_len());
+
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn parse_non_hop_by_hop_packet() {
+        let packet = Mbuf::from_bytes(&IPV6_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert!(ipv6.parse::<HopByHop<Ipv6>>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_hop_by_hop_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let next_header = ipv6.next_header();
+        let hop_by_hop = ipv6.push::<HopByHop<Ipv6>>().unwrap();
+
+        assert_eq!(8, hop_by_hop.len());
+        assert_eq!(next_header, hop_by_hop.next_header());
+        assert_eq!(
+            ProtocolNumbers::Ipv6HopByHop,
+            hop_by_hop.envelope().next_header()
+        );
+
+        // a pushed header only has padding.
+        let mut iter = hop_by_hop.options_iter();
+        let option = iter.next().unwrap().unwrap();
+        assert_eq!(Ipv6OptionTypes::PadN, option.option_type());
+        assert_eq!(4, option.data_len());
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn append_and_retain_options() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut hop_by_hop = ipv6.push::<HopByHop<Ipv6>>().unwrap();
+
+        let mut options = hop_by_hop.options_mut();
+        let mut alert = options.append::<RouterAlert<'_>>().unwrap();
+        alert.set_value(1);
+        assert_eq!(2, alert.data_len());
+
+        // router alert fits in the first 8 octets.
+        assert_eq!(0, hop_by_hop.hdr_ext_len());
+
+        // jumbo payload's 4n+2 alignment is met right after router alert.
+        let mut options = hop_by_hop.options_mut();
+        let mut jumbo = options.append::<JumboPayload<'_>>().unwrap();
+        jumbo.set_payload_len(100_000);
+        assert_eq!(100_000, jumbo.payload_len());
+        assert_eq!(1, hop_by_hop.hdr_ext_len());
+        assert_eq!(16, hop_by_hop.header_len());
+
+        let mut types = vec![];
+        let mut iter = hop_by_hop.options_iter();
+        while let Some(option) = iter.next().unwrap() {
+            types.push(option.option_type());
+        }
+        assert_eq!(
+            vec![
+                Ipv6OptionTypes::RouterAlert,
+                Ipv6OptionTypes::JumboPayload,
+                Ipv6OptionTypes::PadN,
+            ],
+            types
+        );
+
+        let mut options = hop_by_hop.options_mut();
+        options
+            .retain(|option| option.option_type() != Ipv6OptionTypes::JumboPayload)
+            .unwrap();
+        assert_eq!(0, hop_by_hop.hdr_ext_len());
+
+        let mut iter = hop_by_hop.options_iter();
+        let mut option = iter.next().unwrap().unwrap();
+        assert_eq!(1, option.downcast::<RouterAlert<'_>>().unwrap().value());
+        assert_eq!(
+            Ipv6OptionTypes::PadN,
+            iter.next().unwrap().unwrap().option_type()
+        );
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn append_option_with_alignment_padding() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut hop_by_hop = ipv6.push::<HopByHop<Ipv6>>().unwrap();
+
+        let mut options = hop_by_hop.options_mut();
+        let _ = options.append::<JumboPayload<'_>>().unwrap();
+        assert_eq!(0, hop_by_hop.hdr_ext_len());
+
+        // the second option starts at offset 8, so 2 octets of padding
+        // are needed to meet the 4n+2 alignment.
+        let mut options = hop_by_hop.options_mut();
+        let _ = options.append::<JumboPayload<'_>>().unwrap();
+        assert_eq!(1, hop_by_hop.hdr_ext_len());
+
+        let mut iter = hop_by_hop.options_iter();
+        assert_eq!(
+            Ipv6OptionTypes::JumboPayload,
+            iter.next().unwrap().unwrap().option_type()
+        );
+        let padding = iter.next().unwrap().unwrap();
+        assert_eq!(Ipv6OptionTypes::PadN, padding.option_type());
+        assert_eq!(0, padding.data_len());
+        assert_eq!(
+            Ipv6OptionTypes::JumboPayload,
+            iter.next().unwrap().unwrap().option_type()
+        );
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn append_option_exhausted_mempool() {
+        // fills the first segment, so the option needs a new one.
+        let len = 2048 - IPV6_OPTIONS_PACKET.len();
+        let ipv6 = ipv6_with_payload(&IPV6_OPTIONS_PACKET, len);
+        assert_eq!(1, ipv6.mbuf().num_segments());
+        let mut hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>().unwrap();
+        let bytes = bytes_of(&hop_by_hop);
+
+        // holds on to the rest of the mempool
+        let mut held = vec![];
+        while let Ok(other) = Mbuf::new() {
+            held.push(other);
+        }
+
+        // the options are left unchanged
+        let mut options = hop_by_hop.options_mut();
+        assert!(options.append::<JumboPayload<'_>>().is_err());
+        assert_eq!(0, hop_by_hop.hdr_ext_len());
+        assert_eq!(bytes, bytes_of(&hop_by_hop));
+
+        held.clear();
+        let mut options = hop_by_hop.options_mut();
+        assert!(options.append::<JumboPayload<'_>>().is_ok());
+        assert_eq!(1, hop_by_hop.hdr_ext_len());
+    }
+
+    #[capsule::test]
+    fn remove_hop_by_hop_packet() {
+        let packet = Mbuf::from_bytes(&IPV6_OPTIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>().unwrap();
+
+        let ipv6 = hop_by_hop.remove().unwrap();
+        assert_eq!(ProtocolNumbers::Ipv6Opts, ipv6.next_header());
+        assert_eq!(IPV6_OPTIONS_PACKET.len() - 8, ipv6.mbuf().data_len());
+    }
+}
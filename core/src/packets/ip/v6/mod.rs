@@ -18,18 +18,31 @@
 
 //! Internet Protocol v6 and extension headers.
 
+mod chain;
+mod dest_opts;
 mod fragment;
+mod fragmentation;
+mod hop_by_hop;
+mod options;
+mod options_ext;
+mod reassembly;
 mod srh;
 
+pub use self::chain::*;
+pub use self::dest_opts::*;
 pub use self::fragment::*;
+pub use self::hop_by_hop::*;
+pub use self::options::*;
+pub use self::options_ext::*;
+pub use self::reassembly::*;
 pub use self::srh::*;
 
 use crate::ensure;
 use crate::packets::checksum::PseudoHeader;
-use crate::packets::ethernet::{EtherTypes, Ethernet};
-use crate::packets::ip::{IpPacket, ProtocolNumber, DEFAULT_IP_TTL};
+use crate::packets::ethernet::{EtherType, EtherTypes, Ethernet};
+use crate::packets::ip::{self, IpPacket, ProtocolNumber, DEFAULT_IP_TTL};
 use crate::packets::types::{u16be, u32be};
-use crate::packets::{Internal, Packet, SizeOf};
+use crate::packets::{Datalink, Internal, Packet, SizeOf};
 use anyhow::{anyhow, Result, Error};
 use std::fmt;
 use std::net::{IpAddr, Ipv6Addr};
@@ -93,16 +106,30 @@ const FLOW: u32be = u32be(u32::to_be(0xfffff));
 ///                           packet (possibly not the ultimate recipient, if
 ///                           a Routing header is present).
 ///
+/// The packet is normally carried by an [Ethernet] frame. An inner packet
+/// of a tunnel is carried by the tunnel packet instead, which can be any
+/// [`Datalink`] packet, including an outer IPv4 or IPv6 packet of an
+/// IP-in-IP tunnel.
+///
+/// # Example
+///
+/// ```
+/// let outer = ethernet.parse::<Ipv6>()?;
+/// let inner = outer.parse::<Ipv4<Ipv6>>()?;
+/// ```
+///
 /// [IETF RFC 8200]: https://tools.ietf.org/html/rfc8200#section-3
 /// [IETF RFC 2474]: https://tools.ietf.org/html/rfc2474
 /// [IETF RFC 3168]: https://tools.ietf.org/html/rfc3168
-pub struct Ipv6 {
-    envelope: Ethernet,
+/// [Ethernet]: crate::packets::ethernet::Ethernet
+/// [`Datalink`]: crate::packets::Datalink
+pub struct Ipv6<E: Datalink = Ethernet> {
+    envelope: E,
     header: NonNull<Ipv6Header>,
     offset: usize,
 }
 
-impl Ipv6 {
+impl<E: Datalink> Ipv6<E> {
     #[inline]
     fn header(&self) -> &Ipv6Header {
         unsafe { self.header.as_ref() }
@@ -215,9 +242,59 @@ impl Ipv6 {
         self.set_src(self.dst());
         self.set_dst(src_addr)
     }
+
+    /// Encapsulates the IP packet carried by the envelope in an Ipv6
+    /// packet.
+    ///
+    /// The outer Ipv6 header is inserted between the envelope and the
+    /// IP packet, and [`next_header`] is set to the IP-in-IP protocol of the
+    /// inner packet. The caller is responsible for setting the outer
+    /// addresses and reconciling the packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the envelope does not carry an IPv4 or IPv6
+    /// packet. Returns an error if the buffer does not have enough free
+    /// space.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    pub fn encap(envelope: E) -> Result<Self> {
+        let protocol = ip::tunnel_protocol(envelope.protocol_type())
+            .ok_or_else(|| anyhow!("not an IP packet."))?;
+        let mut outer = envelope.push::<Self>()?;
+        outer.set_next_header(protocol);
+        Ok(outer)
+    }
+
+    /// Decapsulates the inner IP packet of an IP-in-IP tunnel.
+    ///
+    /// The Ipv6 header is removed from the buffer, and the envelope's
+    /// protocol type is set to the type of the inner packet. The inner
+    /// packet can then be parsed from the envelope.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet is not an IP-in-IP tunnel packet.
+    /// Returns an error if the buffer fails to shrink.
+    #[inline]
+    pub fn decap(self) -> Result<E> {
+        let ether_type = self.protocol_type();
+        ensure!(
+            ether_type == EtherTypes::Ipv4 || ether_type == EtherTypes::Ipv6,
+            anyhow!("not an IP-in-IP packet.")
+        );
+
+        let offset = self.offset;
+        let len = self.header_len();
+        let mut envelope = self.deparse();
+        envelope.mbuf_mut().shrink(offset, len)?;
+        envelope.set_protocol_type(ether_type);
+        Ok(envelope)
+    }
 }
 
-impl fmt::Debug for Ipv6 {
+impl<E: Datalink> fmt::Debug for Ipv6<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ipv6")
             .field("src", &format!("{}", self.src()))
@@ -235,9 +312,10 @@ impl fmt::Debug for Ipv6 {
     }
 }
 
-impl Packet for Ipv6 {
-    /// The preceding type for IPv6 packet must be Ethernet.
-    type Envelope = Ethernet;
+impl<E: Datalink> Packet for Ipv6<E> {
+    /// The preceding type for an IPv6 packet is Ethernet, or a tunnel
+    /// packet for an inner IPv6 packet.
+    type Envelope = E;
 
     #[inline]
     fn envelope(&self) -> &Self::Envelope {
@@ -268,20 +346,20 @@ impl Packet for Ipv6 {
         }
     }
 
-    /// Parses the Ethernet's payload as an IPv6 packet.
+    /// Parses the envelope's payload as an IPv6 packet.
     ///
     /// # Errors
     ///
-    /// Returns an error if [`ether_type`] is not set to [`EtherTypes::Ipv6`].
+    /// Returns an error if [`protocol_type`] is not set to [`EtherTypes::Ipv6`].
     /// Returns an error if the payload does not have sufficient data for the
     /// IPv6 header.
     ///
-    /// [`ether_type`]: Ethernet::ether_type
+    /// [`protocol_type`]: Datalink::protocol_type
     /// [`EtherTypes::Ipv6`]: EtherTypes::Ipv6
     #[inline]
     fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
         ensure!(
-            envelope.ether_type() == EtherTypes::Ipv6,
+            envelope.protocol_type() == EtherTypes::Ipv6,
             (anyhow!("not an IPv6 packet."), envelope)
         );
 
@@ -299,15 +377,15 @@ impl Packet for Ipv6 {
         })
     }
 
-    /// Prepends an IPv6 packet to the beginning of the Ethernet's payload.
+    /// Prepends an IPv6 packet to the beginning of the envelope's payload.
     ///
-    /// [`ether_type`] is set to [`EtherTypes::Ipv6`].
+    /// [`protocol_type`] is set to [`EtherTypes::Ipv6`].
     ///
     /// # Errors
     ///
     /// Returns an error if the buffer does not have enough free space.
     ///
-    /// [`ether_type`]: Ethernet::ether_type
+    /// [`protocol_type`]: Datalink::protocol_type
     /// [`EtherTypes::Ipv6`]: EtherTypes::Ipv6
     #[inline]
     fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
@@ -317,7 +395,7 @@ impl Packet for Ipv6 {
         mbuf.extend(offset, Ipv6Header::size_of())?;
         let header = mbuf.write_data(offset, &Ipv6Header::default())?;
 
-        envelope.set_ether_type(EtherTypes::Ipv6);
+        envelope.set_protocol_type(EtherTypes::Ipv6);
 
         Ok(Ipv6 {
             envelope,
@@ -342,10 +420,40 @@ impl Packet for Ipv6 {
     fn reconcile(&mut self) {
         let len = self.payload_len() as u16;
         self.set_payload_length(len);
+
+        let offset = self.offset;
+        let header_len = match self.upper_layer() {
+            Ok((_, upper)) => upper - offset,
+            Err(_) => self.header_len(),
+        };
+        self.mbuf_mut().defer_l3_offloads(offset, header_len, false);
+    }
+}
+
+impl<E: Datalink> Datalink for Ipv6<E> {
+    /// Returns the protocol type of the inner packet of an IP-in-IP tunnel,
+    /// mapped from [`next_header`]. Returns `0` if the packet is not an
+    /// IP-in-IP tunnel packet.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        ip::tunnel_ether_type(self.next_header())
+    }
+
+    /// Sets [`next_header`] to the IP-in-IP protocol of the inner packet.
+    /// Protocol types other than IPv4 and IPv6 are ignored.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    fn set_protocol_type(&mut self, ether_type: EtherType) {
+        if let Some(protocol) = ip::tunnel_protocol(ether_type) {
+            self.set_next_header(protocol);
+        }
     }
 }
 
-impl IpPacket for Ipv6 {
+impl<E: Datalink> IpPacket for Ipv6<E> {
     #[inline]
     fn next_protocol(&self) -> ProtocolNumber {
         self.next_header()
@@ -422,13 +530,19 @@ impl IpPacket for Ipv6 {
             anyhow!("MTU {} must be greater than {}.", mtu, IPV6_MIN_MTU)
         );
 
-        // accounts for the Ethernet frame length.
+        // accounts for the headers preceding the packet.
         let to_len = mtu + self.offset();
         self.mbuf_mut().truncate(to_len)
     }
+
+    #[inline]
+    fn set_length_from_buffer(&mut self) {
+        let len = self.payload_len() as u16;
+        self.set_payload_length(len);
+    }
 }
 
-impl Ipv6Packet for Ipv6 {
+impl<E: Datalink> Ipv6Packet for Ipv6<E> {
     #[inline]
     fn next_header(&self) -> ProtocolNumber {
         ProtocolNumber::new(self.header().next_header)
@@ -479,6 +593,8 @@ mod tests {
     use super::*;
     use crate::packets::ip::ProtocolNumbers;
     use crate::packets::Mbuf;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::udp::Udp;
     use crate::testils::byte_arrays::{IPV4_UDP_PACKET, IPV6_TCP_PACKET};
 
     #[test]
@@ -558,4 +674,34 @@ mod tests {
         let _ = ipv6.truncate(1500);
         assert_eq!(1500, ipv6.len());
     }
+
+    #[capsule::test]
+    fn encap_and_decap_4in6() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let inner_len = ethernet.payload_len();
+
+        let mut outer = Ipv6::encap(ethernet).unwrap();
+        outer.set_src("2001:db8::1".parse().unwrap());
+        outer.set_dst("2001:db8::2".parse().unwrap());
+        outer.reconcile_all();
+
+        assert_eq!(EtherTypes::Ipv6, outer.envelope().ether_type());
+        assert_eq!(ProtocolNumbers::Ipip, outer.next_header());
+        assert_eq!(inner_len as u16, outer.payload_length());
+
+        let inner = outer.parse::<Ipv4<Ipv6>>().unwrap();
+        assert_eq!("139.133.217.110", inner.src().to_string());
+        let udp = inner.parse::<Udp<Ipv4<Ipv6>>>().unwrap();
+        assert_eq!(39376, udp.src_port());
+
+        let outer = udp.deparse().deparse();
+        let ethernet = outer.decap().unwrap();
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+        let data = ethernet
+            .mbuf()
+            .read_data_slice::<u8>(0, ethernet.mbuf().data_len())
+            .unwrap();
+        assert_eq!(&IPV4_UDP_PACKET[..], unsafe { data.as_ref() });
+    }
 }
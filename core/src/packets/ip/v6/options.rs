@@ -0,0 +1,949 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! IPv6 hop-by-hop and destination options.
+
+use crate::ensure;
+use crate::packets::tlv::{self, Cursor, Tlv, TlvType};
+use crate::packets::types::{u16be, u32be};
+use crate::packets::{Immutable, Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+/// The maximum length of an options extension header, measured in octets.
+///
+/// The header extension length field can describe a header of at most 256
+/// 8-octet units.
+pub const IPV6_MAX_OPTIONS_HEADER_LEN: usize = 2048;
+
+/// The fixed portion of the hop-by-hop and destination options headers.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C, packed)]
+pub(crate) struct OptionsHeader {
+    pub(crate) next_header: u8,
+    pub(crate) hdr_ext_len: u8,
+}
+
+impl OptionsHeader {
+    /// Returns the length of the header in octets, including the options.
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        (self.hdr_ext_len as usize + 1) * 8
+    }
+}
+
+/// [IANA] assigned IPv6 option type.
+///
+/// The two highest-order bits of the type specify the action to take if
+/// the node does not recognize the option. The third-highest-order bit
+/// specifies whether the option data can change en route. A list of
+/// supported types is under [`Ipv6OptionTypes`].
+///
+/// [IANA]: https://www.iana.org/assignments/ipv6-parameters/ipv6-parameters.xhtml#ipv6-parameters-2
+/// [`Ipv6OptionTypes`]: Ipv6OptionTypes
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct Ipv6OptionType(pub u8);
+
+impl Ipv6OptionType {
+    /// Returns the action to take if the option type is not recognized.
+    ///
+    /// - `0`: skip over the option and continue processing the header.
+    /// - `1`: discard the packet.
+    /// - `2`: discard the packet and send an ICMPv6 parameter problem.
+    /// - `3`: discard the packet and send an ICMPv6 parameter problem only
+    ///   if the destination is not a multicast address.
+    #[inline]
+    pub fn action(self) -> u8 {
+        self.0 >> 6
+    }
+
+    /// Returns a flag indicating whether the option data may change en
+    /// route to the packet's final destination.
+    #[inline]
+    pub fn may_change(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    /// Returns a flag indicating whether the option is padding.
+    #[inline]
+    pub fn is_padding(self) -> bool {
+        self == Ipv6OptionTypes::Pad1 || self == Ipv6OptionTypes::PadN
+    }
+}
+
+/// Supported IPv6 option types.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod Ipv6OptionTypes {
+    use super::Ipv6OptionType;
+
+    /// A single octet of padding. This option has no length or data
+    /// fields.
+    pub const Pad1: Ipv6OptionType = Ipv6OptionType(0x00);
+
+    /// Two or more octets of padding.
+    pub const PadN: Ipv6OptionType = Ipv6OptionType(0x01);
+
+    /// Option type for [Router Alert].
+    ///
+    /// [Router Alert]: crate::packets::ip::v6::RouterAlert
+    pub const RouterAlert: Ipv6OptionType = Ipv6OptionType(0x05);
+
+    /// Option type for [Jumbo Payload].
+    ///
+    /// [Jumbo Payload]: crate::packets::ip::v6::JumboPayload
+    pub const JumboPayload: Ipv6OptionType = Ipv6OptionType(0xC2);
+}
+
+impl fmt::Display for Ipv6OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Ipv6OptionTypes::Pad1 => "Pad1".to_string(),
+                Ipv6OptionTypes::PadN => "PadN".to_string(),
+                Ipv6OptionTypes::RouterAlert => "Router Alert".to_string(),
+                Ipv6OptionTypes::JumboPayload => "Jumbo Payload".to_string(),
+                _ => format!("0x{:02x}", self.0),
+            }
+        )
+    }
+}
+
+impl TlvType for Ipv6OptionType {
+    const ALIGNMENT: usize = 8;
+    const MAX_LEN: usize = IPV6_MAX_OPTIONS_HEADER_LEN;
+
+    #[inline]
+    fn from_octet(octet: u8) -> Self {
+        Ipv6OptionType(octet)
+    }
+
+    #[inline]
+    fn is_single_octet(self) -> bool {
+        self == Ipv6OptionTypes::Pad1
+    }
+
+    #[inline]
+    fn option_len(length: u8) -> Result<usize> {
+        // the length only counts the option data.
+        Ok(length as usize + 2)
+    }
+
+    #[inline]
+    fn is_padding(self) -> bool {
+        Ipv6OptionType::is_padding(self)
+    }
+
+    /// Writes `len` octets of padding to the buffer at offset, using
+    /// either a `Pad1` or a `PadN` option.
+    #[inline]
+    fn write_padding(mbuf: &mut Mbuf, offset: usize, len: usize) -> Result<()> {
+        match len {
+            0 => Ok(()),
+            1 => mbuf
+                .write_data(offset, &Ipv6OptionTypes::Pad1.0)
+                .map(|_| ()),
+            _ => {
+                let mut padding = vec![0u8; len];
+                padding[0] = Ipv6OptionTypes::PadN.0;
+                padding[1] = (len - 2) as u8;
+                mbuf.write_data_slice(offset, &padding).map(|_| ())
+            }
+        }
+    }
+}
+
+/// An immutable generic IPv6 option that can be casted to a more specific
+/// option.
+pub struct ImmutableIpv6Option<'a> {
+    mbuf: &'a mut Mbuf,
+    option_type: Ipv6OptionType,
+    data_len: u8,
+    offset: usize,
+}
+
+impl<'a> ImmutableIpv6Option<'a> {
+    /// Creates a new immutable untyped IPv6 option.
+    #[inline]
+    fn new(mbuf: &'a mut Mbuf, tlv: Tlv<Ipv6OptionType>) -> Self {
+        ImmutableIpv6Option {
+            mbuf,
+            option_type: tlv.option_type,
+            data_len: tlv.len.saturating_sub(2) as u8,
+            offset: tlv.offset,
+        }
+    }
+
+    /// Returns the option type.
+    #[inline]
+    pub fn option_type(&self) -> Ipv6OptionType {
+        self.option_type
+    }
+
+    /// Returns the length of the option data measured in octets. `Pad1`
+    /// options have a data length of `0`.
+    #[inline]
+    pub fn data_len(&self) -> u8 {
+        self.data_len
+    }
+
+    /// Casts the immutable generic option to a specific option `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>()?;
+    /// let mut iter = hop_by_hop.options_iter();
+    ///
+    /// while let Some(mut option) = iter.next()? {
+    ///     if let Ok(alert) = option.downcast::<RouterAlert<'_>>() {
+    ///         println!("{:?}", alert);
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<'b, T: Ipv6Option<'b>>(&'b mut self) -> Result<Immutable<'b, T>> {
+        T::try_parse(self.mbuf, self.offset, Internal(())).map(Immutable::new)
+    }
+}
+
+impl fmt::Debug for ImmutableIpv6Option<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImmutableIpv6Option")
+            .field("option_type", &format!("{}", self.option_type()))
+            .field("data_len", &self.data_len())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in an IPv6 options
+/// extension header immutably.
+///
+/// The iterator returns all the options, including the `Pad1` and `PadN`
+/// padding options.
+pub struct ImmutableIpv6OptionsIterator<'a> {
+    mbuf: Mbuf,
+    cursor: Cursor,
+    _phantom: PhantomData<&'a Mbuf>,
+}
+
+impl ImmutableIpv6OptionsIterator<'_> {
+    /// Creates a new iterator for the options between the offsets.
+    #[inline]
+    pub(crate) fn new(mbuf: &Mbuf, offset: usize, end: usize) -> Self {
+        let mbuf = unsafe { mbuf.clone(Internal(())) };
+        ImmutableIpv6OptionsIterator {
+            mbuf,
+            cursor: Cursor { offset, end },
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Advances the iterator and returns the next value.
+    ///
+    /// Returns `Ok(None)` when iteration is finished; returns `Err` when a
+    /// parse error is encountered during iteration.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<ImmutableIpv6Option<'_>>> {
+        match self.cursor.next::<Ipv6OptionType>(&self.mbuf)? {
+            Some(tlv) => Ok(Some(ImmutableIpv6Option::new(&mut self.mbuf, tlv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for ImmutableIpv6OptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImmutableIpv6OptionsIterator")
+            .field("offset", &self.cursor.offset)
+            .field("end", &self.cursor.end)
+            .finish()
+    }
+}
+
+/// A mutable generic IPv6 option that can be casted to a more specific
+/// option.
+pub struct MutableIpv6Option<'a> {
+    mbuf: &'a mut Mbuf,
+    option_type: Ipv6OptionType,
+    data_len: u8,
+    offset: usize,
+}
+
+impl<'a> MutableIpv6Option<'a> {
+    /// Creates a new mutable untyped IPv6 option.
+    #[inline]
+    fn new(mbuf: &'a mut Mbuf, tlv: Tlv<Ipv6OptionType>) -> Self {
+        MutableIpv6Option {
+            mbuf,
+            option_type: tlv.option_type,
+            data_len: tlv.len.saturating_sub(2) as u8,
+            offset: tlv.offset,
+        }
+    }
+
+    /// Returns the option type.
+    #[inline]
+    pub fn option_type(&self) -> Ipv6OptionType {
+        self.option_type
+    }
+
+    /// Returns the length of the option data measured in octets. `Pad1`
+    /// options have a data length of `0`.
+    #[inline]
+    pub fn data_len(&self) -> u8 {
+        self.data_len
+    }
+
+    /// Casts the mutable generic option to a specific option `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>()?;
+    /// let mut options = hop_by_hop.options_mut();
+    /// let mut iter = options.iter();
+    ///
+    /// while let Some(mut option) = iter.next()? {
+    ///     if let Ok(mut alert) = option.downcast::<RouterAlert<'_>>() {
+    ///         alert.set_value(0);
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<'b, T: Ipv6Option<'b>>(&'b mut self) -> Result<T> {
+        T::try_parse(self.mbuf, self.offset, Internal(()))
+    }
+}
+
+impl fmt::Debug for MutableIpv6Option<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutableIpv6Option")
+            .field("option_type", &format!("{}", self.option_type()))
+            .field("data_len", &self.data_len())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in an IPv6 options
+/// extension header mutably.
+///
+/// The iterator returns all the options, including the `Pad1` and `PadN`
+/// padding options.
+pub struct MutableIpv6OptionsIterator<'a> {
+    mbuf: &'a mut Mbuf,
+    cursor: Cursor,
+}
+
+impl MutableIpv6OptionsIterator<'_> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// Returns `Ok(None)` when iteration is finished; returns `Err` when a
+    /// parse error is encountered during iteration.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<MutableIpv6Option<'_>>> {
+        match self.cursor.next::<Ipv6OptionType>(self.mbuf)? {
+            Some(tlv) => Ok(Some(MutableIpv6Option::new(self.mbuf, tlv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for MutableIpv6OptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutableIpv6OptionsIterator")
+            .field("offset", &self.cursor.offset)
+            .field("end", &self.cursor.end)
+            .finish()
+    }
+}
+
+/// Options in an IPv6 hop-by-hop or destination options extension header.
+///
+/// Adding or removing options resizes the header. The options are padded
+/// with `Pad1` or `PadN` to a multiple of 8 octets and the header extension
+/// length is updated accordingly. The payload length of the IPv6 packet is
+/// fixed when the packet is reconciled.
+pub struct Ipv6Options<'a> {
+    mbuf: &'a mut Mbuf,
+    header: NonNull<OptionsHeader>,
+    offset: usize,
+}
+
+impl<'a> Ipv6Options<'a> {
+    /// Creates a new mutable view of the options in the header at offset.
+    #[inline]
+    pub(crate) fn new(mbuf: &'a mut Mbuf, header: NonNull<OptionsHeader>, offset: usize) -> Self {
+        Ipv6Options {
+            mbuf,
+            header,
+            offset,
+        }
+    }
+
+    #[inline]
+    fn header(&self) -> &OptionsHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut OptionsHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the buffer offset where the options begin.
+    #[inline]
+    fn start(&self) -> usize {
+        self.offset + OptionsHeader::size_of()
+    }
+
+    /// Returns the buffer offset where the options end, including the
+    /// padding.
+    #[inline]
+    fn end(&self) -> usize {
+        self.offset + self.header().len()
+    }
+
+    /// Returns the buffer offset where the trailing padding after the last
+    /// non-padding option begins.
+    fn padding_offset(&self) -> Result<usize> {
+        tlv::padding_offset::<Ipv6OptionType>(self.mbuf, self.start(), self.end())
+    }
+
+    /// Sets the header extension length to cover the header of
+    /// `aligned_len` octets.
+    #[inline]
+    fn set_hdr_ext_len(&mut self, aligned_len: usize) {
+        self.header_mut().hdr_ext_len = (aligned_len / 8 - 1) as u8;
+    }
+
+    /// Returns an iterator that allows modifying each option.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>()?;
+    /// let mut options = hop_by_hop.options_mut();
+    /// let mut iter = options.iter();
+    ///
+    /// while let Some(option) = iter.next()? {
+    ///     println!("{:?}", option);
+    /// }
+    /// ```
+    #[inline]
+    pub fn iter(&mut self) -> MutableIpv6OptionsIterator<'_> {
+        let cursor = Cursor {
+            offset: self.start(),
+            end: self.end(),
+        };
+        MutableIpv6OptionsIterator {
+            mbuf: self.mbuf,
+            cursor,
+        }
+    }
+
+    /// Appends a new option `T` after the last non-padding option.
+    ///
+    /// Padding is inserted before the option to satisfy its alignment
+    /// requirement.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header would exceed
+    /// [`IPV6_MAX_OPTIONS_HEADER_LEN`] or if the buffer does not have enough
+    /// free space. The options are left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut hop_by_hop = ipv6.push::<HopByHop<Ipv6>>()?;
+    /// let mut options = hop_by_hop.options_mut();
+    /// let mut alert = options.append::<RouterAlert<'_>>()?;
+    /// alert.set_value(0);
+    /// ```
+    ///
+    /// [`IPV6_MAX_OPTIONS_HEADER_LEN`]: IPV6_MAX_OPTIONS_HEADER_LEN
+    pub fn append<'b, T: Ipv6Option<'b>>(&'b mut self) -> Result<T> {
+        let end = self.end();
+        let padding = self.padding_offset()?;
+
+        // the option must start at an offset of `xn + y` from the start
+        // of the header.
+        let (x, y) = T::alignment();
+        let pre_padding = (y + x - (padding - self.offset) % x) % x;
+
+        // the buffer and the header extension length are left unchanged
+        // if the room cannot be inserted.
+        let len = pre_padding + T::push_len();
+        let aligned_len =
+            tlv::insert::<Ipv6OptionType>(self.mbuf, self.offset, padding, padding, end, len)?;
+        self.set_hdr_ext_len(aligned_len);
+        Ipv6OptionType::write_padding(self.mbuf, padding, pre_padding)?;
+        T::try_push(self.mbuf, padding + pre_padding, Internal(()))
+    }
+
+    /// Retains only the non-padding options specified by the predicate.
+    ///
+    /// In other words, remove all options `o` such that `f(o)` returns false.
+    /// Padding options are kept. If an error occurs, all removals done prior
+    /// to the error cannot be undone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>()?;
+    /// let mut options = hop_by_hop.options_mut();
+    /// let _ = options.retain(|option| option.option_type() != Ipv6OptionTypes::RouterAlert);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut ImmutableIpv6Option<'_>) -> bool,
+    {
+        let (padding, end) =
+            tlv::retain::<Ipv6OptionType, _>(self.mbuf, self.start(), self.end(), |mbuf, tlv| {
+                f(&mut ImmutableIpv6Option::new(mbuf, tlv))
+            })?;
+
+        let aligned_len =
+            tlv::realign::<Ipv6OptionType>(self.mbuf, self.offset, padding, end, 0)?;
+        self.set_hdr_ext_len(aligned_len);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Ipv6Options<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ipv6Options")
+            .field("offset", &self.offset)
+            .field("end", &self.end())
+            .finish()
+    }
+}
+
+/// A trait that all IPv6 options must implement.
+///
+/// The trait is used for conversion between the generic IPv6 option
+/// and the more specific options. Implementors can use this trait to
+/// add custom IPv6 options. This trait should not be imported and used
+/// directly.
+pub trait Ipv6Option<'a> {
+    /// Returns the option type.
+    fn option_type(&self) -> Ipv6OptionType;
+
+    /// Returns the length of the option data measured in octets.
+    fn data_len(&self) -> u8;
+
+    /// Returns the length of the option in octets when it's pushed by
+    /// [`try_push`].
+    ///
+    /// [`try_push`]: Ipv6Option::try_push
+    fn push_len() -> usize
+    where
+        Self: Sized;
+
+    /// Returns the alignment requirement of the option as `(x, y)`,
+    /// meaning the option must start at an integer multiple of `x` octets
+    /// plus `y` octets from the start of the header.
+    #[inline]
+    fn alignment() -> (usize, usize)
+    where
+        Self: Sized,
+    {
+        (1, 0)
+    }
+
+    /// Parses the buffer at offset as this IPv6 option.
+    ///
+    /// The buffer offset includes `option_type` and `data_len` fields. The
+    /// implementation should verify that `option_type` matches the expected
+    /// type code. Otherwise parse should fail. When applicable, the
+    /// implementation should also verify that `data_len` is consistent with
+    /// the expected length for the given option type as well.
+    ///
+    /// # Remarks
+    ///
+    /// This function cannot be invoked directly. It is internally used by
+    /// [`ImmutableIpv6Option::downcast`] and [`MutableIpv6Option::downcast`].
+    ///
+    /// [`ImmutableIpv6Option::downcast`]: ImmutableIpv6Option::downcast
+    /// [`MutableIpv6Option::downcast`]: MutableIpv6Option::downcast
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, internal: Internal) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Writes a new IPv6 option to the [`push_len`] octets of room already
+    /// inserted in the buffer at offset.
+    ///
+    /// # Remarks
+    ///
+    /// This function cannot be invoked directly. It is internally used by
+    /// [`Ipv6Options::append`].
+    ///
+    /// [`push_len`]: Ipv6Option::push_len
+    /// [`Ipv6Options::append`]: Ipv6Options::append
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, internal: Internal) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+/// Router Alert option defined in [IETF RFC 2711].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |0 0 0|0 0 1 0 1|0 0 0 0 0 0 1 0|        Value (2 octets)       |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Value*:           `0` for a datagram containing a Multicast
+///                      Listener Discovery message, `1` for RSVP and `2`
+///                      for Active Networks.
+///
+/// Only valid in a hop-by-hop options header. The option has an alignment
+/// requirement of `2n+0`.
+///
+/// [IETF RFC 2711]: https://tools.ietf.org/html/rfc2711#section-2.1
+pub struct RouterAlert<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<RouterAlertFields>,
+    offset: usize,
+}
+
+impl RouterAlert<'_> {
+    #[inline]
+    fn fields(&self) -> &RouterAlertFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut RouterAlertFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the router alert value.
+    #[inline]
+    pub fn value(&self) -> u16 {
+        self.fields().value.into()
+    }
+
+    /// Sets the router alert value.
+    #[inline]
+    pub fn set_value(&mut self, value: u16) {
+        self.fields_mut().value = value.into();
+    }
+}
+
+impl fmt::Debug for RouterAlert<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouterAlert")
+            .field("type", &self.option_type())
+            .field("data_len", &self.data_len())
+            .field("value", &self.value())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> Ipv6Option<'a> for RouterAlert<'a> {
+    /// Returns the option type. Should always be `5`.
+    #[inline]
+    fn option_type(&self) -> Ipv6OptionType {
+        Ipv6OptionType(self.fields().option_type)
+    }
+
+    /// Returns the length of the option data. Should always be `2`.
+    #[inline]
+    fn data_len(&self) -> u8 {
+        self.fields().data_len
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        RouterAlertFields::size_of()
+    }
+
+    #[inline]
+    fn alignment() -> (usize, usize) {
+        (2, 0)
+    }
+
+    /// Parses the buffer at offset as router alert option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `option_type` is not set to `RouterAlert`.
+    /// Returns an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<RouterAlert<'a>> {
+        let fields = mbuf.read_data::<RouterAlertFields>(offset)?;
+        let option = RouterAlert {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.option_type() == Ipv6OptionTypes::RouterAlert,
+            anyhow!("not router alert.")
+        );
+
+        ensure!(
+            option.data_len() == 2,
+            anyhow!("invalid router alert option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new router alert option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<RouterAlert<'a>> {
+        let fields = mbuf.write_data(offset, &RouterAlertFields::default())?;
+        Ok(RouterAlert {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// Router alert option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct RouterAlertFields {
+    option_type: u8,
+    data_len: u8,
+    value: u16be,
+}
+
+impl Default for RouterAlertFields {
+    fn default() -> RouterAlertFields {
+        RouterAlertFields {
+            option_type: Ipv6OptionTypes::RouterAlert.0,
+            data_len: 2,
+            value: u16be::default(),
+        }
+    }
+}
+
+/// Jumbo Payload option defined in [IETF RFC 2675].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///                                 +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///                                 |  Option Type  |  Opt Data Len |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                     Jumbo Payload Length                      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Option Type*:     0xC2
+///
+/// - *Opt Data Len*:    4
+///
+/// - *Jumbo Payload Length*:
+///                      Length of the IPv6 packet in octets, excluding the
+///                      IPv6 header but including the hop-by-hop options
+///                      header and any other extension headers present.
+///                      Must be greater than 65,535.
+///
+/// Only valid in a hop-by-hop options header, and the payload length of
+/// the IPv6 header must be `0`. The option has an alignment requirement
+/// of `4n+2`.
+///
+/// [IETF RFC 2675]: https://tools.ietf.org/html/rfc2675#section-2
+pub struct JumboPayload<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<JumboPayloadFields>,
+    offset: usize,
+}
+
+impl JumboPayload<'_> {
+    #[inline]
+    fn fields(&self) -> &JumboPayloadFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut JumboPayloadFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the jumbo payload length.
+    #[inline]
+    pub fn payload_len(&self) -> u32 {
+        self.fields().payload_len.into()
+    }
+
+    /// Sets the jumbo payload length.
+    #[inline]
+    pub fn set_payload_len(&mut self, payload_len: u32) {
+        self.fields_mut().payload_len = payload_len.into();
+    }
+}
+
+impl fmt::Debug for JumboPayload<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JumboPayload")
+            .field("type", &self.option_type())
+            .field("data_len", &self.data_len())
+            .field("payload_len", &self.payload_len())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> Ipv6Option<'a> for JumboPayload<'a> {
+    /// Returns the option type. Should always be `0xC2`.
+    #[inline]
+    fn option_type(&self) -> Ipv6OptionType {
+        Ipv6OptionType(self.fields().option_type)
+    }
+
+    /// Returns the length of the option data. Should always be `4`.
+    #[inline]
+    fn data_len(&self) -> u8 {
+        self.fields().data_len
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        JumboPayloadFields::size_of()
+    }
+
+    #[inline]
+    fn alignment() -> (usize, usize) {
+        (4, 2)
+    }
+
+    /// Parses the buffer at offset as jumbo payload option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `option_type` is not set to `JumboPayload`.
+    /// Returns an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<JumboPayload<'a>> {
+        let fields = mbuf.read_data::<JumboPayloadFields>(offset)?;
+        let option = JumboPayload {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.option_type() == Ipv6OptionTypes::JumboPayload,
+            anyhow!("not jumbo payload.")
+        );
+
+        ensure!(
+            option.data_len() == 4,
+            anyhow!("invalid jumbo payload option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new jumbo payload option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<JumboPayload<'a>> {
+        let fields = mbuf.write_data(offset, &JumboPayloadFields::default())?;
+        Ok(JumboPayload {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// Jumbo payload option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct JumboPayloadFields {
+    option_type: u8,
+    data_len: u8,
+    payload_len: u32be,
+}
+
+impl Default for JumboPayloadFields {
+    fn default() -> JumboPayloadFields {
+        JumboPayloadFields {
+            option_type: Ipv6OptionTypes::JumboPayload.0,
+            data_len: 4,
+            payload_len: u32be::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_of_options() {
+        assert_eq!(2, OptionsHeader::size_of());
+        assert_eq!(4, RouterAlertFields::size_of());
+        assert_eq!(6, JumboPayloadFields::size_of());
+    }
+
+    #[test]
+    fn option_type_bits() {
+        assert_eq!(0, Ipv6OptionTypes::RouterAlert.action());
+        assert!(!Ipv6OptionTypes::RouterAlert.may_change());
+        assert_eq!(3, Ipv6OptionTypes::JumboPayload.action());
+        assert!(!Ipv6OptionTypes::JumboPayload.may_change());
+        assert!(Ipv6OptionType(0x3e).may_change());
+        assert!(Ipv6OptionTypes::Pad1.is_padding());
+        assert!(Ipv6OptionTypes::PadN.is_padding());
+    }
+
+    #[test]
+    fn option_type_to_string() {
+        assert_eq!("Router Alert", Ipv6OptionTypes::RouterAlert.to_string());
+        assert_eq!("0x1e", Ipv6OptionType(0x1e).to_string());
+    }
+}
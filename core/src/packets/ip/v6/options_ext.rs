@@ -0,0 +1,347 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use super::{ImmutableIpv6OptionsIterator, Ipv6OptionTypes, Ipv6Options, OptionsHeader};
+use crate::ensure;
+use crate::packets::checksum::PseudoHeader;
+use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::ip::{IpPacket, ProtocolNumber};
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::ptr::NonNull;
+
+/// The kind of an IPv6 options extension header.
+///
+/// The hop-by-hop and the destination options headers share the same
+/// format and differ only in the protocol number identifying them. This
+/// trait should not be implemented outside of the crate.
+pub trait OptionsExtensionKind {
+    /// The protocol number identifying the header.
+    const PROTOCOL: ProtocolNumber;
+
+    /// The name of the header.
+    const NAME: &'static str;
+}
+
+/// IPv6 options extension packet based on [IETF RFC 8200].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |  Next Header  |  Hdr Ext Len  |                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+                               +
+/// |                                                               |
+/// .                                                               .
+/// .                            Options                            .
+/// .                                                               .
+/// |                                                               |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Next Header*:      8-bit selector.  Identifies the type of header
+///                       immediately following the options header.
+///
+/// - *Hdr Ext Len*:      8-bit unsigned integer.  Length of the options
+///                       header in 8-octet units, not including the first
+///                       8 octets.
+///
+/// - *Options*:          Variable-length field, of length such that the
+///                       complete options header is an integer multiple
+///                       of 8 octets long. Contains one or more
+///                       TLV-encoded options.
+///
+/// The kind `K` distinguishes the [`HopByHop`] and the
+/// [`DestinationOptions`] headers.
+///
+/// [IETF RFC 8200]: https://tools.ietf.org/html/rfc8200#section-4.2
+/// [`HopByHop`]: crate::packets::ip::v6::HopByHop
+/// [`DestinationOptions`]: crate::packets::ip::v6::DestinationOptions
+pub struct OptionsExtension<E: Ipv6Packet, K: OptionsExtensionKind> {
+    envelope: E,
+    header: NonNull<OptionsHeader>,
+    offset: usize,
+    _kind: PhantomData<K>,
+}
+
+impl<E: Ipv6Packet, K: OptionsExtensionKind> OptionsExtension<E, K> {
+    #[inline]
+    fn header(&self) -> &OptionsHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut OptionsHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the length of the header in 8-octet units, not including
+    /// the first 8 octets.
+    #[inline]
+    pub fn hdr_ext_len(&self) -> u8 {
+        self.header().hdr_ext_len
+    }
+
+    /// Returns an iterator to read the options in the header.
+    ///
+    /// The options cannot be modified. To modify the values while iterating
+    /// over the options, use [`options_mut().iter()`] instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let hop_by_hop = ipv6.parse::<HopByHop<Ipv6>>()?;
+    /// let mut iter = hop_by_hop.options_iter();
+    ///
+    /// while let Some(option) = iter.next()? {
+    ///     println!("{:?}", option);
+    /// }
+    /// ```
+    ///
+    /// [`options_mut().iter()`]: Ipv6Options::iter
+    #[inline]
+    pub fn options_iter(&self) -> ImmutableIpv6OptionsIterator<'_> {
+        ImmutableIpv6OptionsIterator::new(
+            self.mbuf(),
+            self.offset + OptionsHeader::size_of(),
+            self.payload_offset(),
+        )
+    }
+
+    /// Returns a mutable reference to the options in the header.
+    #[inline]
+    pub fn options_mut(&mut self) -> Ipv6Options<'_> {
+        let header = self.header;
+        let offset = self.offset;
+        Ipv6Options::new(self.mbuf_mut(), header, offset)
+    }
+}
+
+impl<E: Ipv6Packet, K: OptionsExtensionKind> fmt::Debug for OptionsExtension<E, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(K::NAME)
+            .field("next_header", &format!("{}", self.next_header()))
+            .field("hdr_ext_len", &self.hdr_ext_len())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: Ipv6Packet, K: OptionsExtensionKind> Packet for OptionsExtension<E, K> {
+    /// The preceding type for an IPv6 hop-by-hop packet should be an IPv6
+    /// packet. The preceding type for an IPv6 destination options packet
+    /// can be either an IPv6 packet or any possible IPv6 extension packets.
+    type Envelope = E;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        self.header().len()
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        OptionsExtension {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+            _kind: PhantomData,
+        }
+    }
+
+    /// Parses the envelope's payload as an IPv6 options packet of kind `K`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`next_header`] is not set to the protocol
+    /// number of `K`. Returns an error if the payload does not have
+    /// sufficient data for the options extension header.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    fn try_parse(
+        envelope: Self::Envelope,
+        _internal: Internal,
+    ) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.next_header() == K::PROTOCOL,
+            (anyhow!("not an IPv6 {} packet.", K::NAME), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data::<OptionsHeader>(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        let len = unsafe { header.as_ref() }.len();
+        if let Err(e) = mbuf.read_data_slice::<u8>(offset, len) {
+            return Err((e, envelope));
+        }
+
+        Ok(OptionsExtension {
+            envelope,
+            header,
+            offset,
+            _kind: PhantomData,
+        })
+    }
+
+    /// Prepends an IPv6 options packet of kind `K` to the beginning of the
+    /// envelope's payload.
+    ///
+    /// The header is 8 octets long, with the options filled with padding.
+    /// [`next_header`] is set to the value of the `next_header` field of the
+    /// envelope, and the envelope is set to the protocol number of `K`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        // the smallest header is 8 octets, 6 of which are a padN option.
+        mbuf.extend(offset, 8)?;
+        let header = mbuf.write_data(offset, &OptionsHeader::default())?;
+        let _ = mbuf.write_data_slice(
+            offset + OptionsHeader::size_of(),
+            &[Ipv6OptionTypes::PadN.0, 4, 0, 0, 0, 0],
+        )?;
+
+        let mut packet = OptionsExtension {
+            envelope,
+            header,
+            offset,
+            _kind: PhantomData,
+        };
+
+        packet.set_next_header(packet.envelope().next_header());
+        packet.envelope_mut().set_next_header(K::PROTOCOL);
+
+        Ok(packet)
+    }
+
+    /// Removes IPv6 options packet from the message buffer.
+    ///
+    /// The envelope's [`next_header`] field is set to the value of the
+    /// `next_header` field on the options packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have sufficient data to
+    /// remove.
+    ///
+    /// [`next_header`]: Ipv6Packet::next_header
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let offset = self.offset();
+        let len = self.header_len();
+        let next_header = self.next_header();
+        self.mbuf_mut().shrink(offset, len)?;
+        self.envelope_mut().set_next_header(next_header);
+        Ok(self.envelope)
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+impl<E: Ipv6Packet, K: OptionsExtensionKind> IpPacket for OptionsExtension<E, K> {
+    #[inline]
+    fn next_protocol(&self) -> ProtocolNumber {
+        self.next_header()
+    }
+
+    #[inline]
+    fn set_next_protocol(&mut self, proto: ProtocolNumber) {
+        self.set_next_header(proto);
+    }
+
+    #[inline]
+    fn src(&self) -> IpAddr {
+        self.envelope().src()
+    }
+
+    #[inline]
+    fn set_src(&mut self, src: IpAddr) -> Result<()> {
+        self.envelope_mut().set_src(src)
+    }
+
+    #[inline]
+    fn dst(&self) -> IpAddr {
+        self.envelope().dst()
+    }
+
+    #[inline]
+    fn set_dst(&mut self, dst: IpAddr) -> Result<()> {
+        self.envelope_mut().set_dst(dst)
+    }
+
+    #[inline]
+    fn pseudo_header(&self, packet_len: u16, protocol: ProtocolNumber) -> PseudoHeader {
+        self.envelope().pseudo_header(packet_len, protocol)
+    }
+
+    #[inline]
+    fn truncate(&mut self, mtu: usize) -> Result<()> {
+        self.envelope_mut().truncate(mtu)
+    }
+
+    #[inline]
+    fn set_length_from_buffer(&mut self) {
+        self.envelope_mut().set_length_from_buffer()
+    }
+}
+
+impl<E: Ipv6Packet, K: OptionsExtensionKind> Ipv6Packet for OptionsExtension<E, K> {
+    #[inline]
+    fn next_header(&self) -> ProtocolNumber {
+        ProtocolNumber::new(self.header().next_header)
+    }
+
+    #[inline]
+    fn set_next_header(&mut self, next_header: ProtocolNumber) {
+        self.header_mut().next_header = next_header.0;
+    }
+}
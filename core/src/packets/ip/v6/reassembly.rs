@@ -0,0 +1,376 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+use crate::ensure;
+use crate::packets::icmp::v6::{Icmpv6Packet, TimeExceeded, TimeExceededCodes};
+use crate::packets::ip::reassembly::{FragmentEntry, Reassembly};
+use crate::packets::ip::v6::{ExtensionChain, Fragment, Ipv6, Ipv6Packet};
+use crate::packets::ip::{ProtocolNumbers, ReassemblyConfig};
+use crate::packets::Packet;
+use anyhow::{anyhow, Result};
+use std::net::Ipv6Addr;
+use std::time::Instant;
+
+/// Maximum payload length of an IPv6 packet without a jumbo payload.
+const IPV6_MAX_PAYLOAD_LEN: usize = u16::MAX as usize;
+
+/// The fields that identify the fragments of the same packet.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Ipv6FragmentKey {
+    src: Ipv6Addr,
+    dst: Ipv6Addr,
+    identification: u32,
+}
+
+/// IPv6 fragment reassembler based on [IETF RFC 8200].
+///
+/// Fragments are grouped by source, destination and identification. Once
+/// all the fragments of a packet are received, the fragment header is
+/// removed from the first fragment and the fragmentable parts of the rest
+/// are copied in order into its buffer, so the reassembled packet keeps the
+/// first fragment's unfragmentable part.
+///
+/// An atomic fragment, with both a zero offset and the `M` flag unset, is
+/// reassembled on its own without affecting any other fragments, as
+/// required by [IETF RFC 6946]. An exact duplicate of a received fragment
+/// is dropped. Any other overlap causes the whole packet to be discarded,
+/// as required by [IETF RFC 8200]. Incomplete packets are evicted when they
+/// time out, or oldest first when the configured memory limits are
+/// reached. [IETF RFC 8200] recommends a timeout of 60 seconds.
+///
+/// The reassembler is not thread-safe. Each lcore should have its own
+/// instance, for example as a pipeline thread local.
+///
+/// [IETF RFC 8200]: https://tools.ietf.org/html/rfc8200#section-4.5
+/// [IETF RFC 6946]: https://tools.ietf.org/html/rfc6946
+#[derive(Debug)]
+pub struct Ipv6Reassembler {
+    inner: Reassembly<Ipv6FragmentKey, Fragment<ExtensionChain>>,
+}
+
+impl Ipv6Reassembler {
+    /// Creates a new reassembler.
+    pub fn new(config: ReassemblyConfig) -> Self {
+        Ipv6Reassembler {
+            inner: Reassembly::new(config),
+        }
+    }
+
+    /// Returns the number of incomplete packets.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns whether there are no incomplete packets.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of octets held by the incomplete packets.
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.inner.bytes()
+    }
+
+    /// Adds the packet to the reassembler.
+    ///
+    /// Returns the reassembled packet if the packet completes a fragmented
+    /// packet, or `None` if more fragments are needed. A packet without a
+    /// fragment header is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the fragment is malformed, if it overlaps with
+    /// another fragment of the same packet, or if the reassembled packet
+    /// does not fit in the first fragment's buffer. The packet is discarded
+    /// in the last two cases.
+    pub fn reassemble(&mut self, packet: Ipv6) -> Result<Option<Ipv6>> {
+        let chain =
+            ExtensionChain::parse_until(packet, |walk| walk.protocol == ProtocolNumbers::Ipv6Frag)
+                .map_err(|(e, _)| e)?;
+
+        if chain.next_header() != ProtocolNumbers::Ipv6Frag {
+            return Ok(Some(chain.deparse()));
+        }
+
+        let mut fragment = chain
+            .parse::<Fragment<ExtensionChain>>()
+            .map_err(|(e, _)| e)?;
+        trim(fragment.envelope_mut().envelope_mut())?;
+
+        if fragment.fragment_offset() == 0 && !fragment.more_fragments() {
+            let mut ipv6 = fragment.remove()?.deparse();
+            ipv6.reconcile();
+            return Ok(Some(ipv6));
+        }
+
+        let ipv6 = fragment.envelope().envelope();
+        let end = ipv6.payload_offset() + ipv6.payload_length() as usize;
+        ensure!(
+            end >= fragment.payload_offset(),
+            anyhow!("invalid IPv6 payload length {}.", ipv6.payload_length())
+        );
+
+        let start = fragment.fragment_offset() as usize * 8;
+        let len = end - fragment.payload_offset();
+        let unfragmentable_len = fragment.offset() - ipv6.payload_offset();
+        ensure!(
+            unfragmentable_len + start + len <= IPV6_MAX_PAYLOAD_LEN,
+            anyhow!("fragment extends beyond the maximum IPv6 payload length.")
+        );
+
+        let key = Ipv6FragmentKey {
+            src: ipv6.src(),
+            dst: ipv6.dst(),
+            identification: fragment.identification(),
+        };
+        let last = !fragment.more_fragments();
+        let fragment = FragmentEntry {
+            start,
+            end: start + len,
+            bytes: fragment.mbuf().data_len(),
+            packet: fragment,
+        };
+
+        match self.inner.insert(key, fragment, last, Instant::now())? {
+            Some(fragments) => assemble(fragments).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Evicts the incomplete packets that have timed out.
+    ///
+    /// For each evicted packet whose first fragment was received, returns
+    /// an ICMPv6 time exceeded message with code
+    /// [`FragmentReassemblyTimeExceeded`] addressed to the source of the
+    /// packet. The message is built in the first fragment's buffer, which
+    /// becomes the invoking packet.
+    ///
+    /// [`FragmentReassemblyTimeExceeded`]: TimeExceededCodes::FragmentReassemblyTimeExceeded
+    pub fn evict_expired(&mut self) -> Vec<TimeExceeded<Ipv6>> {
+        self.evict_expired_at(Instant::now())
+    }
+
+    /// Evicts the incomplete packets that have timed out at `now`, and
+    /// returns the time exceeded messages for them.
+    pub(crate) fn evict_expired_at(&mut self, now: Instant) -> Vec<TimeExceeded<Ipv6>> {
+        self.inner
+            .evict_expired(now)
+            .into_iter()
+            .filter_map(|queue| {
+                let first = queue.into_fragments().into_iter().next()?;
+                if first.start == 0 {
+                    time_exceeded(first.packet).ok()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Removes the link layer padding, if any, following the IPv6 packet.
+fn trim(ipv6: &mut Ipv6) -> Result<()> {
+    let len = ipv6.payload_offset() + ipv6.payload_length() as usize;
+    ensure!(
+        len <= ipv6.mbuf().data_len(),
+        anyhow!("invalid IPv6 payload length {}.", ipv6.payload_length())
+    );
+    if len < ipv6.mbuf().data_len() {
+        ipv6.mbuf_mut().truncate(len)?;
+    }
+    Ok(())
+}
+
+/// Removes the fragment header from the first fragment and copies the
+/// fragment payloads, sorted by offset, into its buffer.
+fn assemble(fragments: Vec<FragmentEntry<Fragment<ExtensionChain>>>) -> Result<Ipv6> {
+    let mut fragments = fragments.into_iter();
+    let first = match fragments.next() {
+        Some(first) => first.packet,
+        None => return Err(anyhow!("no fragments to reassemble.")),
+    };
+
+    let mut chain = first.remove()?;
+
+    for fragment in fragments {
+        let len = fragment.end - fragment.start;
+        let mut data = vec![0u8; len];
+        fragment
+            .packet
+            .mbuf()
+            .copy_to_slice(fragment.packet.payload_offset(), &mut data)?;
+        let offset = chain.mbuf().data_len();
+        chain.mbuf_mut().extend(offset, len)?;
+        chain.mbuf_mut().copy_from_slice(offset, &data)?;
+    }
+
+    let mut ipv6 = chain.deparse();
+    ensure!(
+        ipv6.payload_len() <= IPV6_MAX_PAYLOAD_LEN,
+        anyhow!(
+            "reassembled IPv6 payload exceeds {} octets.",
+            IPV6_MAX_PAYLOAD_LEN
+        )
+    );
+    ipv6.reconcile();
+    Ok(ipv6)
+}
+
+/// Builds a fragment reassembly time exceeded message for the first
+/// fragment.
+fn time_exceeded(fragment: Fragment<ExtensionChain>) -> Result<TimeExceeded<Ipv6>> {
+    let ipv6 = fragment.deparse().deparse();
+    let src = ipv6.src();
+    let dst = ipv6.dst();
+    let mut ethernet = ipv6.deparse();
+    ethernet.swap_addresses();
+
+    let mut ipv6 = ethernet.push::<Ipv6>()?;
+    ipv6.set_src(dst);
+    ipv6.set_dst(src);
+
+    let mut exceeded = ipv6.push::<TimeExceeded<Ipv6>>()?;
+    exceeded.set_code(TimeExceededCodes::FragmentReassemblyTimeExceeded);
+    exceeded.reconcile_all();
+    Ok(exceeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::{IPV6_FRAGMENT_PACKET, IPV6_OPTIONS_PACKET, IPV6_TCP_PACKET};
+    use crate::testils::{bytes_of, ipv6_with_payload};
+    use std::time::Duration;
+
+    #[capsule::test]
+    fn reassemble_unfragmented_packet() {
+        let mut reassembler = Ipv6Reassembler::new(ReassemblyConfig::default());
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 100);
+
+        assert!(reassembler.reassemble(ipv6).unwrap().is_some());
+        assert!(reassembler.is_empty());
+    }
+
+    #[capsule::test]
+    fn reassemble_atomic_fragment() {
+        let mut reassembler = Ipv6Reassembler::new(ReassemblyConfig::default());
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 100);
+        let expected = bytes_of(&ipv6);
+
+        let mut fragment = ipv6.push::<Fragment<Ipv6>>().unwrap();
+        fragment.reconcile_all();
+
+        let ipv6 = reassembler.reassemble(fragment.deparse()).unwrap().unwrap();
+        assert!(reassembler.is_empty());
+        assert_eq!(expected, bytes_of(&ipv6));
+    }
+
+    #[capsule::test]
+    fn reassemble_out_of_order_fragments() {
+        let mut reassembler = Ipv6Reassembler::new(ReassemblyConfig::default());
+        let ipv6 = ipv6_with_payload(&IPV6_OPTIONS_PACKET, 1500);
+        let expected = bytes_of(&ipv6);
+
+        let mut fragments = ipv6.fragment(1280, 42).unwrap();
+        assert_eq!(2, fragments.len());
+
+        let first = fragments.remove(0);
+        assert!(reassembler
+            .reassemble(fragments.remove(0))
+            .unwrap()
+            .is_none());
+        assert_eq!(1, reassembler.len());
+
+        let ipv6 = reassembler.reassemble(first).unwrap().unwrap();
+        assert!(reassembler.is_empty());
+        assert_eq!(0, reassembler.bytes());
+        assert_eq!(expected, bytes_of(&ipv6));
+    }
+
+    #[capsule::test]
+    fn reassemble_with_overlapping_fragment() {
+        let mut reassembler = Ipv6Reassembler::new(ReassemblyConfig::default());
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 1500);
+
+        let mut fragments = ipv6.fragment(1280, 42).unwrap();
+        let last = fragments.pop().unwrap();
+        let first = fragments.pop().unwrap();
+        assert!(reassembler.reassemble(first).unwrap().is_none());
+
+        let mut last = last.parse::<Fragment<Ipv6>>().unwrap();
+        last.set_fragment_offset(last.fragment_offset() - 1);
+        assert!(reassembler.reassemble(last.deparse()).is_err());
+        assert!(reassembler.is_empty());
+    }
+
+    #[capsule::test]
+    fn time_exceeded_for_expired_fragments() {
+        let config = ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let mut reassembler = Ipv6Reassembler::new(config);
+        let ipv6 = ipv6_with_payload(&IPV6_TCP_PACKET, 1500);
+        let src = ipv6.src();
+        let dst = ipv6.dst();
+
+        let mut fragments = ipv6.fragment(1280, 42).unwrap();
+        let _ = fragments.pop();
+        let first = fragments.pop().unwrap();
+        assert!(reassembler.reassemble(first).unwrap().is_none());
+
+        let now = Instant::now();
+        assert!(reassembler.evict_expired_at(now).is_empty());
+
+        let mut messages = reassembler.evict_expired_at(now + Duration::from_secs(30));
+        assert!(reassembler.is_empty());
+        assert_eq!(1, messages.len());
+
+        let exceeded = messages.pop().unwrap();
+        assert_eq!(
+            TimeExceededCodes::FragmentReassemblyTimeExceeded,
+            exceeded.code()
+        );
+        assert_eq!(dst, exceeded.envelope().src());
+        assert_eq!(src, exceeded.envelope().dst());
+    }
+
+    #[capsule::test]
+    fn no_time_exceeded_without_first_fragment() {
+        let config = ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let mut reassembler = Ipv6Reassembler::new(config);
+
+        let packet = Mbuf::from_bytes(&IPV6_FRAGMENT_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        assert!(reassembler.reassemble(ipv6).unwrap().is_none());
+        assert_eq!(1, reassembler.len());
+
+        let later = Instant::now() + Duration::from_secs(30);
+        assert!(reassembler.evict_expired_at(later).is_empty());
+        assert!(reassembler.is_empty());
+    }
+}
@@ -482,6 +482,11 @@ impl<E: Ipv6Packet> IpPacket for SegmentRouting<E> {
     fn truncate(&mut self, mtu: usize) -> Result<()> {
         self.envelope_mut().truncate(mtu)
     }
+
+    #[inline]
+    fn set_length_from_buffer(&mut self) {
+        self.envelope_mut().set_length_from_buffer()
+    }
 }
 
 impl<E: Ipv6Packet> Ipv6Packet for SegmentRouting<E> {
@@ -17,13 +17,17 @@
 */
 
 use crate::ffi::dpdk::{self, MbufPtr};
-use crate::packets::{Internal, Packet, SizeOf};
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+use crate::packets::{checksum, Internal, Packet, SizeOf};
 use crate::runtime::Mempool;
 use crate::{ensure, trace};
 use anyhow::{Result, Error};
 use capsule_ffi as cffi;
+use once_cell::sync::OnceCell;
 use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
+use std::borrow::Cow;
 use std::cmp::min;
 use std::ptr::{self, NonNull};
 use std::slice;
@@ -43,17 +47,166 @@ pub(crate) enum BufferError {
     /// The struct size exceeds the remaining buffer length.
     #[error("Struct size {0} exceeds the remaining buffer length {1}.")]
     OutOfBuffer(usize, usize),
+
+    /// The data spans more than one segment.
+    #[error("Data at offset {0} of length {1} spans more than one segment.")]
+    NotContiguous(usize, usize),
+}
+
+/// The maximum amount of data a chained buffer can hold.
+const MAX_DATA_LEN: usize = 128 * 1024;
+
+/// Transmit offloads performed by the NIC.
+///
+/// Used both for the offloads enabled on a port and the offloads requested
+/// for a message buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TxOffloads {
+    /// IPv4 header checksum.
+    pub ipv4_checksum: bool,
+    /// TCP and UDP checksums.
+    pub l4_checksum: bool,
+    /// TCP segmentation.
+    pub tcp_segmentation: bool,
+}
+
+/// The NIC's verification of a checksum on receive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxChecksum {
+    /// No information about the checksum.
+    Unknown,
+    /// The checksum is valid.
+    Good,
+    /// The checksum is invalid.
+    Bad,
+    /// The checksum is not correct in the packet, but the data is valid.
+    None,
+}
+
+impl RxChecksum {
+    fn from_flags(flags: u64, mask: u32, good: u32, bad: u32) -> Self {
+        let flags = flags as u32 & mask;
+        if flags == mask {
+            RxChecksum::None
+        } else if flags == good {
+            RxChecksum::Good
+        } else if flags == bad {
+            RxChecksum::Bad
+        } else {
+            RxChecksum::Unknown
+        }
+    }
 }
 
+/// The packet type recognized by the NIC on receive.
+///
+/// Only the outermost headers are described.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketType(u32);
+
+impl PacketType {
+    /// Returns the raw DPDK packet type.
+    #[inline]
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns whether the packet type is unknown.
+    #[inline]
+    pub fn is_unknown(&self) -> bool {
+        self.0 == cffi::RTE_PTYPE_UNKNOWN
+    }
+
+    /// Returns whether the L3 header is IPv4.
+    #[inline]
+    pub fn is_ipv4(&self) -> bool {
+        matches!(
+            self.0 & cffi::RTE_PTYPE_L3_MASK,
+            cffi::RTE_PTYPE_L3_IPV4
+                | cffi::RTE_PTYPE_L3_IPV4_EXT
+                | cffi::RTE_PTYPE_L3_IPV4_EXT_UNKNOWN
+        )
+    }
+
+    /// Returns whether the L3 header is IPv6.
+    #[inline]
+    pub fn is_ipv6(&self) -> bool {
+        matches!(
+            self.0 & cffi::RTE_PTYPE_L3_MASK,
+            cffi::RTE_PTYPE_L3_IPV6
+                | cffi::RTE_PTYPE_L3_IPV6_EXT
+                | cffi::RTE_PTYPE_L3_IPV6_EXT_UNKNOWN
+        )
+    }
+
+    /// Returns whether the L4 header is TCP.
+    #[inline]
+    pub fn is_tcp(&self) -> bool {
+        self.0 & cffi::RTE_PTYPE_L4_MASK == cffi::RTE_PTYPE_L4_TCP
+    }
+
+    /// Returns whether the L4 header is UDP.
+    #[inline]
+    pub fn is_udp(&self) -> bool {
+        self.0 & cffi::RTE_PTYPE_L4_MASK == cffi::RTE_PTYPE_L4_UDP
+    }
+
+    /// Returns whether the L4 header is SCTP.
+    #[inline]
+    pub fn is_sctp(&self) -> bool {
+        self.0 & cffi::RTE_PTYPE_L4_MASK == cffi::RTE_PTYPE_L4_SCTP
+    }
+
+    /// Returns whether the L4 header is ICMP.
+    #[inline]
+    pub fn is_icmp(&self) -> bool {
+        self.0 & cffi::RTE_PTYPE_L4_MASK == cffi::RTE_PTYPE_L4_ICMP
+    }
+
+    /// Returns whether the packet is an IP fragment.
+    #[inline]
+    pub fn is_fragment(&self) -> bool {
+        self.0 & cffi::RTE_PTYPE_L4_MASK == cffi::RTE_PTYPE_L4_FRAG
+    }
+
+    /// Returns whether the packet is tunneled.
+    #[inline]
+    pub fn is_tunnel(&self) -> bool {
+        self.0 & cffi::RTE_PTYPE_TUNNEL_MASK != 0
+    }
+}
+
+impl fmt::Debug for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PacketType({:#x})", self.0)
+    }
+}
+
+/// TX flags reconcile defers to the NIC.
+const TX_OFFLOAD_FLAGS: u64 = cffi::PKT_TX_IP_CKSUM
+    | cffi::PKT_TX_L4_MASK
+    | cffi::PKT_TX_TCP_SEG
+    | cffi::PKT_TX_IPV4
+    | cffi::PKT_TX_IPV6;
+
+/// Offset of the checksum field in the TCP header.
+const TCP_CHECKSUM_OFFSET: usize = 16;
+
+/// Offset of the checksum field in the UDP header.
+const UDP_CHECKSUM_OFFSET: usize = 6;
+
 /// A DPDK message buffer that carries the network packet.
 ///
 /// # Remarks
 ///
-/// Multi-segment Mbuf is not supported. It's the application's responsibilty
-/// to ensure that the ethernet device's MTU is less than the default size
-/// of a single Mbuf segment (`RTE_MBUF_DEFAULT_DATAROOM` = 2048).
+/// A buffer may be made of multiple chained segments, for example when the
+/// ethernet device's MTU is larger than the default size of a single Mbuf
+/// segment (`RTE_MBUF_DEFAULT_DATAROOM` = 2048). Headers are read in place
+/// and must not span segments. Use `Mbuf::linearize` to copy the data into
+/// the first segment when necessary.
 pub struct Mbuf {
     inner: MbufInner,
+    tx_request: TxOffloads,
 }
 
 /// Original or Clone tagged variant of DPDK message buffer.
@@ -101,6 +254,7 @@ impl Mbuf {
 
         Ok(Mbuf {
             inner: MbufInner::Original(ptr.into()),
+            tx_request: TxOffloads::default(),
         })
     }
 
@@ -110,12 +264,13 @@ impl Mbuf {
     ///
     /// Returns `DpdkError` if the allocation of mbuf fails.
     /// Returns `BufferError::NotResized` if the byte array is larger than
-    /// the maximum mbuf size.
+    /// the maximum mbuf size. A byte array larger than a single segment is
+    /// stored in chained segments.
     #[inline]
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
         let mut mbuf = Mbuf::new()?;
         mbuf.extend(0, data.len())?;
-        mbuf.write_data_slice(0, data)?;
+        mbuf.copy_from_slice(0, data)?;
         Ok(mbuf)
     }
 
@@ -124,6 +279,7 @@ impl Mbuf {
     pub(crate) fn from_easyptr(ptr: MbufPtr) -> Self {
         Mbuf {
             inner: MbufInner::Original(ptr.into()),
+            tx_request: TxOffloads::default(),
         }
     }
 
@@ -139,54 +295,222 @@ impl Mbuf {
         unsafe { self.inner.ptr_mut().as_mut() }
     }
 
-    /// Returns amount of data stored in the buffer.
+    /// Returns amount of data stored in the buffer, across all the
+    /// segments.
     #[inline]
     pub fn data_len(&self) -> usize {
+        self.raw().pkt_len as usize
+    }
+
+    /// Returns amount of data stored in the first segment.
+    #[inline]
+    pub fn first_segment_len(&self) -> usize {
         self.raw().data_len as usize
     }
 
-    /// Returns the raw pointer from the offset
+    /// Returns the number of segments.
     #[inline]
-    pub(crate) unsafe fn data_address(&self, offset: usize) -> *mut u8 {
-        let raw = self.raw();
-        (raw.buf_addr as *mut u8).offset(raw.data_off as isize + offset as isize)
+    pub fn num_segments(&self) -> usize {
+        self.raw().nb_segs as usize
     }
 
-    /// Returns the amount of bytes left in the buffer.
+    /// Returns whether the buffer is made of more than one segment.
     #[inline]
-    fn tailroom(&self) -> usize {
-        let raw = self.raw();
-        (raw.buf_len - raw.data_off - raw.data_len) as usize
+    pub fn is_chained(&self) -> bool {
+        self.num_segments() > 1
+    }
+
+    /// Returns an iterator over the data of each segment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// for segment in mbuf.segments() {
+    ///     println!("{} bytes", segment.len());
+    /// }
+    /// ```
+    #[inline]
+    pub fn segments(&self) -> Segments<'_> {
+        Segments {
+            next: self.inner.ptr().as_ptr(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the segment holding the data at offset, and the offset
+    /// within that segment. An offset at the end of the data resolves to
+    /// the end of the last segment.
+    #[inline]
+    fn segment_at(&self, offset: usize) -> (*mut cffi::rte_mbuf, usize) {
+        let mut seg = self.inner.ptr().as_ptr();
+        let mut offset = offset;
+        unsafe {
+            while offset >= (*seg).data_len as usize && !(*seg).next.is_null() {
+                offset -= (*seg).data_len as usize;
+                seg = (*seg).next;
+            }
+        }
+        (seg, offset)
+    }
+
+    /// Returns the raw pointer to `len` bytes of contiguous data at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufferError::NotContiguous` if the data spans more than
+    /// one segment.
+    #[inline]
+    fn contiguous(&self, offset: usize, len: usize) -> Result<*mut u8> {
+        let (seg, seg_offset) = self.segment_at(offset);
+        unsafe {
+            ensure!(
+                seg_offset + len <= (*seg).data_len as usize,
+                BufferError::NotContiguous(offset, len)
+            );
+            Ok(seg_data(seg, seg_offset))
+        }
+    }
+
+    /// Allocates enough new segments to hold `len` bytes.
+    ///
+    /// The segments are not linked. If an allocation fails, the segments
+    /// already allocated are freed.
+    #[inline]
+    fn alloc_segments(len: usize) -> Result<Vec<*mut cffi::rte_mbuf>> {
+        let mut mp = Mempool::thread_local_ptr()?;
+        let mut segs = Vec::new();
+        let mut room = 0;
+
+        while room < len {
+            match dpdk::pktmbuf_alloc(&mut mp) {
+                Ok(seg) => {
+                    let seg: NonNull<cffi::rte_mbuf> = seg.into();
+                    room += unsafe { seg_tailroom(seg.as_ptr()) };
+                    segs.push(seg.as_ptr());
+                }
+                Err(err) => {
+                    for seg in segs {
+                        unsafe { dpdk::pktmbuf_free(NonNull::new_unchecked(seg).into()) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(segs)
+    }
+
+    /// Links `new_seg` after `seg`.
+    #[inline]
+    fn link_segment(&mut self, seg: *mut cffi::rte_mbuf, new_seg: *mut cffi::rte_mbuf) {
+        unsafe {
+            (*new_seg).next = (*seg).next;
+            (*seg).next = new_seg;
+        }
+        self.raw_mut().nb_segs += 1;
+    }
+
+    /// Unlinks the segment following `prev` and frees it.
+    #[inline]
+    fn remove_segment(&mut self, prev: *mut cffi::rte_mbuf) {
+        unsafe {
+            let seg = (*prev).next;
+            (*prev).next = (*seg).next;
+            (*seg).next = ptr::null_mut();
+            (*seg).nb_segs = 1;
+            dpdk::pktmbuf_free(NonNull::new_unchecked(seg).into());
+        }
+        self.raw_mut().nb_segs -= 1;
     }
 
     /// Extends the data buffer at offset by `len` bytes.
     ///
-    /// If the offset is not at the end of the data. The data after the
-    /// offset is shifted down to make room.
+    /// If the offset is not at the end of the data, the data after the
+    /// offset is shifted down to make room. If the segment holding the
+    /// offset does not have enough free space, the data after the offset
+    /// is moved into newly allocated segments chained after it. The
+    /// extended room is kept in a single segment whenever it fits.
     ///
     /// # Errors
     ///
     /// Returns `BufferError::NotResized` if the offset is out of bound,
-    /// or the length to extend is either 0 or exceeds the available free
-    /// buffer capacity.
+    /// or the length to extend is either 0 or exceeds the maximum buffer
+    /// capacity.
+    /// Returns `DpdkError` if the new segments cannot be allocated. The
+    /// data buffer is left unchanged.
     #[inline]
     pub fn extend(&mut self, offset: usize, len: usize) -> Result<()> {
         ensure!(len > 0, BufferError::NotResized);
         ensure!(offset <= self.data_len(), BufferError::NotResized);
-        ensure!(len < self.tailroom(), BufferError::NotResized);
+        ensure!(
+            self.data_len() + len <= MAX_DATA_LEN,
+            BufferError::NotResized
+        );
 
-        // shifts down data to make room
-        let to_copy = self.data_len() - offset;
-        if to_copy > 0 {
-            unsafe {
-                let src = self.data_address(offset);
-                let dst = self.data_address(offset + len);
-                ptr::copy(src, dst, to_copy);
+        let (seg, seg_offset) = self.segment_at(offset);
+        unsafe {
+            let seg_len = (*seg).data_len as usize;
+            let to_copy = seg_len - seg_offset;
+
+            if len <= seg_tailroom(seg) {
+                // shifts down data to make room
+                if to_copy > 0 {
+                    let src = seg_data(seg, seg_offset);
+                    let dst = seg_data(seg, seg_offset + len);
+                    ptr::copy(src, dst, to_copy);
+                }
+                (*seg).data_len += len as u16;
+            } else {
+                // starts the extended room in a new segment if it does not
+                // fit in the current one but fits in a whole segment.
+                let room = seg_tailroom(seg) + to_copy;
+                let dataroom = ((*seg).buf_len - (*seg).data_off) as usize;
+                let skip = room < len && len <= dataroom;
+                let overflow = if skip {
+                    len + to_copy
+                } else {
+                    len + to_copy - room
+                };
+
+                // allocates the new segments before touching the data, so
+                // a failed allocation leaves the buffer as it was.
+                let mut last = seg;
+                for new_seg in Mbuf::alloc_segments(overflow)? {
+                    self.link_segment(last, new_seg);
+                    last = new_seg;
+                }
+
+                // moves the data after offset out of the way, then refills
+                // the segment and the new segments after it.
+                let tail = slice::from_raw_parts(seg_data(seg, seg_offset), to_copy).to_vec();
+                (*seg).data_len = seg_offset as u16;
+
+                let mut cur = if skip { (*seg).next } else { seg };
+                let mut gap = len;
+                let mut data = &tail[..];
+                while gap > 0 || !data.is_empty() {
+                    if seg_tailroom(cur) == 0 {
+                        cur = (*cur).next;
+                    }
+
+                    let room = seg_tailroom(cur);
+                    let n = min(gap, room);
+                    (*cur).data_len += n as u16;
+                    gap -= n;
+
+                    let room = room - n;
+                    let m = min(data.len(), room);
+                    if m > 0 {
+                        let dst = seg_data(cur, (*cur).data_len as usize);
+                        ptr::copy_nonoverlapping(data.as_ptr(), dst, m);
+                        (*cur).data_len += m as u16;
+                        data = &data[m..];
+                    }
+                }
             }
         }
 
         // do some record keeping
-        self.raw_mut().data_len += len as u16;
         self.raw_mut().pkt_len += len as u32;
 
         Ok(())
@@ -194,7 +518,8 @@ impl Mbuf {
 
     /// Shrinks the data buffer at offset by `len` bytes.
     ///
-    /// The data at offset is shifted up.
+    /// The data at offset is shifted up. Segments left empty, other than
+    /// the first, are freed.
     ///
     /// # Errors
     ///
@@ -205,18 +530,44 @@ impl Mbuf {
         ensure!(len > 0, BufferError::NotResized);
         ensure!(offset + len <= self.data_len(), BufferError::NotResized);
 
-        // shifts up data to fill the room
-        let to_copy = self.data_len() - offset - len;
-        if to_copy > 0 {
-            unsafe {
-                let src = self.data_address(offset + len);
-                let dst = self.data_address(offset);
-                ptr::copy(src, dst, to_copy);
+        let mut prev: *mut cffi::rte_mbuf = ptr::null_mut();
+        let mut seg = self.inner.ptr().as_ptr();
+        let mut offset = offset;
+        let mut remaining = len;
+
+        unsafe {
+            while remaining > 0 {
+                let seg_len = (*seg).data_len as usize;
+                if offset >= seg_len {
+                    offset -= seg_len;
+                    prev = seg;
+                    seg = (*seg).next;
+                    continue;
+                }
+
+                // shifts up data to fill the room
+                let n = min(remaining, seg_len - offset);
+                let to_copy = seg_len - offset - n;
+                if to_copy > 0 {
+                    let src = seg_data(seg, offset + n);
+                    let dst = seg_data(seg, offset);
+                    ptr::copy(src, dst, to_copy);
+                }
+                (*seg).data_len -= n as u16;
+                remaining -= n;
+                offset = 0;
+
+                if (*seg).data_len == 0 && !prev.is_null() {
+                    seg = (*seg).next;
+                    self.remove_segment(prev);
+                } else {
+                    prev = seg;
+                    seg = (*seg).next;
+                }
             }
         }
 
         // do some record keeping
-        self.raw_mut().data_len -= len as u16;
         self.raw_mut().pkt_len -= len as u32;
 
         Ok(())
@@ -247,6 +598,8 @@ impl Mbuf {
 
     /// Truncates the data buffer to len.
     ///
+    /// The segments after the new end of the data are freed.
+    ///
     /// # Errors
     ///
     /// Returns `BufferError::NotResized` if the target length exceeds the
@@ -255,12 +608,62 @@ impl Mbuf {
     pub fn truncate(&mut self, to_len: usize) -> Result<()> {
         ensure!(to_len < self.data_len(), BufferError::NotResized);
 
-        self.raw_mut().data_len = to_len as u16;
+        let mut seg = self.inner.ptr().as_ptr();
+        let mut len = to_len;
+        unsafe {
+            while len > (*seg).data_len as usize {
+                len -= (*seg).data_len as usize;
+                seg = (*seg).next;
+            }
+            (*seg).data_len = len as u16;
+
+            while !(*seg).next.is_null() {
+                self.remove_segment(seg);
+            }
+        }
+
         self.raw_mut().pkt_len = to_len as u32;
 
         Ok(())
     }
 
+    /// Copies the segments' data into the first segment and frees the
+    /// other segments.
+    ///
+    /// Headers that span segments must be linearized before they can be
+    /// read.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufferError::NotResized` if the first segment does not
+    /// have enough free space for all the data.
+    #[inline]
+    pub fn linearize(&mut self) -> Result<()> {
+        if !self.is_chained() {
+            return Ok(());
+        }
+
+        let head = self.inner.ptr().as_ptr();
+        let extra = self.data_len() - self.first_segment_len();
+        unsafe {
+            ensure!(extra <= seg_tailroom(head), BufferError::NotResized);
+
+            while !(*head).next.is_null() {
+                let seg = (*head).next;
+                let len = (*seg).data_len as usize;
+                ptr::copy_nonoverlapping(
+                    seg_data(seg, 0),
+                    seg_data(head, (*head).data_len as usize),
+                    len,
+                );
+                (*head).data_len += len as u16;
+                self.remove_segment(head);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Reads the data at offset as `T` and returns it as a raw pointer.
     ///
     /// # Errors
@@ -268,6 +671,8 @@ impl Mbuf {
     /// Returns `BufferError::BadOffset` if the offset is out of bound.
     /// Returns `BufferError::OutOfBuffer` if the size of `T` exceeds the
     /// size of the data stored at offset.
+    /// Returns `BufferError::NotContiguous` if `T` spans more than one
+    /// segment.
     #[inline]
     pub fn read_data<T: SizeOf>(&self, offset: usize) -> Result<NonNull<T>> {
         ensure!(
@@ -279,10 +684,8 @@ impl Mbuf {
             BufferError::OutOfBuffer(T::size_of(), self.data_len() - offset)
         );
 
-        unsafe {
-            let item = self.data_address(offset) as *mut T;
-            Ok(NonNull::new_unchecked(item))
-        }
+        let item = self.contiguous(offset, T::size_of())? as *mut T;
+        unsafe { Ok(NonNull::new_unchecked(item)) }
     }
 
     /// Writes `T` to the data buffer at offset and returns the new copy
@@ -296,6 +699,8 @@ impl Mbuf {
     ///
     /// Returns `BufferError::OutOfBuffer` if the size of `T` exceeds the
     /// available buffer capacity starting at offset.
+    /// Returns `BufferError::NotContiguous` if `T` spans more than one
+    /// segment.
     #[inline]
     pub fn write_data<T: SizeOf>(&mut self, offset: usize, item: &T) -> Result<NonNull<T>> {
         ensure!(
@@ -305,7 +710,7 @@ impl Mbuf {
 
         unsafe {
             let src = item as *const T;
-            let dst = self.data_address(offset) as *mut T;
+            let dst = self.contiguous(offset, T::size_of())? as *mut T;
             ptr::copy_nonoverlapping(src, dst, 1);
         }
 
@@ -320,6 +725,8 @@ impl Mbuf {
     /// Returns `BufferError::BadOffset` if the offset is out of bound.
     /// Returns `BufferError::OutOfBuffer` if the size of `T` slice exceeds
     /// the size of the data stored at offset.
+    /// Returns `BufferError::NotContiguous` if the slice spans more than one
+    /// segment.
     #[inline]
     pub fn read_data_slice<T: SizeOf>(&self, offset: usize, count: usize) -> Result<NonNull<[T]>> {
         ensure!(
@@ -332,7 +739,7 @@ impl Mbuf {
         );
 
         unsafe {
-            let item0 = self.data_address(offset) as *mut T;
+            let item0 = self.contiguous(offset, T::size_of() * count)? as *mut T;
             let slice = slice::from_raw_parts_mut(item0, count) as *mut [T];
             Ok(NonNull::new_unchecked(slice))
         }
@@ -349,6 +756,8 @@ impl Mbuf {
     ///
     /// Returns `BufferError::OutOfBuffer` if the size of `T` slice exceeds
     /// the available buffer capacity starting at offset.
+    /// Returns `BufferError::NotContiguous` if the slice spans more than one
+    /// segment.
     #[inline]
     pub fn write_data_slice<T: SizeOf>(
         &mut self,
@@ -364,13 +773,394 @@ impl Mbuf {
 
         unsafe {
             let src = slice.as_ptr();
-            let dst = self.data_address(offset) as *mut T;
+            let dst = self.contiguous(offset, T::size_of() * count)? as *mut T;
             ptr::copy_nonoverlapping(src, dst, count);
         }
 
         self.read_data_slice(offset, count)
     }
 
+    /// Copies the data at offset into `dst`, across segments.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufferError::OutOfBuffer` if the length of `dst` exceeds
+    /// the size of the data stored at offset.
+    #[inline]
+    pub fn copy_to_slice(&self, offset: usize, dst: &mut [u8]) -> Result<()> {
+        ensure!(
+            offset + dst.len() <= self.data_len(),
+            BufferError::OutOfBuffer(dst.len(), self.data_len().saturating_sub(offset))
+        );
+
+        let (mut seg, mut seg_offset) = self.segment_at(offset);
+        let mut copied = 0;
+        unsafe {
+            while copied < dst.len() {
+                let n = min(dst.len() - copied, (*seg).data_len as usize - seg_offset);
+                ptr::copy_nonoverlapping(seg_data(seg, seg_offset), dst[copied..].as_mut_ptr(), n);
+                copied += n;
+                seg = (*seg).next;
+                seg_offset = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies `src` to the data buffer at offset, across segments.
+    ///
+    /// Before writing to the data buffer, should call `Mbuf::extend` first
+    /// to make sure enough space is allocated for the write and data is not
+    /// being overridden.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufferError::OutOfBuffer` if the length of `src` exceeds
+    /// the available buffer capacity starting at offset.
+    #[inline]
+    pub fn copy_from_slice(&mut self, offset: usize, src: &[u8]) -> Result<()> {
+        ensure!(
+            offset + src.len() <= self.data_len(),
+            BufferError::OutOfBuffer(src.len(), self.data_len().saturating_sub(offset))
+        );
+
+        let (mut seg, mut seg_offset) = self.segment_at(offset);
+        let mut copied = 0;
+        unsafe {
+            while copied < src.len() {
+                let n = min(src.len() - copied, (*seg).data_len as usize - seg_offset);
+                ptr::copy_nonoverlapping(src[copied..].as_ptr(), seg_data(seg, seg_offset), n);
+                copied += n;
+                seg = (*seg).next;
+                seg_offset = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the ID of the port the packet was received on.
+    #[inline]
+    pub fn port(&self) -> u16 {
+        self.raw().port
+    }
+
+    /// Returns the RSS hash computed by the NIC, if available.
+    ///
+    /// The hash can be used to shard flows without hashing the headers in
+    /// software.
+    #[inline]
+    pub fn rss_hash(&self) -> Option<u32> {
+        let raw = self.raw();
+        if raw.ol_flags & cffi::PKT_RX_RSS_HASH as u64 != 0 {
+            Some(unsafe { raw.__bindgen_anon_4.hash.rss })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the packet type recognized by the NIC.
+    #[inline]
+    pub fn packet_type(&self) -> PacketType {
+        PacketType(unsafe { self.raw().__bindgen_anon_3.packet_type })
+    }
+
+    /// Returns the VLAN tag control information, if available.
+    ///
+    /// If the tag is stripped by the NIC, this is the only place it can be
+    /// found. See `Mbuf::vlan_stripped`.
+    #[inline]
+    pub fn vlan_tci(&self) -> Option<u16> {
+        let raw = self.raw();
+        if raw.ol_flags & cffi::PKT_RX_VLAN as u64 != 0 {
+            Some(raw.vlan_tci)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the VLAN tag is stripped from the packet data.
+    #[inline]
+    pub fn vlan_stripped(&self) -> bool {
+        self.raw().ol_flags & cffi::PKT_RX_VLAN_STRIPPED as u64 != 0
+    }
+
+    /// Returns the receive timestamp set by the NIC, if available.
+    ///
+    /// The timestamp is carried in the dynamic mbuf field registered for
+    /// it. The unit and time reference are device specific.
+    #[inline]
+    pub fn timestamp(&self) -> Option<u64> {
+        let (offset, flag) = rx_timestamp_field()?;
+        let raw = self.raw();
+        if raw.ol_flags & flag != 0 {
+            unsafe { Some(*rx_timestamp_ptr(raw, offset)) }
+        } else {
+            None
+        }
+    }
+
+    /// Returns the NIC's verification of the IPv4 header checksum.
+    #[inline]
+    pub fn rx_ip_checksum(&self) -> RxChecksum {
+        RxChecksum::from_flags(
+            self.raw().ol_flags,
+            cffi::PKT_RX_IP_CKSUM_MASK,
+            cffi::PKT_RX_IP_CKSUM_GOOD,
+            cffi::PKT_RX_IP_CKSUM_BAD,
+        )
+    }
+
+    /// Returns the NIC's verification of the L4 checksum.
+    #[inline]
+    pub fn rx_l4_checksum(&self) -> RxChecksum {
+        RxChecksum::from_flags(
+            self.raw().ol_flags,
+            cffi::PKT_RX_L4_CKSUM_MASK,
+            cffi::PKT_RX_L4_CKSUM_GOOD,
+            cffi::PKT_RX_L4_CKSUM_BAD,
+        )
+    }
+
+    /// Returns the transmit offloads requested for the packet.
+    #[inline]
+    pub fn tx_offloads(&self) -> TxOffloads {
+        self.tx_request
+    }
+
+    /// Requests the NIC to perform the offloads when the packet is sent.
+    ///
+    /// The offloads must be enabled on the transmitting port, see
+    /// `Port::tx_offloads`. The checksums are deferred to the NIC and the
+    /// header lengths are set when the packet is reconciled with
+    /// `Packet::reconcile_all`, the offload flags are only set on the buffer
+    /// for the headers that claim them. Only the innermost IP and transport
+    /// headers are offloaded. The checksums of the outer headers of a
+    /// tunneled packet are computed in software, accounting for the inner
+    /// checksums the NIC fills in. TCP segmentation also requires the
+    /// segment size to be set with `Mbuf::set_tso_segment_size`, and is not
+    /// performed on tunneled packets.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut mbuf = Mbuf::new()?;
+    /// mbuf.set_tx_offloads(port.tx_offloads());
+    /// ```
+    #[inline]
+    pub fn set_tx_offloads(&mut self, offloads: TxOffloads) {
+        self.tx_request = offloads;
+
+        let raw = self.raw_mut();
+        raw.ol_flags &= !TX_OFFLOAD_FLAGS;
+        let lens = unsafe { &mut raw.__bindgen_anon_6.__bindgen_anon_1 };
+        lens.set_l2_len(0);
+        lens.set_l3_len(0);
+        lens.set_l4_len(0);
+    }
+
+    /// Sets the maximum segment size for TCP segmentation offload.
+    #[inline]
+    pub fn set_tso_segment_size(&mut self, mss: u16) {
+        unsafe {
+            self.raw_mut()
+                .__bindgen_anon_6
+                .__bindgen_anon_1
+                .set_tso_segsz(mss as u64);
+        }
+    }
+
+    /// Returns whether the TCP segmentation is requested and the segment
+    /// size is set.
+    #[inline]
+    pub(crate) fn tcp_segmentation(&self) -> bool {
+        self.tx_request.tcp_segmentation
+            && unsafe { self.raw().__bindgen_anon_6.__bindgen_anon_1.tso_segsz() } > 0
+    }
+
+    /// Defers the checksum of the transport header at offset to the NIC
+    /// if requested.
+    ///
+    /// The innermost transport header is reconciled first and claims the
+    /// offload. Returns whether the checksum is deferred.
+    #[inline]
+    pub(crate) fn defer_l4_checksum(
+        &mut self,
+        offset: usize,
+        header_len: usize,
+        protocol: ProtocolNumber,
+    ) -> bool {
+        let flag = match protocol {
+            ProtocolNumbers::Tcp => cffi::PKT_TX_TCP_CKSUM,
+            ProtocolNumbers::Udp => cffi::PKT_TX_UDP_CKSUM,
+            _ => return false,
+        };
+
+        if !self.tx_request.l4_checksum && !self.tx_request.tcp_segmentation {
+            return false;
+        }
+        let segmentation = flag == cffi::PKT_TX_TCP_CKSUM && self.tcp_segmentation();
+
+        let raw = self.raw_mut();
+        let lens = unsafe { &mut raw.__bindgen_anon_6.__bindgen_anon_1 };
+        if lens.l4_len() != 0 && (lens.l2_len() + lens.l3_len()) as usize != offset {
+            // claimed by an inner header.
+            return false;
+        }
+
+        raw.ol_flags = (raw.ol_flags & !(cffi::PKT_TX_L4_MASK | cffi::PKT_TX_TCP_SEG)) | flag;
+        if segmentation {
+            raw.ol_flags |= cffi::PKT_TX_TCP_SEG;
+        }
+        lens.set_l4_len(header_len as u64);
+        true
+    }
+
+    /// Sets the lengths the NIC needs to locate the IP header at offset if
+    /// any offload is requested.
+    ///
+    /// The innermost IP header is reconciled first and claims the offloads.
+    /// Returns whether the IPv4 header checksum is deferred.
+    #[inline]
+    pub(crate) fn defer_l3_offloads(&mut self, offset: usize, header_len: usize, ipv4: bool) -> bool {
+        let ipv4_checksum = ipv4 && self.tx_request.ipv4_checksum;
+
+        let raw = self.raw_mut();
+        if !ipv4_checksum && raw.ol_flags & cffi::PKT_TX_L4_MASK == 0 {
+            return false;
+        }
+
+        let lens = unsafe { &mut raw.__bindgen_anon_6.__bindgen_anon_1 };
+        if lens.l3_len() != 0 && lens.l2_len() as usize != offset {
+            // claimed by an inner header.
+            self.refuse_inner_segmentation();
+            return false;
+        }
+
+        lens.set_l2_len(offset as u64);
+        lens.set_l3_len(header_len as u64);
+
+        raw.ol_flags &= !(cffi::PKT_TX_IPV4 | cffi::PKT_TX_IPV6 | cffi::PKT_TX_IP_CKSUM);
+        if ipv4_checksum {
+            raw.ol_flags |= cffi::PKT_TX_IP_CKSUM;
+        }
+        raw.ol_flags |= if ipv4 {
+            cffi::PKT_TX_IPV4
+        } else {
+            cffi::PKT_TX_IPV6
+        };
+        ipv4_checksum
+    }
+
+    /// Turns off the TCP segmentation claimed by an inner header.
+    ///
+    /// The NIC would segment a tunneled packet without updating the outer
+    /// headers. Instead it only computes the inner TCP checksum, so the
+    /// segment length left out of the pseudo-header sum is added back.
+    fn refuse_inner_segmentation(&mut self) {
+        if self.raw().ol_flags & cffi::PKT_TX_TCP_SEG == 0 {
+            return;
+        }
+        self.raw_mut().ol_flags &= !cffi::PKT_TX_TCP_SEG;
+
+        let (l4_offset, _) = self.inner_offsets();
+        let offset = l4_offset + TCP_CHECKSUM_OFFSET;
+        let len = (self.data_len() - l4_offset) as u16;
+        if let Ok(sum) = self.read_u16(offset) {
+            let sum = !checksum::compute(sum, &len.to_be_bytes());
+            let _ = self.write_data_slice(offset, &sum.to_be_bytes());
+        }
+    }
+
+    /// Reads the big-endian `u16` at offset.
+    #[inline]
+    fn read_u16(&self, offset: usize) -> Result<u16> {
+        let data = self.read_data_slice::<u8>(offset, 2)?;
+        let data = unsafe { data.as_ref() };
+        Ok(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    /// Returns the offsets of the innermost transport and IP headers that
+    /// claimed the offloads, or `0` if none did.
+    #[inline]
+    fn inner_offsets(&self) -> (usize, usize) {
+        let lens = unsafe { &self.raw().__bindgen_anon_6.__bindgen_anon_1 };
+        if lens.l3_len() == 0 {
+            (0, 0)
+        } else {
+            let l3_offset = lens.l2_len() as usize;
+            (l3_offset + lens.l3_len() as usize, l3_offset)
+        }
+    }
+
+    /// Computes the Internet checksum of the data at offset, as the data
+    /// will be once the NIC fills in the checksums deferred by the inner
+    /// headers.
+    ///
+    /// The outer headers of a tunneled packet are reconciled after the
+    /// inner headers claimed the offloads. Data carrying a valid checksum
+    /// sums to zero, so the inner IPv4 header is left out of the sum, and
+    /// the inner transport segment adds up to the complement of the
+    /// pseudo-header sum in its checksum field.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufferError::OutOfBuffer` if the length exceeds the size
+    /// of the data stored at offset.
+    #[inline]
+    pub(crate) fn checksum(&mut self, initial: u16, offset: usize, len: usize) -> Result<u16> {
+        let (l4_offset, l3_offset) = self.inner_offsets();
+        if l3_offset <= offset || l4_offset > offset + len {
+            let data = self.data_slice(offset, len)?;
+            return Ok(checksum::compute(initial, &data));
+        }
+
+        self.refuse_inner_segmentation();
+        let flags = self.raw().ol_flags;
+
+        let data = self.data_slice(offset, l3_offset - offset)?;
+        let mut sum = !checksum::compute(initial, &data);
+
+        if flags & cffi::PKT_TX_IP_CKSUM == 0 {
+            let data = self.data_slice(l3_offset, l4_offset - l3_offset)?;
+            sum = !checksum::compute(sum, &data);
+        }
+
+        let field = match flags & cffi::PKT_TX_L4_MASK {
+            cffi::PKT_TX_TCP_CKSUM => Some(TCP_CHECKSUM_OFFSET),
+            cffi::PKT_TX_UDP_CKSUM => Some(UDP_CHECKSUM_OFFSET),
+            _ => None,
+        };
+        if let Some(field) = field {
+            let pseudo_header_sum = self.read_u16(l4_offset + field)?;
+            sum = !checksum::compute(sum, &(!pseudo_header_sum).to_be_bytes());
+        } else {
+            let data = self.data_slice(l4_offset, offset + len - l4_offset)?;
+            sum = !checksum::compute(sum, &data);
+        }
+
+        Ok(!sum)
+    }
+
+    /// Returns the data at offset, copied out of the segments only if it
+    /// spans more than one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufferError::OutOfBuffer` if the length exceeds the size
+    /// of the data stored at offset.
+    #[inline]
+    pub(crate) fn data_slice(&self, offset: usize, len: usize) -> Result<Cow<'_, [u8]>> {
+        if let Ok(data) = self.read_data_slice::<u8>(offset, len) {
+            Ok(Cow::Borrowed(unsafe { &*data.as_ptr() }))
+        } else {
+            let mut data = vec![0u8; len];
+            self.copy_to_slice(offset, &mut data)?;
+            Ok(Cow::Owned(data))
+        }
+    }
+
     /// Acquires the underlying raw struct pointer.
     ///
     /// The `Mbuf` is consumed. It is the caller's the responsibility to
@@ -412,6 +1202,59 @@ impl Mbuf {
     }
 }
 
+/// Returns the offset of the dynamic RX timestamp field and the flag that
+/// marks it valid. The field is registered on first use, and is `None` if
+/// the registration fails.
+#[inline]
+fn rx_timestamp_field() -> Option<(usize, u64)> {
+    static FIELD: OnceCell<Option<(usize, u64)>> = OnceCell::new();
+    *FIELD.get_or_init(|| dpdk::mbuf_dyn_rx_timestamp_register().ok())
+}
+
+/// Returns the raw pointer to the dynamic RX timestamp field.
+#[inline]
+unsafe fn rx_timestamp_ptr(raw: *const cffi::rte_mbuf, offset: usize) -> *mut u64 {
+    (raw as *mut u8).add(offset) as *mut u64
+}
+
+/// Returns the raw pointer to the data at offset within a segment.
+#[inline]
+unsafe fn seg_data(seg: *mut cffi::rte_mbuf, offset: usize) -> *mut u8 {
+    ((*seg).buf_addr as *mut u8).add((*seg).data_off as usize + offset)
+}
+
+/// Returns the amount of bytes left in a segment.
+#[inline]
+unsafe fn seg_tailroom(seg: *mut cffi::rte_mbuf) -> usize {
+    ((*seg).buf_len - (*seg).data_off - (*seg).data_len) as usize
+}
+
+/// An iterator over the data of each segment of a `Mbuf`.
+#[derive(Debug)]
+pub struct Segments<'a> {
+    next: *mut cffi::rte_mbuf,
+    _phantom: PhantomData<&'a Mbuf>,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            None
+        } else {
+            unsafe {
+                let seg = self.next;
+                self.next = (*seg).next;
+                Some(slice::from_raw_parts(
+                    seg_data(seg, 0),
+                    (*seg).data_len as usize,
+                ))
+            }
+        }
+    }
+}
+
 impl fmt::Debug for Mbuf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let raw = self.raw();
@@ -420,6 +1263,10 @@ impl fmt::Debug for Mbuf {
             .field("pkt_len", &raw.pkt_len)
             .field("data_len", &raw.data_len)
             .field("data_off", &raw.data_off)
+            .field("nb_segs", &raw.nb_segs)
+            .field("port", &raw.port)
+            .field("packet_type", &self.packet_type())
+            .field("ol_flags", &format_args!("{:#x}", raw.ol_flags))
             .finish()
     }
 }
@@ -481,6 +1328,7 @@ impl Packet for Mbuf {
         let raw = self.inner.ptr();
         Mbuf {
             inner: MbufInner::Clone(*raw),
+            tx_request: self.tx_request,
         }
     }
 
@@ -683,6 +1531,206 @@ mod tests {
         assert!(mbuf.read_data_slice::<u8>(10, 16).is_err());
     }
 
+    fn jumbo_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[capsule::test]
+    fn new_from_bytes_chained() {
+        let data = jumbo_bytes(9000);
+        let mbuf = Mbuf::from_bytes(&data).unwrap();
+
+        assert!(mbuf.is_chained());
+        assert_eq!(9000, mbuf.data_len());
+        assert!(mbuf.first_segment_len() < 9000);
+        assert_eq!(mbuf.num_segments(), mbuf.segments().count());
+        assert_eq!(9000, mbuf.segments().map(|s| s.len()).sum::<usize>());
+
+        let mut copy = vec![0u8; 9000];
+        assert!(mbuf.copy_to_slice(0, &mut copy).is_ok());
+        assert_eq!(data, copy);
+    }
+
+    #[capsule::test]
+    fn extend_data_buffer_chained() {
+        let data = jumbo_bytes(4000);
+        let mut mbuf = Mbuf::from_bytes(&data).unwrap();
+        let segments = mbuf.num_segments();
+
+        // extends the middle of the first segment
+        assert!(mbuf.extend(14, 40).is_ok());
+        assert_eq!(4040, mbuf.data_len());
+        assert!(mbuf.num_segments() >= segments);
+
+        // the extended room is contiguous
+        assert!(mbuf.write_data_slice(14, &[0xffu8; 40]).is_ok());
+
+        let mut copy = vec![0u8; 4040];
+        assert!(mbuf.copy_to_slice(0, &mut copy).is_ok());
+        assert_eq!(data[..14], copy[..14]);
+        assert_eq!([0xffu8; 40], copy[14..54]);
+        assert_eq!(data[14..], copy[54..]);
+    }
+
+    #[capsule::test]
+    fn extend_data_buffer_exhausted_mempool() {
+        let data = jumbo_bytes(100);
+        let mut mbuf = Mbuf::from_bytes(&data).unwrap();
+
+        // holds on to the rest of the mempool
+        let mut held = vec![];
+        while let Ok(other) = Mbuf::new() {
+            held.push(other);
+        }
+
+        // the buffer is left unchanged
+        assert!(mbuf.extend(14, 4000).is_err());
+        assert_eq!(100, mbuf.data_len());
+        assert_eq!(1, mbuf.num_segments());
+
+        let mut copy = vec![0u8; 100];
+        assert!(mbuf.copy_to_slice(0, &mut copy).is_ok());
+        assert_eq!(data, copy);
+
+        // succeeds once segments are available again
+        held.clear();
+        assert!(mbuf.extend(14, 4000).is_ok());
+        assert_eq!(4100, mbuf.data_len());
+    }
+
+    #[capsule::test]
+    fn shrink_data_buffer_chained() {
+        let data = jumbo_bytes(9000);
+        let mut mbuf = Mbuf::from_bytes(&data).unwrap();
+        let segments = mbuf.num_segments();
+
+        // shrinks across the segment boundaries
+        assert!(mbuf.shrink(100, 8000).is_ok());
+        assert_eq!(1000, mbuf.data_len());
+        assert!(mbuf.num_segments() < segments);
+        assert_eq!(mbuf.num_segments(), mbuf.segments().count());
+
+        let mut copy = vec![0u8; 1000];
+        assert!(mbuf.copy_to_slice(0, &mut copy).is_ok());
+        assert_eq!(data[..100], copy[..100]);
+        assert_eq!(data[8100..], copy[100..]);
+    }
+
+    #[capsule::test]
+    fn truncate_data_buffer_chained() {
+        let data = jumbo_bytes(9000);
+        let mut mbuf = Mbuf::from_bytes(&data).unwrap();
+
+        assert!(mbuf.truncate(100).is_ok());
+        assert_eq!(100, mbuf.data_len());
+        assert_eq!(1, mbuf.num_segments());
+        assert_eq!(100, mbuf.first_segment_len());
+
+        let slice = mbuf.read_data_slice::<u8>(0, 100).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(data[..100], slice[..]);
+    }
+
+    #[capsule::test]
+    fn read_data_across_segments() {
+        let data = jumbo_bytes(9000);
+        let mbuf = Mbuf::from_bytes(&data).unwrap();
+        let boundary = mbuf.first_segment_len();
+
+        // reads within the second segment
+        let slice = mbuf.read_data_slice::<u8>(boundary, 16).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(data[boundary..boundary + 16], slice[..]);
+
+        // reads spanning the segments should err
+        assert!(mbuf.read_data_slice::<u8>(boundary - 8, 16).is_err());
+        assert!(mbuf.read_data::<[u8; 16]>(boundary - 8).is_err());
+    }
+
+    #[capsule::test]
+    fn linearize_data_buffer() {
+        let data = jumbo_bytes(3000);
+        let mut mbuf = Mbuf::from_bytes(&data).unwrap();
+
+        // frees up enough room in the first segment
+        assert!(mbuf.shrink(0, 1500).is_ok());
+        assert!(mbuf.is_chained());
+
+        assert!(mbuf.linearize().is_ok());
+        assert!(!mbuf.is_chained());
+        assert_eq!(1500, mbuf.first_segment_len());
+
+        let slice = mbuf.read_data_slice::<u8>(0, 1500).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(data[1500..], slice[..]);
+    }
+
+    #[capsule::test]
+    fn linearize_data_buffer_too_much() {
+        let mut mbuf = Mbuf::from_bytes(&jumbo_bytes(9000)).unwrap();
+        assert!(mbuf.linearize().is_err());
+    }
+
+    #[capsule::test]
+    fn rx_metadata() {
+        let mut mbuf = Mbuf::new().unwrap();
+        assert_eq!(None, mbuf.rss_hash());
+        assert_eq!(None, mbuf.vlan_tci());
+        assert_eq!(None, mbuf.timestamp());
+        assert_eq!(RxChecksum::Unknown, mbuf.rx_ip_checksum());
+        assert_eq!(RxChecksum::Unknown, mbuf.rx_l4_checksum());
+
+        let (offset, flag) = rx_timestamp_field().unwrap();
+        let raw = mbuf.raw_mut();
+        raw.ol_flags = (cffi::PKT_RX_RSS_HASH
+            | cffi::PKT_RX_VLAN
+            | cffi::PKT_RX_VLAN_STRIPPED
+            | cffi::PKT_RX_IP_CKSUM_GOOD
+            | cffi::PKT_RX_L4_CKSUM_BAD) as u64
+            | flag;
+        raw.__bindgen_anon_4.hash.rss = 0xdead_beef;
+        raw.__bindgen_anon_3.packet_type = cffi::RTE_PTYPE_L2_ETHER
+            | cffi::RTE_PTYPE_L3_IPV4_EXT_UNKNOWN
+            | cffi::RTE_PTYPE_L4_TCP;
+        raw.vlan_tci = 100;
+        unsafe { *rx_timestamp_ptr(raw, offset) = 42 };
+
+        assert_eq!(Some(0xdead_beef), mbuf.rss_hash());
+        assert_eq!(Some(100), mbuf.vlan_tci());
+        assert!(mbuf.vlan_stripped());
+        assert_eq!(Some(42), mbuf.timestamp());
+        assert_eq!(RxChecksum::Good, mbuf.rx_ip_checksum());
+        assert_eq!(RxChecksum::Bad, mbuf.rx_l4_checksum());
+
+        let ptype = mbuf.packet_type();
+        assert!(ptype.is_ipv4());
+        assert!(!ptype.is_ipv6());
+        assert!(ptype.is_tcp());
+        assert!(!ptype.is_tunnel());
+    }
+
+    #[capsule::test]
+    fn set_tx_offloads() {
+        let mut mbuf = Mbuf::new().unwrap();
+        assert_eq!(TxOffloads::default(), mbuf.tx_offloads());
+
+        let offloads = TxOffloads {
+            ipv4_checksum: true,
+            l4_checksum: true,
+            tcp_segmentation: true,
+        };
+        mbuf.set_tx_offloads(offloads);
+        assert_eq!(offloads, mbuf.tx_offloads());
+
+        // segmentation needs the segment size
+        assert!(!mbuf.tcp_segmentation());
+        mbuf.set_tso_segment_size(1460);
+        assert!(mbuf.tcp_segmentation());
+
+        mbuf.set_tx_offloads(TxOffloads::default());
+        assert_eq!(TxOffloads::default(), mbuf.tx_offloads());
+    }
+
     #[capsule::test]
     fn alloc_bulk() {
         let mbufs = Mbuf::alloc_bulk(8).unwrap();
@@ -692,4 +1740,25 @@ mod tests {
             assert_eq!(0, mbuf.data_len());
         }
     }
+
+    #[capsule::test]
+    fn free_bulk_chained() {
+        fn count_available() -> usize {
+            let mut held = vec![];
+            while let Ok(other) = Mbuf::new() {
+                held.push(other);
+            }
+            held.len()
+        }
+
+        let available = count_available();
+
+        let data = jumbo_bytes(9000);
+        let mbuf = Mbuf::from_bytes(&data).unwrap();
+        assert!(mbuf.num_segments() > 1);
+
+        // the tail segments are freed along with the head
+        Mbuf::free_bulk(vec![mbuf]);
+        assert_eq!(available, count_available());
+    }
 }
@@ -21,18 +21,26 @@
 pub mod arp;
 pub mod checksum;
 pub mod ethernet;
+pub mod geneve;
+pub mod gre;
+pub mod gtpu;
 pub mod icmp;
 pub mod ip;
 mod mbuf;
+pub mod mpls;
+pub mod sctp;
 mod size_of;
 pub mod tcp;
+pub(crate) mod tlv;
 pub mod types;
 pub mod udp;
+pub mod vxlan;
 
 pub use self::mbuf::*;
 pub use self::size_of::*;
 pub use capsule_macros::SizeOf;
 
+use self::ethernet::EtherType;
 use anyhow::{Context, Result, Error};
 use std::fmt;
 use std::marker::PhantomData;
@@ -323,6 +331,22 @@ pub trait Packet {
     }
 }
 
+/// A trait implemented by the packets that can carry a network layer
+/// packet, such as IPv4 or IPv6, as their payload.
+///
+/// The outermost packets are carried by [Ethernet]. Tunneling protocols
+/// implement the trait so the inner packets can be parsed and pushed like
+/// any others.
+///
+/// [Ethernet]: ethernet::Ethernet
+pub trait Datalink: Packet {
+    /// Returns the protocol identifier of the payload.
+    fn protocol_type(&self) -> EtherType;
+
+    /// Sets the protocol identifier of the payload.
+    fn set_protocol_type(&mut self, ether_type: EtherType);
+}
+
 /// Immutable smart pointer to a struct.
 ///
 /// A smart pointer that prevents the struct from being modified. The main
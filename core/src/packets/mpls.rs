@@ -0,0 +1,468 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Multiprotocol Label Switching.
+
+use crate::ensure;
+use crate::packets::ethernet::{EtherType, EtherTypes, Ethernet};
+use crate::packets::ip::{self, DEFAULT_IP_TTL};
+use crate::packets::types::u32be;
+use crate::packets::{Datalink, Internal, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::ptr::NonNull;
+
+const LABEL: u32be = u32be(u32::to_be(0xffff_f000));
+const TC: u32be = u32be(u32::to_be(0x0000_0e00));
+const BOS: u32be = u32be(u32::to_be(0x0000_0100));
+const TTL: u32be = u32be(u32::to_be(0x0000_00ff));
+
+/// Multiprotocol Label Switching label stack based on [IETF RFC 3032].
+///
+/// The packet covers the whole label stack, from the top entry to the
+/// entry with the bottom of stack flag set. Each entry is encoded as
+/// follows.
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                Label                  | TC  |S|       TTL     |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Label*: (20 bits)
+///      Label Value, the value used to forward the packet.
+///
+/// - *TC*: (3 bits)
+///      Traffic Class field for QoS priority and Explicit Congestion
+///      Notification, as defined in [IETF RFC 5462].
+///
+/// - *S*: (1 bit)
+///      Bottom of Stack. This bit is set to one for the last entry in the
+///      label stack, and zero for all other label stack entries.
+///
+/// - *TTL*: (8 bits)
+///      Time to Live.
+///
+/// The accessors read and write the top entry of the stack, which is the
+/// one used to forward the packet. The payload after the bottom of the
+/// stack can be parsed as an [IPv4] or [IPv6] packet enveloped by the
+/// label stack. MPLS does not identify the payload protocol. The protocol
+/// type is inferred from the IP version of the payload instead.
+///
+/// # Example
+///
+/// ```
+/// let mut mpls = ethernet.parse::<Mpls>()?;
+/// mpls.swap_label(200);
+/// let ipv4 = mpls.parse::<Ipv4<Mpls>>()?;
+/// ```
+///
+/// [IETF RFC 3032]: https://tools.ietf.org/html/rfc3032#section-2.1
+/// [IETF RFC 5462]: https://tools.ietf.org/html/rfc5462
+/// [IPv4]: crate::packets::ip::v4::Ipv4
+/// [IPv6]: crate::packets::ip::v6::Ipv6
+pub struct Mpls {
+    envelope: Ethernet,
+    header: NonNull<LabelEntry>,
+    offset: usize,
+    len: usize,
+}
+
+impl Mpls {
+    #[inline]
+    fn header(&self) -> &LabelEntry {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut LabelEntry {
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn set_field(&mut self, mask: u32be, value: u32) {
+        let entry = self.header().entry & !mask;
+        self.header_mut().entry = entry | (u32be::from(value) & mask);
+    }
+
+    /// Returns the label of the top entry.
+    #[inline]
+    pub fn label(&self) -> u32 {
+        u32::from(self.header().entry & LABEL) >> 12
+    }
+
+    /// Returns the traffic class of the top entry.
+    #[inline]
+    pub fn tc(&self) -> u8 {
+        (u32::from(self.header().entry & TC) >> 9) as u8
+    }
+
+    /// Sets the traffic class of the top entry.
+    #[inline]
+    pub fn set_tc(&mut self, tc: u8) {
+        self.set_field(TC, u32::from(tc) << 9);
+    }
+
+    /// Returns a flag indicating whether the top entry is the bottom of
+    /// the stack.
+    #[inline]
+    pub fn bottom_of_stack(&self) -> bool {
+        self.header().entry & BOS != u32be::MIN
+    }
+
+    /// Returns the time to live of the top entry.
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        u32::from(self.header().entry & TTL) as u8
+    }
+
+    /// Sets the time to live of the top entry.
+    #[inline]
+    pub fn set_ttl(&mut self, ttl: u8) {
+        self.set_field(TTL, u32::from(ttl));
+    }
+
+    /// Returns the number of entries in the label stack.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.len / LabelEntry::size_of()
+    }
+
+    /// Replaces the label of the top entry.
+    ///
+    /// The traffic class and the time to live are kept. Only the lower 20
+    /// bits of the label are used.
+    #[inline]
+    pub fn swap_label(&mut self, label: u32) {
+        self.set_field(LABEL, label << 12);
+    }
+
+    /// Pushes a new entry with the label on top of the stack.
+    ///
+    /// The new entry copies the traffic class and the time to live of
+    /// the previous top entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    pub fn push_label(&mut self, label: u32) -> Result<()> {
+        let entry = (self.header().entry & (TC | TTL)) | (u32be::from(label << 12) & LABEL);
+
+        let offset = self.offset;
+        let mbuf = self.mbuf_mut();
+        mbuf.extend(offset, LabelEntry::size_of())?;
+        self.header = mbuf.write_data(offset, &LabelEntry { entry })?;
+        self.len += LabelEntry::size_of();
+        Ok(())
+    }
+
+    /// Pops the top entry of the stack.
+    ///
+    /// The entry below becomes the top entry. The bottom of the stack
+    /// cannot be popped, use [`remove`] instead to remove the label stack
+    /// from the frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the top entry is the bottom of the stack.
+    ///
+    /// [`remove`]: Mpls::remove
+    pub fn pop_label(&mut self) -> Result<()> {
+        ensure!(
+            !self.bottom_of_stack(),
+            anyhow!("cannot pop the bottom of the label stack.")
+        );
+
+        let offset = self.offset;
+        let mbuf = self.mbuf_mut();
+        mbuf.shrink(offset, LabelEntry::size_of())?;
+        self.header = mbuf.read_data(offset)?;
+        self.len -= LabelEntry::size_of();
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Mpls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("mpls")
+            .field("label", &self.label())
+            .field("tc", &self.tc())
+            .field("bottom_of_stack", &self.bottom_of_stack())
+            .field("ttl", &self.ttl())
+            .field("depth", &self.depth())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl Packet for Mpls {
+    /// The preceding type for an MPLS packet must be Ethernet.
+    type Envelope = Ethernet;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the length of the whole label stack.
+    #[inline]
+    fn header_len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Mpls {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+
+    /// Parses the Ethernet's payload as an MPLS label stack.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`ether_type`] is not set to either
+    /// [`EtherTypes::Mpls`] or [`EtherTypes::MplsMulticast`]. Returns an
+    /// error if the payload ends before the bottom of the stack.
+    ///
+    /// [`ether_type`]: Ethernet::ether_type
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.ether_type() == EtherTypes::Mpls
+                || envelope.ether_type() == EtherTypes::MplsMulticast,
+            (anyhow!("not an MPLS packet."), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        // walks the stack down to the bottom entry.
+        let mut end = offset;
+        loop {
+            match mbuf.read_data::<LabelEntry>(end) {
+                Err(e) => return Err((e, envelope)),
+                Ok(entry) => {
+                    end += LabelEntry::size_of();
+                    if unsafe { entry.as_ref() }.entry & BOS != u32be::MIN {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(Mpls {
+            envelope,
+            header,
+            offset,
+            len: end - offset,
+        })
+    }
+
+    /// Prepends a label stack with a single entry to the beginning of the
+    /// Ethernet's payload.
+    ///
+    /// [`ether_type`] is set to [`EtherTypes::Mpls`]. The entry is the
+    /// bottom of the stack, with the label `0` and the default time to
+    /// live.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    ///
+    /// [`ether_type`]: Ethernet::ether_type
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, LabelEntry::size_of())?;
+        let header = mbuf.write_data(
+            offset,
+            &LabelEntry {
+                entry: BOS | u32be::from(u32::from(DEFAULT_IP_TTL)),
+            },
+        )?;
+
+        envelope.set_ether_type(EtherTypes::Mpls);
+
+        Ok(Mpls {
+            envelope,
+            header,
+            offset,
+            len: LabelEntry::size_of(),
+        })
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+
+    /// Removes the label stack from the frame.
+    ///
+    /// [`ether_type`] is set to the protocol type of the payload, inferred
+    /// from its IP version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer fails to shrink.
+    ///
+    /// [`ether_type`]: Ethernet::ether_type
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let ether_type = self.protocol_type();
+        let offset = self.offset;
+        let len = self.len;
+        self.mbuf_mut().shrink(offset, len)?;
+
+        let mut ethernet = self.deparse();
+        ethernet.set_ether_type(ether_type);
+        Ok(ethernet)
+    }
+}
+
+impl Datalink for Mpls {
+    /// Returns the protocol type of the payload, inferred from its IP
+    /// version. Returns `0` if the payload is not an IP packet.
+    #[inline]
+    fn protocol_type(&self) -> EtherType {
+        ip::version_ether_type(self.mbuf(), self.payload_offset())
+    }
+
+    /// The label stack does not carry the protocol type of the payload.
+    /// This is a no-op.
+    #[inline]
+    fn set_protocol_type(&mut self, _ether_type: EtherType) {}
+}
+
+/// MPLS label stack entry.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C)]
+struct LabelEntry {
+    entry: u32be,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::Mbuf;
+    use crate::testils::byte_arrays::{IPV4_UDP_PACKET, MPLS_PACKET};
+
+    #[test]
+    fn size_of_label_entry() {
+        assert_eq!(4, LabelEntry::size_of());
+    }
+
+    #[capsule::test]
+    fn parse_mpls_packet() {
+        let packet = Mbuf::from_bytes(&MPLS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mpls = ethernet.parse::<Mpls>().unwrap();
+
+        assert_eq!(100, mpls.label());
+        assert_eq!(0, mpls.tc());
+        assert!(!mpls.bottom_of_stack());
+        assert_eq!(64, mpls.ttl());
+        assert_eq!(2, mpls.depth());
+        assert_eq!(8, mpls.header_len());
+        assert_eq!(EtherTypes::Ipv4, mpls.protocol_type());
+
+        let ipv4 = mpls.parse::<Ipv4<Mpls>>().unwrap();
+        assert_eq!("192.168.0.1", ipv4.src().to_string());
+        assert_eq!("192.168.0.2", ipv4.dst().to_string());
+    }
+
+    #[capsule::test]
+    fn parse_non_mpls_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+
+        assert!(ethernet.parse::<Mpls>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_pop_and_swap_labels() {
+        let packet = Mbuf::from_bytes(&MPLS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut mpls = ethernet.parse::<Mpls>().unwrap();
+
+        mpls.set_tc(5);
+        mpls.push_label(300).unwrap();
+        assert_eq!(3, mpls.depth());
+        assert_eq!(300, mpls.label());
+        assert_eq!(5, mpls.tc());
+        assert_eq!(64, mpls.ttl());
+        assert!(!mpls.bottom_of_stack());
+
+        mpls.pop_label().unwrap();
+        assert_eq!(100, mpls.label());
+        mpls.swap_label(101);
+        assert_eq!(101, mpls.label());
+        assert_eq!(5, mpls.tc());
+
+        mpls.pop_label().unwrap();
+        assert_eq!(200, mpls.label());
+        assert!(mpls.bottom_of_stack());
+        assert_eq!(1, mpls.depth());
+        assert!(mpls.pop_label().is_err());
+
+        let ethernet = mpls.remove().unwrap();
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert_eq!("192.168.0.1", ipv4.src().to_string());
+    }
+
+    #[capsule::test]
+    fn push_mpls_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let mpls = ethernet.push::<Mpls>().unwrap();
+
+        assert_eq!(EtherTypes::Mpls, mpls.envelope().ether_type());
+        assert_eq!(0, mpls.label());
+        assert!(mpls.bottom_of_stack());
+        assert_eq!(DEFAULT_IP_TTL, mpls.ttl());
+
+        let ipv6 = mpls.push::<Ipv6<Mpls>>().unwrap();
+        assert_eq!(EtherTypes::Ipv6, ipv6.envelope().protocol_type());
+    }
+}
@@ -0,0 +1,647 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Stream Control Transmission Protocol.
+
+use crate::ensure;
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::{Flow, IpPacket, ProtocolNumbers};
+use crate::packets::types::{u16be, u32be};
+use crate::packets::{checksum, Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// The length of the chunk type, flags and length fields.
+const CHUNK_HEADER_LEN: usize = 4;
+
+/// Stream Control Transmission Protocol packet based on [IETF RFC 4960].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Source Port Number        |     Destination Port Number   |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                      Verification Tag                         |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                           Checksum                            |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                            chunks                             |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Source Port Number*: (16 bits)
+///      This is the SCTP sender's port number.
+///
+/// - *Destination Port Number*: (16 bits)
+///      This is the SCTP port number to which this packet is destined.
+///
+/// - *Verification Tag*: (32 bits)
+///      The receiver of this packet uses the Verification Tag to validate
+///      the sender of this SCTP packet.
+///
+/// - *Checksum*: (32 bits)
+///      This field contains the CRC32c checksum of this SCTP packet.
+///      Unlike TCP and UDP, the checksum does not cover a pseudo header.
+///
+/// Each chunk is encoded as follows.
+///
+/// ```
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |   Chunk Type  | Chunk  Flags  |        Chunk Length           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// \                                                               \
+/// /                          Chunk Value                          /
+/// \                                                               \
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Chunk Length*: (16 bits)
+///      The length of the chunk in bytes, including the Chunk Type, Chunk
+///      Flags, Chunk Length and Chunk Value fields, excluding the padding
+///      to a multiple of 4 bytes.
+///
+/// The chunks are the payload of the packet, and can be walked with
+/// [`chunks_iter`].
+///
+/// # Example
+///
+/// ```
+/// let sctp = ipv4.parse::<Sctp<Ipv4>>()?;
+/// let mut iter = sctp.chunks_iter();
+/// while let Some(chunk) = iter.next()? {
+///     println!("{:?}", chunk);
+/// }
+/// ```
+///
+/// [IETF RFC 4960]: https://tools.ietf.org/html/rfc4960#section-3
+/// [`chunks_iter`]: Sctp::chunks_iter
+pub struct Sctp<E: IpPacket> {
+    envelope: E,
+    header: NonNull<SctpHeader>,
+    offset: usize,
+}
+
+impl<E: IpPacket> Sctp<E> {
+    #[inline]
+    fn header(&self) -> &SctpHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut SctpHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the source port.
+    #[inline]
+    pub fn src_port(&self) -> u16 {
+        self.header().src_port.into()
+    }
+
+    /// Sets the source port.
+    #[inline]
+    pub fn set_src_port(&mut self, src_port: u16) {
+        self.header_mut().src_port = src_port.into();
+    }
+
+    /// Returns the destination port.
+    #[inline]
+    pub fn dst_port(&self) -> u16 {
+        self.header().dst_port.into()
+    }
+
+    /// Sets the destination port.
+    #[inline]
+    pub fn set_dst_port(&mut self, dst_port: u16) {
+        self.header_mut().dst_port = dst_port.into();
+    }
+
+    /// Swap source and destination ports.
+    #[inline]
+    pub fn swap_ports(&mut self) {
+        let src_port = self.src_port();
+        self.set_src_port(self.dst_port());
+        self.set_dst_port(src_port);
+    }
+
+    /// Returns the verification tag.
+    #[inline]
+    pub fn verification_tag(&self) -> u32 {
+        self.header().verification_tag.into()
+    }
+
+    /// Sets the verification tag.
+    #[inline]
+    pub fn set_verification_tag(&mut self, verification_tag: u32) {
+        self.header_mut().verification_tag = verification_tag.into();
+    }
+
+    /// Returns the checksum.
+    #[inline]
+    pub fn checksum(&self) -> u32 {
+        // the CRC32c is transmitted in little-endian byte order.
+        u32::from_le(self.header().checksum)
+    }
+
+    #[inline]
+    fn set_checksum(&mut self, checksum: u32) {
+        self.header_mut().checksum = checksum.to_le();
+    }
+
+    #[inline]
+    fn compute_checksum(&mut self) {
+        self.set_checksum(0);
+
+        if let Ok(data) = self.mbuf().data_slice(self.offset, self.len()) {
+            let checksum = checksum::crc32c(&data);
+            drop(data);
+            self.set_checksum(checksum);
+        } else {
+            // we are reading till the end of buffer, should never run out
+            unreachable!()
+        }
+    }
+
+    /// Returns the 5-tuple that uniquely identifies an SCTP association.
+    ///
+    /// Only the primary addresses of a multi-homed association are
+    /// captured by the flow.
+    #[inline]
+    pub fn flow(&self) -> Flow {
+        Flow::new(
+            self.envelope().src(),
+            self.envelope().dst(),
+            self.src_port(),
+            self.dst_port(),
+            ProtocolNumbers::Sctp,
+        )
+    }
+
+    /// Returns an iterator that iterates through the chunks.
+    #[inline]
+    pub fn chunks_iter(&self) -> ChunksIterator<'_> {
+        ChunksIterator {
+            mbuf: self.mbuf(),
+            offset: self.payload_offset(),
+            end: self.offset + self.len(),
+        }
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for Sctp<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("sctp")
+            .field("src_port", &self.src_port())
+            .field("dst_port", &self.dst_port())
+            .field(
+                "verification_tag",
+                &format!("0x{:08x}", self.verification_tag()),
+            )
+            .field("checksum", &format!("0x{:08x}", self.checksum()))
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for Sctp<E> {
+    /// The preceding type for an SCTP packet can be either an IPv4 packet,
+    /// an IPv6 packet, or any IPv6 extension packets.
+    type Envelope = E;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        SctpHeader::size_of()
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Sctp::<E> {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+        }
+    }
+
+    /// Parses the envelope's payload as an SCTP packet.
+    ///
+    /// # Errors
+    ///
+    /// If the envelope is IPv4, returns an error if [`Ipv4::protocol`] is
+    /// not set to [`ProtocolNumbers::Sctp`]. If the envelope is IPv6 or an
+    /// extension header, returns an error if [`next_header`] is not set to
+    /// `ProtocolNumbers::Sctp`. Returns an error if the payload does not
+    /// have sufficient data for the SCTP common header.
+    ///
+    /// [`Ipv4::protocol`]: crate::packets::ip::v4::Ipv4::protocol
+    /// [`ProtocolNumbers::Sctp`]: crate::packets::ip::ProtocolNumbers::Sctp
+    /// [`next_header`]: crate::packets::ip::v6::Ipv6Packet::next_header
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.next_protocol() == ProtocolNumbers::Sctp,
+            (anyhow!("not an SCTP packet."), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        Ok(Sctp {
+            envelope,
+            header,
+            offset,
+        })
+    }
+
+    /// Prepends an SCTP common header to the beginning of the envelope's
+    /// payload.
+    ///
+    /// If the envelope is IPv4, then [`Ipv4::protocol`] is set to
+    /// [`ProtocolNumbers::Sctp`]. If the envelope is IPv6 or an extension
+    /// header, then [`next_header`] is set to `ProtocolNumbers::Sctp`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    ///
+    /// [`Ipv4::protocol`]: crate::packets::ip::v4::Ipv4::protocol
+    /// [`ProtocolNumbers::Sctp`]: crate::packets::ip::ProtocolNumbers::Sctp
+    /// [`next_header`]: crate::packets::ip::v6::Ipv6Packet::next_header
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, SctpHeader::size_of())?;
+        let header = mbuf.write_data(offset, &SctpHeader::default())?;
+
+        envelope.set_next_protocol(ProtocolNumbers::Sctp);
+
+        Ok(Sctp {
+            envelope,
+            header,
+            offset,
+        })
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+
+    /// Reconciles the derivable header fields against the changes made to
+    /// the packet.
+    ///
+    /// * [`checksum`] is computed based on the full packet. The CRC32c
+    /// checksum is always computed in software.
+    ///
+    /// [`checksum`]: Sctp::checksum
+    #[inline]
+    fn reconcile(&mut self) {
+        self.compute_checksum();
+    }
+}
+
+/// A type alias for an IPv4 SCTP packet.
+pub type Sctp4 = Sctp<Ipv4>;
+
+/// A type alias for an IPv6 SCTP packet.
+pub type Sctp6 = Sctp<Ipv6>;
+
+/// SCTP chunk type.
+///
+/// See [`ChunkTypes`] for which are current supported.
+///
+/// [`ChunkTypes`]: crate::packets::sctp::ChunkTypes
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct ChunkType(pub u8);
+
+impl ChunkType {
+    /// Creates a new chunk type.
+    pub fn new(value: u8) -> Self {
+        ChunkType(value)
+    }
+}
+
+/// Supported chunk types.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod ChunkTypes {
+    use super::ChunkType;
+
+    /// Payload Data.
+    pub const Data: ChunkType = ChunkType(0);
+
+    /// Initiation.
+    pub const Init: ChunkType = ChunkType(1);
+
+    /// Initiation Acknowledgement.
+    pub const InitAck: ChunkType = ChunkType(2);
+
+    /// Selective Acknowledgement.
+    pub const Sack: ChunkType = ChunkType(3);
+
+    /// Heartbeat Request.
+    pub const Heartbeat: ChunkType = ChunkType(4);
+
+    /// Heartbeat Acknowledgement.
+    pub const HeartbeatAck: ChunkType = ChunkType(5);
+
+    /// Abort.
+    pub const Abort: ChunkType = ChunkType(6);
+
+    /// Shutdown.
+    pub const Shutdown: ChunkType = ChunkType(7);
+
+    /// Shutdown Acknowledgement.
+    pub const ShutdownAck: ChunkType = ChunkType(8);
+
+    /// Operation Error.
+    pub const Error: ChunkType = ChunkType(9);
+
+    /// State Cookie.
+    pub const CookieEcho: ChunkType = ChunkType(10);
+
+    /// Cookie Acknowledgement.
+    pub const CookieAck: ChunkType = ChunkType(11);
+
+    /// Shutdown Complete.
+    pub const ShutdownComplete: ChunkType = ChunkType(14);
+}
+
+impl fmt::Display for ChunkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                ChunkTypes::Data => "DATA".to_string(),
+                ChunkTypes::Init => "INIT".to_string(),
+                ChunkTypes::InitAck => "INIT ACK".to_string(),
+                ChunkTypes::Sack => "SACK".to_string(),
+                ChunkTypes::Heartbeat => "HEARTBEAT".to_string(),
+                ChunkTypes::HeartbeatAck => "HEARTBEAT ACK".to_string(),
+                ChunkTypes::Abort => "ABORT".to_string(),
+                ChunkTypes::Shutdown => "SHUTDOWN".to_string(),
+                ChunkTypes::ShutdownAck => "SHUTDOWN ACK".to_string(),
+                ChunkTypes::Error => "ERROR".to_string(),
+                ChunkTypes::CookieEcho => "COOKIE ECHO".to_string(),
+                ChunkTypes::CookieAck => "COOKIE ACK".to_string(),
+                ChunkTypes::ShutdownComplete => "SHUTDOWN COMPLETE".to_string(),
+                _ => format!("0x{:02x}", self.0),
+            }
+        )
+    }
+}
+
+/// An SCTP chunk.
+pub struct Chunk<'a> {
+    chunk_type: ChunkType,
+    flags: u8,
+    value: &'a [u8],
+}
+
+impl Chunk<'_> {
+    /// Returns the chunk type.
+    #[inline]
+    pub fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+
+    /// Returns the chunk flags.
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns the chunk value, excluding the chunk header and the
+    /// padding.
+    #[inline]
+    pub fn value(&self) -> &[u8] {
+        self.value
+    }
+}
+
+impl fmt::Debug for Chunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunk")
+            .field("chunk_type", &format!("{}", self.chunk_type()))
+            .field("flags", &format!("0x{:02x}", self.flags()))
+            .field("length", &self.value().len())
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the chunks in the SCTP packet.
+pub struct ChunksIterator<'a> {
+    mbuf: &'a Mbuf,
+    offset: usize,
+    end: usize,
+}
+
+impl<'a> ChunksIterator<'a> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// Returns `Ok(None)` when iteration is finished; returns `Err` when
+    /// a chunk length is less than the chunk header or exceeds the packet.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Chunk<'a>>> {
+        if self.offset >= self.end {
+            return Ok(None);
+        }
+
+        ensure!(
+            self.offset + CHUNK_HEADER_LEN <= self.end,
+            anyhow!("chunk header exceeds the packet length.")
+        );
+        let header = self
+            .mbuf
+            .read_data_slice::<u8>(self.offset, CHUNK_HEADER_LEN)?;
+        let header = unsafe { &*header.as_ptr() };
+
+        let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+        ensure!(
+            len >= CHUNK_HEADER_LEN && self.offset + len <= self.end,
+            anyhow!("invalid chunk length {}.", len)
+        );
+        let value = self.mbuf.read_data_slice::<u8>(
+            self.offset + CHUNK_HEADER_LEN,
+            len - CHUNK_HEADER_LEN,
+        )?;
+
+        // chunks are padded to a multiple of 4 bytes.
+        self.offset += (len + 3) & !3;
+        Ok(Some(Chunk {
+            chunk_type: ChunkType(header[0]),
+            flags: header[1],
+            value: unsafe { &*value.as_ptr() },
+        }))
+    }
+}
+
+impl fmt::Debug for ChunksIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunksIterator")
+            .field("offset", &self.offset)
+            .field("end", &self.end)
+            .finish()
+    }
+}
+
+/// SCTP common header.
+///
+/// The checksum is kept in the byte order it is transmitted in.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C, packed)]
+struct SctpHeader {
+    src_port: u16be,
+    dst_port: u16be,
+    verification_tag: u32be,
+    checksum: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::testils::byte_arrays::{IPV4_UDP_PACKET, SCTP_PACKET};
+
+    #[test]
+    fn size_of_sctp_header() {
+        assert_eq!(12, SctpHeader::size_of());
+    }
+
+    #[test]
+    fn chunk_type_to_string() {
+        assert_eq!("DATA", ChunkTypes::Data.to_string());
+        assert_eq!("INIT ACK", ChunkTypes::InitAck.to_string());
+        assert_eq!("0x40", ChunkType::new(0x40).to_string());
+    }
+
+    #[capsule::test]
+    fn parse_sctp_packet() {
+        let packet = Mbuf::from_bytes(&SCTP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let sctp = ipv4.parse::<Sctp4>().unwrap();
+
+        assert_eq!(2905, sctp.src_port());
+        assert_eq!(2905, sctp.dst_port());
+        assert_eq!(0x1234_5678, sctp.verification_tag());
+        assert_eq!(0x53c6_7213, sctp.checksum());
+
+        let mut iter = sctp.chunks_iter();
+        let chunk = iter.next().unwrap().unwrap();
+        assert_eq!(ChunkTypes::Data, chunk.chunk_type());
+        assert_eq!(0x03, chunk.flags());
+        assert_eq!(17, chunk.value().len());
+        assert_eq!(b"hello", &chunk.value()[12..]);
+        let chunk = iter.next().unwrap().unwrap();
+        assert_eq!(ChunkTypes::Sack, chunk.chunk_type());
+        assert_eq!(12, chunk.value().len());
+        assert!(iter.next().unwrap().is_none());
+
+        let flow = sctp.flow();
+        assert_eq!("10.0.0.1", flow.src_ip().to_string());
+        assert_eq!("10.0.0.2", flow.dst_ip().to_string());
+        assert_eq!(ProtocolNumbers::Sctp, flow.protocol());
+    }
+
+    #[capsule::test]
+    fn parse_non_sctp_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+
+        assert!(ipv4.parse::<Sctp4>().is_err());
+    }
+
+    #[capsule::test]
+    fn compute_checksum() {
+        let packet = Mbuf::from_bytes(&SCTP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut sctp = ipv4.parse::<Sctp4>().unwrap();
+
+        let expected = sctp.checksum();
+        sctp.set_verification_tag(0);
+        sctp.reconcile_all();
+        assert_ne!(expected, sctp.checksum());
+        sctp.set_verification_tag(0x1234_5678);
+        sctp.reconcile_all();
+        assert_eq!(expected, sctp.checksum());
+    }
+
+    #[capsule::test]
+    fn push_sctp_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut sctp = ipv6.push::<Sctp6>().unwrap();
+
+        assert_eq!(SctpHeader::size_of(), sctp.len());
+        assert_eq!(ProtocolNumbers::Sctp, sctp.envelope().next_protocol());
+        assert!(sctp.chunks_iter().next().unwrap().is_none());
+
+        sctp.set_src_port(36412);
+        sctp.set_dst_port(38412);
+        sctp.swap_ports();
+        assert_eq!(38412, sctp.src_port());
+        assert_eq!(36412, sctp.dst_port());
+    }
+
+    #[capsule::test]
+    fn parse_truncated_chunk() {
+        let mut data = SCTP_PACKET;
+        // sets the SACK chunk length past the end of the packet
+        data[73] = 0x20;
+
+        let packet = Mbuf::from_bytes(&data).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let sctp = ipv4.parse::<Sctp4>().unwrap();
+
+        let mut iter = sctp.chunks_iter();
+        assert!(iter.next().is_ok());
+        assert!(iter.next().is_err());
+    }
+}
@@ -18,6 +18,10 @@
 
 //! Transmission Control Protocol.
 
+mod options;
+
+pub use self::options::*;
+
 use crate::ensure;
 use crate::packets::ip::v4::Ipv4;
 use crate::packets::ip::v6::Ipv6;
@@ -191,7 +195,6 @@ impl<E: IpPacket> Tcp<E> {
         (self.header().offset_to_ns & 0xf0) >> 4
     }
 
-    // TODO: support tcp header options.
     #[allow(dead_code)]
     #[inline]
     fn set_data_offset(&mut self, data_offset: u8) {
@@ -460,17 +463,34 @@ impl<E: IpPacket> Tcp<E> {
         Ok(())
     }
 
+    /// Sets the checksum to the pseudo-header sum for the NIC to complete.
+    ///
+    /// With TCP segmentation, the packet length is left out of the sum.
+    #[inline]
+    fn compute_pseudo_header_checksum(&mut self) {
+        let len = if self.mbuf().tcp_segmentation() {
+            0
+        } else {
+            self.len() as u16
+        };
+        let sum = self
+            .envelope()
+            .pseudo_header(len, ProtocolNumbers::Tcp)
+            .sum();
+        self.set_checksum(sum);
+    }
+
     #[inline]
     fn compute_checksum(&mut self) {
         self.set_checksum(0);
 
-        if let Ok(data) = self.mbuf().read_data_slice(self.offset, self.len()) {
-            let data = unsafe { data.as_ref() };
-            let pseudo_header_sum = self
-                .envelope()
-                .pseudo_header(data.len() as u16, ProtocolNumbers::Tcp)
-                .sum();
-            let checksum = checksum::compute(pseudo_header_sum, data);
+        let offset = self.offset;
+        let len = self.len();
+        let pseudo_header_sum = self
+            .envelope()
+            .pseudo_header(len as u16, ProtocolNumbers::Tcp)
+            .sum();
+        if let Ok(checksum) = self.mbuf_mut().checksum(pseudo_header_sum, offset, len) {
             self.set_checksum(checksum);
         } else {
             // we are reading till the end of buffer, should never run out
@@ -529,9 +549,11 @@ impl<E: IpPacket> Packet for Tcp<E> {
         self.offset
     }
 
+    /// Returns the length of the header including the options, as
+    /// indicated by the data offset.
     #[inline]
     fn header_len(&self) -> usize {
-        TcpHeader::size_of()
+        self.data_offset() as usize * 4
     }
 
     #[inline]
@@ -551,7 +573,9 @@ impl<E: IpPacket> Packet for Tcp<E> {
     /// not set to [`ProtocolNumbers::Tcp`]. If the envelope is IPv6 or an
     /// extension header, returns an error if [`next_header`] is not set to
     /// `ProtocolNumbers::Tcp`. Returns an error if the payload does not
-    /// have sufficient data for the TCP header.
+    /// have sufficient data for the TCP header. Returns an error if the
+    /// data offset is less than `5` or if the payload does not have
+    /// sufficient data for the options.
     ///
     /// [`Ipv4::protocol`]: crate::packets::ip::v4::Ipv4::protocol
     /// [`ProtocolNumbers::Tcp`]: crate::packets::ip::ProtocolNumbers::Tcp
@@ -565,11 +589,21 @@ impl<E: IpPacket> Packet for Tcp<E> {
 
         let mbuf = envelope.mbuf();
         let offset = envelope.payload_offset();
-        let header = match mbuf.read_data(offset) {
+        let header = match mbuf.read_data::<TcpHeader>(offset) {
             Err(e) => return Err((e, envelope)),
             Ok(header) => header
         };
 
+        // the options are part of the header as indicated by the data offset.
+        let header_len = (unsafe { header.as_ref() }.offset_to_ns >> 4) as usize * 4;
+        ensure!(
+            header_len >= TcpHeader::size_of(),
+            (anyhow!("invalid TCP header length {}.", header_len), envelope)
+        );
+        if let Err(e) = mbuf.read_data_slice::<u8>(offset, header_len) {
+            return Err((e, envelope));
+        }
+
         Ok(Tcp {
             envelope,
             header,
@@ -622,7 +656,16 @@ impl<E: IpPacket> Packet for Tcp<E> {
     /// [`pseudo-header`]: crate::packets::checksum::PseudoHeader
     #[inline]
     fn reconcile(&mut self) {
-        self.compute_checksum();
+        let offset = self.offset;
+        let header_len = self.header_len();
+        if self
+            .mbuf_mut()
+            .defer_l4_checksum(offset, header_len, ProtocolNumbers::Tcp)
+        {
+            self.compute_pseudo_header_checksum();
+        } else {
+            self.compute_checksum();
+        }
     }
 }
 
@@ -671,7 +714,7 @@ mod tests {
     use super::*;
     use crate::packets::ethernet::Ethernet;
     use crate::packets::ip::v6::SegmentRouting;
-    use crate::packets::Mbuf;
+    use crate::packets::{Mbuf, TxOffloads};
     use crate::testils::byte_arrays::{IPV4_TCP_PACKET, IPV4_UDP_PACKET, SR_TCP_PACKET};
     use std::net::{Ipv4Addr, Ipv6Addr};
 
@@ -782,6 +825,32 @@ mod tests {
         assert_eq!(expected, tcp.checksum());
     }
 
+    #[capsule::test]
+    fn defer_checksum_to_nic() {
+        let mut packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        packet.set_tx_offloads(TxOffloads {
+            ipv4_checksum: true,
+            l4_checksum: true,
+            tcp_segmentation: false,
+        });
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        tcp.reconcile_all();
+
+        let expected = tcp
+            .envelope()
+            .pseudo_header(tcp.len() as u16, ProtocolNumbers::Tcp)
+            .sum();
+        assert_eq!(expected, tcp.checksum());
+        assert_eq!(0, tcp.envelope().checksum());
+
+        let offloads = tcp.mbuf().tx_offloads();
+        assert!(offloads.ipv4_checksum);
+        assert!(offloads.l4_checksum);
+    }
+
     #[capsule::test]
     fn push_tcp_packet() {
         let packet = Mbuf::new().unwrap();
@@ -0,0 +1,1665 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! TCP header options.
+
+use super::{Tcp, TcpHeader};
+use crate::ensure;
+use crate::packets::checksum;
+use crate::packets::ip::IpPacket;
+use crate::packets::tlv::{self, Cursor, Tlv, TlvType};
+use crate::packets::types::{u16be, u32be};
+use crate::packets::{Immutable, Internal, Mbuf, Packet, SizeOf};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+/// The maximum length of the options, measured in octets.
+///
+/// The data offset can describe a header of at most 15 32-bit words, 5 of
+/// which are taken by the fixed portion of the header.
+pub const TCP_MAX_OPTIONS_LEN: usize = 40;
+
+impl<E: IpPacket> Tcp<E> {
+    /// Returns the buffer offset where the options begin.
+    #[inline]
+    fn options_offset(&self) -> usize {
+        self.offset + TcpHeader::size_of()
+    }
+
+    /// Returns the length of the options including the padding, measured
+    /// in octets.
+    #[inline]
+    pub fn options_len(&self) -> usize {
+        self.header_len() - TcpHeader::size_of()
+    }
+
+    /// Returns an iterator to read the options in the header.
+    ///
+    /// The options cannot be modified. To modify the values while iterating
+    /// over the options, use [`options_mut().iter()`] instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut iter = tcp.options_iter();
+    ///
+    /// while let Some(option) = iter.next()? {
+    ///     println!("{:?}", option);
+    /// }
+    /// ```
+    ///
+    /// [`options_mut().iter()`]: TcpOptions::iter
+    #[inline]
+    pub fn options_iter(&self) -> ImmutableTcpOptionsIterator<'_> {
+        let mbuf = unsafe { self.mbuf().clone(Internal(())) };
+        ImmutableTcpOptionsIterator {
+            mbuf,
+            cursor: Cursor {
+                offset: self.options_offset(),
+                end: self.payload_offset(),
+            },
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a mutable reference to the options in the header.
+    ///
+    /// Adding or removing options changes the length of the segment. The
+    /// packet should be reconciled with [`reconcile_all`] afterwards to fix
+    /// the IP lengths and the checksums.
+    ///
+    /// [`reconcile_all`]: Packet::reconcile_all
+    #[inline]
+    pub fn options_mut(&mut self) -> TcpOptions<'_> {
+        let header = self.header;
+        let offset = self.options_offset();
+        TcpOptions {
+            mbuf: self.mbuf_mut(),
+            header,
+            offset,
+        }
+    }
+
+    /// Lowers the maximum segment size option to `mss` if it's larger.
+    ///
+    /// The checksum is recomputed using the incremental method. Returns
+    /// whether the option was changed. Segments without the option are left
+    /// untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options are malformed.
+    pub fn clamp_mss(&mut self, mss: u16) -> Result<bool> {
+        let tcp_offset = self.offset;
+        let mut clamped = None;
+
+        let mut options = self.options_mut();
+        let mut iter = options.iter();
+        while let Some(mut option) = iter.next()? {
+            if option.kind() == TcpOptionKinds::Mss {
+                let mut option = option.downcast::<Mss<'_>>()?;
+                let old_mss = option.mss();
+                if old_mss > mss {
+                    option.set_mss(mss);
+                    clamped = Some((old_mss, option.offset + 2 - tcp_offset));
+                }
+                break;
+            }
+        }
+
+        if let Some((old_mss, value_offset)) = clamped {
+            // a word at an odd offset contributes to the checksum byte swapped.
+            let (old, new) = if value_offset % 2 == 0 {
+                (old_mss, mss)
+            } else {
+                (old_mss.swap_bytes(), mss.swap_bytes())
+            };
+            let checksum = checksum::compute_inc(self.checksum(), &[old], &[new]);
+            self.set_checksum(checksum);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+/// [IANA] assigned TCP option kind.
+///
+/// A list of supported kinds is under [`TcpOptionKinds`].
+///
+/// [IANA]: https://www.iana.org/assignments/tcp-parameters/tcp-parameters.xhtml#tcp-parameters-1
+/// [`TcpOptionKinds`]: TcpOptionKinds
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct TcpOptionKind(pub u8);
+
+impl TcpOptionKind {}
+
+/// Supported TCP option kinds.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod TcpOptionKinds {
+    use super::TcpOptionKind;
+
+    /// End of the option list. Also used as the padding after the last
+    /// option.
+    pub const EndOfOptionList: TcpOptionKind = TcpOptionKind(0);
+
+    /// No operation. Used between options to align the next option.
+    pub const NoOperation: TcpOptionKind = TcpOptionKind(1);
+
+    /// Option kind for [Maximum Segment Size].
+    ///
+    /// [Maximum Segment Size]: crate::packets::tcp::Mss
+    pub const Mss: TcpOptionKind = TcpOptionKind(2);
+
+    /// Option kind for [Window Scale].
+    ///
+    /// [Window Scale]: crate::packets::tcp::WindowScale
+    pub const WindowScale: TcpOptionKind = TcpOptionKind(3);
+
+    /// Option kind for [SACK Permitted].
+    ///
+    /// [SACK Permitted]: crate::packets::tcp::SackPermitted
+    pub const SackPermitted: TcpOptionKind = TcpOptionKind(4);
+
+    /// Option kind for [SACK].
+    ///
+    /// [SACK]: crate::packets::tcp::Sack
+    pub const Sack: TcpOptionKind = TcpOptionKind(5);
+
+    /// Option kind for [Timestamps].
+    ///
+    /// [Timestamps]: crate::packets::tcp::Timestamps
+    pub const Timestamps: TcpOptionKind = TcpOptionKind(8);
+}
+
+impl fmt::Display for TcpOptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                TcpOptionKinds::EndOfOptionList => "End of Option List".to_string(),
+                TcpOptionKinds::NoOperation => "No-Operation".to_string(),
+                TcpOptionKinds::Mss => "Maximum Segment Size".to_string(),
+                TcpOptionKinds::WindowScale => "Window Scale".to_string(),
+                TcpOptionKinds::SackPermitted => "SACK Permitted".to_string(),
+                TcpOptionKinds::Sack => "SACK".to_string(),
+                TcpOptionKinds::Timestamps => "Timestamps".to_string(),
+                _ => format!("{}", self.0),
+            }
+        )
+    }
+}
+
+impl TlvType for TcpOptionKind {
+    const ALIGNMENT: usize = 4;
+    const MAX_LEN: usize = TCP_MAX_OPTIONS_LEN;
+
+    #[inline]
+    fn from_octet(octet: u8) -> Self {
+        TcpOptionKind(octet)
+    }
+
+    #[inline]
+    fn is_single_octet(self) -> bool {
+        self == TcpOptionKinds::EndOfOptionList || self == TcpOptionKinds::NoOperation
+    }
+
+    #[inline]
+    fn option_len(length: u8) -> Result<usize> {
+        // the length includes the kind and length octets.
+        ensure!(length >= 2, anyhow!("invalid option length."));
+        Ok(length as usize)
+    }
+
+    #[inline]
+    fn is_end_of_list(self) -> bool {
+        self == TcpOptionKinds::EndOfOptionList
+    }
+}
+
+/// An immutable generic TCP option that can be casted to a more specific
+/// option.
+pub struct ImmutableTcpOption<'a> {
+    mbuf: &'a mut Mbuf,
+    kind: TcpOptionKind,
+    length: u8,
+    offset: usize,
+}
+
+impl<'a> ImmutableTcpOption<'a> {
+    /// Creates a new immutable untyped TCP option.
+    #[inline]
+    fn new(mbuf: &'a mut Mbuf, tlv: Tlv<TcpOptionKind>) -> Self {
+        ImmutableTcpOption {
+            mbuf,
+            kind: tlv.option_type,
+            length: tlv.len as u8,
+            offset: tlv.offset,
+        }
+    }
+
+    /// Returns the option kind.
+    #[inline]
+    pub fn kind(&self) -> TcpOptionKind {
+        self.kind
+    }
+
+    /// Returns the length of the option measured in octets. Single octet
+    /// options have a length of `1`.
+    #[inline]
+    pub fn length(&self) -> u8 {
+        self.length
+    }
+
+    /// Casts the immutable generic option to a specific option `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut iter = tcp.options_iter();
+    ///
+    /// while let Some(mut option) = iter.next()? {
+    ///     if let Ok(mss) = option.downcast::<Mss<'_>>() {
+    ///         println!("{:?}", mss);
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<'b, T: TcpOption<'b>>(&'b mut self) -> Result<Immutable<'b, T>> {
+        T::try_parse(self.mbuf, self.offset, Internal(())).map(Immutable::new)
+    }
+}
+
+impl fmt::Debug for ImmutableTcpOption<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImmutableTcpOption")
+            .field("kind", &format!("{}", self.kind()))
+            .field("length", &self.length())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in the TCP header
+/// immutably.
+pub struct ImmutableTcpOptionsIterator<'a> {
+    mbuf: Mbuf,
+    cursor: Cursor,
+    _phantom: PhantomData<&'a Mbuf>,
+}
+
+impl ImmutableTcpOptionsIterator<'_> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// The iteration stops at the end of option list marker. Returns
+    /// `Ok(None)` when iteration is finished; returns `Err` when a parse
+    /// error is encountered during iteration.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<ImmutableTcpOption<'_>>> {
+        match self.cursor.next::<TcpOptionKind>(&self.mbuf)? {
+            Some(tlv) => Ok(Some(ImmutableTcpOption::new(&mut self.mbuf, tlv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for ImmutableTcpOptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImmutableTcpOptionsIterator")
+            .field("offset", &self.cursor.offset)
+            .field("end", &self.cursor.end)
+            .finish()
+    }
+}
+
+/// A mutable generic TCP option that can be casted to a more specific
+/// option.
+pub struct MutableTcpOption<'a> {
+    mbuf: &'a mut Mbuf,
+    kind: TcpOptionKind,
+    length: u8,
+    offset: usize,
+}
+
+impl<'a> MutableTcpOption<'a> {
+    /// Creates a new mutable untyped TCP option.
+    #[inline]
+    fn new(mbuf: &'a mut Mbuf, tlv: Tlv<TcpOptionKind>) -> Self {
+        MutableTcpOption {
+            mbuf,
+            kind: tlv.option_type,
+            length: tlv.len as u8,
+            offset: tlv.offset,
+        }
+    }
+
+    /// Returns the option kind.
+    #[inline]
+    pub fn kind(&self) -> TcpOptionKind {
+        self.kind
+    }
+
+    /// Returns the length of the option measured in octets. Single octet
+    /// options have a length of `1`.
+    #[inline]
+    pub fn length(&self) -> u8 {
+        self.length
+    }
+
+    /// Casts the mutable generic option to a specific option `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut options = tcp.options_mut();
+    /// let mut iter = options.iter();
+    ///
+    /// while let Some(mut option) = iter.next()? {
+    ///     if let Ok(mut scale) = option.downcast::<WindowScale<'_>>() {
+    ///         scale.set_shift_count(7);
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn downcast<'b, T: TcpOption<'b>>(&'b mut self) -> Result<T> {
+        T::try_parse(self.mbuf, self.offset, Internal(()))
+    }
+}
+
+impl fmt::Debug for MutableTcpOption<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutableTcpOption")
+            .field("kind", &format!("{}", self.kind()))
+            .field("length", &self.length())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+/// An iterator that iterates through the options in the TCP header
+/// mutably.
+pub struct MutableTcpOptionsIterator<'a> {
+    mbuf: &'a mut Mbuf,
+    cursor: Cursor,
+}
+
+impl MutableTcpOptionsIterator<'_> {
+    /// Advances the iterator and returns the next value.
+    ///
+    /// The iteration stops at the end of option list marker. Returns
+    /// `Ok(None)` when iteration is finished; returns `Err` when a parse
+    /// error is encountered during iteration.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<MutableTcpOption<'_>>> {
+        match self.cursor.next::<TcpOptionKind>(self.mbuf)? {
+            Some(tlv) => Ok(Some(MutableTcpOption::new(self.mbuf, tlv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Debug for MutableTcpOptionsIterator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MutableTcpOptionsIterator")
+            .field("offset", &self.cursor.offset)
+            .field("end", &self.cursor.end)
+            .finish()
+    }
+}
+
+/// Options in the TCP header.
+///
+/// Adding or removing options resizes the header. The options are padded
+/// with end of option list markers to a 32-bit boundary and the data
+/// offset is updated accordingly.
+pub struct TcpOptions<'a> {
+    mbuf: &'a mut Mbuf,
+    header: NonNull<TcpHeader>,
+    offset: usize,
+}
+
+impl TcpOptions<'_> {
+    #[inline]
+    fn header_mut(&mut self) -> &mut TcpHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the buffer offset where the options end, including the
+    /// padding.
+    #[inline]
+    fn end(&self) -> usize {
+        let data_offset = unsafe { self.header.as_ref() }.offset_to_ns >> 4;
+        self.offset + (data_offset as usize * 4).saturating_sub(TcpHeader::size_of())
+    }
+
+    /// Returns the buffer offset where the padding after the last option
+    /// begins.
+    fn padding_offset(&self) -> Result<usize> {
+        tlv::padding_offset::<TcpOptionKind>(self.mbuf, self.offset, self.end())
+    }
+
+    /// Sets the data offset to cover the options of `aligned_len` octets.
+    #[inline]
+    fn set_data_offset(&mut self, aligned_len: usize) {
+        let data_offset = ((TcpHeader::size_of() + aligned_len) / 4) as u8;
+        let header = self.header_mut();
+        header.offset_to_ns = (header.offset_to_ns & 0x0f) | (data_offset << 4);
+    }
+
+    /// Inserts `len` octets of room for a new option at `at`, resizes the
+    /// padding so the options end on a 32-bit boundary, and updates the
+    /// data offset. The buffer and the data offset are left unchanged if
+    /// it fails.
+    fn insert(&mut self, at: usize, len: usize) -> Result<()> {
+        let end = self.end();
+        let padding = self.padding_offset()?;
+        let aligned_len =
+            tlv::insert::<TcpOptionKind>(self.mbuf, self.offset, at, padding, end, len)?;
+        self.set_data_offset(aligned_len);
+        Ok(())
+    }
+
+    /// Returns an iterator that allows modifying each option.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut options = tcp.options_mut();
+    /// let mut iter = options.iter();
+    ///
+    /// while let Some(option) = iter.next()? {
+    ///     println!("{:?}", option);
+    /// }
+    /// ```
+    #[inline]
+    pub fn iter(&mut self) -> MutableTcpOptionsIterator<'_> {
+        let cursor = Cursor {
+            offset: self.offset,
+            end: self.end(),
+        };
+        MutableTcpOptionsIterator {
+            mbuf: self.mbuf,
+            cursor,
+        }
+    }
+
+    /// Prepends a new option `T` at the beginning of the options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options would exceed [`TCP_MAX_OPTIONS_LEN`]
+    /// or if the buffer does not have enough free space. The options are
+    /// left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut options = tcp.options_mut();
+    /// let mut mss = options.prepend::<Mss<'_>>()?;
+    /// mss.set_mss(1460);
+    /// ```
+    ///
+    /// [`TCP_MAX_OPTIONS_LEN`]: TCP_MAX_OPTIONS_LEN
+    pub fn prepend<'a, T: TcpOption<'a>>(&'a mut self) -> Result<T> {
+        let offset = self.offset;
+        self.insert(offset, T::push_len())?;
+        T::try_push(self.mbuf, offset, Internal(()))
+    }
+
+    /// Appends a new option `T` at the end of the options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options would exceed [`TCP_MAX_OPTIONS_LEN`]
+    /// or if the buffer does not have enough free space. The options are
+    /// left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut options = tcp.options_mut();
+    /// let mut timestamps = options.append::<Timestamps<'_>>()?;
+    /// timestamps.set_ts_val(1);
+    /// ```
+    ///
+    /// [`TCP_MAX_OPTIONS_LEN`]: TCP_MAX_OPTIONS_LEN
+    pub fn append<'a, T: TcpOption<'a>>(&'a mut self) -> Result<T> {
+        let padding = self.padding_offset()?;
+        self.insert(padding, T::push_len())?;
+        T::try_push(self.mbuf, padding, Internal(()))
+    }
+
+    /// Retains only the options specified by the predicate.
+    ///
+    /// In other words, remove all options `o` such that `f(o)` returns false.
+    /// If an error occurs, all removals done prior to the error cannot be
+    /// undone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut tcp = ipv4.parse::<Tcp4>()?;
+    /// let mut options = tcp.options_mut();
+    /// let _ = options.retain(|option| option.kind() != TcpOptionKinds::Timestamps);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut ImmutableTcpOption<'_>) -> bool,
+    {
+        let (padding, end) =
+            tlv::retain::<TcpOptionKind, _>(self.mbuf, self.offset, self.end(), |mbuf, tlv| {
+                f(&mut ImmutableTcpOption::new(mbuf, tlv))
+            })?;
+
+        let aligned_len = tlv::realign::<TcpOptionKind>(self.mbuf, self.offset, padding, end, 0)?;
+        self.set_data_offset(aligned_len);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TcpOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpOptions")
+            .field("offset", &self.offset)
+            .field("end", &self.end())
+            .finish()
+    }
+}
+
+/// A trait that all TCP options must implement.
+///
+/// The trait is used for conversion between the generic TCP option
+/// and the more specific options. Implementors can use this trait to
+/// add custom TCP options. This trait should not be imported and used
+/// directly.
+pub trait TcpOption<'a> {
+    /// Returns the option kind.
+    fn kind(&self) -> TcpOptionKind;
+
+    /// Returns the length of the option measured in octets.
+    fn length(&self) -> u8;
+
+    /// Returns the length of the option in octets when it's pushed by
+    /// [`try_push`].
+    ///
+    /// The room for the option is inserted and the options are realigned
+    /// to a 32-bit boundary before the push.
+    ///
+    /// [`try_push`]: TcpOption::try_push
+    fn push_len() -> usize
+    where
+        Self: Sized;
+
+    /// Parses the buffer at offset as this TCP option.
+    ///
+    /// The buffer offset includes `kind` and `length` fields. The
+    /// implementation should verify that `kind` matches the expected
+    /// kind. Otherwise parse should fail. When applicable, the
+    /// implementation should also verify that `length` is consistent with
+    /// the expected length for the given option kind as well.
+    ///
+    /// # Remarks
+    ///
+    /// This function cannot be invoked directly. It is internally used by
+    /// [`ImmutableTcpOption::downcast`] and [`MutableTcpOption::downcast`].
+    ///
+    /// [`ImmutableTcpOption::downcast`]: ImmutableTcpOption::downcast
+    /// [`MutableTcpOption::downcast`]: MutableTcpOption::downcast
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, internal: Internal) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Writes a new TCP option to the [`push_len`] octets of room already
+    /// inserted in the buffer at offset.
+    ///
+    /// # Remarks
+    ///
+    /// This function cannot be invoked directly. It is internally used by
+    /// [`TcpOptions::prepend`] and [`TcpOptions::append`].
+    ///
+    /// [`push_len`]: TcpOption::push_len
+    /// [`TcpOptions::prepend`]: TcpOptions::prepend
+    /// [`TcpOptions::append`]: TcpOptions::append
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, internal: Internal) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+/// Maximum Segment Size option defined in [IETF RFC 793].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Kind      |    Length     |     Maximum Segment Size      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Kind*:            2
+///
+/// - *Length*:          4
+///
+/// - *Maximum Segment Size*:
+///                      The maximum receive segment size at the TCP which
+///                      sends this segment. Only sent in the initial
+///                      connection request.
+///
+/// [IETF RFC 793]: https://tools.ietf.org/html/rfc793#page-19
+pub struct Mss<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<MssFields>,
+    offset: usize,
+}
+
+impl Mss<'_> {
+    #[inline]
+    fn fields(&self) -> &MssFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut MssFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the maximum segment size.
+    #[inline]
+    pub fn mss(&self) -> u16 {
+        self.fields().mss.into()
+    }
+
+    /// Sets the maximum segment size.
+    #[inline]
+    pub fn set_mss(&mut self, mss: u16) {
+        self.fields_mut().mss = mss.into();
+    }
+}
+
+impl fmt::Debug for Mss<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mss")
+            .field("kind", &self.kind())
+            .field("length", &self.length())
+            .field("mss", &self.mss())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> TcpOption<'a> for Mss<'a> {
+    /// Returns the option kind. Should always be `2`.
+    #[inline]
+    fn kind(&self) -> TcpOptionKind {
+        TcpOptionKind(self.fields().kind)
+    }
+
+    /// Returns the length of the option. Should always be `4`.
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        MssFields::size_of()
+    }
+
+    /// Parses the buffer at offset as maximum segment size option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `kind` is not set to `Mss`. Returns an error
+    /// if the option length is incorrect.
+    #[inline]
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Mss<'a>> {
+        let fields = mbuf.read_data::<MssFields>(offset)?;
+        let option = Mss {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.kind() == TcpOptionKinds::Mss,
+            anyhow!("not maximum segment size.")
+        );
+
+        ensure!(
+            option.length() as usize == MssFields::size_of(),
+            anyhow!("invalid maximum segment size option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new maximum segment size option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Mss<'a>> {
+        let fields = mbuf.write_data(offset, &MssFields::default())?;
+        Ok(Mss {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// Maximum segment size option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct MssFields {
+    kind: u8,
+    length: u8,
+    mss: u16be,
+}
+
+impl Default for MssFields {
+    fn default() -> MssFields {
+        MssFields {
+            kind: TcpOptionKinds::Mss.0,
+            length: 4,
+            mss: u16be::default(),
+        }
+    }
+}
+
+/// Window Scale option defined in [IETF RFC 7323].
+///
+/// ```
+///  0                   1                   2
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Kind      |    Length     |  Shift Count  |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Kind*:            3
+///
+/// - *Length*:          3
+///
+/// - *Shift Count*:     The scale factor to apply to the receive window,
+///                      as a power of 2. Must not exceed 14.
+///
+/// [IETF RFC 7323]: https://tools.ietf.org/html/rfc7323#section-2.2
+pub struct WindowScale<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<WindowScaleFields>,
+    offset: usize,
+}
+
+impl WindowScale<'_> {
+    #[inline]
+    fn fields(&self) -> &WindowScaleFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut WindowScaleFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the shift count.
+    #[inline]
+    pub fn shift_count(&self) -> u8 {
+        self.fields().shift_count
+    }
+
+    /// Sets the shift count.
+    #[inline]
+    pub fn set_shift_count(&mut self, shift_count: u8) {
+        self.fields_mut().shift_count = shift_count;
+    }
+}
+
+impl fmt::Debug for WindowScale<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WindowScale")
+            .field("kind", &self.kind())
+            .field("length", &self.length())
+            .field("shift_count", &self.shift_count())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> TcpOption<'a> for WindowScale<'a> {
+    /// Returns the option kind. Should always be `3`.
+    #[inline]
+    fn kind(&self) -> TcpOptionKind {
+        TcpOptionKind(self.fields().kind)
+    }
+
+    /// Returns the length of the option. Should always be `3`.
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        WindowScaleFields::size_of()
+    }
+
+    /// Parses the buffer at offset as window scale option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `kind` is not set to `WindowScale`. Returns
+    /// an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<WindowScale<'a>> {
+        let fields = mbuf.read_data::<WindowScaleFields>(offset)?;
+        let option = WindowScale {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.kind() == TcpOptionKinds::WindowScale,
+            anyhow!("not window scale.")
+        );
+
+        ensure!(
+            option.length() as usize == WindowScaleFields::size_of(),
+            anyhow!("invalid window scale option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new window scale option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<WindowScale<'a>> {
+        let fields = mbuf.write_data(offset, &WindowScaleFields::default())?;
+        Ok(WindowScale {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// Window scale option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct WindowScaleFields {
+    kind: u8,
+    length: u8,
+    shift_count: u8,
+}
+
+impl Default for WindowScaleFields {
+    fn default() -> WindowScaleFields {
+        WindowScaleFields {
+            kind: TcpOptionKinds::WindowScale.0,
+            length: 3,
+            shift_count: 0,
+        }
+    }
+}
+
+/// SACK Permitted option defined in [IETF RFC 2018].
+///
+/// ```
+///  0                   1
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |     Kind      |    Length     |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Kind*:            4
+///
+/// - *Length*:          2
+///
+/// [IETF RFC 2018]: https://tools.ietf.org/html/rfc2018#section-2
+pub struct SackPermitted<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<SackPermittedFields>,
+    offset: usize,
+}
+
+impl SackPermitted<'_> {
+    #[inline]
+    fn fields(&self) -> &SackPermittedFields {
+        unsafe { self.fields.as_ref() }
+    }
+}
+
+impl fmt::Debug for SackPermitted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SackPermitted")
+            .field("kind", &self.kind())
+            .field("length", &self.length())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> TcpOption<'a> for SackPermitted<'a> {
+    /// Returns the option kind. Should always be `4`.
+    #[inline]
+    fn kind(&self) -> TcpOptionKind {
+        TcpOptionKind(self.fields().kind)
+    }
+
+    /// Returns the length of the option. Should always be `2`.
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        SackPermittedFields::size_of()
+    }
+
+    /// Parses the buffer at offset as SACK permitted option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `kind` is not set to `SackPermitted`. Returns
+    /// an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<SackPermitted<'a>> {
+        let fields = mbuf.read_data::<SackPermittedFields>(offset)?;
+        let option = SackPermitted {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.kind() == TcpOptionKinds::SackPermitted,
+            anyhow!("not SACK permitted.")
+        );
+
+        ensure!(
+            option.length() as usize == SackPermittedFields::size_of(),
+            anyhow!("invalid SACK permitted option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new SACK permitted option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(
+        mbuf: &'a mut Mbuf,
+        offset: usize,
+        _internal: Internal,
+    ) -> Result<SackPermitted<'a>> {
+        let fields = mbuf.write_data(offset, &SackPermittedFields::default())?;
+        Ok(SackPermitted {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// SACK permitted option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct SackPermittedFields {
+    kind: u8,
+    length: u8,
+}
+
+impl Default for SackPermittedFields {
+    fn default() -> SackPermittedFields {
+        SackPermittedFields {
+            kind: TcpOptionKinds::SackPermitted.0,
+            length: 2,
+        }
+    }
+}
+
+/// SACK option defined in [IETF RFC 2018].
+///
+/// ```
+///                          +--------+--------+
+///                          | Kind=5 | Length |
+///        +--------+--------+--------+--------+
+///        |      Left Edge of 1st Block       |
+///        +--------+--------+--------+--------+
+///        |      Right Edge of 1st Block      |
+///        +--------+--------+--------+--------+
+///        |                                   |
+///        /            . . .                  /
+///        |                                   |
+///        +--------+--------+--------+--------+
+///        |      Left Edge of nth Block       |
+///        +--------+--------+--------+--------+
+///        |      Right Edge of nth Block      |
+///        +--------+--------+--------+--------+
+/// ```
+///
+/// - *Kind*:            5
+///
+/// - *Length*:          2 + 8 * n, where n is the number of blocks.
+///
+/// - *Left Edge of Block*:
+///                      The first sequence number of this block.
+///
+/// - *Right Edge of Block*:
+///                      The sequence number immediately following the
+///                      last sequence number of this block.
+///
+/// A pushed option has room for a single block.
+///
+/// [IETF RFC 2018]: https://tools.ietf.org/html/rfc2018#section-3
+pub struct Sack<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<SackFields>,
+    blocks: NonNull<[SackBlock]>,
+    offset: usize,
+}
+
+impl Sack<'_> {
+    #[inline]
+    fn fields(&self) -> &SackFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    /// Returns the blocks as pairs of left and right edges.
+    #[inline]
+    pub fn blocks(&self) -> Vec<(u32, u32)> {
+        unsafe { self.blocks.as_ref() }
+            .iter()
+            .map(|block| (block.left.into(), block.right.into()))
+            .collect()
+    }
+
+    /// Sets the edges of the block at index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of bound.
+    #[inline]
+    pub fn set_block(&mut self, index: usize, left: u32, right: u32) -> Result<()> {
+        let blocks = unsafe { self.blocks.as_mut() };
+        ensure!(
+            index < blocks.len(),
+            anyhow!("SACK block index {} out of bound.", index)
+        );
+
+        blocks[index] = SackBlock {
+            left: left.into(),
+            right: right.into(),
+        };
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Sack<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sack")
+            .field("kind", &self.kind())
+            .field("length", &self.length())
+            .field("blocks", &self.blocks())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> TcpOption<'a> for Sack<'a> {
+    /// Returns the option kind. Should always be `5`.
+    #[inline]
+    fn kind(&self) -> TcpOptionKind {
+        TcpOptionKind(self.fields().kind)
+    }
+
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        SackFields::size_of() + SackBlock::size_of()
+    }
+
+    /// Parses the buffer at offset as SACK option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `kind` is not set to `Sack`. Returns an
+    /// error if the option length is not a whole number of blocks.
+    #[inline]
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Sack<'a>> {
+        let fields = mbuf.read_data::<SackFields>(offset)?;
+        let (kind, length) = unsafe { (fields.as_ref().kind, fields.as_ref().length) };
+
+        ensure!(
+            TcpOptionKind(kind) == TcpOptionKinds::Sack,
+            anyhow!("not SACK.")
+        );
+
+        let blocks_len = (length as usize).saturating_sub(SackFields::size_of());
+        ensure!(
+            blocks_len > 0 && blocks_len % SackBlock::size_of() == 0,
+            anyhow!("invalid SACK option length.")
+        );
+
+        let blocks = mbuf.read_data_slice::<SackBlock>(
+            offset + SackFields::size_of(),
+            blocks_len / SackBlock::size_of(),
+        )?;
+
+        Ok(Sack {
+            _mbuf: mbuf,
+            fields,
+            blocks,
+            offset,
+        })
+    }
+
+    /// Writes a new SACK option with one empty block to the room at
+    /// offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Sack<'a>> {
+        let fields = mbuf.write_data(offset, &SackFields::default())?;
+        let blocks =
+            mbuf.write_data_slice(offset + SackFields::size_of(), &[SackBlock::default()])?;
+        Ok(Sack {
+            _mbuf: mbuf,
+            fields,
+            blocks,
+            offset,
+        })
+    }
+}
+
+/// SACK option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct SackFields {
+    kind: u8,
+    length: u8,
+}
+
+impl Default for SackFields {
+    fn default() -> SackFields {
+        SackFields {
+            kind: TcpOptionKinds::Sack.0,
+            length: 10,
+        }
+    }
+}
+
+/// SACK block edges.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C, packed)]
+struct SackBlock {
+    left: u32be,
+    right: u32be,
+}
+
+/// Timestamps option defined in [IETF RFC 7323].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///                                 +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///                                 |     Kind      |    Length     |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                      TS Value (TSval)                         |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                    TS Echo Reply (TSecr)                      |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Kind*:            8
+///
+/// - *Length*:          10
+///
+/// - *TS Value*:        The current value of the timestamp clock of the
+///                      TCP sending the option.
+///
+/// - *TS Echo Reply*:   The timestamp value that was sent by the remote
+///                      TCP, valid only if the ACK bit is set.
+///
+/// [IETF RFC 7323]: https://tools.ietf.org/html/rfc7323#section-3.2
+pub struct Timestamps<'a> {
+    _mbuf: &'a mut Mbuf,
+    fields: NonNull<TimestampsFields>,
+    offset: usize,
+}
+
+impl Timestamps<'_> {
+    #[inline]
+    fn fields(&self) -> &TimestampsFields {
+        unsafe { self.fields.as_ref() }
+    }
+
+    #[inline]
+    fn fields_mut(&mut self) -> &mut TimestampsFields {
+        unsafe { self.fields.as_mut() }
+    }
+
+    /// Returns the timestamp value.
+    #[inline]
+    pub fn ts_val(&self) -> u32 {
+        self.fields().ts_val.into()
+    }
+
+    /// Sets the timestamp value.
+    #[inline]
+    pub fn set_ts_val(&mut self, ts_val: u32) {
+        self.fields_mut().ts_val = ts_val.into();
+    }
+
+    /// Returns the timestamp echo reply.
+    #[inline]
+    pub fn ts_ecr(&self) -> u32 {
+        self.fields().ts_ecr.into()
+    }
+
+    /// Sets the timestamp echo reply.
+    #[inline]
+    pub fn set_ts_ecr(&mut self, ts_ecr: u32) {
+        self.fields_mut().ts_ecr = ts_ecr.into();
+    }
+}
+
+impl fmt::Debug for Timestamps<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timestamps")
+            .field("kind", &self.kind())
+            .field("length", &self.length())
+            .field("ts_val", &self.ts_val())
+            .field("ts_ecr", &self.ts_ecr())
+            .field("$offset", &self.offset)
+            .finish()
+    }
+}
+
+impl<'a> TcpOption<'a> for Timestamps<'a> {
+    /// Returns the option kind. Should always be `8`.
+    #[inline]
+    fn kind(&self) -> TcpOptionKind {
+        TcpOptionKind(self.fields().kind)
+    }
+
+    /// Returns the length of the option. Should always be `10`.
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
+    #[inline]
+    fn push_len() -> usize {
+        TimestampsFields::size_of()
+    }
+
+    /// Parses the buffer at offset as timestamps option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `kind` is not set to `Timestamps`. Returns
+    /// an error if the option length is incorrect.
+    #[inline]
+    fn try_parse(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Timestamps<'a>> {
+        let fields = mbuf.read_data::<TimestampsFields>(offset)?;
+        let option = Timestamps {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        };
+
+        ensure!(
+            option.kind() == TcpOptionKinds::Timestamps,
+            anyhow!("not timestamps.")
+        );
+
+        ensure!(
+            option.length() as usize == TimestampsFields::size_of(),
+            anyhow!("invalid timestamps option length.")
+        );
+
+        Ok(option)
+    }
+
+    /// Writes a new timestamps option to the room at offset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the room exceeds the buffer.
+    #[inline]
+    fn try_push(mbuf: &'a mut Mbuf, offset: usize, _internal: Internal) -> Result<Timestamps<'a>> {
+        let fields = mbuf.write_data(offset, &TimestampsFields::default())?;
+        Ok(Timestamps {
+            _mbuf: mbuf,
+            fields,
+            offset,
+        })
+    }
+}
+
+/// Timestamps option fields.
+#[derive(Clone, Copy, Debug, SizeOf)]
+#[repr(C, packed)]
+struct TimestampsFields {
+    kind: u8,
+    length: u8,
+    ts_val: u32be,
+    ts_ecr: u32be,
+}
+
+impl Default for TimestampsFields {
+    fn default() -> TimestampsFields {
+        TimestampsFields {
+            kind: TcpOptionKinds::Timestamps.0,
+            length: 10,
+            ts_val: u32be::default(),
+            ts_ecr: u32be::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::Ethernet;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::tcp::Tcp4;
+    use crate::testils::byte_arrays::{IPV4_TCP_PACKET, IPV4_TCP_SYN_PACKET};
+    use crate::testils::{bytes_of, ipv4_with_payload};
+
+    #[test]
+    fn size_of_option_fields() {
+        assert_eq!(4, MssFields::size_of());
+        assert_eq!(3, WindowScaleFields::size_of());
+        assert_eq!(2, SackPermittedFields::size_of());
+        assert_eq!(2, SackFields::size_of());
+        assert_eq!(8, SackBlock::size_of());
+        assert_eq!(10, TimestampsFields::size_of());
+    }
+
+    #[capsule::test]
+    fn iterate_immutable_tcp_options() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_SYN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        assert_eq!(20, tcp.options_len());
+
+        let mut iter = tcp.options_iter();
+        let mut kinds = vec![];
+        while let Some(option) = iter.next().unwrap() {
+            kinds.push(option.kind());
+        }
+
+        assert_eq!(
+            vec![
+                TcpOptionKinds::Mss,
+                TcpOptionKinds::SackPermitted,
+                TcpOptionKinds::Timestamps,
+                TcpOptionKinds::NoOperation,
+                TcpOptionKinds::WindowScale
+            ],
+            kinds
+        );
+    }
+
+    #[capsule::test]
+    fn downcast_immutable_tcp_options() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_SYN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let tcp = ipv4.parse::<Tcp4>().unwrap();
+        let mut iter = tcp.options_iter();
+
+        let mut option = iter.next().unwrap().unwrap();
+        assert_eq!(1460, option.downcast::<Mss<'_>>().unwrap().mss());
+
+        let mut option = iter.next().unwrap().unwrap();
+        assert!(option.downcast::<Mss<'_>>().is_err());
+        assert!(option.downcast::<SackPermitted<'_>>().is_ok());
+
+        let mut option = iter.next().unwrap().unwrap();
+        let timestamps = option.downcast::<Timestamps<'_>>().unwrap();
+        assert_eq!(123_456, timestamps.ts_val());
+        assert_eq!(0, timestamps.ts_ecr());
+
+        let _ = iter.next().unwrap().unwrap();
+        let mut option = iter.next().unwrap().unwrap();
+        assert_eq!(
+            7,
+            option.downcast::<WindowScale<'_>>().unwrap().shift_count()
+        );
+
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn invalid_tcp_option_length() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        // the mss length now runs past the header
+        let offset = tcp.options_offset();
+        tcp.mbuf_mut().write_data(offset + 1, &8u8).unwrap();
+
+        let mut iter = tcp.options_iter();
+        assert!(iter.next().is_err());
+    }
+
+    #[capsule::test]
+    fn clamp_tcp_mss() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_SYN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        // already below the clamp
+        assert!(!tcp.clamp_mss(1500).unwrap());
+
+        assert!(tcp.clamp_mss(1400).unwrap());
+        let mut iter = tcp.options_iter();
+        let mut option = iter.next().unwrap().unwrap();
+        assert_eq!(1400, option.downcast::<Mss<'_>>().unwrap().mss());
+
+        // the incremental checksum matches a full recompute
+        let checksum = tcp.checksum();
+        tcp.reconcile();
+        assert_eq!(checksum, tcp.checksum());
+    }
+
+    #[capsule::test]
+    fn append_tcp_option() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        let mut options = tcp.options_mut();
+        let mut timestamps = options.append::<Timestamps<'_>>().unwrap();
+        timestamps.set_ts_val(1);
+        timestamps.set_ts_ecr(2);
+
+        // 4 + 10 + 2 octets of padding
+        assert_eq!(9, tcp.data_offset());
+        assert_eq!(36, tcp.header_len());
+
+        let mut options = tcp.options_mut();
+        let _ = options.append::<SackPermitted<'_>>().unwrap();
+
+        // the padding is reused
+        assert_eq!(9, tcp.data_offset());
+
+        tcp.reconcile_all();
+        assert_eq!(56, tcp.envelope().total_length());
+
+        let checksum = tcp.checksum();
+        tcp.reconcile();
+        assert_eq!(checksum, tcp.checksum());
+
+        let mut iter = tcp.options_iter();
+        assert_eq!(TcpOptionKinds::Mss, iter.next().unwrap().unwrap().kind());
+        let mut option = iter.next().unwrap().unwrap();
+        let timestamps = option.downcast::<Timestamps<'_>>().unwrap();
+        assert_eq!(1, timestamps.ts_val());
+        assert_eq!(2, timestamps.ts_ecr());
+        assert_eq!(
+            TcpOptionKinds::SackPermitted,
+            iter.next().unwrap().unwrap().kind()
+        );
+        assert!(iter.next().unwrap().is_none());
+    }
+
+    #[capsule::test]
+    fn append_tcp_option_exhausted_mempool() {
+        // fills the first segment, so the option needs a new one.
+        let len = 2048 - IPV4_TCP_PACKET.len();
+        let ipv4 = ipv4_with_payload(&IPV4_TCP_PACKET, len);
+        assert_eq!(1, ipv4.mbuf().num_segments());
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+        let data_offset = tcp.data_offset();
+        let bytes = bytes_of(&tcp);
+
+        // holds on to the rest of the mempool
+        let mut held = vec![];
+        while let Ok(other) = Mbuf::new() {
+            held.push(other);
+        }
+
+        // the options are left unchanged
+        let mut options = tcp.options_mut();
+        assert!(options.append::<Timestamps<'_>>().is_err());
+        assert!(options.prepend::<Timestamps<'_>>().is_err());
+        assert_eq!(data_offset, tcp.data_offset());
+        assert_eq!(bytes, bytes_of(&tcp));
+
+        held.clear();
+        assert!(tcp.options_mut().append::<Timestamps<'_>>().is_ok());
+        assert!(tcp.data_offset() > data_offset);
+    }
+
+    #[capsule::test]
+    fn prepend_tcp_option() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        let mut options = tcp.options_mut();
+        let mut sack = options.prepend::<Sack<'_>>().unwrap();
+        sack.set_block(0, 100, 200).unwrap();
+        assert!(sack.set_block(1, 200, 300).is_err());
+
+        // 10 + 4 + 2 octets of padding
+        assert_eq!(9, tcp.data_offset());
+
+        let mut iter = tcp.options_iter();
+        let mut option = iter.next().unwrap().unwrap();
+        assert_eq!(
+            vec![(100, 200)],
+            option.downcast::<Sack<'_>>().unwrap().blocks()
+        );
+        assert_eq!(TcpOptionKinds::Mss, iter.next().unwrap().unwrap().kind());
+    }
+
+    #[capsule::test]
+    fn append_tcp_option_exceeds_max_len() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_SYN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        let mut options = tcp.options_mut();
+        let _ = options.append::<Timestamps<'_>>().unwrap();
+        assert_eq!(13, tcp.data_offset());
+
+        let mut options = tcp.options_mut();
+        let _ = options.append::<Timestamps<'_>>().unwrap();
+        assert_eq!(15, tcp.data_offset());
+
+        let mut options = tcp.options_mut();
+        assert!(options.append::<Sack<'_>>().is_err());
+        assert_eq!(15, tcp.data_offset());
+    }
+
+    #[capsule::test]
+    fn retain_tcp_options() {
+        let packet = Mbuf::from_bytes(&IPV4_TCP_SYN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp4>().unwrap();
+
+        let mut options = tcp.options_mut();
+        options
+            .retain(|option| option.kind() != TcpOptionKinds::Timestamps)
+            .unwrap();
+
+        // 4 + 2 + 1 + 3 = 10, plus 2 octets of padding
+        assert_eq!(8, tcp.data_offset());
+
+        let mut options = tcp.options_mut();
+        options.retain(|_| false).unwrap();
+        assert_eq!(5, tcp.data_offset());
+        assert_eq!(0, tcp.options_len());
+
+        tcp.reconcile_all();
+        assert_eq!(40, tcp.envelope().total_length());
+
+        let checksum = tcp.checksum();
+        tcp.reconcile();
+        assert_eq!(checksum, tcp.checksum());
+    }
+}
@@ -0,0 +1,290 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Type-length-value encoded header options.
+//!
+//! IPv4, TCP and IPv6 options share the same layout: a type octet,
+//! optionally followed by a length octet and the option data. The options
+//! are padded to an alignment boundary at the end of the header. This
+//! module implements the parsing and the resizing of the options once,
+//! parameterized by the option type through [`TlvType`].
+//!
+//! [`TlvType`]: TlvType
+
+use crate::ensure;
+use crate::packets::Mbuf;
+use anyhow::{anyhow, Result};
+
+/// The type code of a type-length-value encoded option.
+pub(crate) trait TlvType: Copy + PartialEq {
+    /// The options are padded to an integer multiple of this many octets.
+    /// Must be a power of two.
+    const ALIGNMENT: usize;
+
+    /// The maximum length of the options, measured in octets.
+    const MAX_LEN: usize;
+
+    /// Converts the type octet into the option type.
+    fn from_octet(octet: u8) -> Self;
+
+    /// Returns a flag indicating whether the option is a single octet
+    /// option without the length field.
+    fn is_single_octet(self) -> bool;
+
+    /// Returns the total length of the option in octets, given the value
+    /// of its length field.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the length field is invalid.
+    fn option_len(length: u8) -> Result<usize>;
+
+    /// Returns a flag indicating whether the option marks the end of the
+    /// option list. Everything after the marker is padding.
+    #[inline]
+    fn is_end_of_list(self) -> bool {
+        false
+    }
+
+    /// Returns a flag indicating whether the option is padding that can
+    /// trail the last option.
+    #[inline]
+    fn is_padding(self) -> bool {
+        false
+    }
+
+    /// Writes `len` octets of padding to the buffer at offset.
+    #[inline]
+    fn write_padding(mbuf: &mut Mbuf, offset: usize, len: usize) -> Result<()> {
+        if len > 0 {
+            let _ = mbuf.write_data_slice(offset, &vec![0u8; len])?;
+        }
+        Ok(())
+    }
+}
+
+/// The location of an option in the buffer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tlv<T> {
+    pub(crate) option_type: T,
+    pub(crate) offset: usize,
+    pub(crate) len: usize,
+}
+
+impl<T> Tlv<T> {
+    /// Returns the buffer offset where the option ends.
+    #[inline]
+    pub(crate) fn end_offset(&self) -> usize {
+        self.offset + self.len
+    }
+}
+
+/// Reads the type and the total length of the option at offset.
+///
+/// # Errors
+///
+/// Returns an error if the option does not fit before the end offset.
+#[inline]
+pub(crate) fn read<T: TlvType>(mbuf: &Mbuf, offset: usize, end: usize) -> Result<Tlv<T>> {
+    let option_type = T::from_octet(unsafe { *mbuf.read_data::<u8>(offset)?.as_ref() });
+
+    if option_type.is_single_octet() {
+        return Ok(Tlv {
+            option_type,
+            offset,
+            len: 1,
+        });
+    }
+
+    ensure!(
+        offset + 2 <= end,
+        anyhow!("option size exceeds the header length.")
+    );
+    let length = unsafe { *mbuf.read_data::<u8>(offset + 1)?.as_ref() };
+
+    let len = T::option_len(length)?;
+    ensure!(
+        offset + len <= end,
+        anyhow!("option size exceeds the header length.")
+    );
+
+    Ok(Tlv {
+        option_type,
+        offset,
+        len,
+    })
+}
+
+/// A cursor over the options between two buffer offsets.
+#[derive(Debug)]
+pub(crate) struct Cursor {
+    pub(crate) offset: usize,
+    pub(crate) end: usize,
+}
+
+impl Cursor {
+    /// Advances the cursor and returns the location of the next option.
+    ///
+    /// The iteration stops at the end of option list marker. Returns
+    /// `Ok(None)` when iteration is finished; returns `Err` when a parse
+    /// error is encountered during iteration.
+    #[inline]
+    pub(crate) fn next<T: TlvType>(&mut self, mbuf: &Mbuf) -> Result<Option<Tlv<T>>> {
+        if self.end > self.offset {
+            let tlv = read::<T>(mbuf, self.offset, self.end)?;
+            if tlv.option_type.is_end_of_list() {
+                // everything after the marker is padding
+                self.offset = self.end;
+                Ok(None)
+            } else {
+                // advances the offset to the next option
+                self.offset = tlv.end_offset();
+                Ok(Some(tlv))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Returns the buffer offset where the trailing padding after the last
+/// option begins.
+pub(crate) fn padding_offset<T: TlvType>(mbuf: &Mbuf, start: usize, end: usize) -> Result<usize> {
+    let mut cursor = Cursor { offset: start, end };
+    let mut padding = start;
+    while let Some(tlv) = cursor.next::<T>(mbuf)? {
+        if !tlv.option_type.is_padding() {
+            padding = tlv.end_offset();
+        }
+    }
+    Ok(padding)
+}
+
+/// Resizes the trailing padding so the octets from `base` to `padding`
+/// plus `len` more octets end on an alignment boundary. Returns the
+/// aligned length measured from `base`.
+///
+/// `padding` is the buffer offset where the padding begins and `end` is
+/// the buffer offset where it currently ends.
+///
+/// # Errors
+///
+/// Returns an error if the aligned length would exceed the maximum.
+pub(crate) fn realign<T: TlvType>(
+    mbuf: &mut Mbuf,
+    base: usize,
+    padding: usize,
+    end: usize,
+    len: usize,
+) -> Result<usize> {
+    let options_len = padding - base + len;
+    let aligned_len = aligned_len::<T>(options_len)?;
+
+    let new_padding = aligned_len - options_len;
+    let old_padding = end - padding;
+    if new_padding != old_padding {
+        mbuf.resize(padding, new_padding as isize - old_padding as isize)?;
+    }
+    T::write_padding(mbuf, padding, new_padding)?;
+
+    Ok(aligned_len)
+}
+
+/// Inserts `len` octets of room for a new option at `at`, and resizes the
+/// trailing padding so the options stay aligned. Returns the aligned length
+/// measured from `base`.
+///
+/// `at` is either `base` or `padding`, the buffer offset where the padding
+/// begins, and `end` is the buffer offset where the padding currently ends.
+/// The room is left for the option to be written into.
+///
+/// # Errors
+///
+/// Returns an error if the aligned length would exceed the maximum, or if
+/// the buffer cannot be extended. The buffer is left unchanged.
+pub(crate) fn insert<T: TlvType>(
+    mbuf: &mut Mbuf,
+    base: usize,
+    at: usize,
+    padding: usize,
+    end: usize,
+    len: usize,
+) -> Result<usize> {
+    let _ = aligned_len::<T>(padding - base + len)?;
+    mbuf.extend(at, len)?;
+
+    match realign::<T>(mbuf, base, padding + len, end + len, 0) {
+        Ok(aligned_len) => Ok(aligned_len),
+        Err(e) => {
+            // a failed resize leaves the padding as it was.
+            let _ = mbuf.shrink(at, len);
+            Err(e)
+        }
+    }
+}
+
+/// Returns the length of the options padded to the alignment boundary.
+///
+/// # Errors
+///
+/// Returns an error if the aligned length would exceed the maximum.
+fn aligned_len<T: TlvType>(options_len: usize) -> Result<usize> {
+    let aligned_len = (options_len + T::ALIGNMENT - 1) & !(T::ALIGNMENT - 1);
+    ensure!(
+        aligned_len <= T::MAX_LEN,
+        anyhow!("options length {} exceeds {}.", aligned_len, T::MAX_LEN)
+    );
+    Ok(aligned_len)
+}
+
+/// Removes the options for which the predicate returns false. Padding
+/// options are kept and not passed to the predicate.
+///
+/// Returns the buffer offsets where the trailing padding begins and where
+/// the options end after the removals.
+pub(crate) fn retain<T, F>(
+    mbuf: &mut Mbuf,
+    start: usize,
+    mut end: usize,
+    mut f: F,
+) -> Result<(usize, usize)>
+where
+    T: TlvType,
+    F: FnMut(&mut Mbuf, Tlv<T>) -> bool,
+{
+    let mut offset = start;
+    let mut padding = start;
+    while end > offset {
+        let tlv = read::<T>(mbuf, offset, end)?;
+        if tlv.option_type.is_end_of_list() {
+            break;
+        }
+
+        if !tlv.option_type.is_padding() && !f(mbuf, tlv) {
+            mbuf.shrink(offset, tlv.len)?;
+            end -= tlv.len;
+        } else {
+            offset = tlv.end_offset();
+            if !tlv.option_type.is_padding() {
+                padding = offset;
+            }
+        }
+    }
+
+    Ok((padding, end))
+}
@@ -19,9 +19,11 @@
 //! User Datagram Protocol.
 
 use crate::ensure;
+use crate::packets::ethernet::{EtherTypes, Ethernet};
 use crate::packets::ip::v4::Ipv4;
 use crate::packets::ip::v6::Ipv6;
 use crate::packets::ip::{Flow, IpPacket, ProtocolNumbers};
+use crate::packets::tcp::Tcp;
 use crate::packets::types::u16be;
 use crate::packets::{checksum, Internal, Packet, SizeOf};
 use anyhow::{anyhow, Result, Error};
@@ -29,6 +31,9 @@ use std::fmt;
 use std::net::IpAddr;
 use std::ptr::NonNull;
 
+/// The first port of the dynamic range tunnel source ports are chosen from.
+const TUNNEL_PORT_BASE: u16 = 49152;
+
 /// User Datagram Protocol packet based on [IETF RFC 768].
 ///
 /// ```
@@ -134,7 +139,7 @@ impl<E: IpPacket> Udp<E> {
     }
 
     #[inline]
-    fn set_length(&mut self, length: u16) {
+    pub(crate) fn set_length(&mut self, length: u16) {
         self.header_mut().length = length.into()
     }
 
@@ -164,6 +169,13 @@ impl<E: IpPacket> Udp<E> {
     }
 
     /// Returns the data as a `u8` slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data spans more than one segment. Use [`data_to_vec`]
+    /// to read data that may be chained.
+    ///
+    /// [`data_to_vec`]: Self::data_to_vec
     #[inline]
     pub fn data(&self) -> &[u8] {
         if let Ok(data) = self
@@ -171,6 +183,19 @@ impl<E: IpPacket> Udp<E> {
             .read_data_slice(self.payload_offset(), self.payload_len())
         {
             unsafe { &*data.as_ptr() }
+        } else {
+            panic!("data spans more than one segment.")
+        }
+    }
+
+    /// Returns a copy of the data, which may span more than one segment.
+    #[inline]
+    pub fn data_to_vec(&self) -> Vec<u8> {
+        if let Ok(data) = self
+            .mbuf()
+            .data_slice(self.payload_offset(), self.payload_len())
+        {
+            data.into_owned()
         } else {
             unreachable!()
         }
@@ -234,13 +259,13 @@ impl<E: IpPacket> Udp<E> {
     fn compute_checksum(&mut self) {
         self.no_checksum();
 
-        if let Ok(data) = self.mbuf().read_data_slice(self.offset, self.len()) {
-            let data = unsafe { data.as_ref() };
-            let pseudo_header_sum = self
-                .envelope()
-                .pseudo_header(data.len() as u16, ProtocolNumbers::Udp)
-                .sum();
-            let checksum = checksum::compute(pseudo_header_sum, data);
+        let offset = self.offset;
+        let len = self.len();
+        let pseudo_header_sum = self
+            .envelope()
+            .pseudo_header(len as u16, ProtocolNumbers::Udp)
+            .sum();
+        if let Ok(checksum) = self.mbuf_mut().checksum(pseudo_header_sum, offset, len) {
             self.set_checksum(checksum);
         } else {
             // we are reading till the end of buffer, should never run out
@@ -383,7 +408,21 @@ impl<E: IpPacket> Packet for Udp<E> {
     fn reconcile(&mut self) {
         let len = self.len() as u16;
         self.set_length(len);
-        self.compute_checksum();
+
+        let offset = self.offset;
+        let header_len = self.header_len();
+        if self
+            .mbuf_mut()
+            .defer_l4_checksum(offset, header_len, ProtocolNumbers::Udp)
+        {
+            let sum = self
+                .envelope()
+                .pseudo_header(len, ProtocolNumbers::Udp)
+                .sum();
+            self.set_checksum(sum);
+        } else {
+            self.compute_checksum();
+        }
     }
 }
 
@@ -393,6 +432,84 @@ pub type Udp4 = Udp<Ipv4>;
 /// A type alias for an IPv6 UDP packet.
 pub type Udp6 = Udp<Ipv6>;
 
+/// Returns the source port of a UDP tunnel packet carrying the frame.
+///
+/// [IETF RFC 7348] recommends a hash of the inner frame's headers as the
+/// source port, taken from the dynamic port range. The port adds the entropy
+/// of the inner flows to the outer headers, so underlay routers that hash
+/// the outer 5-tuple keep the packets of an inner flow on the same path,
+/// while spreading different flows across equal-cost paths.
+///
+/// The RSS hash the NIC computed for the frame is used when available.
+/// Otherwise the hash is computed from the inner 5-tuple, or from the
+/// addresses of frames that are not TCP or UDP.
+///
+/// [IETF RFC 7348]: https://tools.ietf.org/html/rfc7348#section-5
+pub(crate) fn tunnel_src_port(frame: &Ethernet) -> u16 {
+    let hash = frame.mbuf().rss_hash().unwrap_or_else(|| {
+        let flow = match frame.ether_type() {
+            EtherTypes::Ipv4 => frame.peek::<Ipv4>().ok().map(|ip| ip_flow(&*ip)),
+            EtherTypes::Ipv6 => frame.peek::<Ipv6>().ok().map(|ip| ip_flow(&*ip)),
+            _ => None,
+        };
+
+        match flow {
+            Some(flow) => flow_hash(&flow),
+            None => {
+                let hash = fnv1a(FNV_OFFSET_BASIS, &frame.src().octets());
+                let hash = fnv1a(hash, &frame.dst().octets());
+                fnv1a(hash, &frame.ether_type().0.to_be_bytes())
+            }
+        }
+    });
+
+    // the dynamic range is the upper quarter of the ports.
+    TUNNEL_PORT_BASE | ((hash ^ (hash >> 16)) as u16 & !TUNNEL_PORT_BASE)
+}
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Folds the bytes into a 32-bit FNV-1a hash.
+fn fnv1a(hash: u32, bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(hash, |hash, &b| (hash ^ u32::from(b)).wrapping_mul(FNV_PRIME))
+}
+
+/// Returns the hash of the flow's 5-tuple.
+fn flow_hash(flow: &Flow) -> u32 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for ip in &[flow.src_ip(), flow.dst_ip()] {
+        hash = match ip {
+            IpAddr::V4(ip) => fnv1a(hash, &ip.octets()),
+            IpAddr::V6(ip) => fnv1a(hash, &ip.octets()),
+        };
+    }
+    let hash = fnv1a(hash, &flow.src_port().to_be_bytes());
+    let hash = fnv1a(hash, &flow.dst_port().to_be_bytes());
+    fnv1a(hash, &[flow.protocol().0])
+}
+
+/// Returns the flow of the IP packet, with the ports if the payload is
+/// TCP or UDP.
+fn ip_flow<E: IpPacket>(ip: &E) -> Flow {
+    let protocol = ip.next_protocol();
+    let ports = match protocol {
+        ProtocolNumbers::Tcp => ip
+            .peek::<Tcp<E>>()
+            .ok()
+            .map(|tcp| (tcp.src_port(), tcp.dst_port())),
+        ProtocolNumbers::Udp => ip
+            .peek::<Udp<E>>()
+            .ok()
+            .map(|udp| (udp.src_port(), udp.dst_port())),
+        _ => None,
+    };
+    let (src_port, dst_port) = ports.unwrap_or_default();
+    Flow::new(ip.src(), ip.dst(), src_port, dst_port, protocol)
+}
+
 /// UDP header.
 #[derive(Clone, Copy, Debug, Default, SizeOf)]
 #[repr(C)]
@@ -430,6 +547,19 @@ mod tests {
         assert_eq!(10, udp.data().len());
     }
 
+    #[capsule::test]
+    fn tunnel_src_port_per_flow() {
+        let port = |bytes: &[u8]| {
+            let packet = Mbuf::from_bytes(bytes).unwrap();
+            tunnel_src_port(&packet.parse::<Ethernet>().unwrap())
+        };
+
+        let udp = port(&IPV4_UDP_PACKET);
+        assert!(udp >= TUNNEL_PORT_BASE);
+        assert_eq!(udp, port(&IPV4_UDP_PACKET));
+        assert_ne!(udp, port(&IPV4_TCP_PACKET));
+    }
+
     #[capsule::test]
     fn parse_non_udp_packet() {
         let packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
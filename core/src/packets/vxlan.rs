@@ -0,0 +1,412 @@
+/*
+* Copyright 2019 Comcast Cable Communications Management, LLC
+*
+* Licensed under the Apache License, Version 2.0 (the "License");
+* you may not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+* http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific language governing permissions and
+* limitations under the License.
+*
+* SPDX-License-Identifier: Apache-2.0
+*/
+
+//! Virtual eXtensible Local Area Network.
+
+use crate::ensure;
+use crate::packets::ethernet::Ethernet;
+use crate::packets::ip::IpPacket;
+use crate::packets::types::u32be;
+use crate::packets::udp::{self, Udp};
+use crate::packets::{Internal, Packet, SizeOf};
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::ptr::NonNull;
+
+/// The IANA assigned UDP destination port of VXLAN.
+pub const VXLAN_PORT: u16 = 4789;
+
+/// The flag that indicates the network identifier is valid.
+const FLAGS_I: u8 = 0x08;
+
+/// Virtual eXtensible Local Area Network packet based on [IETF RFC 7348].
+///
+/// ```
+///  0                   1                   2                   3
+///  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |R|R|R|R|I|R|R|R|            Reserved                           |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// |                VXLAN Network Identifier (VNI) |   Reserved    |
+/// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+/// ```
+///
+/// - *Flags*: (8 bits)
+///      The I flag must be set to 1 for a valid VXLAN Network ID (VNI).
+///      The other 7 bits (designated "R") are reserved fields and must be
+///      set to zero on transmission and ignored on receipt.
+///
+/// - *VXLAN Network Identifier (VNI)*: (24 bits)
+///      A value used to designate the individual VXLAN overlay network on
+///      which the communicating VMs are situated.
+///
+/// The payload of the packet is the inner Ethernet frame, which can be
+/// parsed as an [`Ethernet`] enveloped by the VXLAN packet.
+///
+/// # Example
+///
+/// ```
+/// let vxlan = udp.parse::<Vxlan<Ipv4>>()?;
+/// let vni = vxlan.vni();
+/// let inner = vxlan.parse::<Ethernet<Vxlan<Ipv4>>>()?;
+/// ```
+///
+/// [IETF RFC 7348]: https://tools.ietf.org/html/rfc7348
+/// [`Ethernet`]: crate::packets::ethernet::Ethernet
+pub struct Vxlan<E: IpPacket> {
+    envelope: Udp<E>,
+    header: NonNull<VxlanHeader>,
+    offset: usize,
+}
+
+impl<E: IpPacket> Vxlan<E> {
+    #[inline]
+    fn header(&self) -> &VxlanHeader {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[inline]
+    fn header_mut(&mut self) -> &mut VxlanHeader {
+        unsafe { self.header.as_mut() }
+    }
+
+    /// Returns the flags.
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.header().flags
+    }
+
+    /// Sets the flags.
+    #[inline]
+    pub fn set_flags(&mut self, flags: u8) {
+        self.header_mut().flags = flags;
+    }
+
+    /// Returns a flag indicating whether the network identifier is valid.
+    #[inline]
+    pub fn has_vni(&self) -> bool {
+        self.flags() & FLAGS_I != 0
+    }
+
+    /// Returns the VXLAN network identifier.
+    #[inline]
+    pub fn vni(&self) -> u32 {
+        u32::from(self.header().vni) >> 8
+    }
+
+    /// Sets the VXLAN network identifier, and the flag indicating it is
+    /// valid. Only the lower 24 bits of the identifier are used.
+    #[inline]
+    pub fn set_vni(&mut self, vni: u32) {
+        self.header_mut().vni = (vni << 8).into();
+        self.header_mut().flags |= FLAGS_I;
+    }
+
+    /// Decapsulates the inner Ethernet frame.
+    ///
+    /// The outer Ethernet, IP, UDP and VXLAN headers are removed from the
+    /// buffer, and the inner frame becomes the outermost frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the inner frame is not a valid Ethernet frame.
+    #[inline]
+    pub fn decap(self) -> Result<Ethernet> {
+        let len = self.payload_offset();
+        let mut mbuf = self.reset();
+        mbuf.shrink(0, len)?;
+        mbuf.parse::<Ethernet>().map_err(|(e, _)| e)
+    }
+}
+
+impl<E: IpPacket<Envelope = Ethernet>> Vxlan<E> {
+    /// Encapsulates the Ethernet frame in a VXLAN packet.
+    ///
+    /// The outer Ethernet, IP, UDP and VXLAN headers are prepended to the
+    /// frame. The UDP source port is derived from the hash of the frame's
+    /// flow, so the packets of a flow take the same path in the underlay
+    /// network. The lengths of the outer headers are set. The caller is
+    /// responsible for setting the outer addresses and reconciling the
+    /// packet, which sets the outer checksums. The checksums are not
+    /// computed here, so the inner headers can still be reconciled first
+    /// and claim the NIC's checksum offloads.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    pub fn encap(frame: Ethernet, vni: u32) -> Result<Self> {
+        let src_port = udp::tunnel_src_port(&frame);
+        let ethernet = frame.reset().push::<Ethernet>()?;
+        let ip = ethernet.push::<E>()?;
+        let mut udp = ip.push::<Udp<E>>()?;
+        udp.set_src_port(src_port);
+        let mut vxlan = udp.push::<Vxlan<E>>()?;
+        vxlan.set_vni(vni);
+
+        let len = vxlan.envelope().len();
+        vxlan.envelope_mut().set_length(len as u16);
+        vxlan.envelope_mut().envelope_mut().set_length_from_buffer();
+        Ok(vxlan)
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for Vxlan<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("vxlan")
+            .field("flags", &format!("{:#04x}", self.flags()))
+            .field("vni", &self.vni())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for Vxlan<E> {
+    /// The preceding type for a VXLAN packet must be UDP.
+    type Envelope = Udp<E>;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        VxlanHeader::size_of()
+    }
+
+    #[inline]
+    unsafe fn clone(&self, internal: Internal) -> Self {
+        Vxlan::<E> {
+            envelope: self.envelope.clone(internal),
+            header: self.header,
+            offset: self.offset,
+        }
+    }
+
+    /// Parses the UDP packet's payload as a VXLAN packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the UDP destination port is not [`VXLAN_PORT`].
+    /// Returns an error if the payload does not have sufficient data for
+    /// the VXLAN header.
+    #[inline]
+    fn try_parse(envelope: Self::Envelope, _internal: Internal) -> Result<Self, (Error, Self::Envelope)> {
+        ensure!(
+            envelope.dst_port() == VXLAN_PORT,
+            (anyhow!("not a VXLAN packet."), envelope)
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = match mbuf.read_data(offset) {
+            Err(e) => return Err((e, envelope)),
+            Ok(header) => header,
+        };
+
+        Ok(Vxlan {
+            envelope,
+            header,
+            offset,
+        })
+    }
+
+    /// Prepends a VXLAN packet to the beginning of the UDP packet's payload.
+    ///
+    /// The UDP destination port is set to [`VXLAN_PORT`], and the I flag
+    /// is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer does not have enough free space.
+    #[inline]
+    fn try_push(mut envelope: Self::Envelope, _internal: Internal) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, VxlanHeader::size_of())?;
+        let header = mbuf.write_data(
+            offset,
+            &VxlanHeader {
+                flags: FLAGS_I,
+                ..Default::default()
+            },
+        )?;
+
+        envelope.set_dst_port(VXLAN_PORT);
+
+        Ok(Vxlan {
+            envelope,
+            header,
+            offset,
+        })
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+/// VXLAN header.
+#[derive(Clone, Copy, Debug, Default, SizeOf)]
+#[repr(C)]
+struct VxlanHeader {
+    flags: u8,
+    reserved: [u8; 3],
+    vni: u32be,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ethernet::EtherTypes;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::tcp::Tcp;
+    use crate::packets::{Mbuf, TxOffloads};
+    use crate::testils::byte_arrays::{IPV4_TCP_PACKET, IPV4_UDP_PACKET, VXLAN_PACKET};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn size_of_vxlan_header() {
+        assert_eq!(8, VxlanHeader::size_of());
+    }
+
+    #[capsule::test]
+    fn parse_vxlan_packet() {
+        let packet = Mbuf::from_bytes(&VXLAN_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        let vxlan = udp.parse::<Vxlan<Ipv4>>().unwrap();
+
+        assert_eq!(FLAGS_I, vxlan.flags());
+        assert!(vxlan.has_vni());
+        assert_eq!(1234, vxlan.vni());
+
+        let inner = vxlan.parse::<Ethernet<Vxlan<Ipv4>>>().unwrap();
+        assert_eq!("00:00:00:00:00:03", inner.dst().to_string());
+        assert_eq!("00:00:00:00:00:04", inner.src().to_string());
+        assert_eq!(EtherTypes::Ipv4, inner.ether_type());
+        assert_eq!(33, inner.payload_len());
+    }
+
+    #[capsule::test]
+    fn parse_non_vxlan_packet() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+
+        assert!(udp.parse::<Vxlan<Ipv4>>().is_err());
+    }
+
+    #[capsule::test]
+    fn push_vxlan_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let udp = ipv6.push::<Udp<Ipv6>>().unwrap();
+        let mut vxlan = udp.push::<Vxlan<Ipv6>>().unwrap();
+
+        assert_eq!(VxlanHeader::size_of(), vxlan.len());
+        assert_eq!(VXLAN_PORT, vxlan.envelope().dst_port());
+        assert_eq!(FLAGS_I, vxlan.flags());
+
+        vxlan.set_vni(0x00ab_cdef);
+        assert_eq!(0x00ab_cdef, vxlan.vni());
+        assert!(vxlan.push::<Ethernet<Vxlan<Ipv6>>>().is_ok());
+    }
+
+    #[capsule::test]
+    fn encap_and_decap_frame() {
+        let packet = Mbuf::from_bytes(&IPV4_UDP_PACKET).unwrap();
+        let frame = packet.parse::<Ethernet>().unwrap();
+        let src_port = udp::tunnel_src_port(&frame);
+        assert!(src_port >= 49152);
+
+        let mut vxlan = Vxlan::<Ipv4>::encap(frame, 1234).unwrap();
+        vxlan
+            .envelope_mut()
+            .envelope_mut()
+            .set_dst(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert_eq!(1234, vxlan.vni());
+        assert_eq!(src_port, vxlan.envelope().src_port());
+        assert_eq!(VXLAN_PORT, vxlan.envelope().dst_port());
+        assert_eq!(
+            (IPV4_UDP_PACKET.len() + 8 + 8) as u16,
+            vxlan.envelope().length()
+        );
+        assert_eq!(
+            (IPV4_UDP_PACKET.len() + 8 + 8 + 20) as u16,
+            vxlan.envelope().envelope().total_length()
+        );
+
+        let frame = vxlan.decap().unwrap();
+        let data = frame
+            .mbuf()
+            .read_data_slice::<u8>(0, frame.mbuf().data_len())
+            .unwrap();
+        assert_eq!(&IPV4_UDP_PACKET[..], unsafe { data.as_ref() });
+    }
+
+    #[capsule::test]
+    fn offload_inner_checksums() {
+        let mut packet = Mbuf::from_bytes(&IPV4_TCP_PACKET).unwrap();
+        packet.set_tx_offloads(TxOffloads {
+            ipv4_checksum: true,
+            l4_checksum: true,
+            tcp_segmentation: false,
+        });
+        let frame = packet.parse::<Ethernet>().unwrap();
+        let vxlan = Vxlan::<Ipv4>::encap(frame, 1234).unwrap();
+        let inner = vxlan.parse::<Ethernet<Vxlan<Ipv4>>>().unwrap();
+        let ipv4 = inner.parse::<Ipv4<Ethernet<Vxlan<Ipv4>>>>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp<Ipv4<Ethernet<Vxlan<Ipv4>>>>>().unwrap();
+
+        // the inner checksums are deferred, the outer ones are not.
+        tcp.reconcile_all();
+        assert_eq!(0, tcp.envelope().checksum());
+        let udp = tcp.envelope().envelope().envelope().envelope();
+        let offloaded = udp.checksum();
+        assert_ne!(0, offloaded);
+        assert_ne!(0, udp.envelope().checksum());
+
+        // matches the checksum over the inner checksums the NIC fills in.
+        tcp.mbuf_mut().set_tx_offloads(TxOffloads::default());
+        tcp.reconcile_all();
+        assert_ne!(0, tcp.envelope().checksum());
+        let udp = tcp.envelope().envelope().envelope().envelope();
+        assert_eq!(offloaded, udp.checksum());
+    }
+}
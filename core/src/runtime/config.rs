@@ -55,6 +55,7 @@ use regex::Regex;
 use serde::Deserialize;
 use std::fmt;
 use std::fs;
+use std::net::IpAddr;
 
 /// Runtime configuration settings.
 #[derive(Clone, Deserialize)]
@@ -308,6 +309,20 @@ pub struct PortConfig {
     /// to `true`.
     #[serde(default = "default_multicast_mode")]
     pub multicast: bool,
+
+    /// The maximum transmission unit of the port. Defaults to `1500`.
+    ///
+    /// Frames larger than the mbuf data room of 2048 bytes are received
+    /// into chained mbufs.
+    #[serde(default = "default_port_mtu")]
+    pub mtu: u16,
+
+    /// The IP addresses assigned to the port.
+    ///
+    /// ARP requests and neighbor solicitations for the addresses are
+    /// answered by the pipelines that receive and transmit on the port.
+    #[serde(default)]
+    pub addresses: Vec<IpAddr>,
 }
 
 fn default_port_rxqs() -> usize {
@@ -326,6 +341,10 @@ fn default_multicast_mode() -> bool {
     true
 }
 
+fn default_port_mtu() -> u16 {
+    1500
+}
+
 impl fmt::Debug for PortConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut d = f.debug_struct("PortConfig");
@@ -341,7 +360,11 @@ impl fmt::Debug for PortConfig {
             .field("txqs", &self.txqs)
             .field("promiscuous", &self.promiscuous)
             .field("multicast", &self.multicast)
-            .finish()
+            .field("mtu", &self.mtu);
+        if !self.addresses.is_empty() {
+            d.field("addresses", &self.addresses);
+        }
+        d.finish()
     }
 }
 
@@ -392,6 +415,8 @@ mod tests {
         assert_eq!(default_port_txqs(), config.ports[0].txqs);
         assert_eq!(default_promiscuous_mode(), config.ports[0].promiscuous);
         assert_eq!(default_multicast_mode(), config.ports[0].multicast);
+        assert_eq!(default_port_mtu(), config.ports[0].mtu);
+        assert!(config.ports[0].addresses.is_empty());
 
         assert_eq!("/var/capsule/myapp", &config.data_dir());
 
@@ -422,9 +447,11 @@ mod tests {
                 lcores = [3, 4]
                 rxqs = 32
                 txqs = 32
+                addresses = ["10.0.0.1", "2001:db8::1"]
         "#;
 
         let config: RuntimeConfig = toml::from_str(CONFIG)?;
+        assert_eq!(2, config.ports[1].addresses.len());
 
         assert_eq!(
             &[
@@ -107,6 +107,8 @@ impl Runtime {
                 .set_multicast(port.multicast)?
                 .set_lcores(port.lcores.clone())?
                 .set_symmetric_rss(config.symmetric_rss.unwrap_or(false))?
+                .set_mtu(port.mtu)?
+                .set_addresses(port.addresses.clone())
                 .build(&mut mempool)?;
 
             debug!(?port);
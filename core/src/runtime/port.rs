@@ -19,12 +19,14 @@
 use super::{LcoreMap, Mempool};
 use crate::ffi::dpdk::{self, MbufPtr, PortId, PortQueueId};
 use crate::net::MacAddr;
-use crate::packets::{Mbuf, Postmark};
+use crate::neighbor::{ArpResponder, NdpResponder};
+use crate::packets::{Mbuf, Packet, Postmark, TxOffloads};
 use crate::{debug, ensure, error, info, warn};
 use anyhow::Result;
 use capsule_ffi as cffi;
 use std::collections::HashMap;
 use std::fmt;
+use std::net::IpAddr;
 use thiserror::Error;
 use triggered::Listener;
 use std::time::Duration;
@@ -42,6 +44,8 @@ pub struct Port {
     name: String,
     port_id: PortId,
     lcores: Vec<usize>,
+    tx_offloads: TxOffloads,
+    addresses: Vec<IpAddr>,
 }
 
 impl Port {
@@ -69,6 +73,14 @@ impl Port {
         ((other_queue_id as f32 * (self.lcores.len() as f32 / other_port.lcores().len() as f32)) as usize).into()
     }
 
+    /// Returns the transmit offloads enabled on the port.
+    ///
+    /// Packets sent through the port can request the offloads with
+    /// `Mbuf::set_tx_offloads`.
+    pub fn tx_offloads(&self) -> TxOffloads {
+        self.tx_offloads
+    }
+
     /// Returns the MAC address of the port.
     ///
     /// If fails to retrieve the MAC address, `MacAddr::default` is returned.
@@ -76,6 +88,36 @@ impl Port {
         dpdk::eth_macaddr_get(self.port_id).unwrap_or_default()
     }
 
+    /// Returns the IP addresses assigned to the port.
+    pub fn addresses(&self) -> &[IpAddr] {
+        &self.addresses
+    }
+
+    /// Returns a responder for the ARP requests and neighbor solicitations
+    /// for the port's addresses, or `None` if the port has no addresses.
+    fn responder(&self) -> Option<Responder> {
+        let mut v4 = vec![];
+        let mut v6 = vec![];
+        for addr in self.addresses.iter() {
+            match addr {
+                IpAddr::V4(addr) => v4.push(*addr),
+                IpAddr::V6(addr) => v6.push(*addr),
+            }
+        }
+
+        let mac = self.mac_addr();
+        let responder = Responder {
+            arp: if v4.is_empty() { None } else { Some(ArpResponder::new(mac, v4)) },
+            ndp: if v6.is_empty() { None } else { Some(NdpResponder::new(mac, v6)) },
+        };
+
+        if responder.arp.is_none() && responder.ndp.is_none() {
+            None
+        } else {
+            Some(responder)
+        }
+    }
+
     /// Returns whether the port has promiscuous mode enabled.
     pub fn promiscuous(&self) -> bool {
         dpdk::eth_promiscuous_get(self.port_id)
@@ -116,6 +158,10 @@ impl Port {
 
     /// Spawns an infinite RX->TX pipeline with the given function and optionally a different port
     /// for TX
+    ///
+    /// If the pipeline transmits through the same port, ARP requests and
+    /// neighbor solicitations for the port's addresses are answered before
+    /// reaching the pipeline.
     pub fn spawn_rx_tx_pipeline<PipelineFn, ThreadLocalCreatorFn, ThreadLocal>(
         &self,
         lcore_map: &LcoreMap,
@@ -135,10 +181,17 @@ impl Port {
         // can't run loop without assigned cores.
         ensure!(!self.lcores.is_empty(), PortError::NoLCores);
 
+        // replies must go out the port the requests came in on.
+        let responder = match tx_port {
+            None => self.responder(),
+            Some(_) => None,
+        };
+
         for (index, lcore_id) in self.lcores.iter().enumerate() {
             let lcore = lcore_map.get(*lcore_id)?;
             let pipeline_fn = pipeline_fn.clone();
             let thread_local_creator_fn = thread_local_creator_fn.clone();
+            let responder = responder.clone();
 
             debug!(port = ?self.name, lcore = ?lcore.id(), "spawning rx/tx pipeline.");
 
@@ -159,6 +212,7 @@ impl Port {
                     32,
                     pipeline_fn,
                     thread_local_creator_fn,
+                    responder,
                     shutdown_listener
                 );
                 Ok(None)
@@ -241,10 +295,38 @@ impl fmt::Debug for Port {
             .field("lcores", &self.lcores)
             .field("promiscuous", &self.promiscuous())
             .field("multicast", &self.multicast())
+            .field("tx_offloads", &self.tx_offloads)
+            .field("addresses", &self.addresses)
             .finish()
     }
 }
 
+/// Answers the neighbor resolution requests for a port's addresses.
+#[derive(Clone, Debug)]
+struct Responder {
+    arp: Option<ArpResponder>,
+    ndp: Option<NdpResponder>,
+}
+
+impl Responder {
+    /// Returns the reply to the packet, or the packet unchanged if it is
+    /// not a request for the port's addresses.
+    fn respond(&self, mbuf: Mbuf) -> std::result::Result<Mbuf, Mbuf> {
+        let mbuf = match &self.arp {
+            Some(arp) => match arp.respond(mbuf) {
+                Ok(reply) => return Ok(reply.reset()),
+                Err(mbuf) => mbuf,
+            },
+            None => mbuf,
+        };
+
+        match &self.ndp {
+            Some(ndp) => ndp.respond(mbuf).map(|reply| reply.reset()),
+            None => Err(mbuf),
+        }
+    }
+}
+
 /// Port's receive queue.
 pub(crate) struct PortRxQueue {
     port_id: PortId,
@@ -282,6 +364,7 @@ impl PortRxQueue {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn rx_tx_pipeline_loop<PipelineFn, ThreadLocalCreatorFn, ThreadLocal>(
     rx_port_id: PortId,
     rx_queue_id: PortQueueId,
@@ -290,6 +373,7 @@ fn rx_tx_pipeline_loop<PipelineFn, ThreadLocalCreatorFn, ThreadLocal>(
     batch_size: usize,
     pipeline_fn: PipelineFn,
     thread_local_creator_fn: ThreadLocalCreatorFn,
+    responder: Option<Responder>,
     shutdown_listener: Listener
 ) where
     PipelineFn: Fn(Mbuf, &mut ThreadLocal) -> Result<Postmark> + Clone + Send + Sync + 'static,
@@ -309,7 +393,17 @@ fn rx_tx_pipeline_loop<PipelineFn, ThreadLocalCreatorFn, ThreadLocal>(
         rxq.receive(&mut ptrs);
 
         for ptr in ptrs.drain(..) {
-            let mbuf = Mbuf::from_easyptr(ptr);
+            let mut mbuf = Mbuf::from_easyptr(ptr);
+            if let Some(responder) = &responder {
+                match responder.respond(mbuf) {
+                    Ok(reply) => {
+                        emits.push(reply);
+                        continue;
+                    }
+                    Err(packet) => mbuf = packet,
+                }
+            }
+
             match pipeline_fn(mbuf, &mut thread_locals) {
                 Ok(postmark) => {
                     emits.extend(postmark.emit);
@@ -477,6 +571,19 @@ pub enum PortError {
     /// Symmetric RSS cannot be disabled
     #[error("symmetric RSS cannot be disabled")]
     SymRSSNoDisable,
+
+    /// The MTU is outside of the device limits, or the device cannot
+    /// receive or transmit frames that large.
+    #[error("mtu {0} not supported.")]
+    MtuNotSupported(u16),
+}
+
+/// The data room of the mbufs the ports receive into.
+const MBUF_DATA_ROOM: usize = cffi::RTE_MBUF_DEFAULT_DATAROOM as usize;
+
+/// Returns the length of the largest Ethernet frame for the MTU.
+fn frame_len(mtu: u16) -> usize {
+    mtu as usize + (cffi::RTE_ETHER_HDR_LEN + cffi::RTE_ETHER_CRC_LEN) as usize
 }
 
 /// Port builder.
@@ -488,7 +595,9 @@ pub(crate) struct Builder {
     lcores: Vec<usize>,
     rxqs: usize,
     txqs: usize,
-    symmetric_rss: bool
+    symmetric_rss: bool,
+    mtu: u16,
+    addresses: Vec<IpAddr>,
 }
 
 impl Builder {
@@ -522,7 +631,9 @@ impl Builder {
             lcores: vec![],
             rxqs: port_info.rx_desc_lim.nb_min as usize,
             txqs: port_info.tx_desc_lim.nb_min as usize,
-            symmetric_rss: false
+            symmetric_rss: false,
+            mtu: cffi::RTE_ETHER_MTU as u16,
+            addresses: vec![],
         })
     }
 
@@ -647,6 +758,48 @@ impl Builder {
         Ok(self)
     }
 
+    /// Sets the maximum transmission unit of the port.
+    ///
+    /// Frames that do not fit in the mbuf data room are received into and
+    /// transmitted from chained mbufs.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PortError` if the MTU is outside of the device limits, or
+    /// the device does not support jumbo frames, scattered receive or
+    /// multi-segment transmit when they are needed.
+    pub(crate) fn set_mtu(&mut self, mtu: u16) -> Result<&mut Self> {
+        ensure!(
+            mtu >= self.port_info.min_mtu && mtu <= self.port_info.max_mtu,
+            PortError::MtuNotSupported(mtu)
+        );
+
+        let rx_capa = self.port_info.rx_offload_capa;
+        let tx_capa = self.port_info.tx_offload_capa;
+        if frame_len(mtu) > cffi::RTE_ETHER_MAX_LEN as usize {
+            ensure!(
+                rx_capa & cffi::DEV_RX_OFFLOAD_JUMBO_FRAME as u64 > 0,
+                PortError::MtuNotSupported(mtu)
+            );
+        }
+        if frame_len(mtu) > MBUF_DATA_ROOM {
+            ensure!(
+                rx_capa & cffi::DEV_RX_OFFLOAD_SCATTER as u64 > 0
+                    && tx_capa & cffi::DEV_TX_OFFLOAD_MULTI_SEGS as u64 > 0,
+                PortError::MtuNotSupported(mtu)
+            );
+        }
+
+        self.mtu = mtu;
+        Ok(self)
+    }
+
+    /// Sets the IP addresses assigned to the port.
+    pub(crate) fn set_addresses(&mut self, addresses: Vec<IpAddr>) -> &mut Self {
+        self.addresses = addresses;
+        self
+    }
+
     /// Builds the port.
     ///
     /// # Errors
@@ -654,10 +807,49 @@ impl Builder {
     /// Returns `DpdkError` if fails to configure the device or any of the
     /// rx and tx queues.
     pub(crate) fn build(&mut self, mempool: &mut Mempool) -> Result<Port> {
-        // turns on optimization for mbuf fast free.
-        if self.port_info.tx_offload_capa & cffi::DEV_TX_OFFLOAD_MBUF_FAST_FREE as u64 > 0 {
-            self.port_conf.txmode.offloads |= cffi::DEV_TX_OFFLOAD_MBUF_FAST_FREE as u64;
-            debug!(port = ?self.name, "mbuf fast free enabled.");
+        // `Mbuf::extend` can chain segments regardless of the MTU, so the
+        // port transmits multi-segment mbufs when the device supports it.
+        // mbuf fast free is left off because it requires single segment
+        // mbufs.
+        if self.port_info.tx_offload_capa & cffi::DEV_TX_OFFLOAD_MULTI_SEGS as u64 > 0 {
+            self.port_conf.txmode.offloads |= cffi::DEV_TX_OFFLOAD_MULTI_SEGS as u64;
+            debug!(port = ?self.name, "multi-segment tx enabled.");
+        } else {
+            warn!(port = ?self.name, "multi-segment tx not supported.");
+        }
+
+        // turns on checksum and segmentation offloads the device supports.
+        let capa = self.port_info.tx_offload_capa;
+        let has = |offload: u32| capa & offload as u64 > 0;
+        let tx_offloads = TxOffloads {
+            ipv4_checksum: has(cffi::DEV_TX_OFFLOAD_IPV4_CKSUM),
+            l4_checksum: has(cffi::DEV_TX_OFFLOAD_TCP_CKSUM)
+                && has(cffi::DEV_TX_OFFLOAD_UDP_CKSUM),
+            tcp_segmentation: has(cffi::DEV_TX_OFFLOAD_TCP_TSO),
+        };
+        if tx_offloads.ipv4_checksum {
+            self.port_conf.txmode.offloads |= cffi::DEV_TX_OFFLOAD_IPV4_CKSUM as u64;
+        }
+        if tx_offloads.l4_checksum {
+            self.port_conf.txmode.offloads |=
+                (cffi::DEV_TX_OFFLOAD_TCP_CKSUM | cffi::DEV_TX_OFFLOAD_UDP_CKSUM) as u64;
+        }
+        if tx_offloads.tcp_segmentation {
+            self.port_conf.txmode.offloads |= cffi::DEV_TX_OFFLOAD_TCP_TSO as u64;
+        }
+        debug!(port = ?self.name, ?tx_offloads, "tx offloads enabled.");
+
+        // turns on jumbo frames, and chains the mbufs for frames that do
+        // not fit in a single mbuf.
+        let frame_len = frame_len(self.mtu);
+        if frame_len > cffi::RTE_ETHER_MAX_LEN as usize {
+            self.port_conf.rxmode.offloads |= cffi::DEV_RX_OFFLOAD_JUMBO_FRAME as u64;
+            self.port_conf.rxmode.max_rx_pkt_len = frame_len as u32;
+            debug!(port = ?self.name, mtu = self.mtu, "jumbo frames enabled.");
+        }
+        if frame_len > MBUF_DATA_ROOM {
+            self.port_conf.rxmode.offloads |= cffi::DEV_RX_OFFLOAD_SCATTER as u64;
+            debug!(port = ?self.name, "scattered rx enabled.");
         }
 
         // configures the device before everything else.
@@ -668,6 +860,10 @@ impl Builder {
             &self.port_conf,
         )?;
 
+        if self.mtu != cffi::RTE_ETHER_MTU as u16 {
+            dpdk::eth_dev_set_mtu(self.port_id, self.mtu)?;
+        }
+
         let socket = self.port_id.socket();
         warn!(
             cond: mempool.socket() != socket,
@@ -702,7 +898,9 @@ impl Builder {
         Ok(Port {
             name: self.name.clone(),
             port_id: self.port_id,
-            lcores: self.lcores.clone()
+            lcores: self.lcores.clone(),
+            tx_offloads,
+            addresses: self.addresses.clone(),
         })
     }
 }
@@ -768,6 +966,23 @@ mod tests {
         Ok(())
     }
 
+    #[capsule::test]
+    fn set_mtu() -> Result<()> {
+        let mut builder = Builder::for_device("test0", "net_ring0")?;
+
+        assert!(builder.set_mtu(1500).is_ok());
+        assert_eq!(1500, builder.mtu);
+
+        // below the minimum ethernet mtu.
+        assert!(builder.set_mtu(60).is_err());
+
+        // ring port does not support jumbo frames.
+        assert!(builder.set_mtu(9000).is_err());
+        assert_eq!(1500, builder.mtu);
+
+        Ok(())
+    }
+
     #[capsule::test]
     fn build_port() -> Result<()> {
         let lcores = (0..2).collect::<Vec<_>>();
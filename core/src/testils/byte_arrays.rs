@@ -122,6 +122,48 @@ pub const IPV4_TCP_PACKET: [u8; 58] = [
     0x02, 0x04, 0x05, 0xb4
 ];
 
+/// An IPv4 TCP SYN packet with the common TCP options.
+#[rustfmt::skip]
+pub const IPV4_TCP_SYN_PACKET: [u8; 74] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x3c,
+    // ident = 7238, flags = 4, frag_offset = 0
+    0x1c, 0x46, 0x40, 0x00,
+    // ttl = 64, protocol = TCP, checksum = 0x0a74
+    0x40, 0x06, 0x0a, 0x74,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// TCP header
+    // src_port = 50000, dst_port = 80
+    0xc3, 0x50, 0x00, 0x50,
+    // seq_no = 1000000000
+    0x3b, 0x9a, 0xca, 0x00,
+    // ack_no = 0
+    0x00, 0x00, 0x00, 0x00,
+    // data_offset = 10, flags = 0x02
+    0xa0, 0x02,
+    // window = 64240, checksum = 0x8d8f, urgent = 0
+    0xfa, 0xf0, 0x8d, 0x8f, 0x00, 0x00,
+    // mss = 1460
+    0x02, 0x04, 0x05, 0xb4,
+    // sack permitted
+    0x04, 0x02,
+    // timestamps, ts_val = 123456, ts_ecr = 0
+    0x08, 0x0a, 0x00, 0x01, 0xe2, 0x40, 0x00, 0x00, 0x00, 0x00,
+    // no operation
+    0x01,
+    // window scale, shift count = 7
+    0x03, 0x03, 0x07
+];
+
 /// An IPv4 UDP packet.
 #[rustfmt::skip]
 pub const IPV4_UDP_PACKET: [u8; 52] = [
@@ -150,6 +192,41 @@ pub const IPV4_UDP_PACKET: [u8; 52] = [
     0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x68, 0x65, 0x6c, 0x6c, 0x6f
 ];
 
+/// An IPv4 UDP packet with header options.
+#[rustfmt::skip]
+pub const IPV4_OPTIONS_PACKET: [u8; 59] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    // version = 4, ihl = 8
+    0x48, 0x00,
+    // IPv4 payload length
+    0x00, 0x2d,
+    // ident = 4660, flags = 4, frag_offset = 0
+    0x12, 0x34, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0x556a
+    0x40, 0x11, 0x55, 0x6a,
+    // src = 192.168.1.1
+    0xc0, 0xa8, 0x01, 0x01,
+    // dst = 192.168.1.2
+    0xc0, 0xa8, 0x01, 0x02,
+    // record route, length = 7, pointer = 8, route = [192.168.1.1]
+    0x07, 0x07, 0x08, 0xc0, 0xa8, 0x01, 0x01,
+    // no operation
+    0x01,
+    // router alert, length = 4, value = 0
+    0x94, 0x04, 0x00, 0x00,
+// UDP header
+    // src_port = 12345, dst_port = 53
+    0x30, 0x39, 0x00, 0x35,
+    // UDP length = 13, checksum = 0x0840
+    0x00, 0x0d, 0x08, 0x40,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
 /// An IPv6 TCP packet.
 #[rustfmt::skip]
 pub const IPV6_TCP_PACKET: [u8; 78] = [
@@ -273,6 +350,44 @@ pub const IPV6_FRAGMENT_PACKET: [u8; 72] = [
     0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x68, 0x65, 0x6c, 0x6c, 0x6f
 ];
 
+/// An IPv6 UDP packet with hop-by-hop and destination options extension
+/// headers.
+#[rustfmt::skip]
+pub const IPV6_OPTIONS_PACKET: [u8; 83] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x86, 0xdd,
+// IPv6 header
+    0x60, 0x00, 0x00, 0x00,
+    // payload length
+    0x00, 0x1d,
+    // next header (hop-by-hop)
+    0x00,
+    0x40,
+    0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+// Hop-by-hop header
+    // next header (destination options), hdr ext len = 0
+    0x3c, 0x00,
+    // router alert = 0 (MLD)
+    0x05, 0x02, 0x00, 0x00,
+    // padn
+    0x01, 0x00,
+// Destination options header
+    // next header (udp), hdr ext len = 0
+    0x11, 0x00,
+    // padn
+    0x01, 0x04, 0x00, 0x00, 0x00, 0x00,
+// UDP header
+    // src port = 1234, dst port = 5678
+    0x04, 0xd2, 0x16, 0x2e,
+    // length = 13, checksum = 0x458d
+    0x00, 0x0d, 0x45, 0x8d,
+// UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
 /// An ICMPv4 packet.
 #[rustfmt::skip]
 pub const ICMPV4_PACKET: [u8; 74] = [
@@ -398,3 +513,338 @@ pub const ROUTER_SOLICIT_PACKET: [u8; 70] = [
     // source link-layer address option
     0x01, 0x01, 0x70, 0x3a, 0xcb, 0x1b, 0xf9, 0x7a
 ];
+
+/// A VXLAN packet carrying an IPv4 UDP frame.
+#[rustfmt::skip]
+pub const VXLAN_PACKET: [u8; 97] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x53,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0x2698
+    0x40, 0x11, 0x26, 0x98,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// UDP header
+    // src_port = 50000, dst_port = 4789
+    0xc3, 0x50, 0x12, 0xb5,
+    // UDP length = 63, checksum = 0
+    0x00, 0x3f, 0x00, 0x00,
+// VXLAN header
+    // flags = I, reserved
+    0x08, 0x00, 0x00, 0x00,
+    // VNI = 1234, reserved
+    0x00, 0x04, 0xd2, 0x00,
+// inner Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+    0x08, 0x00,
+// inner IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x21,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0xb978
+    0x40, 0x11, 0xb9, 0x78,
+    // src = 192.168.0.1
+    0xc0, 0xa8, 0x00, 0x01,
+    // dst = 192.168.0.2
+    0xc0, 0xa8, 0x00, 0x02,
+// inner UDP header
+    // src_port = 1024, dst_port = 53
+    0x04, 0x00, 0x00, 0x35,
+    // UDP length = 13, checksum = 0
+    0x00, 0x0d, 0x00, 0x00,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
+/// A GRE packet with key and sequence number carrying an IPv4 UDP packet.
+#[rustfmt::skip]
+pub const GRE_PACKET: [u8; 79] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x41,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = GRE, checksum = 0x268c
+    0x40, 0x2f, 0x26, 0x8c,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// GRE header
+    // flags = K S, version = 0, protocol type = IPv4
+    0x30, 0x00, 0x08, 0x00,
+    // key = 100
+    0x00, 0x00, 0x00, 0x64,
+    // sequence number = 1
+    0x00, 0x00, 0x00, 0x01,
+// inner IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x21,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0xb978
+    0x40, 0x11, 0xb9, 0x78,
+    // src = 192.168.0.1
+    0xc0, 0xa8, 0x00, 0x01,
+    // dst = 192.168.0.2
+    0xc0, 0xa8, 0x00, 0x02,
+// inner UDP header
+    // src_port = 1024, dst_port = 53
+    0x04, 0x00, 0x00, 0x35,
+    // UDP length = 13, checksum = 0
+    0x00, 0x0d, 0x00, 0x00,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
+/// A Geneve packet with a critical option carrying an IPv4 UDP frame.
+#[rustfmt::skip]
+pub const GENEVE_PACKET: [u8; 105] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x5b,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0x2690
+    0x40, 0x11, 0x26, 0x90,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// UDP header
+    // src_port = 50000, dst_port = 6081
+    0xc3, 0x50, 0x17, 0xc1,
+    // UDP length = 71, checksum = 0
+    0x00, 0x47, 0x00, 0x00,
+// Geneve header
+    // version = 0, options length = 2, flags = C, protocol type = TEB
+    0x02, 0x40, 0x65, 0x58,
+    // VNI = 5678, reserved
+    0x00, 0x16, 0x2e, 0x00,
+    // option class = 0x0102, type = 0x80, length = 1
+    0x01, 0x02, 0x80, 0x01,
+    // option data
+    0xde, 0xad, 0xbe, 0xef,
+// inner Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+    0x08, 0x00,
+// inner IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x21,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0xb978
+    0x40, 0x11, 0xb9, 0x78,
+    // src = 192.168.0.1
+    0xc0, 0xa8, 0x00, 0x01,
+    // dst = 192.168.0.2
+    0xc0, 0xa8, 0x00, 0x02,
+// inner UDP header
+    // src_port = 1024, dst_port = 53
+    0x04, 0x00, 0x00, 0x35,
+    // UDP length = 13, checksum = 0
+    0x00, 0x0d, 0x00, 0x00,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
+/// An MPLS packet with two labels carrying an IPv4 UDP packet.
+#[rustfmt::skip]
+pub const MPLS_PACKET: [u8; 55] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x88, 0x47,
+// MPLS label stack
+    // label = 100, tc = 0, bottom of stack = 0, ttl = 64
+    0x00, 0x06, 0x40, 0x40,
+    // label = 200, tc = 0, bottom of stack = 1, ttl = 64
+    0x00, 0x0c, 0x81, 0x40,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x21,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0xb978
+    0x40, 0x11, 0xb9, 0x78,
+    // src = 192.168.0.1
+    0xc0, 0xa8, 0x00, 0x01,
+    // dst = 192.168.0.2
+    0xc0, 0xa8, 0x00, 0x02,
+// UDP header
+    // src_port = 1024, dst_port = 53
+    0x04, 0x00, 0x00, 0x35,
+    // UDP length = 13, checksum = 0
+    0x00, 0x0d, 0x00, 0x00,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
+/// A GTP-U packet with a PDU session container carrying an IPv4 UDP
+/// packet.
+#[rustfmt::skip]
+pub const GTPU_PACKET: [u8; 91] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x4d,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0x269e
+    0x40, 0x11, 0x26, 0x9e,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// UDP header
+    // src_port = 2152, dst_port = 2152
+    0x08, 0x68, 0x08, 0x68,
+    // UDP length = 57, checksum = 0
+    0x00, 0x39, 0x00, 0x00,
+// GTP-U header
+    // version = 1, PT = 1, flags = E S, message type = G-PDU, length = 41
+    0x36, 0xff, 0x00, 0x29,
+    // TEID = 1234
+    0x00, 0x00, 0x04, 0xd2,
+    // sequence = 7, N-PDU number = 0, next extension = PDU session container
+    0x00, 0x07, 0x00, 0x85,
+    // length = 1, PDU type = UL, QFI = 9, next extension = none
+    0x01, 0x10, 0x09, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x21,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0xb978
+    0x40, 0x11, 0xb9, 0x78,
+    // src = 192.168.0.1
+    0xc0, 0xa8, 0x00, 0x01,
+    // dst = 192.168.0.2
+    0xc0, 0xa8, 0x00, 0x02,
+// UDP header
+    // src_port = 1024, dst_port = 53
+    0x04, 0x00, 0x00, 0x35,
+    // UDP length = 13, checksum = 0
+    0x00, 0x0d, 0x00, 0x00,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
+
+/// An SCTP packet with a DATA chunk and a SACK chunk.
+#[rustfmt::skip]
+pub const SCTP_PACKET: [u8; 86] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x48,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = SCTP, checksum = 0x2630
+    0x40, 0x84, 0x26, 0x30,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// SCTP common header
+    // src_port = 2905, dst_port = 2905
+    0x0b, 0x59, 0x0b, 0x59,
+    // verification tag = 0x12345678
+    0x12, 0x34, 0x56, 0x78,
+    // checksum = 0x53c67213
+    0x13, 0x72, 0xc6, 0x53,
+// DATA chunk
+    // type = DATA, flags = B E, length = 21
+    0x00, 0x03, 0x00, 0x15,
+    // TSN = 1
+    0x00, 0x00, 0x00, 0x01,
+    // stream identifier = 0, stream sequence number = 0
+    0x00, 0x00, 0x00, 0x00,
+    // payload protocol identifier = 0
+    0x00, 0x00, 0x00, 0x00,
+    // user data and padding
+    0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x00, 0x00,
+// SACK chunk
+    // type = SACK, flags = 0, length = 16
+    0x03, 0x00, 0x00, 0x10,
+    // cumulative TSN ack = 16
+    0x00, 0x00, 0x00, 0x10,
+    // advertised receiver window credit = 65536
+    0x00, 0x01, 0x00, 0x00,
+    // gap ack blocks = 0, duplicate TSNs = 0
+    0x00, 0x00, 0x00, 0x00
+];
+
+/// An IPv4 packet encapsulated in an IPv4 packet.
+#[rustfmt::skip]
+pub const IPIP_PACKET: [u8; 67] = [
+// Ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x08, 0x00,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x35,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = IPIP, checksum = 0x26c3
+    0x40, 0x04, 0x26, 0xc3,
+    // src = 10.0.0.1
+    0x0a, 0x00, 0x00, 0x01,
+    // dst = 10.0.0.2
+    0x0a, 0x00, 0x00, 0x02,
+// IPv4 header
+    0x45, 0x00,
+    // IPv4 payload length
+    0x00, 0x21,
+    // ident = 0, flags = 4, frag_offset = 0
+    0x00, 0x00, 0x40, 0x00,
+    // ttl = 64, protocol = UDP, checksum = 0xb978
+    0x40, 0x11, 0xb9, 0x78,
+    // src = 192.168.0.1
+    0xc0, 0xa8, 0x00, 0x01,
+    // dst = 192.168.0.2
+    0xc0, 0xa8, 0x00, 0x02,
+// UDP header
+    // src_port = 1024, dst_port = 53
+    0x04, 0x00, 0x00, 0x35,
+    // UDP length = 13, checksum = 0
+    0x00, 0x0d, 0x00, 0x00,
+    // UDP payload
+    0x68, 0x65, 0x6c, 0x6c, 0x6f
+];
@@ -21,7 +21,7 @@ use crate::packets::ip::v4::Ipv4;
 use crate::packets::ip::v6::{Ipv6, SegmentRouting};
 use crate::packets::tcp::{Tcp, Tcp4, Tcp6};
 use crate::packets::udp::{Udp4, Udp6};
-use crate::packets::Packet;
+use crate::packets::{Mbuf, Packet};
 
 /// [`Packet`] extension trait.
 ///
@@ -78,3 +78,37 @@ pub trait PacketExt: Packet + Sized {
 }
 
 impl<T> PacketExt for T where T: Packet + Sized {}
+
+/// Appends `len` octets of patterned payload to the packet in `bytes`.
+fn mbuf_with_payload(bytes: &[u8], len: usize) -> Mbuf {
+    let mut packet = Mbuf::from_bytes(bytes).unwrap();
+    let offset = packet.data_len();
+    let payload = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+    packet.extend(offset, len).unwrap();
+    packet.write_data_slice(offset, &payload).unwrap();
+    packet
+}
+
+/// Returns the IPv4 packet in `bytes` with `len` octets of patterned
+/// payload appended, and the don't fragment flag unset.
+pub fn ipv4_with_payload(bytes: &[u8], len: usize) -> Ipv4 {
+    let mut ipv4 = mbuf_with_payload(bytes, len).into_v4();
+    ipv4.unset_dont_fragment();
+    ipv4.reconcile();
+    ipv4
+}
+
+/// Returns the IPv6 packet in `bytes` with `len` octets of patterned
+/// payload appended.
+pub fn ipv6_with_payload(bytes: &[u8], len: usize) -> Ipv6 {
+    let mut ipv6 = mbuf_with_payload(bytes, len).into_v6();
+    ipv6.reconcile();
+    ipv6
+}
+
+/// Returns a copy of all the data in the packet's buffer.
+pub fn bytes_of<T: Packet>(packet: &T) -> Vec<u8> {
+    let mut bytes = vec![0u8; packet.mbuf().data_len()];
+    packet.mbuf().copy_to_slice(0, &mut bytes).unwrap();
+    bytes
+}
@@ -102,6 +102,8 @@ const RTE_DEPS_LIBS: &[&str] = &["numa", "pcap"];
 fn bind(path: &Path) {
     cc::Build::new()
         .file("src/shim.c")
+        // the dynamic mbuf RX timestamp is still an experimental API.
+        .define("ALLOW_EXPERIMENTAL_API", None)
         .flag("-march=corei7-avx")
         .compile("rte_shim");
 
@@ -96,3 +96,10 @@ uint16_t _rte_eth_tx_burst(
     uint16_t queue_id,
     struct rte_mbuf **tx_pkts,
     uint16_t nb_pkts);
+
+/**
+ * Register the dynamic mbuf field and flag for the RX timestamp.
+ */
+int _rte_mbuf_dyn_rx_timestamp_register(
+    int *field_offset,
+    uint64_t *rx_flag);
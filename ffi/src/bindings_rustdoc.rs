@@ -26126,6 +26126,13 @@ extern "C" {
         nb_pkts: u16,
     ) -> u16;
 }
+extern "C" {
+    #[doc = " Register the dynamic mbuf field and flag for the RX timestamp."]
+    pub fn _rte_mbuf_dyn_rx_timestamp_register(
+        field_offset: *mut ::std::os::raw::c_int,
+        rx_flag: *mut u64,
+    ) -> ::std::os::raw::c_int;
+}
 pub type __builtin_va_list = [__va_list_tag; 1usize];
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq)]
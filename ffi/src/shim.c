@@ -20,6 +20,7 @@
 #include <rte_ethdev.h>
 #include <rte_lcore.h>
 #include <rte_mbuf.h>
+#include <rte_mbuf_dyn.h>
 #include <rte_mempool.h>
 
 int _rte_errno(void) {
@@ -67,3 +68,9 @@ uint16_t _rte_eth_tx_burst(
     uint16_t nb_pkts) {
     return rte_eth_tx_burst(port_id, queue_id, tx_pkts, nb_pkts);
 }
+
+int _rte_mbuf_dyn_rx_timestamp_register(
+    int *field_offset,
+    uint64_t *rx_flag) {
+    return rte_mbuf_dyn_rx_timestamp_register(field_offset, rx_flag);
+}